@@ -0,0 +1,47 @@
+//! Cost of loading the handler of a `HotSwap`
+//!
+//! Run with:
+//! ```bash
+//! cargo bench --bench hot_swap
+//! ```
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use maker_web::HotSwap;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+struct Routes;
+
+fn current(c: &mut Criterion) {
+    let swap = HotSwap::new(Routes);
+    let mut group = c.benchmark_group("hot_swap");
+    group.bench_function("current", |b| b.iter(|| black_box(swap.current())));
+
+    // 7 more workers loading the handler all the time
+    let stop = Arc::new(AtomicBool::new(false));
+    let workers: Vec<_> = (0..7)
+        .map(|_| {
+            let (swap, stop) = (swap.clone(), stop.clone());
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    black_box(swap.current());
+                }
+            })
+        })
+        .collect();
+    group.bench_function("current_8_threads", |b| {
+        b.iter(|| black_box(swap.current()))
+    });
+    stop.store(true, Ordering::Relaxed);
+    workers
+        .into_iter()
+        .for_each(|worker| worker.join().unwrap());
+    group.finish();
+}
+
+criterion_group!(benches, current);
+criterion_main!(benches);
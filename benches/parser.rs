@@ -0,0 +1,112 @@
+//! Parser micro-benchmarks
+//!
+//! Run with:
+//! ```bash
+//! cargo bench --bench parser
+//! ```
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use maker_web::{bench_helper::ParseBench, limits::ReqLimits};
+
+const TINY_GET: &str = "GET / HTTP/1.1\r\nHost: a\r\n\r\n";
+
+const HEADER_HEAVY: &str = "GET /api/v1/users/123?sort=name&page=2 HTTP/1.1\r
+Host: localhost\r
+User-Agent: Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36\r
+Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r
+Accept-Language: en-US,en;q=0.5\r
+Accept-Encoding: gzip, deflate\r
+Connection: keep-alive\r
+Upgrade-Insecure-Requests: 1\r
+Cache-Control: max-age=0\r
+Sec-Fetch-Dest: document\r
+Sec-Fetch-Mode: navigate\r
+Sec-Fetch-Site: none\r
+Sec-Fetch-User: ?1\r
+DNT: 1\r
+\r
+";
+
+const BODY_POST: &str = "POST /api/v1/orders HTTP/1.1\r
+Host: localhost\r
+Content-Type: application/json\r
+Content-Length: 93\r
+\r
+{\"user_id\":123,\"items\":[{\"id\":1,\"count\":2},{\"id\":7,\"count\":1}],\"comment\":\"leave at the door\"}";
+
+const HTTP_09: &str = "GET /keep_alive/api/user/first_name?lang=en\r\n";
+
+fn parse(c: &mut Criterion) {
+    let cases = [
+        ("tiny_get", TINY_GET, false),
+        ("header_heavy", HEADER_HEAVY, false),
+        ("body_post", BODY_POST, false),
+        ("http_09", HTTP_09, true),
+    ];
+
+    let mut group = c.benchmark_group("parse");
+    for (name, request, http_09) in cases {
+        let mut bench = ParseBench::new(request, http_09);
+        assert!(bench.parse(), "`{name}` must be a valid request");
+
+        group.throughput(Throughput::Bytes(request.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(bench.parse()));
+        });
+    }
+    group.finish();
+}
+
+fn header_lookup(c: &mut Criterion) {
+    const WANTED: [&[u8]; 3] = [b"host", b"authorization", b"x-request-id"];
+
+    let mut request = String::from("GET / HTTP/1.1\r\n");
+    for i in 0..60 {
+        request.push_str(&format!("X-Custom-{i}: value-{i}\r\n"));
+    }
+    request.push_str("Host: localhost\r\nAuthorization: Bearer abc\r\nX-Request-Id: 42\r\n\r\n");
+
+    let cases = [("linear", None), ("captured", Some(&WANTED[..]))];
+
+    let mut group = c.benchmark_group("header_lookup");
+    for (name, captured_headers) in cases {
+        let limits = ReqLimits {
+            header_count: 64,
+            captured_headers,
+            ..ReqLimits::default()
+        };
+        let mut bench = ParseBench::with_limits(&request, limits);
+        assert!(bench.parse(), "header-heavy request must be valid");
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for name in WANTED {
+                    black_box(bench.request().header(black_box(name)));
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+fn query(c: &mut Criterion) {
+    const QUERY_HEAVY: &str =
+        "GET /api/v1/search?q=shoes&color=red&size=42&sort=price&page=3&per_page=50&lang=en HTTP/1.1\r\nHost: a\r\n\r\n";
+
+    let mut group = c.benchmark_group("query");
+    for (name, parse_query) in [("parsed", true), ("skipped", false)] {
+        let limits = ReqLimits {
+            parse_query,
+            ..ReqLimits::default()
+        };
+        let mut bench = ParseBench::with_limits(QUERY_HEAVY, limits);
+        assert!(bench.parse(), "query-heavy request must be valid");
+
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(bench.parse()));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, header_lookup, query);
+criterion_main!(benches);
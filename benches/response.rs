@@ -0,0 +1,37 @@
+//! Response micro-benchmarks
+//!
+//! Run with:
+//! ```bash
+//! cargo bench --bench response
+//! ```
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use maker_web::{bench_helper::RespBench, Handled, Response, ResponseTemplate, StatusCode};
+
+const BODY: [u8; 1024] = [b'x'; 1024];
+
+fn fixed_body(resp: &mut Response) -> Handled {
+    resp.status(StatusCode::Ok)
+        .header("content-type", "text/plain; charset=utf-8")
+        .header("cache-control", "max-age=3600")
+        .body(&BODY[..])
+}
+
+fn template(c: &mut Criterion) {
+    let template = ResponseTemplate::build(fixed_body);
+    let mut bench = RespBench::default();
+    let len = bench.run(fixed_body);
+    assert_eq!(len, bench.run(|resp| resp.from_template(&template)));
+
+    let mut group = c.benchmark_group("fixed_1kb");
+    group.throughput(Throughput::Bytes(len as u64));
+    group.bench_function("builder", |b| {
+        b.iter(|| black_box(bench.run(fixed_body)));
+    });
+    group.bench_function("template", |b| {
+        b.iter(|| black_box(bench.run(|resp| resp.from_template(&template))));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, template);
+criterion_main!(benches);
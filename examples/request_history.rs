@@ -0,0 +1,64 @@
+use maker_web::{ConnectionData, Handled, Handler, Method, Request, Response, Server, StatusCode};
+use std::io::Write;
+use tokio::net::TcpListener;
+
+// How many requests of a connection are remembered
+const HISTORY_SIZE: usize = 8;
+
+struct MyHandler;
+
+struct History {
+    entries: Vec<(Method, Vec<u8>)>,
+    total: usize,
+}
+
+impl History {
+    fn record(&mut self, req: &Request) {
+        if self.entries.len() == HISTORY_SIZE {
+            self.entries.remove(0);
+        }
+
+        self.entries
+            .push((req.method(), req.url().target().to_vec()));
+        self.total += 1;
+    }
+}
+
+impl ConnectionData for History {
+    fn new() -> Self {
+        History {
+            entries: Vec::with_capacity(HISTORY_SIZE),
+            total: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.entries.clear();
+        self.total = 0;
+    }
+}
+
+impl Handler<History> for MyHandler {
+    async fn handle(&self, history: &mut History, req: &Request, resp: &mut Response) -> Handled {
+        history.record(req);
+
+        resp.status(StatusCode::Ok)
+            .header("Content-Type", "text/plain")
+            .body_with(|w| {
+                let _ = writeln!(w, "requests on this connection: {}", history.total);
+                for (method, target) in &history.entries {
+                    let _ = writeln!(w, "{} {}", method.as_str(), String::from_utf8_lossy(target));
+                }
+            })
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    Server::builder()
+        .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+        .handler(MyHandler)
+        .build()
+        .launch()
+        .await;
+}
@@ -0,0 +1,217 @@
+//! In-memory TODO service, see `todo_api.md`.
+//!
+//! ```text
+//! curl -i localhost:8080/healthz
+//! curl -i -X POST localhost:8080/api/todos -d '{"title":"Buy milk"}'
+//! curl -i -X POST localhost:8080/api/todos -d '{"title":"Write docs","done":true}'
+//! curl -i 'localhost:8080/api/todos?done=false&limit=10'
+//! curl -i -X PATCH localhost:8080/api/todos/1 -d '{"done":true}'
+//! curl -i localhost:8080/api/todos/1 -H 'x-request-id: 42'
+//! curl -i -X DELETE localhost:8080/api/todos/1
+//! curl -i localhost:8080/api/todos/1   # 404
+//! ```
+
+use maker_web::{ConnectionData, Handled, Handler, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, sync::Mutex};
+use tokio::net::TcpListener;
+
+#[derive(Default)]
+struct Todos {
+    next_id: u64,
+    items: BTreeMap<u64, Todo>,
+}
+
+struct Todo {
+    title: String,
+    done: bool,
+}
+
+impl Todo {
+    fn to_json(&self, id: u64) -> Value {
+        json!({ "id": id, "title": self.title, "done": self.done })
+    }
+}
+
+// Per-connection buffer for rendering JSON, reused by every request of the connection
+struct Scratch(Vec<u8>);
+
+impl ConnectionData for Scratch {
+    fn new() -> Self {
+        Scratch(Vec::with_capacity(1024))
+    }
+
+    fn reset(&mut self) {
+        self.0.clear();
+    }
+}
+
+struct TodoApi {
+    todos: Mutex<Todos>,
+}
+
+impl Handler<Scratch> for TodoApi {
+    async fn handle(&self, scratch: &mut Scratch, req: &Request, resp: &mut Response) -> Handled {
+        let url = req.url();
+
+        match (req.method(), url.path_segments_str()) {
+            (Method::Get, ["healthz"]) => resp.status(StatusCode::Ok).body("ok"),
+
+            (Method::Get, ["api", "todos"]) => {
+                let done = match url.query_str("done") {
+                    None => None,
+                    Some("true") => Some(true),
+                    Some("false") => Some(false),
+                    Some(_) => return bad_query(resp, "`done` must be `true` or `false`"),
+                };
+                let limit = match url.query_int::<usize>(b"limit") {
+                    None => usize::MAX,
+                    Some(Ok(limit)) => limit,
+                    Some(Err(())) => return bad_query(resp, "`limit` must be a number"),
+                };
+
+                let todos = self.todos.lock().unwrap();
+                let items: Vec<_> = (todos.items.iter())
+                    .filter(|(_, todo)| done.map_or(true, |done| todo.done == done))
+                    .take(limit)
+                    .map(|(id, todo)| todo.to_json(*id))
+                    .collect();
+                drop(todos);
+
+                json_response(scratch, resp, StatusCode::Ok, &Value::Array(items))
+            }
+
+            (Method::Post, ["api", "todos"]) => {
+                let body = match parse_body(req) {
+                    Ok(body) => body,
+                    Err(message) => {
+                        return resp.error_json(StatusCode::BadRequest, "BAD_BODY", message)
+                    }
+                };
+                let Some(title) = body["title"].as_str() else {
+                    return resp.error_json(
+                        StatusCode::BadRequest,
+                        "BAD_BODY",
+                        "`title` is required",
+                    );
+                };
+                let todo = Todo {
+                    title: title.to_string(),
+                    done: body["done"].as_bool().unwrap_or(false),
+                };
+
+                let mut todos = self.todos.lock().unwrap();
+                todos.next_id += 1;
+                let id = todos.next_id;
+                let value = todo.to_json(id);
+                todos.items.insert(id, todo);
+                drop(todos);
+
+                json_response(scratch, resp, StatusCode::Created, &value)
+            }
+
+            (method, ["api", "todos", id]) => {
+                let Ok(id) = id.parse::<u64>() else {
+                    return not_found(resp);
+                };
+                let mut todos = self.todos.lock().unwrap();
+                let Some(todo) = todos.items.get_mut(&id) else {
+                    return not_found(resp);
+                };
+
+                match method {
+                    Method::Get => {
+                        let value = todo.to_json(id);
+                        drop(todos);
+                        json_response(scratch, resp, StatusCode::Ok, &value)
+                    }
+                    Method::Patch => {
+                        let body = match parse_body(req) {
+                            Ok(body) => body,
+                            Err(message) => {
+                                return resp.error_json(StatusCode::BadRequest, "BAD_BODY", message)
+                            }
+                        };
+                        if let Some(title) = body["title"].as_str() {
+                            todo.title = title.to_string();
+                        }
+                        if let Some(done) = body["done"].as_bool() {
+                            todo.done = done;
+                        }
+                        let value = todo.to_json(id);
+                        drop(todos);
+                        json_response(scratch, resp, StatusCode::Ok, &value)
+                    }
+                    Method::Delete => {
+                        todos.items.remove(&id);
+                        resp.status(StatusCode::NoContent).body("")
+                    }
+                    _ => method_not_allowed(resp),
+                }
+            }
+
+            (_, ["healthz"] | ["api", "todos"]) => method_not_allowed(resp),
+            _ => not_found(resp),
+        }
+    }
+
+    // Echoes the client's request id on every response, errors included
+    fn post_process(&self, req: &Request, resp: &mut Response) {
+        if let Some(id) = req.header(b"x-request-id") {
+            resp.insert_header("x-request-id", id);
+        }
+    }
+}
+
+fn parse_body(req: &Request) -> Result<Value, &'static str> {
+    let body = req.body().ok_or("A JSON body is required")?;
+    match serde_json::from_slice(body) {
+        Ok(value @ Value::Object(_)) => Ok(value),
+        _ => Err("The body must be a JSON object"),
+    }
+}
+
+fn json_response(
+    scratch: &mut Scratch,
+    resp: &mut Response,
+    status: StatusCode,
+    value: &Value,
+) -> Handled {
+    scratch.0.clear();
+    serde_json::to_writer(&mut scratch.0, value).unwrap();
+
+    resp.status(status)
+        .header("content-type", "application/json")
+        .body(&scratch.0)
+}
+
+fn bad_query(resp: &mut Response, message: &str) -> Handled {
+    resp.error_json(StatusCode::BadRequest, "BAD_QUERY", message)
+}
+
+fn not_found(resp: &mut Response) -> Handled {
+    resp.error_json(StatusCode::NotFound, "NOT_FOUND", "No such todo")
+}
+
+fn method_not_allowed(resp: &mut Response) -> Handled {
+    resp.error_json(
+        StatusCode::MethodNotAllowed,
+        "METHOD_NOT_ALLOWED",
+        "Method not allowed",
+    )
+}
+
+#[tokio::main]
+async fn main() {
+    let api = TodoApi {
+        todos: Mutex::new(Todos::default()),
+    };
+
+    // Runs until the process is stopped (Ctrl-C), the store is lost with it
+    Server::builder()
+        .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+        .handler(api)
+        .build()
+        .launch()
+        .await;
+}
@@ -0,0 +1,45 @@
+use maker_web::debug::{Direction, WireRecord};
+use std::{env, fs, time::UNIX_EPOCH};
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: wiretap-dump <file.tap>...");
+        return;
+    }
+
+    for path in paths {
+        let file = fs::read(&path).unwrap_or_else(|e| panic!("{path}: {e}"));
+        println!("##### {path}");
+
+        for record in WireRecord::parse_all(&file) {
+            let arrow = match record.direction {
+                Direction::Inbound => "client -> server",
+                Direction::Outbound => "server -> client",
+            };
+            let at = record.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+            println!(
+                "=== connection {}, {arrow}, {}.{:06}, {} bytes",
+                record.connection,
+                at.as_secs(),
+                at.subsec_micros(),
+                record.bytes.len()
+            );
+            println!("{}", readable(record.bytes));
+        }
+    }
+}
+
+// Line breaks are kept, `\r` and other unprintable bytes are escaped
+fn readable(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'\n' => text.push_str("\\n\n"),
+            b'\r' => text.push_str("\\r"),
+            b' '..=b'~' => text.push(byte as char),
+            _ => text.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    text
+}
@@ -0,0 +1,851 @@
+//! Opt-in tools for inspecting a running server.
+//!
+//! Each tool is attached to the [`ServerBuilder`](crate::ServerBuilder) as an
+//! `Arc`; a tool that is not attached costs the connection a single branch
+//! per request.
+
+use crate::Version;
+use memchr::memmem;
+use std::{
+    fs::File,
+    io::{self, Write},
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, SystemTime},
+};
+
+/// Bounded in-memory copy of recent requests whose response status
+/// matches a predicate.
+///
+/// Every slot is allocated in [`new()`](RequestMirror::new); recording
+/// a request only copies bytes into the oldest slot. Requests that do
+/// not match the predicate are not copied.
+///
+/// Both responses written by the handler and error responses sent by the
+/// server itself (`400`, `413`, ...) are recorded. `HTTP/0.9+` responses
+/// have no status and are never recorded.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{debug::RequestMirror, Handled, Handler, Request, Response, Server, StatusCode};
+/// use std::{io::Write, sync::Arc};
+/// use tokio::net::TcpListener;
+///
+/// struct MyHandler {
+///     mirror: Arc<RequestMirror>,
+/// }
+///
+/// impl Handler for MyHandler {
+///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+///         if req.url().path() != b"/admin/recent-4xx" {
+///             return resp.status(StatusCode::NotFound).body("");
+///         }
+///
+///         resp.status(StatusCode::Ok).body_with(|w| {
+///             for entry in self.mirror.snapshot() {
+///                 let _ = writeln!(w, "{} {} {:?}", entry.status, entry.client_addr, entry.handler_time);
+///                 w.write(entry.head);
+///             }
+///         })
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// // The last 50 requests answered with `4xx`, at most 512 bytes of each head
+/// let mirror = Arc::new(RequestMirror::new(50, 512, |status| (400..500).contains(&status)));
+///
+/// Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     .handler(MyHandler { mirror: mirror.clone() })
+///     .request_mirror(mirror)
+///     .build()
+///     .launch()
+///     .await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RequestMirror {
+    head_size: usize,
+    filter: fn(u16) -> bool,
+    ring: Mutex<Ring>,
+}
+
+/// Snapshot of one request stored in a [`RequestMirror`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirroredRequest {
+    /// The first bytes of the request head (request line and headers)
+    pub head: Vec<u8>,
+    /// Status code of the response
+    pub status: u16,
+    /// Address of the client
+    pub client_addr: SocketAddr,
+    /// When the request was recorded
+    pub recorded_at: SystemTime,
+    /// Time spent in [`Handler::handle`](crate::Handler::handle),
+    /// `None` for errors answered by the server itself
+    pub handler_time: Option<Duration>,
+
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub _priv: (),
+}
+
+#[derive(Debug)]
+struct Ring {
+    entries: Vec<MirroredRequest>,
+    // Slot for the next record, the oldest entry once the ring is full
+    next: usize,
+    len: usize,
+}
+
+impl RequestMirror {
+    /// Creates a mirror that keeps the last `capacity` matching requests,
+    /// storing at most `head_size` bytes of each request head.
+    ///
+    /// `filter` receives the response status code; the request is
+    /// recorded when it returns `true`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::debug::RequestMirror;
+    ///
+    /// let server_errors = RequestMirror::new(100, 1024, |status| status >= 500);
+    /// assert!(server_errors.snapshot().is_empty());
+    /// ```
+    pub fn new(capacity: usize, head_size: usize, filter: fn(u16) -> bool) -> Self {
+        let entries = (0..capacity)
+            .map(|_| MirroredRequest {
+                head: Vec::with_capacity(head_size),
+                status: 0,
+                client_addr: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                recorded_at: SystemTime::UNIX_EPOCH,
+                handler_time: None,
+                _priv: (),
+            })
+            .collect();
+
+        Self {
+            head_size,
+            filter,
+            ring: Mutex::new(Ring {
+                entries,
+                next: 0,
+                len: 0,
+            }),
+        }
+    }
+
+    /// Returns the recorded requests, oldest first.
+    pub fn snapshot(&self) -> Vec<MirroredRequest> {
+        let ring = self.ring.lock().unwrap_or_else(PoisonError::into_inner);
+        let capacity = ring.entries.len();
+        let oldest = (ring.next + capacity - ring.len) % capacity.max(1);
+
+        (0..ring.len)
+            .map(|i| ring.entries[(oldest + i) % capacity].clone())
+            .collect()
+    }
+
+    /// Records `request` if the status line of `response` matches the filter
+    #[inline]
+    pub(crate) fn record(
+        &self,
+        request: &[u8],
+        response: &[u8],
+        client_addr: SocketAddr,
+        handler_time: Option<Duration>,
+    ) {
+        let Some(status) = status_code(response) else {
+            return;
+        };
+        if !(self.filter)(status) {
+            return;
+        }
+
+        let head_end = memmem::find(request, b"\r\n\r\n").map_or(request.len(), |i| i + 4);
+        let head = &request[..head_end.min(self.head_size)];
+
+        let mut ring = self.ring.lock().unwrap_or_else(PoisonError::into_inner);
+        let capacity = ring.entries.len();
+        if capacity == 0 {
+            return;
+        }
+
+        let next = ring.next;
+        let entry = &mut ring.entries[next];
+        entry.head.clear();
+        entry.head.extend_from_slice(head);
+        entry.status = status;
+        entry.client_addr = client_addr;
+        entry.recorded_at = SystemTime::now();
+        entry.handler_time = handler_time;
+
+        ring.next = (next + 1) % capacity;
+        ring.len = (ring.len + 1).min(capacity);
+    }
+}
+
+/// High-water marks of parsed requests since startup, to right-size
+/// [`ReqLimits`](crate::limits::ReqLimits).
+///
+/// Updated after each successfully parsed request with a few relaxed
+/// atomic operations. Rejected requests (`413`, `431`, ...) are not counted.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{debug::Watermarks, Server};
+/// use std::{sync::Arc, time::Duration};
+/// # maker_web::impt_default_handler!{ MyStruct }
+/// use tokio::net::TcpListener;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let watermarks = Arc::new(Watermarks::new());
+///
+/// let server = Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     .handler(MyStruct) // structure with Handler implementation
+///     .watermarks(watermarks.clone())
+///     .build();
+/// tokio::spawn(server.launch());
+///
+/// loop {
+///     tokio::time::sleep(Duration::from_secs(60)).await;
+///     println!("{:?}", watermarks.snapshot());
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct Watermarks {
+    head_bytes: AtomicUsize,
+    body_bytes: AtomicUsize,
+    headers: AtomicUsize,
+}
+
+/// Snapshot of [`Watermarks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WatermarkSnapshot {
+    /// The largest request head (request line and headers), in bytes
+    pub observed_max_head_bytes: usize,
+    /// The largest request body as received (chunk framing included), in bytes
+    pub observed_max_body_bytes: usize,
+    /// The largest number of headers in one request, counted like
+    /// [`header_count`](crate::limits::ReqLimits::header_count)
+    pub observed_max_headers: usize,
+
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub _priv: (),
+}
+
+impl Watermarks {
+    /// Creates watermarks with every maximum at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the maxima observed so far.
+    pub fn snapshot(&self) -> WatermarkSnapshot {
+        WatermarkSnapshot {
+            observed_max_head_bytes: self.head_bytes.load(Ordering::Relaxed),
+            observed_max_body_bytes: self.body_bytes.load(Ordering::Relaxed),
+            observed_max_headers: self.headers.load(Ordering::Relaxed),
+            _priv: (),
+        }
+    }
+
+    /// Raises the maxima to the sizes of `request` (head and body) and `headers`
+    #[inline]
+    pub(crate) fn record(&self, request: &[u8], headers: usize) {
+        let head = memmem::find(request, b"\r\n\r\n").map_or(request.len(), |i| i + 4);
+
+        self.head_bytes.fetch_max(head, Ordering::Relaxed);
+        self.body_bytes
+            .fetch_max(request.len() - head, Ordering::Relaxed);
+        self.headers.fetch_max(headers, Ordering::Relaxed);
+    }
+}
+
+/// Requests and error responses counted per protocol version since startup.
+///
+/// A request is counted once it is parsed, before the handler runs; an error
+/// is counted when the server itself answers with an error (`400`, `413`,
+/// `505`, ...), under the version of the request it rejects (`HTTP/1.1` if
+/// the request line was not parsed).
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{debug::VersionCounters, Server};
+/// use std::{sync::Arc, time::Duration};
+/// # maker_web::impt_default_handler!{ MyStruct }
+/// use tokio::net::TcpListener;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let counters = Arc::new(VersionCounters::new());
+///
+/// let server = Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     .handler(MyStruct) // structure with Handler implementation
+///     .version_counters(counters.clone())
+///     .build();
+/// tokio::spawn(server.launch());
+///
+/// loop {
+///     tokio::time::sleep(Duration::from_secs(60)).await;
+///     let snapshot = counters.snapshot();
+///     println!("HTTP/1.0 clients: {}", snapshot.requests_http10);
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct VersionCounters {
+    requests: [AtomicU64; 3],
+    errors: [AtomicU64; 3],
+}
+
+/// Snapshot of [`VersionCounters`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VersionSnapshot {
+    /// Parsed `HTTP/0.9+` requests
+    pub requests_http09: u64,
+    /// Parsed `HTTP/1.0` requests
+    pub requests_http10: u64,
+    /// Parsed `HTTP/1.1` requests
+    pub requests_http11: u64,
+    /// Error responses to `HTTP/0.9+` requests
+    pub errors_http09: u64,
+    /// Error responses to `HTTP/1.0` requests
+    pub errors_http10: u64,
+    /// Error responses to `HTTP/1.1` requests (and to unparsed request lines)
+    pub errors_http11: u64,
+
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub _priv: (),
+}
+
+impl VersionCounters {
+    /// Creates counters with every count at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the counts so far.
+    pub fn snapshot(&self) -> VersionSnapshot {
+        let load = |counters: &[AtomicU64; 3], version: Version| {
+            counters[version as usize].load(Ordering::Relaxed)
+        };
+
+        VersionSnapshot {
+            requests_http09: load(&self.requests, Version::Http09),
+            requests_http10: load(&self.requests, Version::Http10),
+            requests_http11: load(&self.requests, Version::Http11),
+            errors_http09: load(&self.errors, Version::Http09),
+            errors_http10: load(&self.errors, Version::Http10),
+            errors_http11: load(&self.errors, Version::Http11),
+            _priv: (),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record_request(&self, version: Version) {
+        self.requests[version as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_error(&self, version: Version) {
+        self.errors[version as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Byte-accurate capture of the traffic of sampled connections into a file,
+/// for reproducing parser bugs from production.
+///
+/// Every read from and write to the socket of a sampled connection becomes
+/// one record, with exactly the bytes the socket saw: before the parser on
+/// the way in, after the response builder on the way out. Records are copied
+/// into a memory ring allocated in [`new()`](WireTap::new) and written to the
+/// file by [`flush()`](WireTap::flush), which the server calls every
+/// 100 ms from a background task. A record that doesn't fit in the ring
+/// before the next flush is dropped and counted in [`dropped()`](WireTap::dropped).
+///
+/// When the file would grow past the byte budget, it is renamed to
+/// `<path>.1` (replacing the previous one) and a new file is started, so
+/// at most twice the budget is kept on disk.
+///
+/// Each record is a 21-byte header followed by the bytes:
+///
+/// | Offset | Size | Field |
+/// |-|-|-|
+/// | 0  | 1 | Direction: `0` inbound, `1` outbound |
+/// | 1  | 8 | Connection number, little-endian |
+/// | 9  | 8 | Microseconds since the Unix epoch, little-endian |
+/// | 17 | 4 | Length of the bytes, little-endian |
+///
+/// [`WireRecord::parse_all`] reads them back, see the `wiretap-dump` example.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{debug::WireTap, Server};
+/// use std::sync::Arc;
+/// # maker_web::impt_default_handler!{ MyStruct }
+/// use tokio::net::TcpListener;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// // 1 connection in 100, at most 64 MB per file
+/// let tap = Arc::new(WireTap::new("/var/tmp/wire.tap", 64 * 1024 * 1024, 100));
+///
+/// Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     .handler(MyStruct) // structure with Handler implementation
+///     .wire_tap(tap)
+///     .build()
+///     .launch()
+///     .await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct WireTap {
+    path: PathBuf,
+    budget: u64,
+    sample_every: u64,
+    connections: AtomicU64,
+    dropped: AtomicU64,
+    ring: Mutex<Vec<u8>>,
+    file: Mutex<TapFile>,
+}
+
+#[derive(Debug)]
+struct TapFile {
+    file: Option<File>,
+    written: u64,
+    // Swapped with the ring on each flush
+    spare: Vec<u8>,
+}
+
+/// Direction of a [`WireRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Read from the client
+    Inbound = 0,
+    /// Written to the client
+    Outbound = 1,
+}
+
+/// One record written by a [`WireTap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireRecord<'a> {
+    /// Whether the bytes were read or written
+    pub direction: Direction,
+    /// Number of the connection, unique per [`WireTap`]
+    pub connection: u64,
+    /// When the bytes were read or written
+    pub at: SystemTime,
+    /// The bytes, exactly as read or written
+    pub bytes: &'a [u8],
+}
+
+const RECORD_HEADER: usize = 21;
+const MAX_RING: u64 = 256 * 1024;
+
+impl WireTap {
+    /// Creates a tap writing to `path`, keeping each file under `budget`
+    /// bytes and capturing one connection in `sample_every` (`0` and `1`
+    /// capture every connection).
+    ///
+    /// The memory ring takes `min(budget, 256 KB)` bytes, twice: one is
+    /// filled while the other is written. The file is created on the
+    /// first flush, truncating an existing one.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::debug::WireTap;
+    ///
+    /// let tap = WireTap::new(std::env::temp_dir().join("example.tap"), 1024 * 1024, 10);
+    /// assert_eq!(tap.dropped(), 0);
+    /// ```
+    pub fn new(path: impl Into<PathBuf>, budget: u64, sample_every: u32) -> Self {
+        let ring = budget.min(MAX_RING) as usize;
+
+        Self {
+            path: path.into(),
+            budget,
+            sample_every: sample_every.max(1) as u64,
+            connections: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            ring: Mutex::new(Vec::with_capacity(ring)),
+            file: Mutex::new(TapFile {
+                file: None,
+                written: 0,
+                spare: Vec::with_capacity(ring),
+            }),
+        }
+    }
+
+    /// Returns the number of records dropped because the ring was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes the records captured since the last flush to the file,
+    /// rotating it when the budget is hit.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        let TapFile {
+            file,
+            written,
+            spare,
+        } = &mut *file;
+
+        {
+            let mut ring = self.ring.lock().unwrap_or_else(PoisonError::into_inner);
+            std::mem::swap(&mut *ring, spare);
+        }
+
+        let result = self.write_records(file, written, spare);
+        spare.clear();
+        result
+    }
+
+    // Writes whole records, starting a new file before the one that doesn't fit
+    fn write_records(
+        &self,
+        file: &mut Option<File>,
+        written: &mut u64,
+        records: &[u8],
+    ) -> io::Result<()> {
+        let mut start = 0;
+        let mut end = 0;
+
+        while end < records.len() {
+            let len = RECORD_HEADER + record_len(&records[end..]);
+
+            if *written + len as u64 > self.budget && *written > 0 {
+                self.write(file, &records[start..end])?;
+                *file = None;
+                let mut rotated = self.path.clone().into_os_string();
+                rotated.push(".1");
+                std::fs::rename(&self.path, rotated)?;
+
+                *written = 0;
+                start = end;
+            }
+            *written += len as u64;
+            end += len;
+        }
+        self.write(file, &records[start..end])
+    }
+
+    fn write(&self, file: &mut Option<File>, bytes: &[u8]) -> io::Result<()> {
+        let file = match file {
+            Some(file) => file,
+            None => file.insert(File::create(&self.path)?),
+        };
+        file.write_all(bytes)
+    }
+
+    /// Returns the number of a new connection if it is sampled
+    #[inline]
+    pub(crate) fn sample(&self) -> Option<u64> {
+        let connection = self.connections.fetch_add(1, Ordering::Relaxed);
+        (connection % self.sample_every == 0).then_some(connection)
+    }
+
+    /// Appends a record, or counts it as dropped if the ring is full
+    pub(crate) fn record(&self, direction: Direction, connection: u64, bytes: &[u8]) {
+        let micros = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_micros() as u64);
+
+        let mut ring = self.ring.lock().unwrap_or_else(PoisonError::into_inner);
+        if ring.capacity() - ring.len() < RECORD_HEADER + bytes.len() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        ring.push(direction as u8);
+        ring.extend_from_slice(&connection.to_le_bytes());
+        ring.extend_from_slice(&micros.to_le_bytes());
+        ring.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        ring.extend_from_slice(bytes);
+    }
+}
+
+impl<'a> WireRecord<'a> {
+    /// Reads the records of a file written by [`WireTap`], stopping at the
+    /// first incomplete or invalid one.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::debug::WireRecord;
+    ///
+    /// let file = std::fs::read("wire.tap").unwrap_or_default();
+    /// for record in WireRecord::parse_all(&file) {
+    ///     println!("{} {:?}: {} bytes", record.connection, record.direction, record.bytes.len());
+    /// }
+    /// ```
+    pub fn parse_all(mut bytes: &'a [u8]) -> impl Iterator<Item = WireRecord<'a>> {
+        std::iter::from_fn(move || {
+            let (record, rest) = Self::parse(bytes)?;
+            bytes = rest;
+            Some(record)
+        })
+    }
+
+    fn parse(bytes: &'a [u8]) -> Option<(Self, &'a [u8])> {
+        let header = bytes.get(..RECORD_HEADER)?;
+        let direction = match header[0] {
+            0 => Direction::Inbound,
+            1 => Direction::Outbound,
+            _ => return None,
+        };
+        let u64_at = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+        let len = record_len(bytes);
+        let body = bytes.get(RECORD_HEADER..RECORD_HEADER + len)?;
+
+        let record = WireRecord {
+            direction,
+            connection: u64_at(1),
+            at: SystemTime::UNIX_EPOCH + Duration::from_micros(u64_at(9)),
+            bytes: body,
+        };
+        Some((record, &bytes[RECORD_HEADER + len..]))
+    }
+}
+
+// Length field of the record at the start of `record`
+#[inline]
+fn record_len(record: &[u8]) -> usize {
+    u32::from_le_bytes(record[17..RECORD_HEADER].try_into().unwrap()) as usize
+}
+
+// `HTTP/1.X 404 ...` -> `404`
+#[inline]
+fn status_code(response: &[u8]) -> Option<u16> {
+    match response.get(..13)? {
+        [b'H', b'T', b'T', b'P', b'/', b'1', b'.', _, b' ', digits @ .., b' ']
+            if digits.iter().all(u8::is_ascii_digit) =>
+        {
+            Some(digits.iter().fold(0, |n, d| n * 10 + (d - b'0') as u16))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: SocketAddr = SocketAddr::V4(std::net::SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1));
+
+    fn record(mirror: &RequestMirror, path: &str, status: &str) {
+        let request = format!("GET {path} HTTP/1.1\r\nhost: test\r\n\r\nbody");
+        let response = format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\n\r\n");
+        mirror.record(request.as_bytes(), response.as_bytes(), ADDR, None);
+    }
+
+    fn paths(mirror: &RequestMirror) -> Vec<String> {
+        mirror
+            .snapshot()
+            .into_iter()
+            .map(|entry| {
+                let line = String::from_utf8(entry.head).unwrap();
+                line.split(' ').nth(1).unwrap().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evicts_oldest() {
+        let mirror = RequestMirror::new(3, 1024, |_| true);
+        assert!(mirror.snapshot().is_empty());
+
+        record(&mirror, "/1", "200 OK");
+        record(&mirror, "/2", "200 OK");
+        assert_eq!(paths(&mirror), ["/1", "/2"]);
+
+        for i in 3..=7 {
+            record(&mirror, &format!("/{i}"), "200 OK");
+        }
+        assert_eq!(paths(&mirror), ["/5", "/6", "/7"]);
+    }
+
+    #[test]
+    fn filter_by_status() {
+        let mirror = RequestMirror::new(8, 1024, |status| (400..500).contains(&status));
+
+        record(&mirror, "/ok", "200 OK");
+        record(&mirror, "/missing", "404 Not Found");
+        record(&mirror, "/broken", "500 Internal Server Error");
+        record(&mirror, "/bad", "400 Bad Request");
+        mirror.record(b"GET /http09\r\n", b"Hello", ADDR, None);
+
+        assert_eq!(paths(&mirror), ["/missing", "/bad"]);
+        let statuses: Vec<_> = mirror.snapshot().iter().map(|e| e.status).collect();
+        assert_eq!(statuses, [404, 400]);
+    }
+
+    #[test]
+    fn head_is_truncated() {
+        let mirror = RequestMirror::new(2, 14, |_| true);
+        record(&mirror, "/long/path", "200 OK");
+        assert_eq!(mirror.snapshot()[0].head, b"GET /long/path");
+
+        // The body is never copied
+        let mirror = RequestMirror::new(2, 1024, |_| true);
+        record(&mirror, "/", "200 OK");
+        assert_eq!(
+            mirror.snapshot()[0].head,
+            b"GET / HTTP/1.1\r\nhost: test\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mirror = RequestMirror::new(0, 1024, |_| true);
+        record(&mirror, "/", "200 OK");
+        assert!(mirror.snapshot().is_empty());
+    }
+
+    #[test]
+    fn parse_status_code() {
+        let cases: [(&[u8], Option<u16>); 5] = [
+            (b"HTTP/1.1 200 OK\r\n", Some(200)),
+            (b"HTTP/1.0 431 Request Header Fields Too Large", Some(431)),
+            (b"HTTP/1.1 20", None),
+            (b"ERROR: \"400 Bad Request\"", None),
+            (b"HTTP/1.1 2x0 OK\r\n", None),
+        ];
+
+        for (response, result) in cases {
+            assert_eq!(status_code(response), result);
+        }
+    }
+
+    fn tap_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("maker_web-{}-{name}.tap", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn read_tap(path: &PathBuf) -> Vec<(Direction, u64, Vec<u8>)> {
+        let file = std::fs::read(path).unwrap();
+        WireRecord::parse_all(&file)
+            .map(|r| (r.direction, r.connection, r.bytes.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn wire_tap_framing() {
+        let path = tap_path("framing");
+        let tap = WireTap::new(&path, 1024 * 1024, 1);
+
+        let before = SystemTime::now() - Duration::from_secs(1);
+        tap.record(Direction::Inbound, 7, b"GET / HTTP/1.1\r\n");
+        tap.record(Direction::Inbound, 7, b"\r\n");
+        tap.record(Direction::Outbound, 7, b"HTTP/1.1 200 OK\r\n\r\n");
+        tap.flush().unwrap();
+        tap.record(Direction::Inbound, 8, &[0, 255, 13, 10]);
+        tap.flush().unwrap();
+
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file.len(), 4 * RECORD_HEADER + 16 + 2 + 19 + 4);
+        assert_eq!(file[..RECORD_HEADER][17..], 16u32.to_le_bytes());
+
+        let records: Vec<_> = WireRecord::parse_all(&file).collect();
+        assert!(records
+            .iter()
+            .all(|r| r.at > before && r.at <= SystemTime::now()));
+        assert_eq!(
+            read_tap(&path),
+            [
+                (Direction::Inbound, 7, b"GET / HTTP/1.1\r\n".to_vec()),
+                (Direction::Inbound, 7, b"\r\n".to_vec()),
+                (Direction::Outbound, 7, b"HTTP/1.1 200 OK\r\n\r\n".to_vec()),
+                (Direction::Inbound, 8, vec![0, 255, 13, 10]),
+            ]
+        );
+
+        // A truncated or unknown record ends the file
+        assert_eq!(WireRecord::parse_all(&file[..file.len() - 1]).count(), 3);
+        let mut invalid = file.clone();
+        invalid[RECORD_HEADER + 16] = 2;
+        assert_eq!(WireRecord::parse_all(&invalid).count(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn wire_tap_rotation() {
+        let path = tap_path("rotation");
+        let rotated = path.with_extension("tap.1");
+        let _ = std::fs::remove_file(&rotated);
+
+        // Room for exactly two 29-byte records per file
+        let tap = WireTap::new(&path, 2 * (RECORD_HEADER + 8) as u64, 1);
+        for i in 0..3u8 {
+            tap.record(Direction::Inbound, i as u64, &[i; 8]);
+            tap.flush().unwrap();
+        }
+        assert_eq!(read_tap(&rotated).len(), 2);
+        assert_eq!(read_tap(&path), [(Direction::Inbound, 2, vec![2; 8])]);
+
+        // Several records in one flush are split between files
+        tap.record(Direction::Outbound, 3, &[3; 8]);
+        tap.flush().unwrap();
+        tap.record(Direction::Outbound, 4, &[4; 8]);
+        tap.record(Direction::Outbound, 5, &[5; 8]);
+        tap.flush().unwrap();
+        let connections = |path| read_tap(path).iter().map(|r| r.1).collect::<Vec<_>>();
+        assert_eq!(connections(&rotated), [2, 3]);
+        assert_eq!(connections(&path), [4, 5]);
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(rotated).unwrap();
+    }
+
+    #[test]
+    fn wire_tap_sampling_and_drops() {
+        let tap = WireTap::new(tap_path("sampling"), 64, 3);
+        let sampled: Vec<_> = (0..7).filter_map(|_| tap.sample()).collect();
+        assert_eq!(sampled, [0, 3, 6]);
+
+        let every = WireTap::new(tap_path("sampling"), 64, 0);
+        assert_eq!((0..3).filter_map(|_| every.sample()).count(), 3);
+
+        // The ring holds 64 bytes until the next flush
+        tap.record(Direction::Inbound, 0, &[0; 40]);
+        tap.record(Direction::Inbound, 0, &[0; 40]);
+        tap.record(Direction::Inbound, 0, &[0; 100]);
+        assert_eq!(tap.dropped(), 2);
+    }
+
+    #[test]
+    fn watermarks_ratchet_up() {
+        let watermarks = Watermarks::new();
+        assert_eq!(watermarks.snapshot(), WatermarkSnapshot::default());
+
+        watermarks.record(b"GET / HTTP/1.1\r\nhost: a\r\n\r\nbody", 1);
+        let first = watermarks.snapshot();
+        assert_eq!(first.observed_max_head_bytes, 27);
+        assert_eq!(first.observed_max_body_bytes, 4);
+        assert_eq!(first.observed_max_headers, 1);
+
+        // Each maximum is kept separately
+        watermarks.record(b"GET /a-longer-path HTTP/1.1\r\n\r\n", 0);
+        let second = watermarks.snapshot();
+        assert_eq!(second.observed_max_head_bytes, 31);
+        assert_eq!(second.observed_max_body_bytes, 4);
+        assert_eq!(second.observed_max_headers, 1);
+
+        watermarks.record(b"GET /\r\n", 0);
+        assert_eq!(watermarks.snapshot(), second);
+    }
+}
@@ -1,5 +1,9 @@
 use crate::{query, Version};
-use std::{error, fmt, io};
+use memchr::memmem;
+use std::{
+    error, fmt,
+    io::{self, Write},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum ErrorKind {
@@ -17,6 +21,8 @@ pub(crate) enum ErrorKind {
     TooManyHeaders,
     InvalidContentLength,
     InvalidConnection,
+    InvalidHost,
+    MissingHost,
 
     BodyTooLarge,
     #[allow(dead_code)]
@@ -24,11 +30,20 @@ pub(crate) enum ErrorKind {
         expected: usize,
         available: usize,
     },
-    #[allow(dead_code)]
-    UnexpectedBody(usize),
+    BodyNotAllowed,
+    UnsupportedTransferEncoding,
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    ExpectationFailed,
 
     InvalidEncoding,
+    InvalidEscape,
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    Http09WithoutHttp1x,
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
     ServiceUnavailable,
+    // Never answered, the connection is closed silently
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    ReadTimeout,
     Io(IoError),
 }
 
@@ -99,23 +114,140 @@ impl ErrorKind {
             => r#"{"error":"Invalid Content-Length","code":"INVALID_CONTENT_LENGTH"}"#;
         InvalidConnection: "400 Bad Request", "65"
             => r#"{"error":"Invalid Connection header","code":"INVALID_CONNECTION"}"#;
+        InvalidHost: "400 Bad Request", "53"
+            => r#"{"error":"Invalid Host header","code":"INVALID_HOST"}"#;
+        MissingHost: "400 Bad Request", "53"
+            => r#"{"error":"Missing Host header","code":"MISSING_HOST"}"#;
 
         BodyTooLarge: "413 Payload Too Large", "58"
             => r#"{"error":"Request body too large","code":"BODY_TOO_LARGE"}"#;
         BodyMismatch: "400 Bad Request", "55"
             => r#"{"error":"Body length mismatch","code":"BODY_MISMATCH"}"#;
-        UnexpectedBody: "400 Bad Request", "60"
-            => r#"{"error":"Unexpected request body","code":"UNEXPECTED_BODY"}"#;
+        BodyNotAllowed: "400 Bad Request", "62"
+            => r#"{"error":"Request body not allowed","code":"BODY_NOT_ALLOWED"}"#;
+        UnsupportedTransferEncoding: "501 Not Implemented", "82"
+            => r#"{"error":"Transfer-Encoding not supported","code":"UNSUPPORTED_TRANSFER_ENCODING"}"#;
+        ExpectationFailed: "417 Expectation Failed", "58"
+            => r#"{"error":"Expectation failed","code":"EXPECTATION_FAILED"}"#;
 
         InvalidEncoding: "400 Bad Request", "64"
             => r#"{"error":"Invalid character encoding","code":"INVALID_ENCODING"}"#;
+        InvalidEscape: "400 Bad Request", "60"
+            => r#"{"error":"Invalid percent-encoding","code":"INVALID_ESCAPE"}"#;
+        Http09WithoutHttp1x: "403 Forbidden", "86"
+            => r#"{"error":"HTTP/0.9+ requires a prior HTTP/1.X request","code":"HTTP09_WITHOUT_HTTP1X"}"#;
         ServiceUnavailable: "503 Service Unavailable", "72"
             => r#"{"error":"Service temporarily unavailable","code":"SERVICE_UNAVAILABLE"}"#;
+        ReadTimeout: "408 Request Timeout", "52"
+            => r#"{"error":"Request timeout","code":"REQUEST_TIMEOUT"}"#;
         Io: "503 Service Unavailable", "48"
             => r#"{"error":"I/O error occurred","code":"IO_ERROR"}"#;
     }
 }
 
+// Longest verbose error response: the longest JSON error, the offset and
+// a fully escaped excerpt (6 bytes per byte) fit with room to spare
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) const VERBOSE_LEN: usize = 512;
+const EXCERPT_LEN: usize = 32;
+
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+impl ErrorKind {
+    /// The JSON response of `as_http` with the `offset` and `excerpt` of
+    /// `context` added to the body, written into `buffer`
+    ///
+    /// `HTTP/0.9+` responses have no body and are returned unchanged.
+    pub(crate) fn as_verbose_http<'a>(
+        &self,
+        version: Version,
+        context: &ErrorContext,
+        buffer: &'a mut [u8; VERBOSE_LEN],
+    ) -> &'a [u8] {
+        let plain = self.as_http(version, true);
+        if version == Version::Http09 {
+            return plain;
+        }
+
+        match Self::write_verbose(plain, context, buffer) {
+            Ok(len) => &buffer[..len],
+            Err(_) => plain,
+        }
+    }
+
+    fn write_verbose(plain: &[u8], context: &ErrorContext, buffer: &mut [u8]) -> io::Result<usize> {
+        let head_end = memmem::find(plain, b"\r\n\r\n").unwrap_or(plain.len());
+        let status_end = memmem::find(plain, b"\r\n").unwrap_or(plain.len());
+        // `{"error":...}` without the closing brace
+        let json = &plain[(head_end + 4).min(plain.len())..];
+        let json = json.strip_suffix(b"}").unwrap_or(json);
+
+        let mut body = [0; VERBOSE_LEN];
+        let body_len = {
+            let mut out = &mut body[..];
+            out.write_all(json)?;
+            write!(out, r#","offset":{},"excerpt":""#, context.offset)?;
+            context.write_json_excerpt(&mut out)?;
+            out.write_all(b"\"}")?;
+            VERBOSE_LEN - out.len()
+        };
+
+        let mut out = &mut buffer[..];
+        out.write_all(&plain[..status_end])?;
+        write!(
+            out,
+            "\r\nconnection: close\r\ncontent-length: {body_len}\r\n\
+             content-type: application/json\r\n\r\n"
+        )?;
+        out.write_all(&body[..body_len])?;
+        Ok(VERBOSE_LEN - out.len())
+    }
+}
+
+/// Where parsing failed: the offset of the rejected element (method, target,
+/// version, header line or body; the first invalid byte for bad UTF-8) from
+/// the start of the request, and up to 32 bytes around it
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct ErrorContext {
+    pub(crate) offset: usize,
+    excerpt: [u8; EXCERPT_LEN],
+    len: usize,
+}
+
+impl ErrorContext {
+    // The excerpt starts 8 bytes before `offset`
+    #[inline]
+    pub(crate) fn new(request: &[u8], offset: usize) -> Self {
+        let start = offset.saturating_sub(8).min(request.len());
+        let window = &request[start..request.len().min(start + EXCERPT_LEN)];
+
+        let mut excerpt = [0; EXCERPT_LEN];
+        excerpt[..window.len()].copy_from_slice(window);
+        Self {
+            offset,
+            excerpt,
+            len: window.len(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn excerpt(&self) -> &[u8] {
+        &self.excerpt[..self.len]
+    }
+
+    // Printable ASCII is kept, everything else becomes `\u00XX`
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    fn write_json_excerpt(&self, out: &mut impl Write) -> io::Result<()> {
+        for &byte in self.excerpt() {
+            match byte {
+                b'"' | b'\\' => out.write_all(&[b'\\', byte])?,
+                b' '..=b'~' => out.write_all(&[byte])?,
+                _ => write!(out, "\\u{byte:04x}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl error::Error for ErrorKind {}
 impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -0,0 +1,259 @@
+use std::{
+    any::{Any, TypeId},
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Typed values attached to a [`Request`](crate::Request) by middleware,
+/// at most one per type.
+///
+/// A wrapping [`Handler`](crate::Handler) (authentication, tracing, ...)
+/// inserts a value before calling the inner handler, which reads it by type.
+/// Values live until the end of the request. The storage is reused across
+/// the requests of a connection; only inserted values are allocated.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{Handled, Handler, Request, Response, Server, StatusCode};
+/// use tokio::net::TcpListener;
+///
+/// struct UserId(u64);
+///
+/// struct Auth<H>(H);
+///
+/// impl<H: Handler> Handler for Auth<H> {
+///     async fn handle(&self, data: &mut (), req: &Request, resp: &mut Response) -> Handled {
+///         match req.header(b"authorization") {
+///             Some(b"Bearer secret") => req.extensions().insert(UserId(42)),
+///             _ => return resp.status(StatusCode::Unauthorized).body(""),
+///         }
+///         self.0.handle(data, req, resp).await
+///     }
+/// }
+///
+/// struct Profile;
+///
+/// impl Handler for Profile {
+///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+///         let user = req.extensions().get::<UserId>().unwrap();
+///         resp.status(StatusCode::Ok).body(user.0)
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     .handler(Auth(Profile))
+///     .build()
+///     .launch()
+///     .await;
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Extensions {
+    values: Mutex<Vec<Entry>>,
+}
+
+// The type, and for the crate's own values the address of their owner
+// (`0` for the user's values)
+type Key = (TypeId, usize);
+type Entry = (Key, Arc<dyn Any + Send + Sync>);
+
+impl Extensions {
+    /// Attaches `value`, replacing the previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) {
+        self.insert_keyed((TypeId::of::<T>(), 0), Arc::new(value));
+    }
+
+    /// Returns the value of type `T`, if attached.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.get_keyed((TypeId::of::<T>(), 0))
+    }
+
+    /// Removes the value of type `T` and returns it.
+    pub fn remove<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.remove_keyed((TypeId::of::<T>(), 0))
+    }
+
+    /// Returns `true` if a value of type `T` is attached.
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.find((TypeId::of::<T>(), 0)).is_some()
+    }
+
+    // Attaches a shared value under `key`, without allocating once the
+    // storage has grown
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn insert_keyed(&self, key: Key, value: Arc<dyn Any + Send + Sync>) {
+        let mut values = self.lock();
+
+        match values.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old)) => *old = value,
+            None => values.push((key, value)),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get_keyed<T: Send + Sync + 'static>(&self, key: Key) -> Option<Arc<T>> {
+        self.find(key)?.downcast().ok()
+    }
+
+    pub(crate) fn remove_keyed<T: Send + Sync + 'static>(&self, key: Key) -> Option<Arc<T>> {
+        let mut values = self.lock();
+        let i = values.iter().position(|(k, _)| *k == key)?;
+        values.swap_remove(i).1.downcast().ok()
+    }
+
+    /// Returns the number of attached values.
+    pub fn len(&self) -> usize {
+        let values = self.lock();
+        values.iter().filter(|((_, owner), _)| *owner == 0).count()
+    }
+
+    /// Returns `true` if no values are attached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Between requests, keeps the capacity
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.values
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    fn find(&self, key: Key) -> Option<Arc<dyn Any + Send + Sync>> {
+        let values = self.lock();
+        values
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.clone())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Entry>> {
+        self.values.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// The values are shared with the copy
+impl Clone for Extensions {
+    fn clone(&self) -> Self {
+        Self {
+            values: Mutex::new(self.lock().clone()),
+        }
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+// Values are not comparable, two requests are equal whatever is attached
+impl PartialEq for Extensions {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct UserId(u64);
+
+    #[test]
+    fn by_type() {
+        let ext = Extensions::default();
+        assert!(ext.is_empty());
+        assert_eq!(ext.get::<UserId>(), None);
+
+        ext.insert(UserId(1));
+        ext.insert("trace-id");
+        assert_eq!(ext.len(), 2);
+        assert_eq!(ext.get::<UserId>().as_deref(), Some(&UserId(1)));
+        assert_eq!(ext.get::<&str>().as_deref(), Some(&"trace-id"));
+        assert!(!ext.contains::<u64>());
+
+        // One value per type
+        ext.insert(UserId(2));
+        assert_eq!(ext.len(), 2);
+        assert_eq!(ext.get::<UserId>().as_deref(), Some(&UserId(2)));
+
+        assert_eq!(ext.remove::<UserId>().as_deref(), Some(&UserId(2)));
+        assert!(!ext.contains::<UserId>());
+        assert_eq!(ext.len(), 1);
+    }
+
+    #[test]
+    fn clear() {
+        let mut ext = Extensions::default();
+        ext.insert(UserId(1));
+        ext.clear();
+        assert!(ext.is_empty());
+
+        // A clone keeps its values
+        ext.insert(UserId(1));
+        let copy = ext.clone();
+        ext.clear();
+        assert!(ext.is_empty());
+        assert!(copy.contains::<UserId>());
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn middleware() {
+        use crate::{
+            server::connection::HttpConnection, tools::str_op, Handled, Handler, Request, Response,
+            StatusCode,
+        };
+
+        struct Auth<H>(H);
+
+        impl<H: Handler> Handler for Auth<H> {
+            async fn handle(&self, data: &mut (), req: &Request, resp: &mut Response) -> Handled {
+                if let Some(b"Bearer 7") = req.header(b"authorization") {
+                    req.extensions().insert(UserId(7));
+                }
+                self.0.handle(data, req, resp).await
+            }
+        }
+
+        struct Profile;
+
+        impl Handler for Profile {
+            async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+                match req.extensions().get::<UserId>() {
+                    Some(user) => resp.status(StatusCode::Ok).body(user.0),
+                    None => resp.status(StatusCode::Unauthorized).body("anonymous"),
+                }
+            }
+        }
+
+        let handler = Auth(Profile);
+        let send = |req: &str| {
+            let mut t = HttpConnection::from_req(req);
+            t.parse_request().unwrap();
+            t
+        };
+
+        let mut t = send("GET / HTTP/1.1\r\nAuthorization: Bearer 7\r\n\r\n");
+        handler.handle(&mut (), &t.request, &mut t.response).await;
+        assert!(str_op(t.response.buffer()).starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(str_op(t.response.buffer()).ends_with("\r\n\r\n7"));
+
+        // Not carried over to the next request
+        t.request.reset();
+        assert!(t.request.extensions().is_empty());
+
+        let mut t = send("GET / HTTP/1.1\r\n\r\n");
+        handler.handle(&mut (), &t.request, &mut t.response).await;
+        assert!(str_op(t.response.buffer()).ends_with("anonymous"));
+    }
+}
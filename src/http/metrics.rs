@@ -0,0 +1,145 @@
+//! Helpers for exporting per-endpoint metrics with bounded label values.
+
+use crate::Url;
+
+/// Maps request paths to one of a fixed set of path templates, for metric
+/// labels like `/api/users/:id` instead of the raw path.
+///
+/// Patterns are matched segment by segment, the first match wins:
+/// - a literal segment matches itself
+/// - `:name` matches any single segment
+/// - a final `*` matches the rest of the path, possibly empty
+///
+/// A path that matches no pattern gives [`OTHER`](Self::OTHER), so the
+/// number of distinct labels is at most the number of patterns plus one.
+/// Matching doesn't allocate.
+///
+/// # Examples
+/// ```
+/// use maker_web::metrics::PathTemplater;
+///
+/// const ROUTES: PathTemplater = PathTemplater::new(&[
+///     "/api/users/:id",
+///     "/api/users/:id/posts/:post",
+///     "/static/*",
+/// ]);
+///
+/// maker_web::docs_rs_helper::example_url_http1x("/api/users/42/posts/7", |req| {
+///     assert_eq!(ROUTES.template(req.url()), "/api/users/:id/posts/:post");
+/// });
+/// maker_web::docs_rs_helper::example_url_http1x("/unknown/path", |req| {
+///     assert_eq!(ROUTES.template(req.url()), "other");
+/// });
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct PathTemplater {
+    patterns: &'static [&'static str],
+}
+
+impl PathTemplater {
+    /// The template of paths that match no pattern.
+    pub const OTHER: &'static str = "other";
+
+    /// Creates a templater for `patterns`, tried in order.
+    #[inline]
+    pub const fn new(patterns: &'static [&'static str]) -> Self {
+        Self { patterns }
+    }
+
+    /// Returns the first pattern matching the path of `url`, or [`OTHER`](Self::OTHER).
+    #[inline]
+    pub fn template(&self, url: &Url) -> &'static str {
+        self.find(url.path_segments_str()).unwrap_or(Self::OTHER)
+    }
+
+    /// Returns the first pattern matching the path of `url`, if any.
+    #[inline]
+    pub fn matched(&self, url: &Url) -> Option<&'static str> {
+        self.find(url.path_segments_str())
+    }
+
+    fn find(&self, path: &[&str]) -> Option<&'static str> {
+        self.patterns
+            .iter()
+            .copied()
+            .find(|pattern| matches(pattern, path))
+    }
+}
+
+pub(crate) fn matches(pattern: &str, path: &[&str]) -> bool {
+    let mut path = path.iter();
+
+    for segment in pattern.split('/').filter(|s| !s.is_empty()) {
+        if segment == "*" {
+            return true;
+        }
+        match path.next() {
+            Some(_) if segment.starts_with(':') => {}
+            Some(part) if *part == segment => {}
+            _ => return false,
+        }
+    }
+    path.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{limits::ReqLimits, parser::RequestParser};
+
+    const ROUTES: PathTemplater = PathTemplater::new(&[
+        "/",
+        "/api/users",
+        "/api/users/:id",
+        "/api/users/:id/posts/:post",
+        "/api/users/me",
+        "/static/*",
+    ]);
+
+    fn template(path: &str) -> &'static str {
+        let mut parser = RequestParser::new(ReqLimits::default(), false);
+        let request = format!("GET {path} HTTP/1.1\r\nHost: a\r\n\r\n");
+        ROUTES.template(parser.parse(request.as_bytes()).unwrap().url())
+    }
+
+    #[test]
+    fn templates() {
+        let cases = [
+            ("/", "/"),
+            ("/api/users", "/api/users"),
+            ("/api/users/42", "/api/users/:id"),
+            ("/api/users/42?full=true", "/api/users/:id"),
+            ("/api/users/42/posts/7", "/api/users/:id/posts/:post"),
+            // First match wins
+            ("/api/users/me", "/api/users/:id"),
+            ("/static", "/static/*"),
+            ("/static/css/site.css", "/static/*"),
+        ];
+
+        for (path, expected) in cases {
+            assert_eq!(template(path), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn fallback_bounds_labels() {
+        let paths = [
+            "/api",
+            "/api/users/42/posts",
+            "/api/users/42/posts/7/comments",
+            "/api/orders/1",
+            "/favicon.ico",
+        ];
+        for path in paths {
+            assert_eq!(template(path), PathTemplater::OTHER, "{path}");
+        }
+
+        // Any number of distinct ids ends up in one label
+        let mut labels: Vec<_> = (0..1000)
+            .map(|id| template(&format!("/api/users/{id}")))
+            .chain((0..1000).map(|id| template(&format!("/unknown/{id}"))))
+            .collect();
+        labels.dedup();
+        assert_eq!(labels, ["/api/users/:id", PathTemplater::OTHER]);
+    }
+}
@@ -0,0 +1,252 @@
+//! Transport-independent request parsing.
+//!
+//! The server feeds this parser from `tokio` sockets; with
+//! `default-features = false` (no `tokio`) it can be driven by any transport.
+
+use crate::{
+    errors::{ErrorContext, ErrorKind},
+    http::request::{Parser, Request},
+    limits::ReqLimits,
+    Version,
+};
+use std::{error, fmt};
+
+/// Parses requests from bytes supplied by the caller.
+///
+/// Buffers are allocated once in [`new()`](RequestParser::new) and reused
+/// for every request, like in the server's workers.
+///
+/// # Examples
+/// ```
+/// use maker_web::{limits::ReqLimits, parser::RequestParser, Method};
+///
+/// let mut parser = RequestParser::new(ReqLimits::default(), false);
+///
+/// let req = parser.parse(b"GET /users?id=7 HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+/// assert_eq!(req.method(), Method::Get);
+/// assert_eq!(req.url().path(), b"/users");
+/// assert_eq!(req.header(b"host"), Some(&b"example.com"[..]));
+///
+/// let err = parser.parse(b"GET //users HTTP/1.1\r\n\r\n").unwrap_err();
+/// assert!(err.response(false).starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+/// ```
+#[derive(Debug)]
+pub struct RequestParser {
+    parser: Parser,
+    request: Request,
+    limits: ReqLimits,
+    http_09: bool,
+}
+
+impl RequestParser {
+    /// Creates a parser with the given limits.
+    ///
+    /// `http_09` enables parsing of `HTTP/0.9+` requests.
+    pub fn new(limits: ReqLimits, http_09: bool) -> Self {
+        let limits = limits.precalculate();
+
+        Self {
+            parser: Parser::new(&limits),
+            request: Request::new(&limits),
+            limits,
+            http_09,
+        }
+    }
+
+    /// Size of the internal buffer, the longest request that can be parsed.
+    #[inline(always)]
+    pub fn buffer_size(&self) -> usize {
+        self.limits.precalc.buffer
+    }
+
+    /// Parses one request from the start of `data`.
+    ///
+    /// Bytes beyond [`buffer_size()`](RequestParser::buffer_size) are ignored,
+    /// the same as a socket read into a full buffer.
+    #[inline]
+    pub fn parse(&mut self, data: &[u8]) -> Result<&Request, ParseError> {
+        self.parser.load(data);
+        self.request.reset();
+
+        match self
+            .parser
+            .parse_request(&mut self.request, &self.limits, self.http_09)
+        {
+            Ok(()) => Ok(&self.request),
+            Err(kind) => Err(ParseError {
+                kind,
+                version: self.request.version(),
+                context: self.parser.error_context().unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+/// Error returned by [`RequestParser::parse`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    kind: ErrorKind,
+    version: Version,
+    context: ErrorContext,
+}
+
+impl ParseError {
+    /// The response the server would send for this error.
+    ///
+    /// With `json` the response has a JSON body, see
+    /// [`ServerLimits::json_errors`](crate::limits::ServerLimits::json_errors).
+    #[inline]
+    pub fn response(&self, json: bool) -> &'static [u8] {
+        self.kind.as_http(self.version, json)
+    }
+
+    /// Offset of the rejected element (method, target, version, header line
+    /// or body) from the start of the request; for invalid UTF-8, of the
+    /// first invalid byte.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{limits::ReqLimits, parser::RequestParser};
+    ///
+    /// let mut parser = RequestParser::new(ReqLimits::default(), false);
+    ///
+    /// let err = parser.parse(b"GET / HTTP/1.1\r\nHost: a\r\nbad header\r\n\r\n").unwrap_err();
+    /// assert_eq!(err.offset(), 25);
+    /// assert_eq!(err.excerpt(), b"ost: a\r\nbad header\r\n\r\n");
+    /// ```
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.context.offset
+    }
+
+    /// Up to 32 bytes of the request around [`offset()`](Self::offset),
+    /// starting 8 bytes before it.
+    #[inline]
+    pub fn excerpt(&self) -> &[u8] {
+        self.context.excerpt()
+    }
+}
+
+impl error::Error for ParseError {}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid request: {} at byte {} near \"{}\"",
+            self.kind,
+            self.offset(),
+            self.excerpt().escape_ascii()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::*;
+
+    #[test]
+    fn parse_valid() {
+        let mut parser = RequestParser::new(ReqLimits::default(), false);
+
+        let req = parser
+            .parse(b"POST /a/b?x=1 HTTP/1.0\r\nContent-Length: 4\r\n\r\nbody")
+            .unwrap();
+        assert_eq!(req.version(), Version::Http10);
+        assert_eq!(str_op(req.url().path()), "/a/b");
+        assert_eq!(req.body(), Some(&b"body"[..]));
+
+        // A shorter request must not see the rest of the previous one
+        let req = parser.parse(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        assert_eq!(req.url().path(), b"/");
+        assert_eq!(req.body(), None);
+        assert_eq!(req.header(b"content-length"), None);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let mut parser = RequestParser::new(ReqLimits::default(), false);
+
+        let err = parser
+            .parse(b"GET / HTTP/1.0\r\nbad header\r\n\r\n")
+            .unwrap_err();
+        assert_eq!(
+            str_op(err.response(false)),
+            "HTTP/1.0 400 Bad Request\r\nconnection: close\r\ncontent-length: 0\r\n\r\n"
+        );
+        assert!(err.to_string().contains("InvalidHeader"));
+
+        let err = parser.parse(b"GET /\r\n").unwrap_err();
+        assert!(str_op(err.response(true)).contains("505 HTTP Version Not Supported"));
+
+        assert!(parser.parse(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").is_ok());
+    }
+
+    #[test]
+    fn error_offsets() {
+        let mut parser = RequestParser::new(ReqLimits::default(), false);
+
+        let cases: [(&[u8], usize, &str); 8] = [
+            (b"GETT / HTTP/1.1\r\n\r\n", 0, "InvalidMethod"),
+            (b"GET //x HTTP/1.1\r\nHost: a\r\n\r\n", 4, "DoubleSlash"),
+            (b"GET / HTTP/7.1\r\nHost: a\r\n\r\n", 6, "Version"),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nbad header\r\n\r\n",
+                25,
+                "InvalidHeader",
+            ),
+            (
+                b"GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n",
+                16,
+                "InvalidContentLength",
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nx: \xff\r\n\r\n",
+                28,
+                "InvalidEncoding",
+            ),
+            (b"GET / HTTP/1.1\r\nAccept: */*\r\n\r\n", 31, "MissingHost"),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 9\r\n\r\nabc",
+                47,
+                "BodyMismatch",
+            ),
+        ];
+
+        for (request, offset, kind) in cases {
+            let err = parser.parse(request).unwrap_err();
+            assert!(err.to_string().contains(kind), "{err}");
+            assert_eq!(err.offset(), offset, "{err}");
+
+            let start = offset.saturating_sub(8);
+            let end = request.len().min(start + 32);
+            assert_eq!(err.excerpt(), &request[start..end], "{err}");
+        }
+
+        let err = parser
+            .parse(b"GET / HTTP/1.1\r\nHost: a\r\nx: \xff\r\n\r\n")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"invalid request: InvalidEncoding at byte 28 near ": a\r\nx: \xff\r\n\r\n""#
+        );
+    }
+
+    #[test]
+    fn parse_http_09() {
+        let mut parser = RequestParser::new(ReqLimits::default(), true);
+
+        let req = parser.parse(b"GET /index\r\n").unwrap();
+        assert_eq!(req.version(), Version::Http09);
+        assert_eq!(req.url().path(), b"/index");
+    }
+
+    #[test]
+    fn oversized_input_is_cut() {
+        let mut parser = RequestParser::new(ReqLimits::default(), false);
+
+        let mut data = b"GET / HTTP/1.1\r\nx-pad: ".to_vec();
+        data.resize(parser.buffer_size() * 2, b'a');
+        assert!(parser.parse(&data).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 //! Zero-copy URL query string parser with flexible collection support.
 
+use crate::url;
 use std::{collections::HashMap, error, fmt};
 
 /// Zero-copy URL query string parser.
@@ -7,10 +8,10 @@ use std::{collections::HashMap, error, fmt};
 /// Provides high-performance parsing of URL query strings without allocating
 /// new strings for parameter names and values.
 ///
-/// Can be used to parse form data (application/x-www-form-urlencoded),
-/// but **there is currently no decoder support (%20, %40, etc.)**,
-/// due to zero-copy & zero-alloc.
-/// URL decoding may be added in future versions via optional features.
+/// Can be used to parse form data (application/x-www-form-urlencoded).
+/// [`parse`](Query::parse) returns the raw bytes (`user%40example.com`),
+/// [`parse_decoded`](Query::parse_decoded) decodes `%XX` and `+` into a
+/// buffer provided by the caller.
 ///
 /// # Examples
 /// ```rust
@@ -117,7 +118,7 @@ impl Query {
     /// let result = Query::parse_into(&mut collector, b"a=1&b=2&c=3", 2);
     /// assert!(result.is_err()); // limit exceeded after 2 parameters
     ///
-    /// // Parse form data with URL-encoded values (no decoding)
+    /// // Parse form data with URL-encoded values (no decoding, see `parse_decoded`)
     /// let mut collector = Vec::new();
     /// Query::parse_into(&mut collector, b"email=user%40example.com", 10).unwrap();
     /// assert_eq!(collector[0].1, b"user%40example.com"); // raw bytes
@@ -128,44 +129,135 @@ impl Query {
         query: &'a [u8],
         limit: usize,
     ) -> Result<(), Error> {
+        for (key, value) in Self::params(query)? {
+            // Check parameter limit
+            if result.length() >= limit {
+                return Err(Error::OverLimit(limit));
+            }
+
+            result.add_param(key, value);
+        }
+
+        Ok(())
+    }
+
+    /// Parses a URL query string or form body into a new collection,
+    /// percent-decoding keys and values into `scratch`.
+    ///
+    /// `%XX` is decoded to its byte and `+` to a space, as in
+    /// `application/x-www-form-urlencoded`. The collected keys and values
+    /// borrow from `scratch`, which is cleared first and grows at most once,
+    /// to the length of `query`. Decoded bytes are not checked to be UTF-8.
+    ///
+    /// # Errors
+    /// Besides the errors of [`parse`](Query::parse),
+    /// [`Error::InvalidEncoding`] for a `%` not followed by two hex digits.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::query::{Error, Query};
+    ///
+    /// let mut scratch = Vec::new();
+    /// let params: Vec<(&[u8], &[u8])> =
+    ///     Query::parse_decoded(b"email=user%40example.com&q=hello+world", &mut scratch, 10)
+    ///         .unwrap();
+    /// assert!(params[0] == (b"email", b"user@example.com"));
+    /// assert!(params[1] == (b"q", b"hello world"));
+    ///
+    /// let mut scratch = Vec::new();
+    /// let result = Query::parse_decoded::<Vec<_>>(b"q=100%", &mut scratch, 10);
+    /// assert_eq!(result, Err(Error::InvalidEncoding));
+    /// ```
+    #[inline]
+    pub fn parse_decoded<'a, C: QueryCollector<'a>>(
+        query: &[u8],
+        scratch: &'a mut Vec<u8>,
+        limit: usize,
+    ) -> Result<C, Error> {
+        let mut result = C::with_capacity(limit);
+        Self::parse_decoded_into(&mut result, query, scratch, limit)?;
+        Ok(result)
+    }
+
+    /// Parses a URL query string into an existing collection like
+    /// [`parse_into`](Query::parse_into), decoding like
+    /// [`parse_decoded`](Query::parse_decoded).
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::query::Query;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut scratch = Vec::new();
+    /// let mut params = HashMap::new();
+    /// Query::parse_decoded_into(&mut params, b"?city=S%C3%A3o+Paulo", &mut scratch, 10).unwrap();
+    /// assert_eq!(params[&b"city"[..]], "São Paulo".as_bytes());
+    /// ```
+    pub fn parse_decoded_into<'a, C: QueryCollector<'a>>(
+        result: &mut C,
+        query: &[u8],
+        scratch: &'a mut Vec<u8>,
+        limit: usize,
+    ) -> Result<(), Error> {
+        // Decoded parts are never longer than the encoded ones
+        scratch.clear();
+        scratch.reserve(query.len());
+        for (key, value) in Self::params(query)? {
+            for part in [key, value] {
+                url::decode_into(part, scratch, true, true).map_err(|_| Error::InvalidEncoding)?;
+            }
+        }
+
+        // The parts are stored back to back, a valid `%XX` is 3 bytes for 1
+        let decoded_len = |part: &[u8]| part.len() - 2 * memchr::memchr_iter(b'%', part).count();
+        let mut decoded: &'a [u8] = scratch;
+        let mut take = |part: &[u8]| {
+            let (head, rest) = decoded.split_at(decoded_len(part));
+            decoded = rest;
+            head
+        };
+
+        for (key, value) in Self::params(query)? {
+            if result.length() >= limit {
+                return Err(Error::OverLimit(limit));
+            }
+
+            let key = take(key);
+            result.add_param(key, take(value));
+        }
+
+        Ok(())
+    }
+
+    // The raw `key=value` pairs, a missing `=` gives an empty value
+    #[inline]
+    fn params(query: &[u8]) -> Result<impl Iterator<Item = (&[u8], &[u8])>, Error> {
         let data = match query.first().ok_or(Error::Empty)? {
             b'?' => &query[1..],
             _ => query,
         };
 
         let mut start = 0;
-        while start < data.len() {
-            // Check parameter limit
-            if result.length() >= limit {
-                return Err(Error::OverLimit(limit));
+        let params = std::iter::from_fn(move || {
+            if start >= data.len() {
+                return None;
             }
 
             // Find next '&' or end of string
-            let end = &data[start..]
+            let end = data[start..]
                 .iter()
                 .position(|&c| c == b'&')
-                .map(|pos| start + pos)
-                .unwrap_or(data.len());
-
-            // Find '=' within current parameter segment
-            let index = &data[start..*end]
-                .iter()
-                .position(|&c| c == b'=')
-                .unwrap_or(end - start);
-            let split_index = start + index;
-
-            // Extract key and value
-            let key = &data[start..split_index];
-            let value = match split_index < *end {
-                true => &data[split_index + 1..*end], // Has value after '='
-                false => b"",                         // No value (key only)
-            };
-
-            result.add_param(key, value);
+                .map_or(data.len(), |pos| start + pos);
+            let param = &data[start..end];
             start = end + 1;
-        }
 
-        Ok(())
+            // Find '=' within current parameter segment
+            Some(match param.iter().position(|&c| c == b'=') {
+                Some(index) => (&param[..index], &param[index + 1..]), // Has value after '='
+                None => (param, &b""[..]),                             // No value (key only)
+            })
+        });
+        Ok(params)
     }
 }
 
@@ -259,6 +351,123 @@ impl<'a> QueryCollector<'a> for HashMap<&'a [u8], &'a [u8]> {
     }
 }
 
+/// How a parameter is looked up by key, see [`Url::query_with`](crate::Url::query_with).
+///
+/// The default is the behavior of [`Url::query`](crate::Url::query):
+/// case-sensitive, first match. The same lookup works on any list of
+/// parsed parameters, e.g. a form body parsed with [`Query::parse`].
+///
+/// # Examples
+/// ```
+/// use maker_web::query::{Pick, Query, QueryLookup};
+///
+/// let lookup = QueryLookup {
+///     case_insensitive: true,
+///     pick: Pick::Last,
+///     ..QueryLookup::default()
+/// };
+///
+/// // `application/x-www-form-urlencoded` body
+/// let form: Vec<(&[u8], &[u8])> = Query::parse(b"Page=1&tag=a&page=2", 10).unwrap();
+/// assert_eq!(lookup.find(form.iter().copied(), b"PAGE"), Some(&b"2"[..]));
+/// assert_eq!(QueryLookup::default().find(form.iter().copied(), b"page"), Some(&b"2"[..]));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueryLookup {
+    /// Compare keys ignoring ASCII case (default: `false`)
+    pub case_insensitive: bool,
+    /// Which value to return when the key occurs more than once (default: [`Pick::First`])
+    pub pick: Pick,
+
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub _priv: (),
+}
+
+/// Which of the duplicated parameters [`QueryLookup`] returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pick {
+    /// The first occurrence
+    #[default]
+    First,
+    /// The last occurrence, like [`HashMap`] collected with [`Query::parse`]
+    Last,
+}
+
+impl QueryLookup {
+    /// Returns the value for `key` from `params`, with a single linear scan.
+    #[inline]
+    pub fn find<'a, I>(&self, params: I, key: &[u8]) -> Option<&'a [u8]>
+    where
+        I: IntoIterator<Item = (&'a [u8], &'a [u8])>,
+        I::IntoIter: DoubleEndedIterator,
+    {
+        let case_insensitive = self.case_insensitive;
+        let matches = |&(k, _): &(&[u8], &[u8])| match case_insensitive {
+            true => k.eq_ignore_ascii_case(key),
+            false => k == key,
+        };
+
+        let mut params = params.into_iter();
+        let found = match self.pick {
+            Pick::First => params.find(matches),
+            Pick::Last => params.rfind(matches),
+        };
+        found.map(|(_, v)| v)
+    }
+}
+
+/// Integers parsed from decimal ASCII bytes, see [`Url::query_int`](crate::Url::query_int).
+///
+/// Digits only, with a leading `-` for signed types: no `+`, whitespace or
+/// empty values. Overflow is an error rather than a wrapped value.
+///
+/// # Examples
+/// ```
+/// use maker_web::query::FromRadix;
+///
+/// assert_eq!(u16::from_radix_10(b"8080"), Some(8080));
+/// assert_eq!(i32::from_radix_10(b"-15"), Some(-15));
+/// assert_eq!(u8::from_radix_10(b"256"), None);
+/// assert_eq!(u32::from_radix_10(b"-1"), None);
+/// assert_eq!(i64::from_radix_10(b""), None);
+/// ```
+pub trait FromRadix: Sized {
+    /// Parses `bytes` as a decimal integer, `None` if they are not one.
+    fn from_radix_10(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! from_radix {
+    ($($ty:ty),*) => {$(
+        impl FromRadix for $ty {
+            #[inline]
+            fn from_radix_10(bytes: &[u8]) -> Option<Self> {
+                let (negative, digits) = match bytes {
+                    // Only signed types take a sign
+                    [b'-', digits @ ..] if <$ty>::MIN != 0 => (true, digits),
+                    digits => (false, digits),
+                };
+                if digits.is_empty() {
+                    return None;
+                }
+                // Accumulated with the sign, so `MIN` does not overflow
+                digits.iter().try_fold(0 as $ty, |n, &byte| {
+                    let digit = byte.wrapping_sub(b'0');
+                    if digit > 9 {
+                        return None;
+                    }
+                    match negative {
+                        true => n.checked_mul(10)?.checked_sub(digit as $ty),
+                        false => n.checked_mul(10)?.checked_add(digit as $ty),
+                    }
+                })
+            }
+        }
+    )*};
+}
+
+from_radix!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
 /// Error types that can occur during query parsing.
 ///
 /// This enum provides detailed error information for different failure scenarios
@@ -279,6 +488,17 @@ pub enum Error {
     /// This error occurs when the input query string has no meaningful content
     /// to parse (empty, or just "?").
     Empty,
+
+    /// The request is not an `application/x-www-form-urlencoded` form.
+    ///
+    /// Returned by [`Request::body_form`](crate::Request::body_form)
+    /// when `content-type` is missing or has another media type.
+    NotForm,
+
+    /// A `%` is not followed by two hex digits.
+    ///
+    /// Returned by [`Query::parse_decoded`] and [`Query::parse_decoded_into`].
+    InvalidEncoding,
 }
 
 impl error::Error for Error {}
@@ -292,6 +512,12 @@ impl fmt::Display for Error {
             Error::Empty => {
                 write!(f, "Query string is empty or contains no parameters")
             }
+            Error::NotForm => {
+                write!(f, "Content type is not application/x-www-form-urlencoded")
+            }
+            Error::InvalidEncoding => {
+                write!(f, "Invalid percent-encoding in query string")
+            }
         }
     }
 }
@@ -345,6 +571,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn lookup() {
+        let params: Vec<(&[u8], &[u8])> =
+            Query::parse(b"page=1&Page=2&PAGE=3&sort=name&page=4", 10).unwrap();
+        let find = |case_insensitive, pick, key: &str| {
+            let lookup = QueryLookup {
+                case_insensitive,
+                pick,
+                ..QueryLookup::default()
+            };
+            lookup
+                .find(params.iter().copied(), key.as_bytes())
+                .map(str_op)
+        };
+
+        assert_eq!(find(false, Pick::First, "page"), Some("1"));
+        assert_eq!(find(false, Pick::Last, "page"), Some("4"));
+        assert_eq!(find(false, Pick::First, "Page"), Some("2"));
+        assert_eq!(find(false, Pick::Last, "PAGE"), Some("3"));
+        assert_eq!(find(false, Pick::First, "pAgE"), None);
+
+        assert_eq!(find(true, Pick::First, "pAgE"), Some("1"));
+        assert_eq!(find(true, Pick::Last, "pAgE"), Some("4"));
+        assert_eq!(find(true, Pick::Last, "SORT"), Some("name"));
+        assert_eq!(find(true, Pick::First, "missing"), None);
+    }
+
     #[test]
     fn empty_error() {
         assert_eq!(
@@ -352,4 +605,88 @@ mod tests {
             Err(Error::Empty)
         );
     }
+
+    #[test]
+    fn decoded() {
+        let mut scratch = Vec::new();
+        let params: Vec<(&[u8], &[u8])> = Query::parse_decoded(
+            b"?email=user%40example.com&q=tom+%26+jerry&a%3Db=%20x%20&flag&&=%2b",
+            &mut scratch,
+            10,
+        )
+        .unwrap();
+
+        assert_eq!(params.len(), 6);
+        assert_eq!(str_2(params[0]), ("email", "user@example.com"));
+        assert_eq!(str_2(params[1]), ("q", "tom & jerry"));
+        // Encoded separators stay inside their part
+        assert_eq!(str_2(params[2]), ("a=b", " x "));
+        assert_eq!(str_2(params[3]), ("flag", ""));
+        assert_eq!(str_2(params[4]), ("", ""));
+        assert_eq!(str_2(params[5]), ("", "+"));
+
+        // Same pairs as without decoding when there's nothing to decode
+        let raw = b"debug&name=&=Qwe&key=sda&&";
+        let mut scratch = Vec::new();
+        let decoded: Vec<(&[u8], &[u8])> = Query::parse_decoded(raw, &mut scratch, 10).unwrap();
+        assert_eq!(decoded, Query::parse::<Vec<_>>(raw, 10).unwrap());
+    }
+
+    #[test]
+    fn decoded_errors() {
+        for query in ["a=%4", "a=%", "%4=1", "a=%zz", "a=%4g&b=1", "a=1&b=%%41"] {
+            let mut scratch = Vec::new();
+            assert_eq!(
+                Query::parse_decoded::<Vec<_>>(query.as_bytes(), &mut scratch, 10),
+                Err(Error::InvalidEncoding),
+                "{query}"
+            );
+        }
+
+        let mut scratch = Vec::new();
+        assert_eq!(
+            Query::parse_decoded::<Vec<_>>(b"a=%41&b=%42", &mut scratch, 1),
+            Err(Error::OverLimit(1))
+        );
+        assert_eq!(
+            Query::parse_decoded::<Vec<_>>(b"", &mut scratch, 1),
+            Err(Error::Empty)
+        );
+    }
+
+    #[test]
+    fn from_radix() {
+        assert_eq!(u8::from_radix_10(b"0"), Some(0));
+        assert_eq!(u8::from_radix_10(b"255"), Some(255));
+        assert_eq!(u8::from_radix_10(b"0042"), Some(42));
+        assert_eq!(u64::from_radix_10(b"18446744073709551615"), Some(u64::MAX));
+        assert_eq!(i8::from_radix_10(b"-128"), Some(i8::MIN));
+        assert_eq!(i8::from_radix_10(b"127"), Some(i8::MAX));
+        assert_eq!(i64::from_radix_10(b"-20"), Some(-20));
+        assert_eq!(i32::from_radix_10(b"-0"), Some(0));
+
+        // Overflow
+        assert_eq!(u8::from_radix_10(b"256"), None);
+        assert_eq!(i8::from_radix_10(b"128"), None);
+        assert_eq!(i8::from_radix_10(b"-129"), None);
+        assert_eq!(u64::from_radix_10(b"18446744073709551616"), None);
+
+        // Not numbers
+        for value in [
+            &b""[..],
+            b"-",
+            b"+1",
+            b" 1",
+            b"1 ",
+            b"1.5",
+            b"abc",
+            b"--1",
+            b"1-",
+        ] {
+            assert_eq!(i32::from_radix_10(value), None, "{:?}", str_op(value));
+        }
+        assert_eq!(u32::from_radix_10(b"-1"), None);
+        assert_eq!(u32::from_radix_10(b"-0"), None);
+        assert_eq!(usize::from_radix_10(b"18446744073709551616"), None);
+    }
 }
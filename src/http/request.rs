@@ -1,20 +1,24 @@
 use crate::{
     errors::*,
-    http::types::{self, Header},
-    limits::ReqLimits,
-    query::Query,
-    server::connection::HttpConnection,
-    ConnectionData, Handler, Method, Url, Version,
+    http::{
+        extensions::Extensions,
+        types::{self, Header, IfRange},
+    },
+    limits::{BodyPolicy, CapturedSlots, ReqLimits, ZeroPolicy},
+    query::{self, Query, QueryCollector},
+    url, Method, Url, Version,
 };
+#[cfg(feature = "server")]
+use crate::{CancelToken, ConnInfo};
 use memchr::{memchr2_iter, memchr3_iter, Memchr3};
 use std::{
-    io, mem,
+    mem,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     str,
 };
+#[cfg(feature = "server")]
 use tokio::{
-    io::AsyncReadExt,
-    net::TcpStream,
+    io::{AsyncRead, AsyncReadExt},
     time::{sleep, Duration},
 };
 
@@ -93,14 +97,18 @@ use tokio::{
 /// Name:   invalid value  \r\n     // Value: `  invalid value  `
 /// ```
 ///
-/// **Note**: The parser extracts semantics from two headers:
+/// **Note**: The parser extracts semantics from these headers:
 ///
-/// | Header           | Purpose              | Values                                                                 |
-/// |------------------|----------------------|------------------------------------------------------------------------|
-/// | `Content-Length` | Body size validation | Any `usize` values (not exceeding the [limits](ReqLimits::body_size))  |
-/// | `Connection`     | Keep-alive flag      | `keep-alive` or `close` (case-insensitive)                             |
+/// | Header              | Purpose              | Values                                                                 |
+/// |---------------------|----------------------|------------------------------------------------------------------------|
+/// | `Content-Length`    | Body size validation | Any `usize` values (not exceeding the [limits](ReqLimits::body_size))  |
+/// | `Connection`        | Keep-alive flag      | `keep-alive` or `close` (case-insensitive)                             |
+/// | `Transfer-Encoding` | Rejected             | Any value, `chunked` included, gives `501 Not Implemented`             |
+/// | `Expect`            | Interim response     | `100-continue` (case-insensitive, `HTTP/1.1` only), see [`Request::expects_continue`] |
+/// | `Host`              | Request target       | Required on `HTTP/1.1` ([`require_host_http11`](ReqLimits::require_host_http11)), syntax checked with [`validate_authority`](ReqLimits::validate_authority) |
 ///
-/// All other headers are preserved but not interpreted (including `Host`).
+/// `Expect` and `Host` are still returned by [`Request::header`]; all other
+/// headers are preserved but not interpreted.
 ///
 /// ## End of headings
 ///  
@@ -117,27 +125,43 @@ use tokio::{
 /// **Not supported**:
 /// - `Transfer-Encoding: chunked`
 /// - Implicit-length bodies (read until connection close)
-/// - `Expect: 100-continue`
 ///
-/// Attempts to use unsupported methods result in error. This is an architectural
-/// decision aimed at security and memory protection, and is also related to a
-/// limitation of the server architecture itself. **Don't expect these features to
-/// be added in the future.**
+/// Attempts to use unsupported methods result in error; any `Transfer-Encoding`
+/// gives `501 Not Implemented`, so an encoded body is never parsed as the next
+/// request. This is an architectural decision aimed at security and memory
+/// protection, and is also related to a limitation of the server architecture
+/// itself. **Don't expect these features to be added in the future.**
 #[derive(Debug, Clone, PartialEq)]
 #[repr(align(128))]
 pub struct Request {
     method: Method,
     url: Url,
     version: Version,
+    request_line: &'static [u8],
 
     headers: Vec<Header>,
+    // Slots for `ReqLimits::captured_headers`, in the same order as the names
+    captured_slots: CapturedSlots,
+    captured: Vec<Option<&'static str>>,
+    captured_count: usize,
     content_length: Option<usize>,
     keep_alive: bool,
+    has_host: bool,
+    expect_continue: bool,
+    trusted_proxies: &'static [IpAddr],
+    // `ReqLimits::url_query_parts`, also the limit of `body_form()`
+    max_query_parts: usize,
 
     body: Option<&'static [u8]>,
+    extensions: Extensions,
 
     pub(crate) client_addr: SocketAddr,
     pub(crate) server_addr: SocketAddr,
+    #[cfg(feature = "server")]
+    pub(crate) cancel: CancelToken,
+    // Kept across the requests of a connection, reset by the connection
+    #[cfg(feature = "server")]
+    pub(crate) conn_info: ConnInfo,
 }
 
 impl Request {
@@ -150,15 +174,28 @@ impl Request {
             method: Method::Get,
             url: Url::new(limits),
             version: Version::Http11,
+            request_line: b"",
 
             headers: Vec::with_capacity(limits.header_count),
+            captured_slots: limits.precalc.captured.clone(),
+            captured: vec![None; limits.precalc.captured.len()],
+            captured_count: 0,
             content_length: None,
             keep_alive: true,
+            has_host: false,
+            expect_continue: false,
+            trusted_proxies: limits.trusted_proxies,
+            max_query_parts: limits.url_query_parts,
 
             body: None,
+            extensions: Extensions::default(),
 
             client_addr: Self::UNKNOWN_CLIENT,
             server_addr: Self::DEFAULT_SERVER,
+            #[cfg(feature = "server")]
+            cancel: CancelToken::default(),
+            #[cfg(feature = "server")]
+            conn_info: ConnInfo::default(),
         }
     }
 
@@ -167,12 +204,18 @@ impl Request {
         self.method = Method::Get;
         self.url.clear();
         self.version = Version::Http11;
+        self.request_line = b"";
 
         self.headers.clear();
+        self.captured.fill(None);
+        self.captured_count = 0;
         self.content_length = None;
         self.keep_alive = true;
+        self.has_host = false;
+        self.expect_continue = false;
 
         self.body = None;
+        self.extensions.clear();
     }
 }
 
@@ -188,6 +231,34 @@ impl Request {
         &self.server_addr
     }
 
+    /// Returns the token that fires when the client disconnects
+    /// while this request is being handled.
+    ///
+    /// See [`CancelToken`] for an example.
+    #[cfg(feature = "server")]
+    #[inline(always)]
+    pub const fn cancel_token(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    /// Returns the protocol versions of the requests handled earlier
+    /// on this connection.
+    ///
+    /// See [`ConnInfo`] for an example.
+    #[cfg(feature = "server")]
+    #[inline(always)]
+    pub const fn conn_info(&self) -> &ConnInfo {
+        &self.conn_info
+    }
+
+    /// Returns the typed values attached to this request by middleware.
+    ///
+    /// See [`Extensions`] for an example.
+    #[inline(always)]
+    pub const fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
     #[inline(always)]
     pub const fn method(&self) -> Method {
         self.method
@@ -203,26 +274,52 @@ impl Request {
         self.version
     }
 
+    /// Returns `true` if the request's version is `version` or newer
+    /// (`Http09 < Http10 < Http11`).
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Version;
+    ///
+    /// maker_web::docs_rs_helper::example_url_http1x("/", |req| {
+    ///     // `transfer-encoding: chunked` needs HTTP/1.1
+    ///     assert!(req.version_at_least(Version::Http11));
+    ///     assert!(req.version_at_least(Version::Http09));
+    /// });
+    /// ```
+    #[inline(always)]
+    pub const fn version_at_least(&self, version: Version) -> bool {
+        self.version as u8 >= version as u8
+    }
+
+    /// Returns the request line exactly as received, without the trailing `CRLF`
+    /// (e.g. `GET /path?q=1 HTTP/1.1`). Useful for audit logs and signatures.
+    ///
+    /// # Examples
+    /// ```
+    /// maker_web::docs_rs_helper::example_url_http1x("/users?id=7", |req| {
+    ///     assert_eq!(req.request_line(), b"GET /users?id=7 HTTP/1.1");
+    /// });
+    /// ```
+    #[inline(always)]
+    pub const fn request_line(&self) -> &[u8] {
+        self.request_line
+    }
+
     /// Returns the first header value with case-insensitive name matching
     /// (per [RFC 7230](https://tools.ietf.org/html/rfc7230#section-3.2)).
-    /// Uses linear search.
+    /// Uses linear search, except for [captured](ReqLimits::captured_headers) headers.
     #[inline(always)]
     pub fn header_str(&self, name: &str) -> Option<&str> {
-        self.headers
-            .iter()
-            .find(|h| h.name.eq_ignore_ascii_case(name))
-            .map(|h| h.value)
+        self.find_header(name.as_bytes())
     }
 
     /// Returns the first header value with case-insensitive name matching
     /// (per [RFC 7230](https://tools.ietf.org/html/rfc7230#section-3.2)).
-    /// Uses linear search.
+    /// Uses linear search, except for [captured](ReqLimits::captured_headers) headers.
     #[inline(always)]
     pub fn header(&self, name: &[u8]) -> Option<&[u8]> {
-        self.headers
-            .iter()
-            .find(|h| h.name.as_bytes().eq_ignore_ascii_case(name))
-            .map(|h| h.value.as_bytes())
+        self.find_header(name).map(str::as_bytes)
     }
 
     /// Returns the value of the `Content-Length` header if present.
@@ -231,6 +328,116 @@ impl Request {
         self.content_length
     }
 
+    /// Whether the client sent `Expect: 100-continue` and waits for
+    /// `100 Continue` before sending the body.
+    ///
+    /// The server sends it when the body is read, unless
+    /// [`Handler::expect_continue`](crate::Handler::expect_continue) refuses
+    /// the request from its head. Only `HTTP/1.1` requests expect it.
+    #[inline(always)]
+    pub const fn expects_continue(&self) -> bool {
+        self.expect_continue
+    }
+
+    /// Returns the value of the `Idempotency-Key` header if present: the client's
+    /// key for a request that may be retried without repeating its effect
+    /// (e.g. a payment). The server can deduplicate such requests with `IdempotencyStore`.
+    #[inline(always)]
+    pub fn idempotency_key(&self) -> Option<&[u8]> {
+        self.header(b"idempotency-key")
+    }
+
+    /// Returns the value of the first cookie named `name` from the `Cookie` header.
+    ///
+    /// Names are case-sensitive. Surrounding double quotes are removed from
+    /// the value, no other decoding is done. See [`cookies()`](Self::cookies).
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Request, Response, StatusCode};
+    ///
+    /// fn profile(req: &Request, resp: &mut Response) -> Handled {
+    ///     match req.cookie(b"session") {
+    ///         Some(session) => resp.status(StatusCode::Ok).body(session),
+    ///         None => resp.status(StatusCode::Unauthorized).body("Log in first"),
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn cookie(&self, name: &[u8]) -> Option<&[u8]> {
+        self.cookies().find(|&(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    /// Returns the `(name, value)` pairs of the `Cookie` header in order,
+    /// without allocating.
+    ///
+    /// Pairs are split on `;` with optional spaces around them, so `a=1;b=2`
+    /// works as well as `a=1; b=2`. A value may be empty (`a=`) or quoted
+    /// (`a="1"`, returned as `1`). Pairs without `=` or without a name are
+    /// skipped.
+    #[inline]
+    pub fn cookies(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let header = self.header(b"cookie").unwrap_or_default();
+
+        header.split(|&b| b == b';').filter_map(|pair| {
+            let eq = pair.iter().position(|&b| b == b'=')?;
+            let name = trim_spaces(&pair[..eq]);
+            let value = trim_spaces(&pair[eq + 1..]);
+            let value = match value {
+                [b'"', inner @ .., b'"'] => inner,
+                value => value,
+            };
+            (!name.is_empty()).then_some((name, value))
+        })
+    }
+
+    /// Returns the value of the `If-Range` header if present.
+    ///
+    /// Check it with [`IfRange::matches`] before answering a `Range` request
+    /// with `206 Partial Content`; if it doesn't match, send the whole
+    /// representation with `200 OK`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Request, Response, StatusCode};
+    ///
+    /// const ETAG: &[u8] = b"\"v2\"";
+    /// const DATA: &[u8] = b"0123456789";
+    ///
+    /// fn serve(req: &Request, resp: &mut Response) -> Handled {
+    ///     let partial = req.header(b"range") == Some(b"bytes=0-4")
+    ///         && req.if_range().map_or(true, |cond| cond.matches(Some(ETAG), None));
+    ///
+    ///     if partial {
+    ///         resp.status(StatusCode::PartialContent)
+    ///             .header("etag", ETAG)
+    ///             .content_range(0, 4, DATA.len())
+    ///             .body(&DATA[..5])
+    ///     } else {
+    ///         resp.status(StatusCode::Ok).header("etag", ETAG).body(DATA)
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn if_range(&self) -> Option<IfRange<'_>> {
+        self.header(b"if-range").map(IfRange::parse)
+    }
+
+    /// Returns `true` if the client reached the server over HTTPS, e.g. to
+    /// redirect plain HTTP or to set cookies with `Secure`.
+    ///
+    /// The server itself speaks plain HTTP only, so this is `true` only when
+    /// the client address is one of the [`trusted_proxies`](ReqLimits::trusted_proxies)
+    /// and its `X-Forwarded-Proto` is `https` (case-insensitive). The header is
+    /// ignored from any other client, which could set it freely.
+    #[inline]
+    pub fn is_secure(&self) -> bool {
+        self.is_trusted_proxy()
+            && self
+                .header(b"x-forwarded-proto")
+                .is_some_and(|proto| proto.eq_ignore_ascii_case(b"https"))
+    }
+
     /// Returns the keep-alive status of the connection.
     #[inline(always)]
     pub const fn is_keep_alive(&self) -> bool {
@@ -242,59 +449,189 @@ impl Request {
     pub const fn body(&self) -> Option<&[u8]> {
         self.body
     }
+
+    /// Returns the length of the request body, `0` if there is none.
+    #[inline(always)]
+    pub const fn body_len(&self) -> usize {
+        match self.body {
+            Some(body) => body.len(),
+            None => 0,
+        }
+    }
+
+    /// Returns the number of headers available from [`header()`](Self::header),
+    /// [captured](ReqLimits::captured_headers) ones included.
+    ///
+    /// [Stripped](ReqLimits::strip_headers) headers are not counted, nor
+    /// `Content-Length` and `Connection`, which are only read into
+    /// [`content_length()`](Self::content_length) and
+    /// [`is_keep_alive()`](Self::is_keep_alive).
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{limits::ReqLimits, parser::RequestParser};
+    ///
+    /// let mut parser = RequestParser::new(ReqLimits::default(), false);
+    /// let req = parser
+    ///     .parse(b"POST / HTTP/1.1\r\nhost: a\r\ncontent-length: 5\r\n\r\nhello")
+    ///     .unwrap();
+    ///
+    /// assert_eq!(req.header_count(), 1);
+    /// assert_eq!(req.body_len(), 5);
+    /// ```
+    #[inline(always)]
+    pub fn header_count(&self) -> usize {
+        self.headers.len() + self.captured_count
+    }
+
+    /// Parses an `application/x-www-form-urlencoded` body (an HTML form
+    /// submitted with `POST`) like a query string, see [`Query::parse`].
+    ///
+    /// At most [`url_query_parts`](ReqLimits::url_query_parts) parameters
+    /// are parsed. Keys and values are not percent-decoded.
+    ///
+    /// # Errors
+    /// - [`query::Error::NotForm`] - `content-type` is missing or different
+    /// - [`query::Error::Empty`] - there is no body
+    /// - [`query::Error::OverLimit`] - too many parameters
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{limits::ReqLimits, parser::RequestParser};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut parser = RequestParser::new(ReqLimits::default(), false);
+    /// let req = parser
+    ///     .parse(
+    ///         b"POST /login HTTP/1.1\r\n\
+    ///           host: example.com\r\n\
+    ///           content-type: application/x-www-form-urlencoded\r\n\
+    ///           content-length: 20\r\n\r\n\
+    ///           user=alice&remember=",
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let form: HashMap<&[u8], &[u8]> = req.body_form().unwrap();
+    /// assert_eq!(form[&b"user"[..]], b"alice");
+    /// assert_eq!(form[&b"remember"[..]], b"");
+    /// ```
+    #[inline]
+    pub fn body_form<'a, C: QueryCollector<'a>>(&'a self) -> Result<C, query::Error> {
+        const FORM: &[u8] = b"application/x-www-form-urlencoded";
+
+        // Parameters such as `; charset=utf-8` may follow the media type
+        let content_type = self.header(b"content-type").unwrap_or_default();
+        let is_form = content_type
+            .get(..FORM.len())
+            .is_some_and(|media_type| media_type.eq_ignore_ascii_case(FORM))
+            && matches!(content_type.get(FORM.len()), None | Some(b';' | b' '));
+        if !is_form {
+            return Err(query::Error::NotForm);
+        }
+
+        let body = self.body.ok_or(query::Error::Empty)?;
+        Query::parse(body, self.max_query_parts)
+    }
 }
 
-impl<H: Handler<S>, S: ConnectionData> HttpConnection<H, S> {
+impl Request {
+    #[inline(always)]
+    fn find_header(&self, name: &[u8]) -> Option<&'static str> {
+        if let Some(i) = self.captured_slots.slot(name) {
+            return self.captured[i];
+        }
+
+        self.headers
+            .iter()
+            .find(|h| h.name.as_bytes().eq_ignore_ascii_case(name))
+            .map(|h| h.value)
+    }
+
+    // The peer is only looked up for headers on the list
     #[inline]
-    pub(crate) fn parse_request(&mut self) -> Result<(), ErrorKind> {
-        let mut iter = memchr2_iter(
-            b'\n',
-            b':',
-            &self.parser.buffer[..self.req_limits.precalc.req_without_body],
-        );
+    fn is_stripped(&self, req_limits: &ReqLimits, name: &[u8]) -> bool {
+        req_limits.strip_headers.is_some_and(|strip| {
+            strip.iter().any(|n| n.eq_ignore_ascii_case(name)) && !self.is_trusted_proxy()
+        })
+    }
+
+    #[inline]
+    fn is_trusted_proxy(&self) -> bool {
+        self.trusted_proxies.contains(&self.client_addr.ip())
+    }
+}
+
+// Transport-independent: the caller fills the buffer and handles the result
+impl Parser {
+    #[inline]
+    pub(crate) fn parse_request(
+        &mut self,
+        request: &mut Request,
+        limits: &ReqLimits,
+        http_09: bool,
+    ) -> Result<(), ErrorKind> {
+        let mut at = 0;
+        let result = self.parse_request_at(request, limits, http_09, &mut at);
+        self.failed_at = result.is_err().then_some(at);
+        result
+    }
+
+    /// Where the last [`parse_request`](Self::parse_request) failed, `None` if it succeeded
+    #[inline]
+    pub(crate) fn error_context(&self) -> Option<ErrorContext> {
+        let at = self.failed_at?;
+        Some(ErrorContext::new(&self.buffer[..self.len], at))
+    }
+
+    // `at` follows the element being parsed: method, target, version,
+    // each header line, then the body
+    #[inline]
+    fn parse_request_at(
+        &mut self,
+        request: &mut Request,
+        limits: &ReqLimits,
+        http_09: bool,
+        at: &mut usize,
+    ) -> Result<(), ErrorKind> {
+        let end = limits.precalc.req_without_body.min(self.len);
+        let mut iter = memchr2_iter(b'\n', b':', &self.buffer[..end]);
 
         let end_first_line = self
-            .parser
-            .find_next_crlf(&mut iter, self.req_limits.precalc.first_line)
-            .filter(|i| *i <= self.req_limits.precalc.first_line)
+            .find_next_crlf(&mut iter, limits.precalc.first_line)
+            .filter(|i| *i <= limits.precalc.first_line)
             .ok_or(ErrorKind::InvalidVersion)?;
 
         // Parsing the first line
         {
             let first_line = self
-                .parser
                 .get_slice(0, end_first_line)
                 .ok_or(ErrorKind::InvalidVersion)?;
             let mut fl_iter = memchr3_iter(b' ', b'/', b'?', first_line);
 
-            let method_end = self.request.parse_method(&mut fl_iter, &self.parser)?;
-            let space_before_version = self.request.parse_url(
-                &mut fl_iter,
-                [method_end, end_first_line],
-                &self.parser,
-                &self.req_limits,
-            )?;
-            let (is_end, keep_alive) = self.request.parse_version(
-                &self.parser,
-                space_before_version,
-                end_first_line,
-                self.http_09_limits.is_some(),
-            )?;
-
-            self.request.keep_alive = keep_alive;
+            let method_end = request.parse_method(&mut fl_iter, self)?;
+            *at = method_end + 1;
+            let space_before_version =
+                request.parse_url(&mut fl_iter, [method_end, end_first_line], self, limits)?;
+            *at = space_before_version + 1;
+            let (is_end, keep_alive) =
+                request.parse_version(self, space_before_version, end_first_line, http_09)?;
+
+            request.keep_alive = keep_alive;
+            request.request_line = self
+                .get_slice_static(0, end_first_line - 1)
+                .ok_or(ErrorKind::InvalidVersion)?;
             if is_end {
+                self.consumed = self.len;
                 return Ok(());
             }
         }
 
         // Parsing headers
         let mut start_header_line = end_first_line + 1;
-        for _ in 0..=self.req_limits.header_count {
-            let Some(colon) = self.parser.find_next_byte(&mut iter, b':') else {
-                if self
-                    .parser
-                    .get_slice(start_header_line - 2, start_header_line + 2)
-                    == Some(b"\r\n\r\n")
+        for _ in 0..=limits.header_count {
+            *at = start_header_line;
+            let Some(colon) = self.find_next_byte(&mut iter, b':') else {
+                if self.get_slice(start_header_line - 2, start_header_line + 2) == Some(b"\r\n\r\n")
                 {
                     break;
                 }
@@ -302,29 +639,32 @@ impl<H: Handler<S>, S: ConnectionData> HttpConnection<H, S> {
                 return Err(ErrorKind::InvalidHeader);
             };
 
-            if self.request.headers.len() >= self.req_limits.header_count {
+            if request.header_count() >= limits.header_count {
                 return Err(ErrorKind::TooManyHeaders);
             }
 
             let crlf = iter
-                .find(|pos| self.parser.buffer[*pos] == b'\n')
-                .filter(|pos| self.parser.get_slice(pos - 1, pos + 1) == Some(b"\r\n"))
+                .find(|pos| self.buffer[*pos] == b'\n')
+                .filter(|pos| self.get_slice(pos - 1, pos + 1) == Some(b"\r\n"))
                 .ok_or(ErrorKind::InvalidHeader)?;
 
-            self.request.parse_header(
-                &self.parser,
-                &self.req_limits,
-                [start_header_line, colon, crlf],
-            )?;
+            request.parse_header(self, limits, [start_header_line, colon, crlf])?;
 
             start_header_line = crlf + 1;
         }
 
         let end_headers = start_header_line + 2;
+        *at = end_headers;
 
-        self.parser.check_utf8(end_headers)?;
+        if limits.require_host_http11 && request.version == Version::Http11 && !request.has_host {
+            return Err(ErrorKind::MissingHost);
+        }
+        if let Err(valid_up_to) = self.check_utf8(end_headers) {
+            *at = valid_up_to;
+            return Err(ErrorKind::InvalidEncoding);
+        }
 
-        self.request.process_body(&self.parser, end_headers)?;
+        self.consumed = request.process_body(self, end_headers, limits.body_on_get_head)?;
 
         Ok(())
     }
@@ -393,15 +733,19 @@ impl Request {
                         }
                     };
 
+                    end = end_query;
+                    if !limits.parse_query {
+                        break;
+                    }
+
                     let slice = parser
                         .get_str_static(current_slash, end_query)
                         .filter(|slice| slice.len() <= limits.url_query_size)
                         .ok_or(ErrorKind::InvalidUrl)?;
 
-                    let limit = self.url.query_parts.capacity();
+                    let limit = limits.url_query_parts;
                     Query::parse_into(&mut self.url.query_parts, slice.as_bytes(), limit)?;
 
-                    end = end_query;
                     self.url.query = Some(slice);
 
                     break;
@@ -429,6 +773,9 @@ impl Request {
             .get_str_static(start, end)
             .filter(|target| target.len() <= limits.url_size)
             .ok_or(ErrorKind::InvalidUrl)?;
+        if limits.strict_escapes && !url::has_valid_escapes(self.url.target.as_bytes()) {
+            return Err(ErrorKind::InvalidEscape);
+        }
         self.url.path = parser
             .get_str_static(start, current_slash)
             .ok_or(ErrorKind::InvalidUrl)?;
@@ -563,9 +910,50 @@ impl Request {
                 b't' | b'T',
                 b'h' | b'H'
             ] => self.parse_header_content_length(req_limits, value.as_bytes())?,
+            // No transfer coding is decoded, `chunked` included
+            #[rustfmt::skip]
+            [
+                b't' | b'T',
+                b'r' | b'R',
+                b'a' | b'A',
+                b'n' | b'N',
+                b's' | b'S',
+                b'f' | b'F',
+                b'e' | b'E',
+                b'r' | b'R',
+                b'-',
+                b'e' | b'E',
+                b'n' | b'N',
+                b'c' | b'C',
+                b'o' | b'O',
+                b'd' | b'D',
+                b'i' | b'I',
+                b'n' | b'N',
+                b'g' | b'G'
+            ] => return Err(ErrorKind::UnsupportedTransferEncoding),
+            _ if self.is_stripped(req_limits, name.as_bytes()) => {}
             _ => {
-                let header = Header { name, value };
-                self.headers.push(header);
+                if name.as_bytes().eq_ignore_ascii_case(b"host") {
+                    if req_limits.validate_authority && !is_valid_host(value.as_bytes()) {
+                        return Err(ErrorKind::InvalidHost);
+                    }
+                    self.has_host = true;
+                }
+                if self.version == Version::Http11
+                    && name.as_bytes().eq_ignore_ascii_case(b"expect")
+                    && value.as_bytes().eq_ignore_ascii_case(b"100-continue")
+                {
+                    self.expect_continue = true;
+                }
+
+                match self.captured_slots.slot(name.as_bytes()) {
+                    Some(i) => {
+                        // Keeps the first value, like the linear search does
+                        self.captured[i].get_or_insert(value);
+                        self.captured_count += 1;
+                    }
+                    None => self.headers.push(Header { name, value }),
+                }
             }
         }
 
@@ -618,32 +1006,94 @@ impl Request {
     }
 }
 
+// `Host` value by RFC 3986: `reg-name`, IPv4 or `[IPv6]`, then an optional `:port`.
+// An empty value is allowed (RFC 7230 §5.4)
+#[inline]
+fn is_valid_host(value: &[u8]) -> bool {
+    let (host, port) = match value {
+        [b'[', rest @ ..] => {
+            let Some(end) = rest.iter().position(|&b| b == b']') else {
+                return false;
+            };
+            let ip = &rest[..end];
+            let valid = !ip.is_empty()
+                && ip
+                    .iter()
+                    .all(|&b| b.is_ascii_hexdigit() || b == b':' || b == b'.');
+
+            match &rest[end + 1..] {
+                [] => return valid,
+                [b':', port @ ..] => (valid, port),
+                _ => return false,
+            }
+        }
+        _ => {
+            let (host, port) = match value.iter().rposition(|&b| b == b':') {
+                Some(colon) => (&value[..colon], &value[colon + 1..]),
+                None => (value, &b""[..]),
+            };
+            let valid = host
+                .iter()
+                .all(|&b| b.is_ascii_alphanumeric() || b"-._~%!$&'()*+,;=".contains(&b));
+
+            (valid && (!host.is_empty() || value.is_empty()), port)
+        }
+    };
+
+    host && port.iter().all(u8::is_ascii_digit)
+}
+
+// Spaces and tabs around a `Cookie` pair
+#[inline]
+fn trim_spaces(mut bytes: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = bytes {
+        bytes = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = bytes {
+        bytes = rest;
+    }
+    bytes
+}
+
 // Parse body
 impl Request {
+    // Returns the end of the request, anything after it belongs to the next
+    // pipelined request
     #[inline]
-    fn process_body(&mut self, parser: &Parser, start: usize) -> Result<(), ErrorKind> {
-        let body_len = parser.len - start;
+    fn process_body(
+        &mut self,
+        parser: &Parser,
+        start: usize,
+        policy: BodyPolicy,
+    ) -> Result<usize, ErrorKind> {
+        let available = parser.len - start;
+        let bodyless = matches!(
+            self.method,
+            Method::Get | Method::Head | Method::Delete | Method::Options
+        );
 
-        match (self.content_length, body_len) {
-            (Some(0), 0) => Ok(()),
-            (Some(len), available) if len == available => {
+        match self.content_length {
+            None | Some(0) => Ok(start),
+            Some(_) if bodyless && policy == BodyPolicy::Reject => Err(ErrorKind::BodyNotAllowed),
+            Some(len) if len <= available && bodyless && policy == BodyPolicy::Ignore => {
+                Ok(start + len)
+            }
+            Some(len) if len <= available => {
                 let slice =
                     parser
-                        .get_slice_static(start, parser.len)
+                        .get_slice_static(start, start + len)
                         .ok_or(ErrorKind::BodyMismatch {
                             expected: len,
                             available,
                         })?;
 
                 self.body = Some(slice);
-                Ok(())
+                Ok(start + len)
             }
-            (Some(len), available) => Err(ErrorKind::BodyMismatch {
+            Some(len) => Err(ErrorKind::BodyMismatch {
                 expected: len,
                 available,
             }),
-            (None, 0) => Ok(()),
-            (None, available) => Err(ErrorKind::UnexpectedBody(available)),
         }
     }
 }
@@ -654,15 +1104,31 @@ impl Request {
 #[repr(align(64))]
 pub(crate) struct Parser {
     len: usize,
+    // End of the last parsed request
+    consumed: usize,
+    // End of the bytes written since the buffer was last zeroed
+    dirty: usize,
+    // Offset `parse_request` failed at, for `ServerLimits::verbose_parse_errors`
+    failed_at: Option<usize>,
+    zero_on_reset: ZeroPolicy,
     buffer: Box<[u8]>,
 }
 
+// Buffer management for the server's connections
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
 impl Parser {
     #[inline(always)]
     pub(crate) fn new(limits: &ReqLimits) -> Self {
         let buffer = vec![0; limits.precalc.buffer].into_boxed_slice();
 
-        Parser { len: 0, buffer }
+        Parser {
+            len: 0,
+            consumed: 0,
+            dirty: 0,
+            failed_at: None,
+            zero_on_reset: limits.zero_on_reset,
+            buffer,
+        }
     }
 
     #[inline]
@@ -675,6 +1141,10 @@ impl Parser {
 
         Parser {
             len: value.len(),
+            consumed: 0,
+            dirty: value.len(),
+            failed_at: None,
+            zero_on_reset: limits.zero_on_reset,
             buffer: buffer.into_boxed_slice(),
         }
     }
@@ -683,36 +1153,147 @@ impl Parser {
     #[inline]
     pub(crate) fn reset(&mut self) {
         self.len = 0;
-        self.buffer.fill(0);
+        self.consumed = 0;
+        self.failed_at = None;
+
+        match self.zero_on_reset {
+            ZeroPolicy::None => return,
+            ZeroPolicy::UsedPrefix => self.buffer[..self.dirty].fill(0),
+            ZeroPolicy::Full => self.buffer.fill(0),
+        }
+        self.dirty = 0;
+    }
+
+    /// Moves the bytes after the last parsed request to the start of the buffer.
+    /// Returns `false` if there was nothing left and the buffer was reset.
+    #[inline]
+    pub(crate) fn advance(&mut self) -> bool {
+        let rest = self.len - self.consumed;
+        if rest == 0 {
+            self.reset();
+            return false;
+        }
+
+        self.buffer.copy_within(self.consumed..self.len, 0);
+        self.len = rest;
+        self.consumed = 0;
+
+        true
+    }
+
+    /// Replaces the contents with `data`, cut to the buffer size
+    #[inline]
+    pub(crate) fn load(&mut self, data: &[u8]) {
+        let len = data.len().min(self.buffer.len());
+        self.buffer[..len].copy_from_slice(&data[..len]);
+
+        self.len = len;
+        self.dirty = self.dirty.max(len);
+        self.consumed = 0;
+    }
+
+    #[inline(always)]
+    pub(crate) const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the buffered bytes may be the start of a valid request whose
+    /// head hasn't fully arrived: no request line yet, or an `HTTP/1.X`
+    /// request line without the blank line ending the head
+    #[cfg(feature = "server")]
+    #[inline]
+    pub(crate) fn head_incomplete(&self) -> bool {
+        let data = &self.buffer[..self.len];
+        if self.len == self.buffer.len() || memchr::memmem::find(data, b"\r\n\r\n").is_some() {
+            return false;
+        }
+
+        match memchr::memmem::find(data, b"\r\n") {
+            None => true,
+            Some(end) => matches!(
+                data[..end].rsplit(|b| *b == b' ').next(),
+                Some(b"HTTP/1.0" | b"HTTP/1.1")
+            ),
+        }
+    }
+
+    /// Whether bytes after the last parsed request are already buffered
+    #[cfg(feature = "server")]
+    #[inline(always)]
+    pub(crate) const fn has_pending(&self) -> bool {
+        self.consumed != 0 && self.consumed < self.len
+    }
+
+    /// The last parsed request, or everything read if parsing did not finish
+    #[inline]
+    pub(crate) fn request_bytes(&self) -> &[u8] {
+        match self.consumed {
+            0 => &self.buffer[..self.len],
+            end => &self.buffer[..end],
+        }
     }
 }
 
 // Work with Buffer
 impl Parser {
     // High level
+    #[cfg(feature = "server")]
+    #[inline]
+    pub(crate) async fn fill_buffer<T: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut T,
+        time: Duration,
+    ) -> Result<usize, ErrorKind> {
+        self.read_at(stream, 0, time).await
+    }
+
+    /// Appends to the buffered bytes, for a body that arrives over several reads.
+    /// Returns `0` if the buffer is full; EOF is an `UnexpectedEof` error.
+    #[cfg(feature = "server")]
+    #[inline]
+    pub(crate) async fn fill_more<T: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut T,
+        time: Duration,
+    ) -> Result<usize, ErrorKind> {
+        if self.len == self.buffer.len() {
+            return Ok(0);
+        }
+        match self.read_at(stream, self.len, time).await? {
+            0 => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            n => Ok(n),
+        }
+    }
+
+    #[cfg(feature = "server")]
     #[inline]
-    pub(crate) async fn fill_buffer(
+    async fn read_at<T: AsyncRead + Unpin>(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut T,
+        start: usize,
         time: Duration,
-    ) -> Result<usize, io::Error> {
+    ) -> Result<usize, ErrorKind> {
         tokio::select! {
-            read_result = stream.read(&mut self.buffer) => {
+            read_result = stream.read(&mut self.buffer[start..]) => {
                 let n = read_result?;
-                self.len = n;
+                self.len = start + n;
+                self.dirty = self.dirty.max(self.len);
                 Ok(n)
             }
-            _ = sleep(time) => {
-                Err(io::Error::new(io::ErrorKind::TimedOut, "read timeout"))
-            },
+            _ = sleep(time) => Err(ErrorKind::ReadTimeout),
         }
     }
 
     #[inline]
-    fn check_utf8(&self, end: usize) -> Result<(), ErrorKind> {
-        simdutf8::basic::from_utf8(&self.buffer[..end])
-            .map(|_| ())
-            .map_err(|_| ErrorKind::InvalidEncoding)
+    // The error is the offset of the first invalid byte, only located on failure
+    fn check_utf8(&self, end: usize) -> Result<(), usize> {
+        let head = &self.buffer[..end];
+        match simdutf8::basic::from_utf8(head) {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                Err(simdutf8::compat::from_utf8(head).map_or_else(|e| e.valid_up_to(), |_| 0))
+            }
+        }
     }
 
     // Search level
@@ -733,14 +1314,15 @@ impl Parser {
     }
 
     // Low level
+    // Everything after `len` is left over from earlier reads
     #[inline(always)]
     fn get_slice(&self, start: usize, end: usize) -> Option<&[u8]> {
-        self.buffer.get(start..end)
+        self.buffer[..self.len].get(start..end)
     }
 
     #[inline(always)]
     fn get_byte(&self, index: usize) -> Option<u8> {
-        self.buffer.get(index).copied()
+        self.buffer[..self.len].get(index).copied()
     }
 
     // Unsafe level
@@ -752,7 +1334,7 @@ impl Parser {
     // see `Parser::into_static`.
     // DO NOT SUGGEST FIXES without full server architecture context.
     fn get_str_static(&self, start: usize, end: usize) -> Option<&'static str> {
-        let value = self.buffer.get(start..end)?;
+        let value = self.buffer[..self.len].get(start..end)?;
         unsafe {
             let value_str = str::from_utf8_unchecked(value);
             Some(Self::into_static(value_str))
@@ -761,7 +1343,7 @@ impl Parser {
 
     #[inline(always)]
     fn get_slice_static(&self, start: usize, end: usize) -> Option<&'static [u8]> {
-        let value = self.buffer.get(start..end)?;
+        let value = self.buffer[..self.len].get(start..end)?;
         unsafe { Some(Parser::into_static(value)) }
     }
 
@@ -781,18 +1363,74 @@ impl Parser {
 mod request_self {
     use super::*;
     use crate::limits::Http09Limits;
+    use crate::tools::str_op;
+    use crate::StatusCode;
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // The parser and the request of a connection, without the server
+    struct Fixture {
+        parser: Parser,
+        request: Request,
+        req_limits: ReqLimits,
+        http_09_limits: Option<Http09Limits>,
+    }
+
+    impl Fixture {
+        fn from_req<V: AsRef<[u8]>>(value: V) -> Self {
+            Self::from_req_with(value, ReqLimits::bare())
+        }
+
+        fn from_req_with<V: AsRef<[u8]>>(value: V, req_limits: ReqLimits) -> Self {
+            let req_limits = req_limits.precalculate();
+            Self {
+                parser: Parser::from(&req_limits, value),
+                request: Request::new(&req_limits),
+                req_limits,
+                http_09_limits: None,
+            }
+        }
+
+        fn parse_request(&mut self) -> Result<(), ErrorKind> {
+            let http_09 = self.http_09_limits.is_some();
+            (self.parser).parse_request(&mut self.request, &self.req_limits, http_09)
+        }
+    }
 
     #[test]
     fn reset() {
-        let limits = ReqLimits::default();
-        let mut t =
-            HttpConnection::from_req("OPTIONS /qwe&q=1 HTTP/1.0\r\nHost: 127.0.0.1\r\n\r\n");
+        let limits = ReqLimits::bare();
+        let mut t = Fixture::from_req("OPTIONS /qwe&q=1 HTTP/1.0\r\nHost: 127.0.0.1\r\n\r\n");
 
         assert_eq!(t.parse_request(), Ok(()));
         t.request.reset();
         assert_eq!(Request::new(&limits), t.request);
     }
 
+    #[test]
+    fn request_line() {
+        let cases = [
+            "GET / HTTP/1.1\r\n\r\n",
+            "OPTIONS /qwe&q=1 HTTP/1.0\r\nHost: 127.0.0.1\r\n\r\n",
+            "POST /a/%7Eb?x=1&y HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi",
+            "GET /keep_alive/api\r\n",
+        ];
+
+        for req in cases {
+            let mut t = Fixture::from_req(req);
+            t.http_09_limits = Some(Http09Limits::default());
+
+            assert_eq!(t.parse_request(), Ok(()));
+            let line = req.split("\r\n").next().unwrap();
+            assert_eq!(str_op(t.request.request_line()), line);
+        }
+
+        let mut t = Fixture::from_req("GET //x HTTP/1.1\r\n\r\n");
+        assert!(t.parse_request().is_err());
+        assert_eq!(t.request.request_line(), b"");
+    }
+
     #[test]
     fn parse_method() {
         #[rustfmt::skip]
@@ -812,7 +1450,7 @@ mod request_self {
         ];
 
         for (method, expected) in cases {
-            let mut t = HttpConnection::from_req(method);
+            let mut t = Fixture::from_req(method);
             t.http_09_limits = Some(Http09Limits::default());
 
             if let Some(expected) = expected {
@@ -881,7 +1519,7 @@ mod request_self {
             ];
 
             for i in &http_any {
-                let mut t = HttpConnection::from_req(i);
+                let mut t = Fixture::from_req(i);
                 t.http_09_limits = Some(Http09Limits::default());
 
                 if let Ok((url, query)) = &expected {
@@ -912,7 +1550,7 @@ mod request_self {
         ];
 
         for data in cases {
-            let mut t = HttpConnection::from_req(data);
+            let mut t = Fixture::from_req(data);
             t.http_09_limits = Some(Http09Limits::default());
 
             let segments = &["api", "users", "123"];
@@ -945,6 +1583,318 @@ mod request_self {
         }
     }
 
+    #[test]
+    fn body_on_get_head() {
+        use crate::limits::BodyPolicy;
+
+        const GET: &str = "GET / HTTP/1.1\r\ncontent-length: 4\r\n\r\nbodyGET / HTTP/1.1\r\n\r\n";
+        const POST: &str = "POST / HTTP/1.1\r\ncontent-length: 4\r\n\r\nbody";
+        const DELETE: &str = "DELETE / HTTP/1.1\r\ncontent-length: 4\r\n\r\nbody";
+        const EMPTY: &str = "GET / HTTP/1.1\r\ncontent-length: 0\r\n\r\n";
+
+        let cases = [
+            (BodyPolicy::Allow, GET, Ok(Some(&b"body"[..]))),
+            (BodyPolicy::Allow, POST, Ok(Some(b"body"))),
+            (BodyPolicy::Reject, GET, Err(ErrorKind::BodyNotAllowed)),
+            (BodyPolicy::Reject, DELETE, Err(ErrorKind::BodyNotAllowed)),
+            (BodyPolicy::Reject, POST, Ok(Some(b"body"))),
+            (BodyPolicy::Reject, EMPTY, Ok(None)),
+            (BodyPolicy::Ignore, GET, Ok(None)),
+            (BodyPolicy::Ignore, DELETE, Ok(None)),
+            (BodyPolicy::Ignore, POST, Ok(Some(b"body"))),
+        ];
+
+        for (policy, request, expected) in cases {
+            let limits = ReqLimits {
+                body_on_get_head: policy,
+                ..ReqLimits::bare()
+            };
+            let mut t = Fixture::from_req_with(request, limits);
+
+            let result = t.parse_request().map(|_| t.request.body());
+            assert_eq!(result, expected, "{policy:?} {request:?}");
+        }
+
+        // An ignored body is still consumed, the next request starts after it
+        let limits = ReqLimits {
+            body_on_get_head: BodyPolicy::Ignore,
+            ..ReqLimits::bare()
+        };
+        let mut t = Fixture::from_req_with(GET, limits);
+        t.parse_request().unwrap();
+        assert!(t.parser.advance());
+        t.request.reset();
+        t.parse_request().unwrap();
+        assert_eq!(t.request.content_length(), None);
+    }
+
+    #[test]
+    fn version_at_least() {
+        let versions = [Version::Http09, Version::Http10, Version::Http11];
+        let requests = [
+            "GET /x\r\n",
+            "GET / HTTP/1.0\r\n\r\n",
+            "GET / HTTP/1.1\r\n\r\n",
+        ];
+
+        for (i, request) in requests.iter().enumerate() {
+            let mut t = Fixture::from_req(request);
+            t.http_09_limits = Some(Http09Limits::default());
+            t.parse_request().unwrap();
+
+            for (j, version) in versions.iter().enumerate() {
+                assert_eq!(
+                    t.request.version_at_least(*version),
+                    i >= j,
+                    "{request:?} {version}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn require_host_http11() {
+        let cases = [
+            ("GET / HTTP/1.1\r\n\r\n", Err(ErrorKind::MissingHost)),
+            ("GET / HTTP/1.1\r\nHost: a\r\n\r\n", Ok(())),
+            ("GET / HTTP/1.1\r\nhost: \r\n\r\n", Ok(())),
+            ("GET / HTTP/1.0\r\n\r\n", Ok(())),
+        ];
+        for (req, expected) in cases {
+            let mut t = Fixture::from_req_with(req, ReqLimits::default());
+            assert_eq!(t.parse_request(), expected, "{req:?}");
+
+            let mut t = Fixture::from_req_with(req, ReqLimits::bare());
+            assert_eq!(t.parse_request(), Ok(()), "{req:?}");
+        }
+    }
+
+    #[test]
+    fn is_secure() {
+        const PROXIES: &[IpAddr] = &[IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))];
+        let proxy = SocketAddr::from(([10, 0, 0, 1], 4000));
+        let client = SocketAddr::from(([203, 0, 113, 7], 4000));
+
+        let cases = [
+            (proxy, "X-Forwarded-Proto: https\r\n", true),
+            (proxy, "X-Forwarded-Proto: HTTPS\r\n", true),
+            (proxy, "X-Forwarded-Proto: http\r\n", false),
+            (proxy, "", false),
+            // Plaintext connections and spoofed headers
+            (client, "", false),
+            (client, "X-Forwarded-Proto: https\r\n", false),
+        ];
+        for (client_addr, header, expected) in cases {
+            let mut t = Fixture::from_req_with(
+                format!("GET / HTTP/1.1\r\n{header}\r\n"),
+                ReqLimits {
+                    trusted_proxies: PROXIES,
+                    ..ReqLimits::bare()
+                },
+            );
+            t.request.client_addr = client_addr;
+            t.parse_request().unwrap();
+            assert_eq!(t.request.is_secure(), expected, "{client_addr} {header:?}");
+        }
+    }
+
+    #[test]
+    fn if_range() {
+        const ETAG: &[u8] = b"\"v2\"";
+        let modified = UNIX_EPOCH + Duration::from_secs(784111777);
+
+        let status = |if_range: &str| {
+            let mut t = Fixture::from_req(format!(
+                "GET /file HTTP/1.1\r\nRange: bytes=0-4\r\n{if_range}\r\n"
+            ));
+            t.parse_request().unwrap();
+
+            let partial = (t.request.if_range())
+                .map_or(true, |cond| cond.matches(Some(ETAG), Some(modified)));
+            if partial {
+                StatusCode::PartialContent
+            } else {
+                StatusCode::Ok
+            }
+        };
+
+        let cases = [
+            ("", StatusCode::PartialContent),
+            ("If-Range: \"v2\"\r\n", StatusCode::PartialContent),
+            (
+                "If-Range: Sun, 06 Nov 1994 08:49:37 GMT\r\n",
+                StatusCode::PartialContent,
+            ),
+            // Stale validators
+            ("If-Range: \"v1\"\r\n", StatusCode::Ok),
+            (
+                "If-Range: Sun, 06 Nov 1994 08:49:36 GMT\r\n",
+                StatusCode::Ok,
+            ),
+            // Weak tags and unknown values never match
+            ("If-Range: W/\"v2\"\r\n", StatusCode::Ok),
+            ("If-Range: v2\r\n", StatusCode::Ok),
+        ];
+        for (if_range, expected) in cases {
+            assert_eq!(status(if_range), expected, "{if_range:?}");
+        }
+
+        let mut t =
+            Fixture::from_req("GET / HTTP/1.1\r\nIf-Range: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n");
+        t.parse_request().unwrap();
+        assert_eq!(t.request.if_range(), Some(IfRange::Date(modified)));
+    }
+
+    #[test]
+    fn query_int() {
+        let mut t = Fixture::from_req(
+            "GET /items?page=2&limit=20&offset=-5&sort=name&empty=&big=300 HTTP/1.1\r\n\r\n",
+        );
+        t.parse_request().unwrap();
+        let url = t.request.url();
+
+        assert_eq!(url.query_int::<u32>(b"page"), Some(Ok(2)));
+        assert_eq!(url.query_int::<usize>(b"limit"), Some(Ok(20)));
+        assert_eq!(url.query_int::<i32>(b"offset"), Some(Ok(-5)));
+        assert_eq!(url.query_int::<i16>(b"big"), Some(Ok(300)));
+
+        assert_eq!(url.query_int::<u32>(b"offset"), Some(Err(())));
+        assert_eq!(url.query_int::<i32>(b"sort"), Some(Err(())));
+        assert_eq!(url.query_int::<u32>(b"empty"), Some(Err(())));
+        assert_eq!(url.query_int::<u8>(b"big"), Some(Err(())));
+        assert_eq!(url.query_int::<u32>(b"missing"), None);
+    }
+
+    #[test]
+    fn query_all() {
+        let mut t = Fixture::from_req("GET /?a=1&b=x&a=2&A=4&a=3 HTTP/1.1\r\n\r\n");
+        t.parse_request().unwrap();
+        let url = t.request.url();
+
+        let values: Vec<&str> = url.query_all(b"a").map(str_op).collect();
+        assert_eq!(values, ["1", "2", "3"]);
+        assert_eq!(url.query_all(b"b").count(), 1);
+        assert_eq!(url.query_all(b"c").count(), 0);
+    }
+
+    #[test]
+    fn cookies() {
+        let mut t = Fixture::from_req(
+            "GET / HTTP/1.1\r\n\
+             Cookie: session=abc; theme=\"dark mode\";lang=en; empty=;flag; =x ;session=second\r\n\r\n",
+        );
+        t.parse_request().unwrap();
+        let req = &t.request;
+
+        let pairs: Vec<(&str, &str)> = req.cookies().map(|(n, v)| (str_op(n), str_op(v))).collect();
+        assert_eq!(
+            pairs,
+            [
+                ("session", "abc"),
+                ("theme", "dark mode"),
+                ("lang", "en"),
+                ("empty", ""),
+                ("session", "second"),
+            ]
+        );
+
+        // The first of duplicate names wins
+        assert_eq!(req.cookie(b"session"), Some(b"abc" as &[u8]));
+        assert_eq!(req.cookie(b"theme"), Some(b"dark mode" as &[u8]));
+        assert_eq!(req.cookie(b"empty"), Some(b"" as &[u8]));
+        assert_eq!(req.cookie(b"flag"), None);
+        assert_eq!(req.cookie(b"Lang"), None);
+
+        let mut t = Fixture::from_req("GET / HTTP/1.1\r\nX-Cookie: a=1\r\n\r\n");
+        t.parse_request().unwrap();
+        assert_eq!(t.request.cookies().count(), 0);
+        assert_eq!(t.request.cookie(b"a"), None);
+    }
+
+    #[test]
+    fn decoded_url() {
+        let mut t = Fixture::from_req(
+            "GET /api/users/John%20Doe/%G1?name=a%2Bb+c&raw=x&cut=%2 HTTP/1.1\r\n\r\n",
+        );
+        t.parse_request().unwrap();
+        let url = t.request.url();
+
+        assert_eq!(url.path_segment(2), Some(b"John%20Doe" as &[u8]));
+        let mut scratch = Vec::with_capacity(64);
+        let buffer = scratch.as_ptr();
+        let mut decoded = |index| {
+            url.path_segment_decoded(index, &mut scratch)
+                .map(|v| str_op(v).to_owned())
+        };
+        assert_eq!(decoded(2).as_deref(), Some("John Doe"));
+        assert_eq!(decoded(3).as_deref(), Some("%G1"));
+        assert_eq!(decoded(4).as_deref(), None);
+
+        let mut decoded = |key, plus| {
+            url.query_decoded(key, plus, &mut scratch)
+                .map(|v| str_op(v).to_owned())
+        };
+        assert_eq!(decoded(b"name", true).as_deref(), Some("a+b c"));
+        assert_eq!(decoded(b"name", false).as_deref(), Some("a+b+c"));
+        assert_eq!(decoded(b"raw", true).as_deref(), Some("x"));
+        assert_eq!(decoded(b"cut", true).as_deref(), Some("%2"));
+        assert_eq!(decoded(b"missing", true).as_deref(), None);
+        // Reused, not reallocated
+        assert_eq!(scratch.as_ptr(), buffer);
+
+        let strict = ReqLimits {
+            strict_escapes: true,
+            ..ReqLimits::bare()
+        };
+        for (target, result) in [
+            ("/John%20Doe?name=a%2Bb+c", Ok(())),
+            ("/users/%G1", Err(ErrorKind::InvalidEscape)),
+            ("/users?cut=%2", Err(ErrorKind::InvalidEscape)),
+            ("/users?q=%", Err(ErrorKind::InvalidEscape)),
+        ] {
+            let req = format!("GET {target} HTTP/1.1\r\n\r\n");
+            let mut t = Fixture::from_req_with(req, strict.clone());
+            assert_eq!(t.parse_request(), result, "{target}");
+        }
+    }
+
+    #[test]
+    fn parse_query_disabled() {
+        let limits = ReqLimits {
+            parse_query: false,
+            ..ReqLimits::bare()
+        };
+        // Over `url_query_parts` and `url_query_size`, ignored without parsing
+        let query = format!("?{}", "x=1&".repeat(40));
+
+        for data in [
+            format!("GET /api/users/123{query} HTTP/1.1\r\n\r\n"),
+            format!("GET /keep_alive/api/users/123{query}\r\n"),
+        ] {
+            let mut t = Fixture::from_req_with(&data, limits.clone());
+            t.http_09_limits = Some(Http09Limits::default());
+            assert_eq!(t.parse_request(), Ok(()));
+
+            let url = t.request.url();
+            assert!(url
+                .target_str()
+                .ends_with(&format!("/api/users/123{query}")));
+            assert_eq!(url.path_str(), "/api/users/123");
+            assert_eq!(url.path_segments_str(), ["api", "users", "123"]);
+            assert_eq!(url.query_full(), None);
+            assert_eq!(url.query(b"x"), None);
+
+            // The same URL is rejected with parsing
+            let mut t = Fixture::from_req(&data);
+            t.http_09_limits = Some(Http09Limits::default());
+            assert!(t.parse_request().is_err());
+        }
+
+        // The rest of the URL is still checked
+        let mut t = Fixture::from_req_with("GET //a?b HTTP/1.1\r\n\r\n", limits);
+        assert_eq!(t.parse_request(), Err(ErrorKind::DoubleSlash));
+    }
+
     #[test]
     fn parse_version() {
         #[rustfmt::skip]
@@ -970,7 +1920,7 @@ mod request_self {
         ];
 
         for (value, expected) in cases {
-            let mut t = HttpConnection::from_req(value);
+            let mut t = Fixture::from_req(value);
             t.http_09_limits = Some(Http09Limits::default());
 
             if let Ok(version) = expected {
@@ -1010,7 +1960,7 @@ mod request_self {
         ];
 
         for (header, expected) in cases {
-            let mut t = HttpConnection::from_req(format!("GET / HTTP/1.1\r\n{header}\r\n"));
+            let mut t = Fixture::from_req(format!("GET / HTTP/1.1\r\n{header}\r\n"));
 
             if let Some((name, value)) = expected {
                 assert_eq!(t.parse_request(), Ok(()));
@@ -1024,6 +1974,159 @@ mod request_self {
 
     #[test]
     fn parse_headers() {
+        check_headers(ReqLimits::bare());
+    }
+
+    #[test]
+    fn parse_captured_headers() {
+        check_headers(captured_limits());
+
+        // Captured headers count towards `header_count`
+        let limits = ReqLimits {
+            header_count: 2,
+            ..captured_limits()
+        };
+        let mut t = Fixture::from_req_with(
+            "GET / HTTP/1.1\r\nHost: a\r\nQwE: b\r\n\r\n",
+            limits.clone(),
+        );
+        assert_eq!(t.parse_request(), Ok(()));
+        assert!(t.request.headers.is_empty());
+        assert_eq!(t.request.header(b"HOST"), Some(b"a" as &[u8]));
+        assert_eq!(t.request.header_str("empty-value"), None);
+
+        let mut t = Fixture::from_req_with(
+            "GET / HTTP/1.1\r\nHost: a\r\nQwE: b\r\nAsd: c\r\n\r\n",
+            limits,
+        );
+        assert_eq!(t.parse_request(), Err(ErrorKind::TooManyHeaders));
+    }
+
+    #[test]
+    fn captured_slots() {
+        // Same lengths and edge bytes, so most of them share buckets
+        let names: Vec<&'static [u8]> = (0..40)
+            .map(|i| &*Box::leak(format!("x-{i:02}-h").into_bytes().into_boxed_slice()))
+            .chain([b"Host" as &[u8], b"host"])
+            .collect();
+        let names: &'static [&'static [u8]] = Box::leak(names.into_boxed_slice());
+        let limits = ReqLimits {
+            captured_headers: Some(names),
+            ..ReqLimits::bare()
+        }
+        .precalculate();
+        let slots = &limits.precalc.captured;
+
+        for (i, name) in names[..40].iter().enumerate() {
+            assert_eq!(slots.slot(name), Some(i));
+            assert_eq!(slots.slot(&name.to_ascii_uppercase()), Some(i));
+        }
+        // A repeated name keeps its first slot
+        assert_eq!(slots.slot(b"HOST"), Some(40));
+        for name in [b"x-40-h" as &[u8], b"x-00-", b"", b"h"] {
+            assert_eq!(slots.slot(name), None);
+        }
+    }
+
+    #[test]
+    fn strip_headers() {
+        const PROXIES: &[IpAddr] = &[IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))];
+
+        let limits = ReqLimits {
+            header_count: 2,
+            strip_headers: Some(&[b"x-internal-auth"]),
+            trusted_proxies: PROXIES,
+            ..captured_limits()
+        };
+        let req =
+            "GET / HTTP/1.1\r\nHost: a\r\nX-Internal-Auth: admin\r\nx-internal-auth: b\r\n\r\n";
+
+        let untrusted = [
+            Request::UNKNOWN_CLIENT,
+            SocketAddr::from(([10, 0, 0, 2], 4000)),
+        ];
+        for client_addr in untrusted {
+            let mut t = Fixture::from_req_with(req, limits.clone());
+            t.request.client_addr = client_addr;
+            assert_eq!(t.parse_request(), Ok(()));
+            assert_eq!(t.request.header_str("x-internal-auth"), None);
+            assert_eq!(t.request.header_str("host"), Some("a"));
+        }
+
+        // Stripped headers don't count towards `header_count`, kept ones do
+        let mut t = Fixture::from_req_with(req, limits);
+        t.request.client_addr = SocketAddr::from(([10, 0, 0, 1], 4000));
+        assert_eq!(t.parse_request(), Err(ErrorKind::TooManyHeaders));
+
+        let mut t = Fixture::from_req_with(
+            "GET / HTTP/1.1\r\nX-Internal-Auth: admin\r\n\r\n",
+            ReqLimits {
+                strip_headers: Some(&[b"x-internal-auth"]),
+                trusted_proxies: PROXIES,
+                ..ReqLimits::bare()
+            },
+        );
+        t.request.client_addr = SocketAddr::from(([10, 0, 0, 1], 4000));
+        assert_eq!(t.parse_request(), Ok(()));
+        assert_eq!(t.request.header_str("x-internal-auth"), Some("admin"));
+    }
+
+    #[test]
+    fn header_count_and_body_len() {
+        let cases = [
+            (ReqLimits::bare(), "GET / HTTP/1.1\r\n\r\n", 0, 0),
+            (
+                ReqLimits::bare(),
+                "GET / HTTP/1.1\r\nHost: a\r\nx-a: 1\r\nx-b: 2\r\n\r\n",
+                3,
+                0,
+            ),
+            (
+                ReqLimits::bare(),
+                // Only read into `content_length` and `is_keep_alive`
+                "POST / HTTP/1.1\r\nx-a: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                1,
+                0,
+            ),
+            (
+                ReqLimits::bare(),
+                "POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world",
+                0,
+                11,
+            ),
+            (
+                captured_limits(),
+                "PUT / HTTP/1.1\r\nHost: a\r\nQwE: b\r\nx-c: d\r\nContent-Length: 3\r\n\r\nabc",
+                3,
+                3,
+            ),
+            (
+                ReqLimits {
+                    strip_headers: Some(&[b"x-internal-auth"]),
+                    ..ReqLimits::bare()
+                },
+                "GET / HTTP/1.1\r\nHost: a\r\nX-Internal-Auth: admin\r\n\r\n",
+                1,
+                0,
+            ),
+        ];
+
+        for (limits, req, headers, body) in cases {
+            let mut t = Fixture::from_req_with(req, limits);
+            assert_eq!(t.parse_request(), Ok(()), "{req:?}");
+            assert_eq!(t.request.header_count(), headers, "{req:?}");
+            assert_eq!(t.request.body_len(), body, "{req:?}");
+        }
+    }
+
+    fn captured_limits() -> ReqLimits {
+        ReqLimits {
+            captured_headers: Some(&[b"header", b"QWE", b"multi", b"empty-value", b"host"]),
+            ..ReqLimits::bare()
+        }
+    }
+
+    fn check_headers(limits: ReqLimits) {
         #[rustfmt::skip]
         let cases = [
             (
@@ -1058,7 +2161,8 @@ mod request_self {
         ];
 
         for (headers, expected) in cases {
-            let mut t = HttpConnection::from_req(format!("GET / HTTP/1.1\r\n{headers}"));
+            let mut t =
+                Fixture::from_req_with(format!("GET / HTTP/1.1\r\n{headers}"), limits.clone());
 
             if let Some((names, value)) = expected {
                 assert_eq!(t.parse_request(), Ok(()));
@@ -1101,7 +2205,7 @@ mod request_self {
         ];
 
         for (headers, result) in cases {
-            let mut t = HttpConnection::from_req(format!("GET / HTTP/1.1\r\n{headers}"));
+            let mut t = Fixture::from_req(format!("GET / HTTP/1.1\r\n{headers}"));
 
             if let Ok((content_length, keep_alive)) = result {
                 assert_eq!(t.parse_request(), Ok(()));
@@ -1119,10 +2223,126 @@ mod request_self {
         }
     }
 
+    #[test]
+    fn validate_authority() {
+        #[rustfmt::skip]
+        let cases = [
+            ("example.com", true),
+            ("Example.COM:8080", true),
+            ("127.0.0.1:80", true),
+            ("[::1]", true),
+            ("[2001:db8::7]:443", true),
+            ("my_host.local:", true),
+            ("", true),
+
+            ("example.com:80a", false),
+            ("example.com:80:80", false),
+            (":80", false),
+            ("exa mple.com", false),
+            ("example.com/path", false),
+            ("user@example.com", false),
+            ("[::1", false),
+            ("[]:80", false),
+            ("[::1]80", false),
+            ("[fe80::1%eth0]", false),
+        ];
+
+        let limits = ReqLimits {
+            validate_authority: true,
+            ..ReqLimits::bare()
+        };
+        for (host, valid) in cases {
+            let req = format!("GET / HTTP/1.1\r\nHoSt: {host}\r\n\r\n");
+
+            let mut t = Fixture::from_req_with(&req, limits.clone());
+            match valid {
+                true => assert_eq!(t.parse_request(), Ok(()), "{host}"),
+                false => assert_eq!(t.parse_request(), Err(ErrorKind::InvalidHost), "{host}"),
+            }
+
+            // Also for captured headers, and never without the limit
+            let mut t = Fixture::from_req_with(
+                &req,
+                ReqLimits {
+                    validate_authority: true,
+                    ..captured_limits()
+                },
+            );
+            assert_eq!(t.parse_request().is_ok(), valid, "{host}");
+            let mut t = Fixture::from_req(&req);
+            assert_eq!(t.parse_request(), Ok(()), "{host}");
+        }
+    }
+
+    #[test]
+    fn body_form() {
+        let parse = |content_type: &str, body: &str| {
+            let mut t = Fixture::from_req(format!(
+                "POST / HTTP/1.1\r\n{content_type}content-length: {}\r\n\r\n{body}",
+                body.len()
+            ));
+            t.parse_request().unwrap();
+            t
+        };
+        const FORM: &str = "Content-Type: application/x-www-form-urlencoded\r\n";
+
+        let t = parse(FORM, "a=1&b=2");
+        let form: Vec<(&[u8], &[u8])> = t.request.body_form().unwrap();
+        assert_eq!(form, [(&b"a"[..], &b"1"[..]), (b"b", b"2")]);
+
+        let t = parse(
+            "content-type: Application/X-WWW-Form-Urlencoded; charset=utf-8\r\n",
+            "a=1&b=2&a=3",
+        );
+        let form: HashMap<&[u8], &[u8]> = t.request.body_form().unwrap();
+        assert_eq!(form.len(), 2);
+        assert_eq!(form[&b"b"[..]], b"2");
+
+        // Limited by `url_query_parts`
+        let t = parse(FORM, &"x=1&".repeat(9));
+        let form: Result<Vec<_>, _> = t.request.body_form();
+        assert_eq!(form, Err(query::Error::OverLimit(8)));
+
+        let limits = ReqLimits {
+            url_query_parts: 2,
+            ..ReqLimits::bare()
+        };
+        let body = "x=1&y=2&z=3";
+        let mut t = Fixture::from_req_with(
+            format!("POST / HTTP/1.1\r\n{FORM}content-length: 11\r\n\r\n{body}"),
+            limits,
+        );
+        t.parse_request().unwrap();
+        let form: Result<Vec<_>, _> = t.request.body_form();
+        assert_eq!(form, Err(query::Error::OverLimit(2)));
+
+        for (content_type, body, error) in [
+            (FORM, "", query::Error::Empty),
+            ("", "a=1", query::Error::NotForm),
+            (
+                "content-type: application/json\r\n",
+                "a=1",
+                query::Error::NotForm,
+            ),
+            (
+                "content-type: application/x-www-form-urlencodedx\r\n",
+                "a=1",
+                query::Error::NotForm,
+            ),
+        ] {
+            let t = parse(content_type, body);
+            let form: Result<Vec<_>, _> = t.request.body_form();
+            assert_eq!(form, Err(error), "{content_type:?} {body:?}");
+        }
+    }
+
     macro_rules! parse_request {
         ($cases:expr) => {
+            parse_request! { $cases, ReqLimits::bare() }
+        };
+        ($cases:expr, $limits:expr) => {
             for (req, result) in $cases {
-                let mut t = HttpConnection::from_req(req);
+                let mut t = Fixture::from_req_with(req, $limits);
 
                 if let Ok(result) = result {
                     assert_eq!(t.parse_request(), Ok(()));
@@ -1250,7 +2470,8 @@ Content-Length: 17\r\n\r\n{\"data\": \"value\"}",
             ),
         ];
 
-        parse_request! { cases }
+        parse_request! { cases.clone() }
+        parse_request! { cases, captured_limits() }
     }
 
     #[test]
@@ -1333,6 +2554,14 @@ Content-Length: 17\r\n\r\n{\"data\": \"value\"}",
                 "POST / HTTP/1.1\r\nContent-Length: invalid\r\n\r\nbody",
                 Err(ErrorKind::InvalidContentLength),
             ),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\nContent-Length: 4\r\n\r\nbody",
+                Err(ErrorKind::UnsupportedTransferEncoding),
+            ),
+            (
+                "POST / HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n4\r\nbody\r\n0\r\n\r\n",
+                Err(ErrorKind::UnsupportedTransferEncoding),
+            ),
         ];
 
         parse_request! { cases }
@@ -1342,7 +2571,7 @@ Content-Length: 17\r\n\r\n{\"data\": \"value\"}",
     fn check_limits() {
         use crate::query::Error as Qerror;
 
-        let limits = ReqLimits::default().precalculate();
+        let limits = ReqLimits::bare().precalculate();
 
         let def_url = "/".to_string();
         let url_size = format!("/{}", "q".repeat(limits.url_size - 1));
@@ -1480,6 +2709,84 @@ Content-Length: 17\r\n\r\n{\"data\": \"value\"}",
             )
         ];
 
-        parse_request! { cases }
+        parse_request! { cases.clone() }
+
+        let h_name: &'static str = Box::leak(h_name.clone().into_boxed_str());
+        let captured: &'static [&'static [u8]] = Box::leak(Box::new([h_name.as_bytes()]));
+        parse_request! {
+            cases,
+            ReqLimits {
+                captured_headers: Some(captured),
+                ..ReqLimits::bare()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod zero_policy {
+    use super::*;
+
+    const SECRET: &[u8] = b"GET / HTTP/1.1\r\nHost: a\r\nAuthorization: secret\r\n\r\n";
+
+    fn limits(zero_on_reset: ZeroPolicy) -> ReqLimits {
+        ReqLimits {
+            zero_on_reset,
+            ..ReqLimits::default()
+        }
+        .precalculate()
+    }
+
+    // Loads `SECRET`, resets, and plants a byte past everything written so far
+    fn after_reset(policy: ZeroPolicy) -> Parser {
+        let mut parser = Parser::new(&limits(policy));
+        parser.load(SECRET);
+        parser.advance();
+
+        let last = parser.buffer.len() - 1;
+        parser.buffer[last] = b'!';
+        parser.reset();
+        parser
+    }
+
+    #[test]
+    fn none() {
+        let parser = after_reset(ZeroPolicy::None);
+        assert!(parser.buffer.starts_with(SECRET));
+        assert_eq!(parser.buffer.last(), Some(&b'!'));
+    }
+
+    #[test]
+    fn used_prefix() {
+        let parser = after_reset(ZeroPolicy::UsedPrefix);
+        let (used, rest) = parser.buffer.split_at(SECRET.len());
+        assert!(used.iter().all(|b| *b == 0));
+        // Never written by the parser, so not zeroed
+        assert_eq!(rest.last(), Some(&b'!'));
+    }
+
+    #[test]
+    fn full() {
+        let parser = after_reset(ZeroPolicy::Full);
+        assert!(parser.buffer.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn stale_data_is_not_parsed() {
+        let limits = limits(ZeroPolicy::None);
+        let mut parser = Parser::new(&limits);
+        let mut request = Request::new(&limits);
+
+        parser.load(SECRET);
+        assert_eq!(parser.parse_request(&mut request, &limits, false), Ok(()));
+        parser.reset();
+
+        // The rest of the head is still in the buffer from the previous request
+        parser.load(&SECRET[..SECRET.len() - 4]);
+        request.reset();
+        assert_eq!(
+            parser.parse_request(&mut request, &limits, false),
+            Err(ErrorKind::InvalidHeader)
+        );
     }
 }
@@ -1,11 +1,23 @@
 //! A highly efficient, zero-allocation HTTP response builder for embedded web servers.
 
 use crate::{
-    http::types::{StatusCode, Version},
+    http::{
+        tmpl::Template,
+        types::{StatusClass, StatusCode, Version},
+    },
     limits::RespLimits,
-    BodyWriter, Request, WriteBuffer,
+    BodyWriter, HttpDate, Request, WriteBuffer, YieldingBodyWriter,
+};
+use std::{
+    borrow::Cow,
+    fmt,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    panic::Location,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use std::{borrow::Cow, rc::Rc, sync::Arc};
 
 #[derive(Debug)]
 /// HTTP response builder for constructing server responses.
@@ -47,13 +59,156 @@ pub struct Response {
     buffer: Vec<u8>,
     pub(crate) version: Version,
     pub(crate) keep_alive: bool,
+    // The server closes the connection after this response, `keep_alive(true)` can't change it
+    close_required: bool,
+    // `close_after()`: requests the connection may serve after this one
+    pub(crate) close_after: Option<usize>,
+    // `RespLimits::emit_keep_alive_hint`: idle timeout in seconds and requests left
+    keep_alive_hint: Option<(u64, usize)>,
     posit_length: usize,
     start_body: usize,
+    hard_max_body: usize,
+    max_response_size: Option<usize>,
+    validate_protocol_usage: bool,
+    // Where a method for the wrong protocol replaced the response with `500`
+    misused_at: Option<&'static Location<'static>>,
+    // The response a body over a limit replaced with `500`
+    overflow: Option<BodyOverflow>,
     state: ResponseState,
 }
 
+/// A response replaced with `500 Internal Server Error` because it went over
+/// [`RespLimits::hard_max_body`] or [`RespLimits::max_response_size`], see
+/// [`Response::overflow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyOverflow {
+    /// The status the handler chose
+    pub status: StatusCode,
+    /// Size of the response the handler built, in bytes
+    pub size: usize,
+}
+
 #[doc(hidden)]
-pub struct Handled(());
+#[derive(Debug)]
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub struct Handled(Outcome);
+
+/// What the connection does after the handler returns.
+///
+/// Only [`Response`] methods create [`Handled`], so the outcome always
+/// matches the state of the response buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Outcome {
+    /// The buffer holds a complete response to send
+    Complete,
+    /// Nothing to send, the connection is closed
+    NoResponse,
+}
+
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+impl Handled {
+    #[inline(always)]
+    pub(crate) const fn outcome(&self) -> Outcome {
+        self.0
+    }
+}
+
+/// A finished `HTTP/1.X` response rendered once and copied into
+/// responses with [`Response::from_template`].
+///
+/// For endpoints that always answer with the same bytes (health checks,
+/// maintenance pages, canned errors): the status line, headers and
+/// `content-length` are not written again for every request.
+///
+/// # Examples
+/// ```
+/// use maker_web::{Handled, Handler, Request, Response, ResponseTemplate, StatusCode};
+///
+/// struct MyHandler {
+///     health: ResponseTemplate,
+/// }
+///
+/// impl Handler for MyHandler {
+///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+///         match req.url().path() {
+///             b"/health" => resp.from_template(&self.health),
+///             _ => resp.reply(StatusCode::NotFound, "Not Found"),
+///         }
+///     }
+/// }
+///
+/// let handler = MyHandler {
+///     health: ResponseTemplate::build(|resp| {
+///         resp.status(StatusCode::Ok)
+///             .header("content-type", "application/json")
+///             .body(r#"{"status":"ok"}"#)
+///     }),
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseTemplate {
+    // The response without a `connection` header
+    bytes: Box<[u8]>,
+    // Where the `connection` header goes, right before `content-length`
+    connection_at: usize,
+    body_len: usize,
+    close: bool,
+}
+
+impl ResponseTemplate {
+    /// Renders the response built by `f`.
+    ///
+    /// The response is built for an `HTTP/1.1` keep-alive request.
+    /// [`close()`](Response::close) is kept: responses from the template
+    /// close the connection.
+    ///
+    /// # Panics
+    /// Panics if `f` doesn't finish the response with [`body()`](Response::body)
+    /// or [`body_with()`](Response::body_with), and on any misuse of the builder
+    /// that panics in [`Response`] itself.
+    #[track_caller]
+    pub fn build<F: FnOnce(&mut Response) -> Handled>(f: F) -> Self {
+        let mut resp = Response::new(&RespLimits::default());
+        let handled = f(&mut resp);
+        let close = !resp.keep_alive;
+
+        match Self::capture(&resp, close) {
+            Some(template) if handled.0 == Outcome::Complete => template,
+            _ => panic!("A template must be an `HTTP/1.X` response with a body"),
+        }
+    }
+
+    /// Copies a finished `HTTP/1.X` response, `None` for anything else
+    /// (`HTTP/0.9+`, no response, an oversized body replaced with `500`).
+    ///
+    /// `close` is whether the copies close the connection.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) fn capture(resp: &Response, close: bool) -> Option<Self> {
+        if resp.state != ResponseState::Complete || resp.posit_length == 0 {
+            return None;
+        }
+
+        let mut bytes = resp.buffer.clone();
+        // `HTTP/1.0` -> `HTTP/1.1`, `from_template` adjusts it back
+        bytes[7] = b'1';
+
+        let mut len = (resp.keep_alive_values()).map_or(0, |(timeout, max)| {
+            Response::write_keep_alive(&mut Vec::new(), timeout, max)
+        });
+        if let Some(value) = resp.connection_header() {
+            len += b"connection: \r\n".len() + value.len();
+        }
+        let connection_at = resp.posit_length - CONTENT_LENGTH.len() - len;
+        bytes.drain(connection_at..connection_at + len);
+
+        Some(Self {
+            bytes: bytes.into_boxed_slice(),
+            connection_at,
+            body_len: resp.buffer.len() - resp.start_body,
+            close,
+        })
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum ResponseState {
@@ -62,6 +217,29 @@ enum ResponseState {
     Complete,
 }
 
+/// Context for the `debug` panics of the builder: what was called, for which
+/// header, and the state of the response. Only formatted when an assertion
+/// fails, so `release` builds never touch it.
+struct Misuse<'a> {
+    method: &'static str,
+    header: Option<&'a dyn WriteBuffer>,
+    state: ResponseState,
+    version: Version,
+}
+
+impl fmt::Display for Misuse<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(`{}()`", self.method)?;
+        if let Some(name) = self.header {
+            let mut buffer = Vec::new();
+            name.write_to(&mut buffer);
+            write!(f, " for header `{}`", String::from_utf8_lossy(&buffer))?;
+        }
+        write!(f, ", state: {:?}, version: {:?})", self.state, self.version)
+    }
+}
+
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
 impl Response {
     #[inline(always)]
     pub(crate) fn new(limits: &RespLimits) -> Self {
@@ -69,8 +247,16 @@ impl Response {
             buffer: Vec::with_capacity(limits.default_capacity),
             version: Version::Http11,
             keep_alive: true,
+            close_required: false,
+            close_after: None,
+            keep_alive_hint: None,
             posit_length: 0,
             start_body: 0,
+            hard_max_body: limits.hard_max_body,
+            max_response_size: limits.max_response_size,
+            validate_protocol_usage: limits.validate_protocol_usage,
+            misused_at: None,
+            overflow: None,
             state: ResponseState::Clean,
         }
     }
@@ -83,7 +269,10 @@ impl Response {
 
     #[inline(always)]
     pub(crate) fn reset(&mut self, limits: &RespLimits) {
-        if self.buffer.capacity() > limits.max_capacity {
+        // Under `default_capacity` only when a `body_with_async` builder was
+        // dropped with the buffer: pre-allocate it again once
+        let capacity = self.buffer.capacity();
+        if capacity > limits.max_capacity || capacity < limits.default_capacity {
             self.buffer = Vec::with_capacity(limits.default_capacity);
         } else {
             self.buffer.clear();
@@ -91,11 +280,32 @@ impl Response {
 
         self.version = Version::Http11;
         self.keep_alive = true;
+        self.close_required = false;
+        self.close_after = None;
+        self.keep_alive_hint = None;
         self.posit_length = 0;
         self.start_body = 0;
+        self.hard_max_body = limits.hard_max_body;
+        self.max_response_size = limits.max_response_size;
+        self.validate_protocol_usage = limits.validate_protocol_usage;
+        self.misused_at = None;
+        self.overflow = None;
         self.state = ResponseState::Clean;
     }
 
+    /// Announces `keep-alive: timeout=N, max=M` if the connection stays open
+    #[inline(always)]
+    pub(crate) fn keep_alive_hint(&mut self, timeout: u64, max: usize) {
+        self.keep_alive_hint = Some((timeout, max));
+    }
+
+    /// Closes the connection after this response whatever the handler asks for
+    #[inline(always)]
+    pub(crate) fn require_close(&mut self) {
+        self.keep_alive = false;
+        self.close_required = true;
+    }
+
     #[inline(always)]
     pub(crate) fn buffer(&self) -> &Vec<u8> {
         &self.buffer
@@ -132,13 +342,96 @@ impl Response {
     pub fn close(&mut self) -> &mut Self {
         debug_assert!(
             self.state != ResponseState::Complete,
-            "Must be called before any finalizing method",
+            "Must be called before any finalizing method {}",
+            self.misuse("close", None),
         );
 
         self.keep_alive = false;
         self
     }
 
+    /// Keeps the connection open after this response (`true`) or closes it
+    /// (`false`, the same as [`close()`](Response::close)), whatever the
+    /// request asked for.
+    ///
+    /// The `connection` header is written to match: e.g. `keep-alive` for an
+    /// `HTTP/1.0` request without it. `true` is ignored when the server closes
+    /// the connection anyway (the last request allowed by
+    /// [`ConnLimits`](crate::limits::ConnLimits)).
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|req, resp| {
+    /// use maker_web::StatusCode;
+    ///
+    /// // The client should reconnect to pick up the new configuration
+    /// resp.status(StatusCode::Ok)
+    ///     .keep_alive(false)
+    ///     .body("Config reloaded")
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be called before any finalizing method`
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called after any finalizing method (method returning `Handler`)
+    #[inline]
+    #[track_caller]
+    pub fn keep_alive(&mut self, keep_alive: bool) -> &mut Self {
+        debug_assert!(
+            self.state != ResponseState::Complete,
+            "Must be called before any finalizing method {}",
+            self.misuse("keep_alive", None),
+        );
+
+        self.keep_alive = keep_alive && !self.close_required;
+        self
+    }
+
+    /// Closes the connection after `remaining` more requests, the last of
+    /// them answered with `connection: close`.
+    ///
+    /// Works like a lower [`max_requests_per_connection`](crate::limits::ConnLimits::max_requests_per_connection)
+    /// for the rest of this connection, e.g. when the session is about to expire.
+    /// A later call can bring the end closer but not move it further.
+    /// `close_after(0)` is the same as [`close()`](Response::close).
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|req, resp| {
+    /// use maker_web::StatusCode;
+    ///
+    /// // The token expires soon, let the client finish what it's doing
+    /// resp.status(StatusCode::Ok)
+    ///     .close_after(2)
+    ///     .body("Token expires in 5 seconds")
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be called before any finalizing method`
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called after any finalizing method (method returning `Handler`)
+    #[inline]
+    #[track_caller]
+    pub fn close_after(&mut self, remaining: usize) -> &mut Self {
+        debug_assert!(
+            self.state != ResponseState::Complete,
+            "Must be called before any finalizing method {}",
+            self.misuse("close_after", None),
+        );
+
+        if remaining == 0 {
+            self.keep_alive = false;
+        }
+        self.close_after = Some(remaining);
+        self
+    }
+
     /// Closes the connection without sending a response.
     ///
     /// # Examples
@@ -165,12 +458,78 @@ impl Response {
     pub fn close_without_response(&mut self) -> Handled {
         debug_assert!(
             self.state == ResponseState::Clean,
-            "The response must be empty and incomplete",
+            "The response must be empty and incomplete {}",
+            self.misuse("close_without_response", None),
         );
 
         self.keep_alive = false;
         self.state = ResponseState::Complete;
-        Handled(())
+        Handled(Outcome::NoResponse)
+    }
+
+    /// Where a method for the wrong protocol was called, if that replaced
+    /// the response with `500 Internal Server Error`.
+    ///
+    /// Only happens in `release` builds with
+    /// [`RespLimits::validate_protocol_usage`]: e.g. `status()` for an
+    /// `HTTP/0.9+` request. The methods after it change nothing.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Handler, Request, Response, StatusCode};
+    ///
+    /// struct MyHandler;
+    ///
+    /// impl Handler for MyHandler {
+    ///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+    ///         resp.status(StatusCode::Ok).body("Hello")
+    ///     }
+    ///
+    ///     fn post_process(&self, req: &Request, resp: &mut Response) {
+    ///         if let Some(location) = resp.misused_at() {
+    ///             eprintln!("wrong response method for {:?} at {location}", req.version());
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    #[inline(always)]
+    pub fn misused_at(&self) -> Option<&'static Location<'static>> {
+        self.misused_at
+    }
+
+    /// The status and the size of a response whose body went over a limit,
+    /// if that replaced it with `500 Internal Server Error`.
+    ///
+    /// The limits are [`RespLimits::hard_max_body`] and
+    /// [`RespLimits::max_response_size`]; the size is that of the whole
+    /// response, headers included.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Handler, Request, Response, StatusCode};
+    ///
+    /// struct MyHandler;
+    ///
+    /// impl Handler for MyHandler {
+    ///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+    ///         resp.status(StatusCode::Ok).body("Hello")
+    ///     }
+    ///
+    ///     fn post_process(&self, req: &Request, resp: &mut Response) {
+    ///         if let Some(overflow) = resp.overflow() {
+    ///             eprintln!(
+    ///                 "{:?} response of {} bytes for {:?} dropped",
+    ///                 overflow.status,
+    ///                 overflow.size,
+    ///                 req.url().path_str(),
+    ///             );
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    #[inline(always)]
+    pub fn overflow(&self) -> Option<BodyOverflow> {
+        self.overflow
     }
 }
 
@@ -202,12 +561,18 @@ impl Response {
     pub fn status(&mut self, status: StatusCode) -> &mut Self {
         debug_assert!(
             self.state == ResponseState::Clean,
-            "Must be first and called only once"
+            "Must be first and called only once {}",
+            self.misuse("status", None),
         );
         debug_assert!(
             self.version != Version::Http09,
-            "This method is only for `HTTP/1.X`"
+            "This method is only for `HTTP/1.X` {}",
+            self.misuse("status", None),
         );
+        if self.validate_protocol_usage && self.version == Version::Http09 {
+            self.reject_misuse();
+            return self;
+        }
 
         self.buffer
             .extend_from_slice(status.to_first_line(self.version));
@@ -235,22 +600,33 @@ impl Response {
     /// ```
     ///
     /// # Panics
-    /// Error message: `Must be called after status() and before any body method`
+    /// Error messages:
+    /// - `Must be called after status() and before any body method`
+    /// - ``Header name must not contain `:`, CR or LF``
+    /// - `Header value must not contain CR or LF`
     ///
     /// Panics in `debug` mode when:
     /// - Called before [`status()`](Response::status)
     /// - Called after [`body()`](Response::body) or [`body_with()`](Response::body_with)
+    /// - The written header could split the response (header injection)
     #[inline]
     #[track_caller]
     pub fn header<N: WriteBuffer, V: WriteBuffer>(&mut self, name: N, value: V) -> &mut Self {
         debug_assert!(
             self.state == ResponseState::Headers,
-            "Must be called after status() and before any body method"
+            "Must be called after status() and before any body method {}",
+            self.misuse("header", Some(&name)),
         );
+        if self.misused_at.is_some() {
+            return self;
+        }
 
+        let line_start = self.buffer.len();
         name.write_to(&mut self.buffer);
         self.buffer.extend_from_slice(b": ");
+        let value_start = self.buffer.len();
         value.write_to(&mut self.buffer);
+        self.debug_check_header(line_start, value_start);
         self.buffer.extend_from_slice(b"\r\n");
         self
     }
@@ -278,11 +654,15 @@ impl Response {
     /// ```
     ///
     /// # Panics
-    /// Error message: `Must be called after status() and before any body method`
+    /// Error messages:
+    /// - `Must be called after status() and before any body method`
+    /// - ``Header name must not contain `:`, CR or LF``
+    /// - `Header value must not contain CR or LF`
     ///
     /// Panics in `debug` mode when:
     /// - Called before [`status()`](Response::status)
     /// - Called after [`body()`](Response::body) or [`body_with()`](Response::body_with)
+    /// - The written header could split the response (header injection)
     #[inline]
     #[track_caller]
     pub fn header_multi<N, S, I, V>(&mut self, name: N, split: S, values: I) -> &mut Self
@@ -294,11 +674,17 @@ impl Response {
     {
         debug_assert!(
             self.state == ResponseState::Headers,
-            "Must be called after status() and before any body method"
+            "Must be called after status() and before any body method {}",
+            self.misuse("header_multi", Some(&name)),
         );
+        if self.misused_at.is_some() {
+            return self;
+        }
 
+        let line_start = self.buffer.len();
         name.write_to(&mut self.buffer);
         self.buffer.extend_from_slice(b": ");
+        let value_start = self.buffer.len();
 
         let mut iter = values.into_iter();
         if let Some(first) = iter.next() {
@@ -310,6 +696,7 @@ impl Response {
             }
         }
 
+        self.debug_check_header(line_start, value_start);
         self.buffer.extend_from_slice(b"\r\n");
         self
     }
@@ -341,11 +728,15 @@ impl Response {
     /// ```
     ///
     /// # Panics
-    /// Error message: `Must be called after status() and before any body method`
+    /// Error messages:
+    /// - `Must be called after status() and before any body method`
+    /// - ``Header name must not contain `:`, CR or LF``
+    /// - `Header value must not contain CR or LF`
     ///
     /// Panics in `debug` mode when:
     /// - Called before [`status()`](Response::status)
     /// - Called after [`body()`](Response::body) or [`body_with()`](Response::body_with)
+    /// - The written header could split the response (header injection)
     #[inline]
     #[track_caller]
     pub fn header_params<N, S, I, K, V>(&mut self, name: N, split: S, params: I) -> &mut Self
@@ -358,11 +749,17 @@ impl Response {
     {
         debug_assert!(
             self.state == ResponseState::Headers,
-            "Must be called after status() and before any body method"
+            "Must be called after status() and before any body method {}",
+            self.misuse("header_params", Some(&name)),
         );
+        if self.misused_at.is_some() {
+            return self;
+        }
 
+        let line_start = self.buffer.len();
         name.write_to(&mut self.buffer);
         self.buffer.extend_from_slice(b": ");
+        let value_start = self.buffer.len();
 
         let mut iter = params.into_iter();
         if let Some((first_key, first_val)) = iter.next() {
@@ -382,10 +779,92 @@ impl Response {
             }
         }
 
+        self.debug_check_header(line_start, value_start);
         self.buffer.extend_from_slice(b"\r\n");
         self
     }
 
+    /// Adds a header with a date in the `IMF-fixdate` format, e.g.
+    /// `last-modified: Sun, 06 Nov 1994 08:49:37 GMT`.
+    ///
+    /// Same as <code>[header](Response::header)(name, [HttpDate]::from(time))</code>.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::StatusCode;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// resp.status(StatusCode::Ok)
+    ///     .header_date("expires", SystemTime::now() + Duration::from_secs(3600))
+    ///     .body("Done")
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// The same as [`header()`](Response::header).
+    #[inline]
+    #[track_caller]
+    pub fn header_date<N: WriteBuffer>(&mut self, name: N, time: SystemTime) -> &mut Self {
+        self.header(name, HttpDate::from(time))
+    }
+
+    /// Adds a header with a duration in whole seconds, rounded down,
+    /// e.g. `retry-after: 120`.
+    ///
+    /// Same as <code>[header](Response::header)(name, duration.as_secs())</code>.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::StatusCode;
+    /// use std::time::Duration;
+    ///
+    /// resp.status(StatusCode::ServiceUnavailable)
+    ///     .header_duration_secs("retry-after", Duration::from_secs(120))
+    ///     .body("Maintenance")
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// The same as [`header()`](Response::header).
+    #[inline]
+    #[track_caller]
+    pub fn header_duration_secs<N: WriteBuffer>(
+        &mut self,
+        name: N,
+        duration: Duration,
+    ) -> &mut Self {
+        self.header(name, duration.as_secs())
+    }
+
+    /// Adds an `alt-svc` header advertising the same service on other protocols
+    /// or endpoints ([RFC 7838](https://www.rfc-editor.org/rfc/rfc7838)),
+    /// e.g. `h3=":443"; ma=86400`.
+    ///
+    /// Same as <code>[header](Response::header)("alt-svc", value)</code>.
+    /// The value is written as is; clients that don't support the advertised
+    /// protocols ignore it.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::StatusCode;
+    ///
+    /// resp.status(StatusCode::Ok)
+    ///     .alt_svc(br#"h3=":443"; ma=86400, h2="alt.example.com:443""#)
+    ///     .body("Done")
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// The same as [`header()`](Response::header).
+    #[inline]
+    #[track_caller]
+    pub fn alt_svc(&mut self, value: &[u8]) -> &mut Self {
+        self.header("alt-svc", value)
+    }
+
     /// Sets the response body and finalizes the response.
     ///
     /// # Side Effects
@@ -417,8 +896,12 @@ impl Response {
     pub fn body<T: WriteBuffer>(&mut self, data: T) -> Handled {
         debug_assert!(
             self.state == ResponseState::Headers,
-            "Must be called after status() and any header methods"
+            "Must be called after status() and any header methods {}",
+            self.misuse("body", None),
         );
+        if self.misused_at.is_some() {
+            return Handled(Outcome::Complete);
+        }
 
         self.start_body();
         data.write_to(&mut self.buffer);
@@ -478,52 +961,643 @@ impl Response {
     pub fn body_with<F: FnOnce(&mut BodyWriter)>(&mut self, f: F) -> Handled {
         debug_assert!(
             self.state == ResponseState::Headers,
-            "Must be called after status() and any header methods"
+            "Must be called after status() and any header methods {}",
+            self.misuse("body_with", None),
         );
+        if self.misused_at.is_some() {
+            return Handled(Outcome::Complete);
+        }
 
         self.start_body();
         f(&mut BodyWriter(&mut self.buffer));
         self.end_body()
     }
-}
 
-impl Response {
-    #[inline(always)]
+    /// Renders `template` with `values` as the body and finalizes the response.
+    ///
+    /// Values are HTML-escaped unless their slot is `{name:raw}`, see
+    /// [`Template`] and [`Template::render`].
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::{tmpl::Template, StatusCode};
+    ///
+    /// let greeting = Template::new("<p>Hi, {user}! You have {count} orders.</p>");
+    ///
+    /// resp.status(StatusCode::Ok)
+    ///     .header("content-type", "text/html; charset=utf-8")
+    ///     .body_template(&greeting, &[&"Ann", &2])
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Panics in `debug` mode like [`body_with()`](Response::body_with), or
+    /// when the number of values doesn't match the template's slots.
+    #[inline]
     #[track_caller]
-    fn start_body(&mut self) -> &mut Self {
-        if let Some(value) = self.connection_header() {
-            self.header("connection", value);
-        }
-
-        self.buffer.extend_from_slice(b"content-length: ");
-        self.posit_length = self.buffer.len();
-        self.buffer.extend_from_slice(b"0000000000\r\n\r\n");
-        self.start_body = self.buffer.len();
-        self
+    pub fn body_template(&mut self, template: &Template, values: &[&dyn WriteBuffer]) -> Handled {
+        self.body_with(|writer| template.render(values, writer.0))
     }
 
-    #[inline(always)]
-    fn end_body(&mut self) -> Handled {
-        let body_len = self.buffer.len() - self.start_body;
-        let (arr, _) = Response::number_to_bytes(body_len as u128);
-
-        let target_range = self.posit_length..self.posit_length + 10;
-        self.buffer[target_range].copy_from_slice(&arr[29..39]);
-        self.state = ResponseState::Complete;
-
-        Handled(())
+    /// Awaits the body produced by `f`, then sets it and finalizes the response
+    /// like [`body()`](Response::body).
+    ///
+    /// For bodies that need an `.await` (a database query, another service)
+    /// after the status and headers are set. The same can be written with an
+    /// `.await` between [`status()`](Response::status) and `body()`; this keeps
+    /// the builder chain in one expression.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Handler, Request, Response, StatusCode};
+    ///
+    /// async fn load_user(id: &str) -> String {
+    ///     format!(r#"{{"id": "{id}"}}"#)
+    /// }
+    ///
+    /// struct MyHandler;
+    ///
+    /// impl Handler for MyHandler {
+    ///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+    ///         let id = req.url().path_segment_str(1).unwrap_or("0");
+    ///
+    ///         resp.status(StatusCode::Ok)
+    ///             .header("content-type", "application/json")
+    ///             .body_async(|| load_user(id))
+    ///             .await
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    /// The same as [`body()`](Response::body), checked before `f` is called.
+    #[inline]
+    pub async fn body_async<F, Fut, T>(&mut self, f: F) -> Handled
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+        T: WriteBuffer,
+    {
+        debug_assert!(
+            self.state == ResponseState::Headers,
+            "Must be called after status() and any header methods {}",
+            self.misuse("body_async", None),
+        );
+        let data = f().await;
+        self.body(data)
     }
 
-    #[inline(always)]
-    const fn connection_header(&self) -> Option<&'static [u8]> {
-        match (self.version, self.keep_alive) {
-            (Version::Http11, true) => None,
-            (Version::Http11, false) => Some(b"close"),
-            (Version::Http10, true) => Some(b"keep-alive"),
-            (Version::Http10, false) => Some(b"close"),
-            _ => None,
-        }
-    }
+    /// Writes the body with the async builder `f`, which can give up the
+    /// thread while it writes, then finalizes the response like
+    /// [`body_with()`](Response::body_with).
+    ///
+    /// For large bodies built on the worker's own task: with
+    /// [`maybe_yield()`](YieldingBodyWriter::maybe_yield) in its loop, `f`
+    /// lets the other connections of the runtime thread run every
+    /// [`yield_every()`](YieldingBodyWriter::yield_every) bytes. `f` owns the
+    /// writer and returns it when it is done.
+    ///
+    /// Dropping the returned future before it completes costs the response
+    /// buffer, which the next response allocates again (one allocation).
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Handler, Request, Response, StatusCode};
+    ///
+    /// struct Export(Vec<u64>);
+    ///
+    /// impl Handler for Export {
+    ///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+    ///         resp.status(StatusCode::Ok)
+    ///             .header("content-type", "text/csv")
+    ///             .body_with_async(|mut writer| async move {
+    ///                 for (i, value) in self.0.iter().enumerate() {
+    ///                     writer.write(i);
+    ///                     writer.write(",");
+    ///                     writer.write(*value);
+    ///                     writer.write("\n");
+    ///                     writer.maybe_yield().await;
+    ///                 }
+    ///                 writer
+    ///             })
+    ///             .await
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    /// The same as [`body_with()`](Response::body_with), checked before `f` is called.
+    #[inline]
+    pub async fn body_with_async<F, Fut>(&mut self, f: F) -> Handled
+    where
+        F: FnOnce(YieldingBodyWriter) -> Fut,
+        Fut: Future<Output = YieldingBodyWriter>,
+    {
+        debug_assert!(
+            self.state == ResponseState::Headers,
+            "Must be called after status() and any header methods {}",
+            self.misuse("body_with_async", None),
+        );
+        if self.misused_at.is_some() {
+            return Handled(Outcome::Complete);
+        }
+
+        self.start_body();
+        let max_body = self.start_body.saturating_add(self.hard_max_body);
+        let limit = self
+            .max_response_size
+            .map_or(max_body, |max| max.min(max_body));
+
+        let writer = YieldingBodyWriter::new(std::mem::take(&mut self.buffer), limit);
+        let writer = f(writer).await;
+        self.buffer = writer.buffer;
+        match writer.dropped {
+            0 => self.end_body(),
+            dropped => self.record_overflow(self.buffer.len() + dropped),
+        }
+    }
+
+    /// Sets the status code and the body in one call, for responses
+    /// without custom headers.
+    ///
+    /// Same as <code>[status](Response::status)(status).[body](Response::body)(data)</code>.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::StatusCode;
+    ///
+    /// resp.reply(StatusCode::NotFound, r#"{"status": "not found", "code": 404}"#)
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be first and called only once`
+    /// - <code>This method is only for \`HTTP/1.X\`</code>
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called after any other method
+    /// - Called for a non-HTTP/1.X response
+    #[inline]
+    #[track_caller]
+    pub fn reply<T: WriteBuffer>(&mut self, status: StatusCode, data: T) -> Handled {
+        self.status(status).body(data)
+    }
+
+    /// Sends an error in the JSON format of the server's own error responses:
+    /// `{"error":"<message>","code":"<code>"}` with `content-type: application/json`.
+    ///
+    /// `code` and `message` are escaped as JSON strings. Unlike the server's
+    /// errors, the connection is kept alive.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::StatusCode;
+    ///
+    /// // {"error":"User not found","code":"USER_NOT_FOUND"}
+    /// resp.error_json(StatusCode::NotFound, "USER_NOT_FOUND", "User not found")
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be first and called only once`
+    /// - <code>This method is only for \`HTTP/1.X\`</code>
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called after any other method
+    /// - Called for a non-HTTP/1.X response
+    #[inline]
+    #[track_caller]
+    pub fn error_json(&mut self, status: StatusCode, code: &str, message: &str) -> Handled {
+        self.status(status)
+            .header("content-type", "application/json")
+            .body_with(|w| {
+                w.write(r#"{"error":""#);
+                write_json_str(w.0, message);
+                w.write(r#"","code":""#);
+                write_json_str(w.0, code);
+                w.write(r#""}"#);
+            })
+    }
+
+    /// Adds a `content-range` header for a part of a resource:
+    /// `content-range: bytes start-end/total`.
+    ///
+    /// `start` and `end` are inclusive byte positions, as in the `Range` request header.
+    /// See [`partial()`](Response::partial) for a complete `206` response.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::StatusCode;
+    ///
+    /// resp.status(StatusCode::PartialContent)
+    ///     .header("content-type", "video/mp4")
+    ///     .content_range(0, 3, 1000)
+    ///     .body(b"\x00\x00\x00\x18")
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be called after status() and before any body method`
+    /// - `Range must satisfy start <= end < total`
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called before [`status()`](Response::status)
+    /// - Called after [`body()`](Response::body) or [`body_with()`](Response::body_with)
+    /// - The range is empty or outside of `total`
+    #[inline]
+    #[track_caller]
+    pub fn content_range(&mut self, start: usize, end: usize, total: usize) -> &mut Self {
+        debug_assert!(
+            self.state == ResponseState::Headers,
+            "Must be called after status() and before any body method {}",
+            self.misuse("content_range", None),
+        );
+        debug_assert!(
+            start <= end && end < total,
+            "Range must satisfy start <= end < total {}",
+            self.misuse("content_range", None),
+        );
+        if self.misused_at.is_some() {
+            return self;
+        }
+
+        self.buffer.extend_from_slice(b"content-range: bytes ");
+        start.write_to(&mut self.buffer);
+        self.buffer.push(b'-');
+        end.write_to(&mut self.buffer);
+        self.buffer.push(b'/');
+        total.write_to(&mut self.buffer);
+        self.buffer.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Finalizes a `206 Partial Content` response with a `content-range` header.
+    ///
+    /// Same as <code>[status](Response::status)(StatusCode::PartialContent).[content_range](Response::content_range)(start, end, total).[body](Response::body)(data)</code>,
+    /// `data` is the part of the resource from `start` to `end` inclusive.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// const FILE: &[u8] = b"0123456789";
+    ///
+    /// // Range: bytes=2-5
+    /// let (start, end) = (2, 5);
+    /// resp.partial(start, end, FILE.len(), &FILE[start..=end])
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be first and called only once`
+    /// - <code>This method is only for \`HTTP/1.X\`</code>
+    /// - `Range must satisfy start <= end < total`
+    /// - `Body length must match the range`
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called after any other method
+    /// - Called for a non-HTTP/1.X response
+    /// - The range is empty or outside of `total`
+    /// - `data` is not `end - start + 1` bytes long
+    #[inline]
+    #[track_caller]
+    pub fn partial<T: WriteBuffer>(
+        &mut self,
+        start: usize,
+        end: usize,
+        total: usize,
+        data: T,
+    ) -> Handled {
+        self.status(StatusCode::PartialContent)
+            .content_range(start, end, total)
+            .start_body();
+        data.write_to(&mut self.buffer);
+
+        debug_assert!(
+            self.buffer.len() - self.start_body == end - start + 1,
+            "Body length must match the range {}",
+            self.misuse("partial", None),
+        );
+        self.end_body()
+    }
+
+    /// Copies a pre-rendered [`ResponseTemplate`] into the response and finalizes it.
+    ///
+    /// Only the version in the status line and the `connection` header are
+    /// adjusted to the current request, everything else is copied as is.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::{ResponseTemplate, StatusCode};
+    ///
+    /// // Once at startup
+    /// let not_found = ResponseTemplate::build(|resp| {
+    ///     resp.status(StatusCode::NotFound)
+    ///         .header("content-type", "text/html")
+    ///         .body("<h1>Not Found</h1>")
+    /// });
+    ///
+    /// // For every request
+    /// resp.from_template(&not_found)
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `The response must be empty and incomplete`
+    /// - <code>This method is only for \`HTTP/1.X\`</code>
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called after any method
+    /// - Called for a non-HTTP/1.X response
+    #[inline]
+    #[track_caller]
+    pub fn from_template(&mut self, template: &ResponseTemplate) -> Handled {
+        debug_assert!(
+            self.state == ResponseState::Clean,
+            "The response must be empty and incomplete {}",
+            self.misuse("from_template", None),
+        );
+        debug_assert!(
+            self.version != Version::Http09,
+            "This method is only for `HTTP/1.X` {}",
+            self.misuse("from_template", None),
+        );
+        if self.validate_protocol_usage && self.version == Version::Http09 {
+            return self.reject_misuse();
+        }
+
+        if template.close {
+            self.keep_alive = false;
+        }
+
+        let (head, rest) = template.bytes.split_at(template.connection_at);
+        self.buffer.extend_from_slice(head);
+        if self.version == Version::Http10 {
+            // `HTTP/1.1` -> `HTTP/1.0`
+            self.buffer[7] = b'0';
+        }
+        if let Some(value) = self.connection_header() {
+            self.buffer.extend_from_slice(b"connection: ");
+            self.buffer.extend_from_slice(value);
+            self.buffer.extend_from_slice(b"\r\n");
+        }
+        if let Some((timeout, max)) = self.keep_alive_values() {
+            Self::write_keep_alive(&mut self.buffer, timeout, max);
+        }
+
+        self.posit_length = self.buffer.len() + CONTENT_LENGTH.len();
+        self.buffer.extend_from_slice(rest);
+        self.start_body = self.buffer.len() - template.body_len;
+
+        if template.body_len > self.hard_max_body
+            || self
+                .max_response_size
+                .is_some_and(|max| self.buffer.len() > max)
+        {
+            return self.record_overflow(self.buffer.len());
+        }
+
+        self.state = ResponseState::Complete;
+        Handled(Outcome::Complete)
+    }
+
+    /// Adds a header to a finished `HTTP/1.X` response.
+    ///
+    /// Intended for [`Handler::post_process`](crate::Handler::post_process): the
+    /// header is inserted after the existing headers and the body is moved, so
+    /// prefer [`header()`](Response::header) inside the handler itself.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::StatusCode;
+    ///
+    /// let handled = resp.status(StatusCode::Ok).body("Hello");
+    /// resp.insert_header("x-frame-options", "DENY");
+    /// handled
+    /// # });
+    /// ```
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be called after a body method of an \`HTTP/1.X\` response`
+    /// - ``Header name must not contain `:`, CR or LF``
+    /// - `Header value must not contain CR or LF`
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called before [`body()`](Response::body) or [`body_with()`](Response::body_with)
+    /// - Called for an `HTTP/0.9+` response or after [`close_without_response()`](Response::close_without_response)
+    /// - The written header could split the response (header injection)
+    #[inline]
+    #[track_caller]
+    pub fn insert_header<N: WriteBuffer, V: WriteBuffer>(
+        &mut self,
+        name: N,
+        value: V,
+    ) -> &mut Self {
+        debug_assert!(
+            self.state == ResponseState::Complete && self.start_body != 0,
+            "Must be called after a body method of an `HTTP/1.X` response {}",
+            self.misuse("insert_header", Some(&name)),
+        );
+        // Before the empty line that ends the headers
+        let Some(headers_end) = self.start_body.checked_sub(2) else {
+            return self;
+        };
+
+        // Written at the end for the checks, then moved into place
+        let line_start = self.buffer.len();
+        name.write_to(&mut self.buffer);
+        self.buffer.extend_from_slice(b": ");
+        let value_start = self.buffer.len();
+        value.write_to(&mut self.buffer);
+        self.debug_check_header(line_start, value_start);
+        self.buffer.extend_from_slice(b"\r\n");
+
+        let line_len = self.buffer.len() - line_start;
+        self.buffer[headers_end..].rotate_right(line_len);
+        self.start_body += line_len;
+        self
+    }
+}
+
+const CONTENT_LENGTH: &[u8; 16] = b"content-length: ";
+/// Placeholder for `content-length`, backfilled in `end_body`
+const LENGTH_PLACEHOLDER: &[u8; 10] = b"0000000000";
+/// The largest body length that fits into [`LENGTH_PLACEHOLDER`]
+const MAX_PLACEHOLDER_BODY: u128 = 9_999_999_999;
+
+impl Response {
+    #[inline(always)]
+    #[track_caller]
+    fn start_body(&mut self) -> &mut Self {
+        if let Some(value) = self.connection_header() {
+            self.header("connection", value);
+        }
+        if let Some((timeout, max)) = self.keep_alive_values() {
+            Self::write_keep_alive(&mut self.buffer, timeout, max);
+        }
+
+        self.buffer.extend_from_slice(CONTENT_LENGTH);
+        self.posit_length = self.buffer.len();
+        self.buffer.extend_from_slice(LENGTH_PLACEHOLDER);
+        self.buffer.extend_from_slice(b"\r\n\r\n");
+        self.start_body = self.buffer.len();
+        self
+    }
+
+    /// Catches response splitting in `debug` mode; the line is not yet terminated
+    #[inline(always)]
+    #[track_caller]
+    fn debug_check_header(&self, line_start: usize, value_start: usize) {
+        if cfg!(debug_assertions) {
+            let name = &self.buffer[line_start..value_start - 2];
+            let value = &self.buffer[value_start..];
+
+            assert!(
+                !name.iter().any(|b| matches!(b, b':' | b'\r' | b'\n')),
+                "Header name must not contain `:`, CR or LF (name: {:?})",
+                String::from_utf8_lossy(name),
+            );
+            assert!(
+                !value.iter().any(|b| matches!(b, b'\r' | b'\n')),
+                "Header value must not contain CR or LF (header `{}`, value: {:?})",
+                String::from_utf8_lossy(name),
+                String::from_utf8_lossy(value),
+            );
+        }
+    }
+
+    #[cold]
+    fn misuse<'a>(&self, method: &'static str, header: Option<&'a dyn WriteBuffer>) -> Misuse<'a> {
+        Misuse {
+            method,
+            header,
+            state: self.state,
+            version: self.version,
+        }
+    }
+
+    #[inline(always)]
+    fn end_body(&mut self) -> Handled {
+        let body_len = self.buffer.len() - self.start_body;
+        if body_len as u128 > MAX_PLACEHOLDER_BODY
+            || body_len > self.hard_max_body
+            || self
+                .max_response_size
+                .is_some_and(|max| self.buffer.len() > max)
+        {
+            return self.record_overflow(self.buffer.len());
+        }
+
+        let (arr, start) = Response::number_to_bytes(body_len as u128);
+        assert!(
+            start >= arr.len() - LENGTH_PLACEHOLDER.len(),
+            "Body length does not fit into the `content-length` placeholder"
+        );
+
+        let target_range = self.posit_length..self.posit_length + LENGTH_PLACEHOLDER.len();
+        self.buffer[target_range].copy_from_slice(&arr[arr.len() - LENGTH_PLACEHOLDER.len()..]);
+        self.state = ResponseState::Complete;
+
+        Handled(Outcome::Complete)
+    }
+
+    /// Keeps the status and the `size` of an `HTTP/1.X` response over a limit
+    /// for [`Response::overflow`], then replaces it
+    #[cold]
+    fn record_overflow(&mut self, size: usize) -> Handled {
+        // `HTTP/1.X NNN `, written by `status()` or the template
+        let status = std::str::from_utf8(&self.buffer[9..12])
+            .ok()
+            .and_then(|code| code.parse().ok())
+            .and_then(StatusCode::from_u16)
+            .expect("The response starts with a status line");
+        self.overflow = Some(BodyOverflow { status, size });
+        self.body_overflow()
+    }
+
+    /// Replaces an oversized response with an empty `500` and closes the connection,
+    /// so the client never receives a truncated `content-length` or a response
+    /// over `RespLimits::max_response_size`. Also the rollback of `reject_misuse`.
+    #[cold]
+    fn body_overflow(&mut self) -> Handled {
+        self.buffer.clear();
+        self.keep_alive = false;
+        // No `content-length` placeholder any more
+        self.posit_length = 0;
+
+        self.buffer
+            .extend_from_slice(StatusCode::InternalServerError.to_first_line(self.version));
+        self.buffer
+            .extend_from_slice(b"connection: close\r\ncontent-length: 0\r\n\r\n");
+        self.start_body = self.buffer.len();
+        self.state = ResponseState::Complete;
+
+        Handled(Outcome::Complete)
+    }
+
+    /// Replaces the response with `500` after a method for the wrong protocol;
+    /// only reached in `release`, `debug` builds panic before
+    #[cold]
+    #[track_caller]
+    fn reject_misuse(&mut self) -> Handled {
+        self.misused_at = Some(Location::caller());
+
+        match self.version {
+            Version::Http09 => {
+                self.buffer.clear();
+                self.keep_alive = false;
+                self.http09_status_line(StatusCode::InternalServerError);
+                self.state = ResponseState::Complete;
+
+                Handled(Outcome::Complete)
+            }
+            _ => self.body_overflow(),
+        }
+    }
+
+    #[inline(always)]
+    const fn connection_header(&self) -> Option<&'static [u8]> {
+        match (self.version, self.keep_alive) {
+            (Version::Http11, true) => None,
+            (Version::Http11, false) => Some(b"close"),
+            (Version::Http10, true) => Some(b"keep-alive"),
+            (Version::Http10, false) => Some(b"close"),
+            _ => None,
+        }
+    }
+
+    // The `keep-alive` header values, if it's written for this response
+    #[inline(always)]
+    fn keep_alive_values(&self) -> Option<(u64, usize)> {
+        let (timeout, max) = self.keep_alive_hint?;
+        if !self.keep_alive || self.version == Version::Http09 {
+            return None;
+        }
+        Some((timeout, self.close_after.map_or(max, |n| n.min(max))))
+    }
+
+    // Writes the `keep-alive` line to `out` and returns its length
+    #[inline(always)]
+    fn write_keep_alive(out: &mut Vec<u8>, timeout: u64, max: usize) -> usize {
+        let start = out.len();
+        out.extend_from_slice(b"keep-alive: timeout=");
+        timeout.write_to(out);
+        out.extend_from_slice(b", max=");
+        max.write_to(out);
+        out.extend_from_slice(b"\r\n");
+        out.len() - start
+    }
 
     #[inline]
     const fn number_to_bytes(mut n: u128) -> ([u8; 39], usize) {
@@ -595,17 +1669,22 @@ impl Response {
     pub fn http09<T: WriteBuffer>(&mut self, data: T) -> Handled {
         debug_assert!(
             self.version == Version::Http09,
-            "This method is only for `HTTP/0.9+`"
+            "This method is only for `HTTP/0.9+` {}",
+            self.misuse("http09", None),
         );
         debug_assert!(
             self.state == ResponseState::Clean,
-            "An `HTTP/0.9+` response must use exactly one method"
+            "An `HTTP/0.9+` response must use exactly one method {}",
+            self.misuse("http09", None),
         );
+        if self.validate_protocol_usage && self.version != Version::Http09 {
+            return self.reject_misuse();
+        }
 
         data.write_to(&mut self.buffer);
         self.state = ResponseState::Complete;
 
-        Handled(())
+        Handled(Outcome::Complete)
     }
 
     /// Writes `HTTP/0.9+` response via closure and finalizes it.
@@ -655,17 +1734,22 @@ impl Response {
     pub fn http09_with<F: FnOnce(&mut Vec<u8>)>(&mut self, f: F) -> Handled {
         debug_assert!(
             self.version == Version::Http09,
-            "This method is only for `HTTP/0.9+`"
+            "This method is only for `HTTP/0.9+` {}",
+            self.misuse("http09_with", None),
         );
         debug_assert!(
             self.state == ResponseState::Clean,
-            "An `HTTP/0.9+` response must use exactly one method"
+            "An `HTTP/0.9+` response must use exactly one method {}",
+            self.misuse("http09_with", None),
         );
+        if self.validate_protocol_usage && self.version != Version::Http09 {
+            return self.reject_misuse();
+        }
 
         f(&mut self.buffer);
         self.state = ResponseState::Complete;
 
-        Handled(())
+        Handled(Outcome::Complete)
     }
 
     /// Writes a status code response in `HTTP/0.9+` format and finalizes it.
@@ -696,98 +1780,444 @@ impl Response {
     /// ```
     #[inline]
     #[track_caller]
-    pub fn http09_status(&mut self, status: StatusCode) -> Handled {
-        debug_assert!(
-            self.version == Version::Http09,
-            "This method is only for `HTTP/0.9+`"
-        );
-        debug_assert!(
-            self.state == ResponseState::Clean,
-            "An `HTTP/0.9+` response must use exactly one method"
-        );
+    pub fn http09_status(&mut self, status: StatusCode) -> Handled {
+        debug_assert!(
+            self.version == Version::Http09,
+            "This method is only for `HTTP/0.9+` {}",
+            self.misuse("http09_status", None),
+        );
+        debug_assert!(
+            self.state == ResponseState::Clean,
+            "An `HTTP/0.9+` response must use exactly one method {}",
+            self.misuse("http09_status", None),
+        );
+        if self.validate_protocol_usage && self.version != Version::Http09 {
+            return self.reject_misuse();
+        }
+
+        self.http09_status_line(status);
+
+        self.state = ResponseState::Complete;
+        Handled(Outcome::Complete)
+    }
+
+    /// Writes a custom message response in `HTTP/0.9+` format and finalizes it.
+    ///
+    /// Uses semantic prefixes based on status code range:
+    /// - `5xx`: `SERVER_ERROR: [code] [custom_message]\r\n`
+    /// - `4xx`: `CLIENT_ERROR: [code] [custom_message]\r\n`
+    /// - `3xx`: `REDIRECT: [code] [custom_message]\r\n`
+    /// - `2xx`: `SUCCESS: [code] [custom_message]\r\n`
+    /// - `1xx`: `INFO: [code] [custom_message]\r\n`
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|req, resp| {
+    /// # let invalid_input = true;
+    /// use maker_web::{StatusCode, Version};
+    ///
+    /// // Custom error message for HTTP/0.9+
+    /// if req.version() == Version::Http09 {
+    ///     if invalid_input {
+    ///         return resp.http09_msg(StatusCode::BadRequest, "Invalid query parameters");
+    ///     }
+    ///     resp.http09("success")
+    /// } else {
+    ///     resp.status(StatusCode::BadRequest).body("Invalid query parameters")
+    /// }
+    /// # });
+    /// ```
+    /// Success with custom data:
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|req, resp| {
+    /// use maker_web::{StatusCode, Version};
+    ///
+    /// // Success response with structured data
+    /// if req.version() == Version::Http09 {
+    ///     resp.http09_msg(StatusCode::Ok, r#"{"status":"ok","id":12345}"#)
+    /// } else {
+    ///     resp.status(StatusCode::Ok)
+    ///         .header("content-type", "application/json")
+    ///         .body(r#"{"status":"ok","id":12345}"#)
+    /// }
+    /// # });
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn http09_msg<T: WriteBuffer>(&mut self, status: StatusCode, value: T) -> Handled {
+        debug_assert!(
+            self.version == Version::Http09,
+            "This method is only for `HTTP/0.9+` {}",
+            self.misuse("http09_msg", None),
+        );
+        debug_assert!(
+            self.state == ResponseState::Clean,
+            "An `HTTP/0.9+` response must use exactly one method {}",
+            self.misuse("http09_msg", None),
+        );
+        if self.validate_protocol_usage && self.version != Version::Http09 {
+            return self.reject_misuse();
+        }
+
+        self.buffer
+            .extend_from_slice(Self::get_prefix(&status).as_bytes());
+        self.buffer.extend_from_slice(status.as_u16_bytes());
+        value.write_to(&mut self.buffer);
+        self.buffer.extend_from_slice(b"\r\n");
+
+        self.state = ResponseState::Complete;
+        Handled(Outcome::Complete)
+    }
+
+    #[inline]
+    fn http09_status_line(&mut self, status: StatusCode) {
+        self.buffer
+            .extend_from_slice(Self::get_prefix(&status).as_bytes());
+        self.buffer
+            .extend_from_slice(status.to_first_line(Version::Http09));
+    }
+
+    #[inline]
+    const fn get_prefix(status: &StatusCode) -> &str {
+        match status.class() {
+            StatusClass::ClientError => "CLIENT_ERROR:",
+            StatusClass::ServerError => "SERVER_ERROR:",
+            StatusClass::Redirection => "REDIRECT:",
+            StatusClass::Success => "SUCCESS:",
+            StatusClass::Informational => "INFO:",
+        }
+    }
+}
+
+/// Builder steps checked by the compiler instead of `debug` assertions.
+///
+/// [`Response::typed`] returns a [`ResponseClean`], whose methods take it by
+/// value: [`status()`](ResponseClean::status) gives a [`ResponseHeaders`], and
+/// the body methods consume that and return [`Handled`]. A header after the
+/// body or a second `status()` is a compile error, in `release` builds too.
+///
+/// The plain [`Response`] methods stay as they are, so existing
+/// `resp.status(..).body(..)` chains compile unchanged and a handler can
+/// move to the checked builder one endpoint at a time. Only `HTTP/1.X`
+/// responses are covered; `HTTP/0.9+` responses are a single call anyway.
+///
+/// # Examples
+/// ```
+/// # maker_web::docs_rs_helper::run_test(|_, resp| {
+/// use maker_web::StatusCode;
+///
+/// resp.typed()
+///     .status(StatusCode::Ok)
+///     .header("content-type", "text/plain")
+///     .body("Hello")
+/// # });
+/// ```
+///
+/// Misuse doesn't compile:
+/// ```compile_fail
+/// # maker_web::docs_rs_helper::run_test(|_, resp| {
+/// use maker_web::StatusCode;
+///
+/// let headers = resp.typed().status(StatusCode::Ok);
+/// let handled = headers.body("Hello");
+/// headers.header("x-late", "value"); // `headers` was moved by `body()`
+/// # handled
+/// # });
+/// ```
+impl Response {
+    /// Starts a response checked at compile time, see [`ResponseClean`].
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be first and called only once`
+    ///
+    /// Panics in `debug` mode when:
+    /// - Any builder method was already called on this response
+    #[inline]
+    #[track_caller]
+    pub fn typed(&mut self) -> ResponseClean<'_> {
+        debug_assert!(
+            self.state == ResponseState::Clean,
+            "Must be first and called only once {}",
+            self.misuse("typed", None),
+        );
+        ResponseClean(self)
+    }
+
+    /// Continues a finished `HTTP/1.X` response, e.g. in
+    /// [`Handler::post_process`](crate::Handler::post_process), see
+    /// [`ResponseComplete`].
+    ///
+    /// # Panics
+    /// Error messages:
+    /// - `Must be called after a body method of an \`HTTP/1.X\` response`
+    ///
+    /// Panics in `debug` mode when:
+    /// - Called before a body method, for an `HTTP/0.9+` response or after
+    ///   [`close_without_response()`](Response::close_without_response)
+    #[inline]
+    #[track_caller]
+    pub fn typed_complete(&mut self) -> ResponseComplete<'_> {
+        debug_assert!(
+            self.state == ResponseState::Complete && self.start_body != 0,
+            "Must be called after a body method of an `HTTP/1.X` response {}",
+            self.misuse("typed_complete", None),
+        );
+        ResponseComplete(self)
+    }
+}
+
+/// A response without a status line, from [`Response::typed`].
+///
+/// Headers need a status line first:
+/// ```compile_fail
+/// # maker_web::docs_rs_helper::run_test(|_, resp| {
+/// resp.typed().header("x-early", "value").body("Hello")
+/// # });
+/// ```
+#[must_use = "a response must be finished with a body method"]
+pub struct ResponseClean<'a>(&'a mut Response);
+
+/// A response with a status line, accepting headers until a body method.
+///
+/// The status line is written only once:
+/// ```compile_fail
+/// # maker_web::docs_rs_helper::run_test(|_, resp| {
+/// use maker_web::StatusCode;
+///
+/// resp.typed()
+///     .status(StatusCode::Ok)
+///     .status(StatusCode::NotFound)
+///     .body("Hello")
+/// # });
+/// ```
+#[must_use = "a response must be finished with a body method"]
+pub struct ResponseHeaders<'a>(&'a mut Response);
+
+/// A finished `HTTP/1.X` response, from [`Response::typed_complete`].
+pub struct ResponseComplete<'a>(&'a mut Response);
+
+impl<'a> ResponseClean<'a> {
+    /// Writes the status line, see [`Response::status`].
+    #[inline]
+    #[track_caller]
+    pub fn status(self, status: StatusCode) -> ResponseHeaders<'a> {
+        self.0.status(status);
+        ResponseHeaders(self.0)
+    }
+
+    /// See [`Response::close`].
+    #[inline]
+    #[track_caller]
+    pub fn close(self) -> Self {
+        self.0.close();
+        self
+    }
+
+    /// See [`Response::keep_alive`].
+    #[inline]
+    #[track_caller]
+    pub fn keep_alive(self, keep_alive: bool) -> Self {
+        self.0.keep_alive(keep_alive);
+        self
+    }
+
+    /// See [`Response::close_after`].
+    #[inline]
+    #[track_caller]
+    pub fn close_after(self, remaining: usize) -> Self {
+        self.0.close_after(remaining);
+        self
+    }
+
+    /// See [`Response::reply`].
+    #[inline]
+    #[track_caller]
+    pub fn reply<T: WriteBuffer>(self, status: StatusCode, data: T) -> Handled {
+        self.0.reply(status, data)
+    }
+
+    /// See [`Response::error_json`].
+    #[inline]
+    #[track_caller]
+    pub fn error_json(self, status: StatusCode, code: &str, message: &str) -> Handled {
+        self.0.error_json(status, code, message)
+    }
+
+    /// Writes a `206 Partial Content` response, see [`Response::partial`].
+    #[inline]
+    #[track_caller]
+    pub fn partial<T: WriteBuffer>(
+        self,
+        start: usize,
+        end: usize,
+        total: usize,
+        data: T,
+    ) -> Handled {
+        self.0.partial(start, end, total, data)
+    }
+
+    /// See [`Response::from_template`].
+    #[inline]
+    #[track_caller]
+    pub fn from_template(self, template: &ResponseTemplate) -> Handled {
+        self.0.from_template(template)
+    }
+
+    /// See [`Response::close_without_response`].
+    #[inline]
+    #[track_caller]
+    pub fn close_without_response(self) -> Handled {
+        self.0.close_without_response()
+    }
+}
+
+impl ResponseHeaders<'_> {
+    /// See [`Response::header`].
+    #[inline]
+    #[track_caller]
+    pub fn header<N: WriteBuffer, V: WriteBuffer>(self, name: N, value: V) -> Self {
+        self.0.header(name, value);
+        self
+    }
+
+    /// See [`Response::header_multi`].
+    #[inline]
+    #[track_caller]
+    pub fn header_multi<N, S, I, V>(self, name: N, split: S, values: I) -> Self
+    where
+        N: WriteBuffer,
+        S: WriteBuffer,
+        I: IntoIterator<Item = V>,
+        V: WriteBuffer,
+    {
+        self.0.header_multi(name, split, values);
+        self
+    }
+
+    /// See [`Response::header_params`].
+    #[inline]
+    #[track_caller]
+    pub fn header_params<N, S, I, K, V>(self, name: N, split: S, params: I) -> Self
+    where
+        N: WriteBuffer,
+        S: WriteBuffer,
+        I: IntoIterator<Item = (K, Option<V>)>,
+        K: WriteBuffer,
+        V: WriteBuffer,
+    {
+        self.0.header_params(name, split, params);
+        self
+    }
+
+    /// See [`Response::header_date`].
+    #[inline]
+    #[track_caller]
+    pub fn header_date<N: WriteBuffer>(self, name: N, time: SystemTime) -> Self {
+        self.0.header_date(name, time);
+        self
+    }
+
+    /// See [`Response::header_duration_secs`].
+    #[inline]
+    #[track_caller]
+    pub fn header_duration_secs<N: WriteBuffer>(self, name: N, duration: Duration) -> Self {
+        self.0.header_duration_secs(name, duration);
+        self
+    }
+
+    /// See [`Response::alt_svc`].
+    #[inline]
+    #[track_caller]
+    pub fn alt_svc(self, value: &[u8]) -> Self {
+        self.0.alt_svc(value);
+        self
+    }
 
-        self.buffer
-            .extend_from_slice(Self::get_prefix(&status).as_bytes());
-        self.buffer
-            .extend_from_slice(status.to_first_line(Version::Http09));
+    /// See [`Response::content_range`].
+    #[inline]
+    #[track_caller]
+    pub fn content_range(self, start: usize, end: usize, total: usize) -> Self {
+        self.0.content_range(start, end, total);
+        self
+    }
 
-        self.state = ResponseState::Complete;
-        Handled(())
+    /// See [`Response::close`].
+    #[inline]
+    #[track_caller]
+    pub fn close(self) -> Self {
+        self.0.close();
+        self
     }
 
-    /// Writes a custom message response in `HTTP/0.9+` format and finalizes it.
-    ///
-    /// Uses semantic prefixes based on status code range:
-    /// - `5xx`: `SERVER_ERROR: [code] [custom_message]\r\n`
-    /// - `4xx`: `CLIENT_ERROR: [code] [custom_message]\r\n`
-    /// - `3xx`: `REDIRECT: [code] [custom_message]\r\n`
-    /// - `2xx`: `SUCCESS: [code] [custom_message]\r\n`
-    /// - `1xx`: `INFO: [code] [custom_message]\r\n`
-    ///
-    /// # Examples
-    /// ```
-    /// # maker_web::docs_rs_helper::run_test(|req, resp| {
-    /// # let invalid_input = true;
-    /// use maker_web::{StatusCode, Version};
-    ///
-    /// // Custom error message for HTTP/0.9+
-    /// if req.version() == Version::Http09 {
-    ///     if invalid_input {
-    ///         return resp.http09_msg(StatusCode::BadRequest, "Invalid query parameters");
-    ///     }
-    ///     resp.http09("success")
-    /// } else {
-    ///     resp.status(StatusCode::BadRequest).body("Invalid query parameters")
-    /// }
-    /// # });
-    /// ```
-    /// Success with custom data:
-    /// ```
-    /// # maker_web::docs_rs_helper::run_test(|req, resp| {
-    /// use maker_web::{StatusCode, Version};
-    ///
-    /// // Success response with structured data
-    /// if req.version() == Version::Http09 {
-    ///     resp.http09_msg(StatusCode::Ok, r#"{"status":"ok","id":12345}"#)
-    /// } else {
-    ///     resp.status(StatusCode::Ok)
-    ///         .header("content-type", "application/json")
-    ///         .body(r#"{"status":"ok","id":12345}"#)
-    /// }
-    /// # });
-    /// ```
+    /// See [`Response::keep_alive`].
     #[inline]
     #[track_caller]
-    pub fn http09_msg<T: WriteBuffer>(&mut self, status: StatusCode, value: T) -> Handled {
-        debug_assert!(
-            self.version == Version::Http09,
-            "This method is only for `HTTP/0.9+`"
-        );
-        debug_assert!(
-            self.state == ResponseState::Clean,
-            "An `HTTP/0.9+` response must use exactly one method"
-        );
+    pub fn keep_alive(self, keep_alive: bool) -> Self {
+        self.0.keep_alive(keep_alive);
+        self
+    }
 
-        self.buffer
-            .extend_from_slice(Self::get_prefix(&status).as_bytes());
-        self.buffer.extend_from_slice(status.as_u16_bytes());
-        value.write_to(&mut self.buffer);
-        self.buffer.extend_from_slice(b"\r\n");
+    /// See [`Response::close_after`].
+    #[inline]
+    #[track_caller]
+    pub fn close_after(self, remaining: usize) -> Self {
+        self.0.close_after(remaining);
+        self
+    }
 
-        self.state = ResponseState::Complete;
-        Handled(())
+    /// Writes the body and finishes the response, see [`Response::body`].
+    #[inline]
+    #[track_caller]
+    pub fn body<T: WriteBuffer>(self, data: T) -> Handled {
+        self.0.body(data)
     }
 
+    /// Writes the body with `f` and finishes the response, see [`Response::body_with`].
     #[inline]
-    const fn get_prefix(status: &StatusCode) -> &str {
-        match *status as u16 {
-            400..=499 => "CLIENT_ERROR:",
-            500..=599 => "SERVER_ERROR:",
-            300..=399 => "REDIRECT:",
-            200..=299 => "SUCCESS:",
-            100..=199 => "INFO:",
-            _ => "?:",
-        }
+    #[track_caller]
+    pub fn body_with<F: FnOnce(&mut BodyWriter)>(self, f: F) -> Handled {
+        self.0.body_with(f)
+    }
+
+    /// Renders the body from a template and finishes the response, see
+    /// [`Response::body_template`].
+    #[inline]
+    #[track_caller]
+    pub fn body_template(self, template: &Template, values: &[&dyn WriteBuffer]) -> Handled {
+        self.0.body_template(template, values)
+    }
+
+    /// Awaits the body and finishes the response, see [`Response::body_async`].
+    #[inline]
+    pub async fn body_async<F, Fut, T>(self, f: F) -> Handled
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+        T: WriteBuffer,
+    {
+        self.0.body_async(f).await
+    }
+
+    /// Writes the body with the async builder `f` and finishes the response,
+    /// see [`Response::body_with_async`].
+    #[inline]
+    pub async fn body_with_async<F, Fut>(self, f: F) -> Handled
+    where
+        F: FnOnce(YieldingBodyWriter) -> Fut,
+        Fut: Future<Output = YieldingBodyWriter>,
+    {
+        self.0.body_with_async(f).await
+    }
+}
+
+impl ResponseComplete<'_> {
+    /// See [`Response::insert_header`].
+    #[inline]
+    #[track_caller]
+    pub fn insert_header<N: WriteBuffer, V: WriteBuffer>(self, name: N, value: V) -> Self {
+        self.0.insert_header(name, value);
+        self
     }
 }
 
@@ -866,10 +2296,134 @@ pub mod write {
         }
     }
 
+    /// Writer for constructing the HTTP response body from an async builder.
+    /// Used in [body_with_async](Response::body_with_async).
+    ///
+    /// Owns the response buffer while the builder runs. If the builder is
+    /// dropped before it returns the writer (cancelled or panicked), the
+    /// buffer goes with it: the next response on the connection allocates
+    /// [`RespLimits::default_capacity`](crate::limits::RespLimits::default_capacity)
+    /// once again, then reuses it as usual.
+    ///
+    /// The buffer doesn't grow over [`RespLimits::hard_max_body`] and
+    /// [`RespLimits::max_response_size`](crate::limits::RespLimits::max_response_size):
+    /// the writes after the limit are only counted, and the response is
+    /// replaced with `500` (see [`Response::overflow`]).
+    ///
+    /// [`RespLimits::hard_max_body`]: crate::limits::RespLimits::hard_max_body
+    #[derive(Debug)]
+    pub struct YieldingBodyWriter {
+        pub(crate) buffer: Vec<u8>,
+        limit: usize,
+        // Bytes written after the limit
+        pub(crate) dropped: usize,
+        yielded_at: usize,
+        yield_every: usize,
+    }
+
+    impl YieldingBodyWriter {
+        const DEFAULT_YIELD_EVERY: usize = 64 * 1024;
+
+        #[inline]
+        pub(crate) fn new(buffer: Vec<u8>, limit: usize) -> Self {
+            Self {
+                yielded_at: buffer.len(),
+                buffer,
+                limit,
+                dropped: 0,
+                yield_every: Self::DEFAULT_YIELD_EVERY,
+            }
+        }
+
+        /// Appends content to the response body, see [`BodyWriter::write`].
+        #[inline]
+        pub fn write<T: WriteBuffer>(&mut self, value: T) {
+            let len = self.buffer.len();
+            value.write_to(&mut self.buffer);
+            self.check_limit(len);
+        }
+
+        /// Whether the body went over the limit, the rest of it can be skipped.
+        #[inline]
+        pub fn is_over_limit(&self) -> bool {
+            self.dropped != 0
+        }
+
+        #[inline(always)]
+        fn check_limit(&mut self, len: usize) {
+            if self.dropped != 0 || self.buffer.len() > self.limit {
+                self.dropped += self.buffer.len() - len;
+                self.buffer.truncate(len);
+            }
+        }
+
+        #[inline(always)]
+        fn written(&self) -> usize {
+            self.buffer.len() + self.dropped
+        }
+
+        /// Sets how many bytes are written between two yields of
+        /// [`maybe_yield()`](Self::maybe_yield) (default: 64 KB).
+        #[inline]
+        pub fn yield_every(&mut self, bytes: usize) -> &mut Self {
+            self.yield_every = bytes.max(1);
+            self
+        }
+
+        /// Yields to the runtime once [`yield_every()`](Self::yield_every)
+        /// bytes have been written since the last yield, otherwise returns
+        /// at once.
+        ///
+        /// Works like `tokio::task::yield_now()`: the task is woken at once
+        /// and goes to the back of the run queue, behind the tasks that were
+        /// waiting for the thread.
+        #[inline]
+        pub async fn maybe_yield(&mut self) {
+            if self.written() - self.yielded_at >= self.yield_every {
+                self.yielded_at = self.written();
+                YieldNow(false).await;
+            }
+        }
+    }
+
+    impl std::io::Write for YieldingBodyWriter {
+        #[inline]
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let len = self.buffer.len();
+            self.buffer.extend_from_slice(buf);
+            self.check_limit(len);
+            Ok(buf.len())
+        }
+
+        #[inline]
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Pending once, runtime-independent: the parser part doesn't depend on tokio
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<()> {
+            if self.0 {
+                return std::task::Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    }
+
     /// Trait for writing data to the [`Response`] buffer.
     ///
-    /// Implemented for common types like strings, bytes, booleans
-    /// and numeric types (excluding floating-point numbers)
+    /// Implemented for common types like strings, bytes, booleans,
+    /// numeric types (excluding floating-point numbers), IP and socket addresses
     ///
     /// # Note on Floating-Point
     /// Floating-point numbers are not implemented to avoid locale-dependent
@@ -924,64 +2478,346 @@ pub mod write {
         };
     }
 
-    impl<T: WriteBuffer> WriteBuffer for &T {
-        #[inline]
+    impl<T: WriteBuffer> WriteBuffer for &T {
+        #[inline]
+        fn write_to(&self, buffer: &mut Vec<u8>) {
+            T::write_to(*self, buffer);
+        }
+    }
+    impl<T: WriteBuffer> WriteBuffer for &mut T {
+        #[inline]
+        fn write_to(&self, buffer: &mut Vec<u8>) {
+            T::write_to(*self, buffer);
+        }
+    }
+    impl_write_buffer! {
+        bytes, |value: &str, buffer: &mut Vec<u8>| {
+            buffer.extend_from_slice(value.as_bytes());
+        } => &str, String, Box<str>, Cow<'_, str>,
+        Arc<str>, Rc<str>, Arc<String>, Rc<String>
+    }
+    impl_write_buffer! {
+        bytes, |value: &[u8], buffer: &mut Vec<u8>| {
+            buffer.extend_from_slice(value);
+        } => &[u8], Vec<u8>, Box<[u8]>, Cow<'_, [u8]>,
+        Arc<[u8]>, Rc<[u8]>, Arc<Vec<u8>>, Rc<Vec<u8>>
+    }
+    impl<const N: usize> WriteBuffer for [u8; N] {
+        #[inline]
+        fn write_to(&self, buffer: &mut Vec<u8>) {
+            buffer.extend_from_slice(self);
+        }
+    }
+    impl_write_buffer! {
+        number(u128), impl_write_buffer_u128 => u8, u16, u32, u64, u128, usize
+    }
+    impl_write_buffer! {
+        non_zero(u128), impl_write_buffer_u128 => NonZeroU8,
+        NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU128, NonZeroUsize
+    }
+    impl_write_buffer! {
+        number(i128), impl_write_buffer_i128 => i8, i16, i32, i64, i128, isize
+    }
+    impl_write_buffer! {
+        non_zero(i128), impl_write_buffer_i128 => NonZeroI8,
+        NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI128, NonZeroIsize
+    }
+    impl WriteBuffer for bool {
+        #[inline]
+        fn write_to(&self, buffer: &mut Vec<u8>) {
+            buffer.extend_from_slice(match self {
+                true => b"true",
+                false => b"false",
+            });
+        }
+    }
+    impl WriteBuffer for char {
+        #[inline]
+        fn write_to(&self, buffer: &mut Vec<u8>) {
+            let mut buf = [0u8; 4];
+            buffer.extend_from_slice(self.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+
+    /// A point in time written in the `IMF-fixdate` format of HTTP dates
+    /// ([RFC 9110](https://datatracker.ietf.org/doc/html/rfc9110#section-5.6.7)),
+    /// e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+    ///
+    /// Fractions of a second are dropped, times before 1970 are written as
+    /// the Unix epoch. See also [`Response::header_date`].
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{HttpDate, WriteBuffer};
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let mut buffer = Vec::new();
+    /// HttpDate::from(UNIX_EPOCH + Duration::from_secs(784111777)).write_to(&mut buffer);
+    /// assert_eq!(buffer, b"Sun, 06 Nov 1994 08:49:37 GMT");
+    /// ```
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct HttpDate(SystemTime);
+
+    impl From<SystemTime> for HttpDate {
+        #[inline]
+        fn from(time: SystemTime) -> Self {
+            Self(time)
+        }
+    }
+
+    impl From<HttpDate> for SystemTime {
+        #[inline]
+        fn from(date: HttpDate) -> Self {
+            date.0
+        }
+    }
+
+    impl HttpDate {
+        /// Parses an `IMF-fixdate`, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+        ///
+        /// The day name is not checked. Returns `None` for the obsolete
+        /// `rfc850` and `asctime` formats, dates before 1970 and values
+        /// out of range.
+        ///
+        /// # Examples
+        /// ```
+        /// use maker_web::HttpDate;
+        /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+        ///
+        /// let date = HttpDate::parse(b"Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        /// assert_eq!(SystemTime::from(date), UNIX_EPOCH + Duration::from_secs(784111777));
+        /// assert_eq!(HttpDate::parse(b"Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        /// ```
+        pub fn parse(value: &[u8]) -> Option<Self> {
+            const MONTHS: [&[u8]; 12] = [
+                b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct",
+                b"Nov", b"Dec",
+            ];
+
+            // `Sun, 06 Nov 1994 08:49:37 GMT`
+            #[rustfmt::skip]
+            let [
+                _, _, _, b',', b' ',
+                d1, d2, b' ', m1, m2, m3, b' ', y1, y2, y3, y4, b' ',
+                h1, h2, b':', mi1, mi2, b':', s1, s2, b' ', b'G', b'M', b'T'
+            ] = *value else {
+                return None;
+            };
+            let digits = |bytes: &[u8]| {
+                bytes.iter().try_fold(0u64, |n, b| {
+                    b.is_ascii_digit().then(|| n * 10 + (b - b'0') as u64)
+                })
+            };
+
+            let day = digits(&[d1, d2])?;
+            let month = MONTHS.iter().position(|m| *m == [m1, m2, m3])? as u64 + 1;
+            let year = digits(&[y1, y2, y3, y4])?;
+            let (hour, min, sec) = (digits(&[h1, h2])?, digits(&[mi1, mi2])?, digits(&[s1, s2])?);
+            if !(1..=31).contains(&day) || year < 1970 || hour > 23 || min > 59 || sec > 60 {
+                return None;
+            }
+
+            // Civil date to days since 1970-01-01, the inverse of `write_to`
+            let year = year - (month <= 2) as u64;
+            let era = year / 400;
+            let yoe = year % 400;
+            let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+            let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+            let days = era * 146097 + doe - 719468;
+
+            let secs = days * 86400 + hour * 3600 + min * 60 + sec;
+            Some(Self(UNIX_EPOCH + Duration::from_secs(secs)))
+        }
+    }
+
+    impl WriteBuffer for HttpDate {
+        fn write_to(&self, buffer: &mut Vec<u8>) {
+            const WEEKDAYS: [&[u8]; 7] = [b"Thu", b"Fri", b"Sat", b"Sun", b"Mon", b"Tue", b"Wed"];
+            const MONTHS: [&[u8]; 12] = [
+                b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct",
+                b"Nov", b"Dec",
+            ];
+
+            let secs = self.0.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let (days, secs) = (secs / 86400, secs % 86400);
+
+            // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+            let z = days + 719468;
+            let era = z / 146097;
+            let doe = z % 146097;
+            let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+            let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+            let mp = (5 * doy + 2) / 153;
+            let day = doy - (153 * mp + 2) / 5 + 1;
+            let month = if mp < 10 { mp + 3 } else { mp - 9 };
+            let year = yoe + era * 400 + (month <= 2) as u64;
+
+            let two_digits = |buffer: &mut Vec<u8>, n: u64| {
+                buffer.extend_from_slice(&[b'0' + (n / 10) as u8, b'0' + (n % 10) as u8]);
+            };
+
+            buffer.extend_from_slice(WEEKDAYS[(days % 7) as usize]);
+            buffer.extend_from_slice(b", ");
+            two_digits(buffer, day);
+            buffer.push(b' ');
+            buffer.extend_from_slice(MONTHS[month as usize - 1]);
+            buffer.push(b' ');
+            year.write_to(buffer);
+            buffer.push(b' ');
+            two_digits(buffer, secs / 3600);
+            buffer.push(b':');
+            two_digits(buffer, secs / 60 % 60);
+            buffer.push(b':');
+            two_digits(buffer, secs % 60);
+            buffer.extend_from_slice(b" GMT");
+        }
+    }
+
+    /// A fixed-point decimal: `value` in units of `10^-SCALE`,
+    /// e.g. cents with `SCALE = 2`.
+    ///
+    /// Written with exactly `SCALE` fractional digits, without floating point
+    /// or rounding: `Decimal::<2>(1234)` is `12.34`, `Decimal::<2>(-5)` is
+    /// `-0.05` and `Decimal::<3>(7000)` is `7.000`. With `SCALE = 0` it is
+    /// written as the integer.
+    ///
+    /// # Examples
+    /// ```
+    /// # maker_web::docs_rs_helper::run_test(|_, resp| {
+    /// use maker_web::{Decimal, StatusCode};
+    ///
+    /// let price_cents = 1234;
+    ///
+    /// resp.status(StatusCode::Ok)
+    ///     .header("content-type", "application/json")
+    ///     .body_with(|w| {
+    ///         w.write(r#"{"price":"#);
+    ///         w.write(Decimal::<2>(price_cents));
+    ///         w.write("}");
+    ///     })
+    /// # });
+    /// ```
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Decimal<const SCALE: u32>(pub i128);
+
+    impl<const SCALE: u32> WriteBuffer for Decimal<SCALE> {
         fn write_to(&self, buffer: &mut Vec<u8>) {
-            T::write_to(*self, buffer);
+            if self.0 < 0 {
+                buffer.push(b'-');
+            }
+            let (arr, start) = Response::number_to_bytes(self.0.unsigned_abs());
+            let digits = &arr[start..];
+            let scale = SCALE as usize;
+
+            if scale == 0 {
+                buffer.extend_from_slice(digits);
+            } else if digits.len() > scale {
+                let (int, fraction) = digits.split_at(digits.len() - scale);
+                buffer.extend_from_slice(int);
+                buffer.push(b'.');
+                buffer.extend_from_slice(fraction);
+            } else {
+                buffer.extend_from_slice(b"0.");
+                buffer.resize(buffer.len() + scale - digits.len(), b'0');
+                buffer.extend_from_slice(digits);
+            }
         }
     }
-    impl<T: WriteBuffer> WriteBuffer for &mut T {
+
+    impl WriteBuffer for Ipv4Addr {
         #[inline]
         fn write_to(&self, buffer: &mut Vec<u8>) {
-            T::write_to(*self, buffer);
+            let [a, b, c, d] = self.octets();
+            a.write_to(buffer);
+            for octet in [b, c, d] {
+                buffer.push(b'.');
+                octet.write_to(buffer);
+            }
         }
     }
-    impl_write_buffer! {
-        bytes, |value: &str, buffer: &mut Vec<u8>| {
-            buffer.extend_from_slice(value.as_bytes());
-        } => &str, String, Box<str>, Cow<'_, str>,
-        Arc<str>, Rc<str>, Arc<String>, Rc<String>
-    }
-    impl_write_buffer! {
-        bytes, |value: &[u8], buffer: &mut Vec<u8>| {
-            buffer.extend_from_slice(value);
-        } => &[u8], Vec<u8>, Box<[u8]>, Cow<'_, [u8]>,
-        Arc<[u8]>, Rc<[u8]>, Arc<Vec<u8>>, Rc<Vec<u8>>
-    }
-    impl<const N: usize> WriteBuffer for [u8; N] {
-        #[inline]
+    impl WriteBuffer for Ipv6Addr {
+        /// Writes the [RFC 5952](https://datatracker.ietf.org/doc/html/rfc5952)
+        /// form, the same as [`Display`](std::fmt::Display)
         fn write_to(&self, buffer: &mut Vec<u8>) {
-            buffer.extend_from_slice(self);
+            if let Some(v4) = self.to_ipv4_mapped() {
+                buffer.extend_from_slice(b"::ffff:");
+                return v4.write_to(buffer);
+            }
+
+            let segments = self.segments();
+            // The longest run of at least two zero segments, the first one on a tie
+            let (mut zeros_at, mut zeros_len) = (usize::MAX, 1);
+            let mut i = 0;
+            while i < segments.len() {
+                let start = i;
+                while i < segments.len() && segments[i] == 0 {
+                    i += 1;
+                }
+                if i - start > zeros_len {
+                    (zeros_at, zeros_len) = (start, i - start);
+                }
+                i += 1;
+            }
+
+            // "xxxx:" * 8 fits into 40 bytes
+            let mut scratch = [0u8; 40];
+            let mut len = 0;
+            let mut i = 0;
+            while i < segments.len() {
+                if i == zeros_at {
+                    if i == 0 {
+                        scratch[len] = b':';
+                        len += 1;
+                    }
+                    scratch[len] = b':';
+                    len += 1;
+                    i += zeros_len;
+                    continue;
+                }
+
+                let segment = segments[i];
+                let digits = (16 - segment.leading_zeros() as usize).div_ceil(4).max(1);
+                for shift in (0..digits).rev() {
+                    scratch[len] = b"0123456789abcdef"[(segment >> (shift * 4)) as usize & 0xf];
+                    len += 1;
+                }
+                if i + 1 < segments.len() {
+                    scratch[len] = b':';
+                    len += 1;
+                }
+                i += 1;
+            }
+
+            buffer.extend_from_slice(&scratch[..len]);
         }
     }
-    impl_write_buffer! {
-        number(u128), impl_write_buffer_u128 => u8, u16, u32, u64, u128, usize
-    }
-    impl_write_buffer! {
-        non_zero(u128), impl_write_buffer_u128 => NonZeroU8,
-        NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU128, NonZeroUsize
-    }
-    impl_write_buffer! {
-        number(i128), impl_write_buffer_i128 => i8, i16, i32, i64, i128, isize
-    }
-    impl_write_buffer! {
-        non_zero(i128), impl_write_buffer_i128 => NonZeroI8,
-        NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI128, NonZeroIsize
-    }
-    impl WriteBuffer for bool {
+    impl WriteBuffer for IpAddr {
         #[inline]
         fn write_to(&self, buffer: &mut Vec<u8>) {
-            buffer.extend_from_slice(match self {
-                true => b"true",
-                false => b"false",
-            });
+            match self {
+                IpAddr::V4(ip) => ip.write_to(buffer),
+                IpAddr::V6(ip) => ip.write_to(buffer),
+            }
         }
     }
-    impl WriteBuffer for char {
+    impl WriteBuffer for SocketAddr {
+        /// Writes `ip:port` or `[ip%scope]:port`
         #[inline]
         fn write_to(&self, buffer: &mut Vec<u8>) {
-            let mut buf = [0u8; 4];
-            buffer.extend_from_slice(self.encode_utf8(&mut buf).as_bytes());
+            match self {
+                SocketAddr::V4(addr) => addr.ip().write_to(buffer),
+                SocketAddr::V6(addr) => {
+                    buffer.push(b'[');
+                    addr.ip().write_to(buffer);
+                    if addr.scope_id() != 0 {
+                        buffer.push(b'%');
+                        addr.scope_id().write_to(buffer);
+                    }
+                    buffer.push(b']');
+                }
+            }
+            buffer.push(b':');
+            self.port().write_to(buffer);
         }
     }
 
@@ -1003,6 +2839,34 @@ pub mod write {
     }
 }
 
+// Escapes `value` for use inside a JSON string
+fn write_json_str(buffer: &mut Vec<u8>, value: &str) {
+    let mut start = 0;
+    for (i, byte) in value.bytes().enumerate() {
+        let escape: &[u8] = match byte {
+            b'"' => b"\\\"",
+            b'\\' => b"\\\\",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\t' => b"\\t",
+            0..=0x1f => b"",
+            _ => continue,
+        };
+
+        buffer.extend_from_slice(&value.as_bytes()[start..i]);
+        match escape.is_empty() {
+            true => {
+                buffer.extend_from_slice(b"\\u00");
+                buffer.push(b"0123456789abcdef"[(byte >> 4) as usize]);
+                buffer.push(b"0123456789abcdef"[(byte & 0xf) as usize]);
+            }
+            false => buffer.extend_from_slice(escape),
+        }
+        start = i + 1;
+    }
+    buffer.extend_from_slice(&value.as_bytes()[start..]);
+}
+
 #[cfg(test)]
 mod close_tests {
     use super::*;
@@ -1041,7 +2905,10 @@ mod close_tests {
     }
 
     #[test]
-    #[should_panic(expected = "Must be called before any finalizing method")]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be called before any finalizing method (`close()`, state: Complete, version: Http11)"
+    )]
     fn after_body() {
         let mut resp = Response::new(&RespLimits::default());
         resp.status(StatusCode::Ok).body("");
@@ -1049,6 +2916,69 @@ mod close_tests {
     }
 }
 
+#[cfg(test)]
+mod keep_alive_tests {
+    use super::*;
+    use crate::tools::*;
+
+    #[test]
+    fn basic() {
+        let cases = [
+            // The request's default, then the header for `keep_alive(true)` and `(false)`
+            (
+                Version::Http10,
+                false,
+                "connection: keep-alive\r\n",
+                "connection: close\r\n",
+            ),
+            (Version::Http11, true, "", "connection: close\r\n"),
+        ];
+
+        for (version, default, keep_header, close_header) in cases {
+            for (keep_alive, header) in [(true, keep_header), (false, close_header)] {
+                let mut resp = Response::new(&RespLimits::default());
+                resp.version = version;
+                resp.keep_alive = default;
+
+                resp.status(StatusCode::Ok).keep_alive(keep_alive).body("");
+                assert_eq!(resp.keep_alive, keep_alive);
+                assert_eq!(
+                    str_op(&resp.buffer),
+                    format!(
+                        "{}{header}content-length: 0000000000\r\n\r\n",
+                        str_op(StatusCode::Ok.to_first_line(version))
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn close_required() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.require_close();
+
+        resp.keep_alive(true).status(StatusCode::Ok).body("");
+        assert!(!resp.keep_alive);
+        assert!(str_op(&resp.buffer).contains("connection: close\r\n"));
+
+        resp.reset(&RespLimits::default());
+        resp.keep_alive(false).keep_alive(true);
+        assert!(resp.keep_alive);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be called before any finalizing method (`keep_alive()`, state: Complete, version: Http11)"
+    )]
+    fn after_body() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.status(StatusCode::Ok).body("");
+        resp.keep_alive(true);
+    }
+}
+
 #[cfg(test)]
 mod close_without_response_tests {
     use super::*;
@@ -1063,6 +2993,7 @@ mod close_without_response_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "The response must be empty and incomplete")]
     fn after_any_method() {
         let mut resp = Response::new(&RespLimits::default());
@@ -1070,6 +3001,7 @@ mod close_without_response_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "The response must be empty and incomplete")]
     fn double_call() {
         let mut resp = Response::new(&RespLimits::default());
@@ -1105,6 +3037,7 @@ mod status_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "Must be first and called only once")]
     fn double_call() {
         Response::new(&RespLimits::default())
@@ -1113,7 +3046,10 @@ mod status_tests {
     }
 
     #[test]
-    #[should_panic(expected = "This method is only for `HTTP/1.X`")]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "This method is only for `HTTP/1.X` (`status()`, state: Clean, version: Http09)"
+    )]
     fn http09_panic() {
         let mut resp = Response::new(&RespLimits::default());
         resp.version = Version::Http09;
@@ -1154,14 +3090,74 @@ mod header_tests {
         ("name", -123; "name: -123\r\n");
     }
 
+    test_header! {header_date,
+        ("last-modified", UNIX_EPOCH; "last-modified: Thu, 01 Jan 1970 00:00:00 GMT\r\n");
+        (
+            "date", UNIX_EPOCH + Duration::from_millis(784_111_777_999);
+            "date: Sun, 06 Nov 1994 08:49:37 GMT\r\n"
+        );
+        // Leap day and the last second of a year
+        (
+            "expires", UNIX_EPOCH + Duration::from_secs(951_782_400);
+            "expires: Tue, 29 Feb 2000 00:00:00 GMT\r\n"
+        );
+        (
+            "expires", UNIX_EPOCH + Duration::from_secs(4_102_444_799);
+            "expires: Thu, 31 Dec 2099 23:59:59 GMT\r\n"
+        );
+        ("expires", UNIX_EPOCH - Duration::from_secs(1); "expires: Thu, 01 Jan 1970 00:00:00 GMT\r\n");
+    }
+
+    test_header! {header_duration_secs,
+        ("retry-after", Duration::from_secs(120); "retry-after: 120\r\n");
+        ("retry-after", Duration::from_millis(1999); "retry-after: 1\r\n");
+        ("x-max-age", Duration::ZERO; "x-max-age: 0\r\n");
+    }
+
+    test_header! {alt_svc,
+        (br#"h3=":443"; ma=86400"#; "alt-svc: h3=\":443\"; ma=86400\r\n");
+        (br#"h2="alt.example.com:8443", h3=":443""#; "alt-svc: h2=\"alt.example.com:8443\", h3=\":443\"\r\n");
+        (b"clear"; "alt-svc: clear\r\n");
+    }
+
+    test_header! {content_range,
+        (0, 0, 1; "content-range: bytes 0-0/1\r\n");
+        (0, 99, 1000; "content-range: bytes 0-99/1000\r\n");
+        (500, 999, 1000; "content-range: bytes 500-999/1000\r\n");
+    }
+
     #[test]
-    #[should_panic(expected = "Must be called after status() and before any body method")]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Range must satisfy start <= end < total")]
+    fn content_range_outside() {
+        Response::new(&RespLimits::default())
+            .status(StatusCode::PartialContent)
+            .content_range(10, 1000, 1000);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Range must satisfy start <= end < total")]
+    fn content_range_reversed() {
+        Response::new(&RespLimits::default())
+            .status(StatusCode::PartialContent)
+            .content_range(5, 4, 1000);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be called after status() and before any body method (`header()` for header `Name`, state: Clean, version: Http11)"
+    )]
     fn header_before_status() {
         Response::new(&RespLimits::default()).header("Name", "value");
     }
 
     #[test]
-    #[should_panic(expected = "Must be called after status() and before any body method")]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be called after status() and before any body method (`header()` for header `Name`, state: Complete, version: Http11)"
+    )]
     fn header_after_body() {
         let mut resp = Response::new(&RespLimits::default());
         resp.status(StatusCode::Ok).body("");
@@ -1175,74 +3171,263 @@ mod header_tests {
     }
 
     #[test]
-    #[should_panic(expected = "Must be called after status() and before any body method")]
-    fn header_multi_before_status() {
-        Response::new(&RespLimits::default()).header_multi("Name", ",", ["value1", "value2"]);
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Must be called after status() and before any body method")]
+    fn header_multi_before_status() {
+        Response::new(&RespLimits::default()).header_multi("Name", ",", ["value1", "value2"]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be called after status() and before any body method (`header_multi()` for header `Name`, state: Complete, version: Http11)"
+    )]
+    fn header_multi_after_body() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.status(StatusCode::Ok).body("");
+        resp.header_multi("Name", ",", ["value1", "value2"]);
+    }
+
+    test_header! {header_params,
+        ("name", ", ", [("name", Some("value"))]; "name: name=value\r\n");
+        (
+            "name", ", ", [("q", Some("1")), ("w", Some("2")), ("e", Some("3"))];
+            "name: q=1, w=2, e=3\r\n"
+        );
+        (
+            "name", ";", [("q", Some("v1")), ("w", Some("v2")), ("e", Some("v3"))];
+            "name: q=v1;w=v2;e=v3\r\n"
+        );
+        (
+            "name", ", ", [("min", Some(-128)), ("max", Some(128)), ("mean", Some(0))];
+            "name: min=-128, max=128, mean=0\r\n"
+        );
+        (
+            "u128", ", ", [("min", Some(u128::MIN)), ("max", Some(u128::MAX))];
+            "u128: min=0, max=340282366920938463463374607431768211455\r\n"
+        );
+        (
+            "i128", ", ", [("min", Some(i128::MIN)), ("max", Some(i128::MAX))];
+    "i128: min=-170141183460469231731687303715884105728, max=170141183460469231731687303715884105727\r\n"
+        );
+        (
+            "name", ", ", [("debug", Some(true)), ("doc", Some(false))];
+            "name: debug=true, doc=false\r\n"
+        );
+        (
+            "name", "; ", [("debug", None), ("text", Some("asd"))];
+            "name: debug; text=asd\r\n"
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Must be called after status() and before any body method")]
+    fn header_params_before_status() {
+        Response::new(&RespLimits::default()).header_params(
+            "Name",
+            ",",
+            [("name1", Some("value1")), ("name2", None)],
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be called after status() and before any body method (`header_params()` for header `Name`, state: Complete, version: Http11)"
+    )]
+    fn header_params_after_body() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.status(StatusCode::Ok).body("");
+        resp.header_params("Name", ",", [("name1", Some("value1")), ("name2", None)]);
+    }
+
+    #[test]
+    fn insert_header() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.status(StatusCode::Ok).header("a", 1).body("body");
+        resp.insert_header("b", 2).insert_header("c", "3");
+
+        assert_eq!(
+            str_op(&resp.buffer),
+            "HTTP/1.1 200 OK\r\na: 1\r\ncontent-length: 0000000004\r\nb: 2\r\nc: 3\r\n\r\nbody"
+        );
+        assert_eq!(resp.state, ResponseState::Complete);
+
+        // The replacement `500` of an oversized body gets the header too
+        let mut resp = Response::new(&RespLimits {
+            hard_max_body: 2,
+            ..RespLimits::default()
+        });
+        resp.status(StatusCode::Ok).body("body");
+        resp.insert_header("b", 2);
+        assert!(str_op(&resp.buffer).ends_with("content-length: 0\r\nb: 2\r\n\r\n"));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Must be called after a body method of an `HTTP/1.X` response")]
+    fn insert_header_before_body() {
+        Response::new(&RespLimits::default())
+            .status(StatusCode::Ok)
+            .insert_header("Name", "value");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be called after a body method of an `HTTP/1.X` response (`insert_header()` for header `Name`, state: Complete, version: Http09)"
+    )]
+    fn insert_header_http09() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.version = Version::Http09;
+        resp.http09("data");
+        resp.insert_header("Name", "value");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Header value must not contain CR or LF")]
+    fn insert_header_injection() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.status(StatusCode::Ok).body("");
+        resp.insert_header("x-name", "value\r\n\r\nevil");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Header value must not contain CR or LF (header `location`, value: \"/home\\r\\nset-cookie: session=evil\")"
+    )]
+    fn header_value_injection() {
+        Response::new(&RespLimits::default())
+            .status(StatusCode::Ok)
+            .header("location", "/home\r\nset-cookie: session=evil");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Header name must not contain `:`, CR or LF (name: \"x-name: evil\")"
+    )]
+    fn header_name_injection() {
+        Response::new(&RespLimits::default())
+            .status(StatusCode::Ok)
+            .header("x-name: evil", "value");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Header value must not contain CR or LF")]
+    fn header_multi_value_injection() {
+        Response::new(&RespLimits::default())
+            .status(StatusCode::Ok)
+            .header_multi("x-tags", ", ", ["user", "admin\n"]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Header value must not contain CR or LF")]
+    fn header_params_value_injection() {
+        Response::new(&RespLimits::default())
+            .status(StatusCode::Ok)
+            .header_params("content-type", "; ", [("charset", Some("utf-8\r"))]);
+    }
+}
+
+#[cfg(test)]
+mod template_tests {
+    use super::*;
+    use crate::tools::*;
+
+    fn build(resp: &mut Response) -> Handled {
+        resp.status(StatusCode::NotFound)
+            .header("content-type", "text/plain")
+            .body("Not Found")
+    }
+
+    fn build_close(resp: &mut Response) -> Handled {
+        resp.status(StatusCode::Ok).close().body("bye")
+    }
+
+    fn response(version: Version, keep_alive: bool) -> Response {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.version = version;
+        resp.keep_alive = keep_alive;
+        resp
+    }
+
+    #[test]
+    fn same_as_builder() {
+        let builders: [fn(&mut Response) -> Handled; 2] = [build, build_close];
+
+        for f in builders {
+            let template = ResponseTemplate::build(f);
+
+            for version in [Version::Http11, Version::Http10] {
+                for keep_alive in [true, false] {
+                    let mut expected = response(version, keep_alive);
+                    f(&mut expected);
+
+                    let mut resp = response(version, keep_alive);
+                    resp.from_template(&template);
+
+                    assert_eq!(str_op(&resp.buffer), str_op(&expected.buffer));
+                    assert_eq!(resp.keep_alive, expected.keep_alive);
+                    assert_eq!(resp.state, ResponseState::Complete);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn insert_header_after_template() {
+        let template = ResponseTemplate::build(build);
+        let mut resp = response(Version::Http10, true);
+        resp.from_template(&template);
+        resp.insert_header("x-a", 1);
+
+        assert_eq!(
+            str_op(&resp.buffer),
+            "HTTP/1.0 404 Not Found\r\ncontent-type: text/plain\r\nconnection: keep-alive\r\n\
+             content-length: 0000000009\r\nx-a: 1\r\n\r\nNot Found"
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Must be called after status() and before any body method")]
-    fn header_multi_after_body() {
-        let mut resp = Response::new(&RespLimits::default());
-        resp.status(StatusCode::Ok).body("");
-        resp.header_multi("Name", ",", ["value1", "value2"]);
-    }
+    fn respects_limits() {
+        let template = ResponseTemplate::build(build);
+        let mut resp = Response::new(&RespLimits {
+            hard_max_body: 4,
+            ..RespLimits::default()
+        });
+        resp.from_template(&template);
 
-    test_header! {header_params,
-        ("name", ", ", [("name", Some("value"))]; "name: name=value\r\n");
-        (
-            "name", ", ", [("q", Some("1")), ("w", Some("2")), ("e", Some("3"))];
-            "name: q=1, w=2, e=3\r\n"
-        );
-        (
-            "name", ";", [("q", Some("v1")), ("w", Some("v2")), ("e", Some("v3"))];
-            "name: q=v1;w=v2;e=v3\r\n"
-        );
-        (
-            "name", ", ", [("min", Some(-128)), ("max", Some(128)), ("mean", Some(0))];
-            "name: min=-128, max=128, mean=0\r\n"
-        );
-        (
-            "u128", ", ", [("min", Some(u128::MIN)), ("max", Some(u128::MAX))];
-            "u128: min=0, max=340282366920938463463374607431768211455\r\n"
-        );
-        (
-            "i128", ", ", [("min", Some(i128::MIN)), ("max", Some(i128::MAX))];
-    "i128: min=-170141183460469231731687303715884105728, max=170141183460469231731687303715884105727\r\n"
-        );
-        (
-            "name", ", ", [("debug", Some(true)), ("doc", Some(false))];
-            "name: debug=true, doc=false\r\n"
-        );
-        (
-            "name", "; ", [("debug", None), ("text", Some("asd"))];
-            "name: debug; text=asd\r\n"
-        );
+        assert!(str_op(&resp.buffer).starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(!resp.keep_alive);
     }
 
     #[test]
-    #[should_panic(expected = "Must be called after status() and before any body method")]
-    fn header_params_before_status() {
-        Response::new(&RespLimits::default()).header_params(
-            "Name",
-            ",",
-            [("name1", Some("value1")), ("name2", None)],
-        );
+    #[should_panic(expected = "A template must be an `HTTP/1.X` response with a body")]
+    fn without_response() {
+        ResponseTemplate::build(|resp| resp.close_without_response());
     }
 
     #[test]
-    #[should_panic(expected = "Must be called after status() and before any body method")]
-    fn header_params_after_body() {
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "The response must be empty and incomplete")]
+    fn after_status() {
+        let template = ResponseTemplate::build(build);
         let mut resp = Response::new(&RespLimits::default());
-        resp.status(StatusCode::Ok).body("");
-        resp.header_params("Name", ",", [("name1", Some("value1")), ("name2", None)]);
+        resp.status(StatusCode::Ok);
+        resp.from_template(&template);
     }
 }
 
 #[cfg(test)]
 mod body_tests {
     use super::*;
-    use crate::tools::*;
+    use crate::{errors::ErrorKind, tools::*};
 
     macro_rules! test_body {
         ($method:ident, $(($data:expr, $len:expr);)*) => {
@@ -1287,14 +3472,106 @@ mod body_tests {
         (-1234, 5);
     }
 
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn body_async() {
+        let mut resp = Response::new(&RespLimits::default());
+
+        resp.status(StatusCode::Ok)
+            .header("x-step", 1)
+            .body_async(|| async {
+                tokio::task::yield_now().await;
+                "awaited body"
+            })
+            .await;
+
+        assert_eq!(
+            str_op(&resp.buffer),
+            "HTTP/1.1 200 OK\r\nx-step: 1\r\ncontent-length: 0000000012\r\n\r\nawaited body"
+        );
+        assert_eq!(resp.state, ResponseState::Complete);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn body_with_async_yields() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const CHUNK: [u8; 1024] = [b'x'; 1024];
+        const CHUNKS: usize = 6 * 1024;
+
+        // Another connection on the same (single) runtime thread
+        let progress = Arc::new(AtomicUsize::new(0));
+        let neighbour = tokio::spawn({
+            let progress = progress.clone();
+            async move {
+                loop {
+                    progress.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        let limits = RespLimits {
+            hard_max_body: 8 << 20,
+            ..RespLimits::default()
+        };
+
+        // The blocking builder keeps the thread for the whole body
+        let mut resp = Response::new(&limits);
+        let before = progress.load(Ordering::Relaxed);
+        resp.status(StatusCode::Ok).body_with(|writer| {
+            (0..CHUNKS).for_each(|_| writer.write(&CHUNK[..]));
+        });
+        assert_eq!(progress.load(Ordering::Relaxed), before);
+
+        let mut resp = Response::new(&limits);
+        let before = progress.load(Ordering::Relaxed);
+        resp.status(StatusCode::Ok)
+            .body_with_async(|mut writer| async move {
+                for _ in 0..CHUNKS {
+                    writer.write(&CHUNK[..]);
+                    writer.maybe_yield().await;
+                }
+                writer
+            })
+            .await;
+        // One yield every 64 KB
+        assert!(progress.load(Ordering::Relaxed) - before >= CHUNKS / 64);
+
+        assert_eq!(resp.state, ResponseState::Complete);
+        let head = "HTTP/1.1 200 OK\r\ncontent-length: 0006291456\r\n\r\n";
+        assert_eq!(str_op(&resp.buffer[..head.len()]), head);
+        assert_eq!(resp.buffer.len(), head.len() + CHUNKS * CHUNK.len());
+
+        // A smaller step yields more often
+        let before = progress.load(Ordering::Relaxed);
+        let mut resp = Response::new(&limits);
+        resp.status(StatusCode::Ok)
+            .body_with_async(|mut writer| async move {
+                writer.yield_every(1024);
+                for _ in 0..64 {
+                    writer.write(&CHUNK[..]);
+                    writer.maybe_yield().await;
+                }
+                writer
+            })
+            .await;
+        assert!(progress.load(Ordering::Relaxed) - before >= 64);
+        neighbour.abort();
+    }
+
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "Must be called after status() and any header methods")]
     fn body_before_status() {
         Response::new(&RespLimits::default()).body("Name");
     }
 
     #[test]
-    #[should_panic(expected = "Must be called after status() and any header methods")]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be called after status() and any header methods (`body()`, state: Complete, version: Http11)"
+    )]
     fn body_double_call() {
         let mut resp = Response::new(&RespLimits::default());
         resp.status(StatusCode::Ok).body("");
@@ -1308,18 +3585,255 @@ mod body_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "Must be called after status() and any header methods")]
     fn body_with_before_status() {
         Response::new(&RespLimits::default()).body_with(|_| {});
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "Must be called after status() and any header methods")]
     fn body_with_double_call() {
         let mut resp = Response::new(&RespLimits::default());
         resp.status(StatusCode::Ok).body_with(|_| {});
         resp.body_with(|_| {});
     }
+
+    #[test]
+    fn reply() {
+        let cases: [(StatusCode, &str); 3] = [
+            (StatusCode::Ok, "hi"),
+            (StatusCode::NotFound, ""),
+            (StatusCode::InternalServerError, "{\"error\": true}"),
+        ];
+
+        for (status, data) in cases {
+            let mut expected = Response::new(&RespLimits::default());
+            expected.status(status).body(data);
+
+            let mut resp = Response::new(&RespLimits::default());
+            resp.reply(status, data);
+
+            assert_eq!(resp.buffer, expected.buffer);
+            assert_eq!(resp.state, ResponseState::Complete);
+        }
+    }
+
+    #[test]
+    fn partial() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.partial(2, 5, 10, "2345");
+
+        assert_eq!(
+            str_op(&resp.buffer),
+            "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes 2-5/10\r\n\
+             content-length: 0000000004\r\n\r\n2345"
+        );
+        assert_eq!(resp.state, ResponseState::Complete);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Body length must match the range")]
+    fn partial_wrong_length() {
+        Response::new(&RespLimits::default()).partial(2, 5, 10, "234");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "Must be first and called only once (`status()`, state: Headers, version: Http11)"
+    )]
+    fn reply_after_status() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.status(StatusCode::Ok);
+        resp.reply(StatusCode::Ok, "hi");
+    }
+
+    #[test]
+    fn error_json() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.error_json(
+            StatusCode::BadRequest,
+            "INVALID_METHOD",
+            "Invalid HTTP method",
+        );
+
+        // The same envelope as the server's own `400`
+        let framework = str_op(ErrorKind::InvalidMethod.as_http(Version::Http11, true));
+        let buffer = str_op(&resp.buffer);
+        assert_eq!(
+            buffer.split_once("\r\n\r\n").unwrap().1,
+            framework.split_once("\r\n\r\n").unwrap().1
+        );
+        assert!(buffer.contains("\r\ncontent-type: application/json\r\n"));
+        assert!(resp.keep_alive);
+
+        resp.reset(&RespLimits::default());
+        resp.error_json(StatusCode::NotFound, "NOT_FOUND", "No \"x\"\\\n\u{1}é");
+        assert_eq!(
+            str_op(&resp.buffer),
+            "HTTP/1.1 404 Not Found\r\ncontent-type: application/json\r\n\
+             content-length: 0000000051\r\n\r\n\
+             {\"error\":\"No \\\"x\\\"\\\\\\n\\u0001é\",\"code\":\"NOT_FOUND\"}"
+        );
+    }
+
+    #[test]
+    fn body_over_hard_max() {
+        let limits = RespLimits {
+            hard_max_body: 4,
+            ..RespLimits::default()
+        };
+
+        let mut resp = Response::new(&limits);
+        resp.status(StatusCode::Ok).body("four");
+        assert!(resp.keep_alive);
+        assert_eq!(
+            str_op(&resp.buffer),
+            "HTTP/1.1 200 OK\r\ncontent-length: 0000000004\r\n\r\nfour"
+        );
+
+        resp.reset(&limits);
+        resp.status(StatusCode::Ok)
+            .header("x-test", "1")
+            .body("fives");
+        assert!(!resp.keep_alive);
+        assert_eq!(resp.state, ResponseState::Complete);
+        assert_eq!(
+            str_op(&resp.buffer),
+            "HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\ncontent-length: 0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn response_over_max_size() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.status(StatusCode::Ok).header("x-id", 7).body("body");
+        let size = resp.buffer.len();
+
+        let limits = RespLimits {
+            max_response_size: Some(size),
+            ..RespLimits::default()
+        };
+        let mut resp = Response::new(&limits);
+        resp.status(StatusCode::Ok).header("x-id", 7).body("body");
+        assert!(resp.keep_alive);
+        assert_eq!(resp.buffer.len(), size);
+
+        // One byte more in a header or in the body
+        for (header, body) in [(70, "body"), (7, "body!")] {
+            resp.reset(&limits);
+            resp.status(StatusCode::NotFound)
+                .header("x-id", header)
+                .body(body);
+            assert!(!resp.keep_alive);
+            assert_eq!(
+                str_op(&resp.buffer),
+                "HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\ncontent-length: 0\r\n\r\n"
+            );
+            let overflow = BodyOverflow {
+                status: StatusCode::NotFound,
+                size: size + 1 + "Not Found".len() - "OK".len(),
+            };
+            assert_eq!(resp.overflow(), Some(overflow));
+        }
+
+        resp.reset(&limits);
+        assert_eq!(resp.overflow(), None);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn body_with_async_cancelled() {
+        let limits = RespLimits::default();
+        let mut resp = Response::new(&limits);
+        let capacity = resp.buffer.capacity();
+
+        let builder = resp
+            .status(StatusCode::Ok)
+            .body_with_async(|mut writer| async move {
+                writer.write("never finished");
+                std::future::pending::<()>().await;
+                writer
+            });
+        let timeout = tokio::time::timeout(std::time::Duration::from_millis(10), builder);
+        assert!(timeout.await.is_err());
+        assert_eq!(resp.buffer.capacity(), 0);
+
+        // The next response gets its pre-allocated buffer back
+        resp.reset(&limits);
+        assert_eq!(resp.buffer.capacity(), capacity);
+        resp.status(StatusCode::Ok).body("ok");
+        assert_eq!(resp.buffer.capacity(), capacity);
+
+        // and keeps it
+        resp.reset(&limits);
+        assert_eq!(resp.buffer.capacity(), capacity);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn body_with_async_over_max_size() {
+        let limits = RespLimits {
+            max_response_size: Some(1024),
+            ..RespLimits::default()
+        };
+        let mut resp = Response::new(&limits);
+        resp.status(StatusCode::Created)
+            .body_with_async(|mut writer| async move {
+                for _ in 0..100 {
+                    writer.write(&[b'x'; 64][..]);
+                    assert!(writer.buffer.len() <= 1024);
+                }
+                assert!(writer.is_over_limit());
+                writer
+            })
+            .await;
+
+        assert!(!resp.keep_alive);
+        assert_eq!(resp.state, ResponseState::Complete);
+        assert_eq!(
+            str_op(&resp.buffer),
+            "HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\ncontent-length: 0\r\n\r\n"
+        );
+        let head = "HTTP/1.1 201 Created\r\ncontent-length: 0000000000\r\n\r\n";
+        let overflow = BodyOverflow {
+            status: StatusCode::Created,
+            size: head.len() + 100 * 64,
+        };
+        assert_eq!(resp.overflow(), Some(overflow));
+
+        // Under the limit nothing is dropped
+        resp.reset(&limits);
+        resp.status(StatusCode::Ok)
+            .body_with_async(|mut writer| async move {
+                writer.write("small");
+                assert!(!writer.is_over_limit());
+                writer
+            })
+            .await;
+        assert!(str_op(&resp.buffer).ends_with("\r\n\r\nsmall"));
+        assert_eq!(resp.overflow(), None);
+    }
+
+    #[test]
+    fn placeholder_fits_max_body() {
+        let (arr, start) = Response::number_to_bytes(MAX_PLACEHOLDER_BODY);
+        assert_eq!(arr.len() - start, LENGTH_PLACEHOLDER.len());
+
+        let (_, start) = Response::number_to_bytes(MAX_PLACEHOLDER_BODY + 1);
+        assert!(arr.len() - start > LENGTH_PLACEHOLDER.len());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "32")]
+    fn placeholder_fits_usize_max() {
+        // On 32-bit targets a body can never overflow the placeholder
+        let (arr, start) = Response::number_to_bytes(usize::MAX as u128);
+        assert_eq!(&arr[start..], b"4294967295");
+    }
 }
 
 #[cfg(test)]
@@ -1358,6 +3872,80 @@ mod integration_tests {
         assert_eq!(resp.state, ResponseState::Complete);
     }
 
+    #[test]
+    fn typed_matches_plain() {
+        let mut plain = Response::new(&RespLimits::default());
+        plain
+            .status(StatusCode::Found)
+            .header("location", "/api/update")
+            .close()
+            .body("Sample body");
+
+        let mut typed = Response::new(&RespLimits::default());
+        typed
+            .typed()
+            .status(StatusCode::Found)
+            .header("location", "/api/update")
+            .close()
+            .body("Sample body");
+
+        assert_eq!(str_op(&typed.buffer), str_op(&plain.buffer));
+        assert_eq!(typed.keep_alive, plain.keep_alive);
+        assert_eq!(typed.state, ResponseState::Complete);
+    }
+
+    #[test]
+    fn typed_covers_plain_methods() {
+        let mut plain = Response::new(&RespLimits::default());
+        plain
+            .status(StatusCode::Ok)
+            .header_params(
+                "cache-control",
+                ", ",
+                [("max-age", Some(60)), ("public", None)],
+            )
+            .header_duration_secs("retry-after", Duration::from_secs(120))
+            .alt_svc(br#"h3=":443""#)
+            .close_after(3)
+            .body("Sample body");
+        plain.insert_header("x-frame-options", "DENY");
+
+        let mut typed = Response::new(&RespLimits::default());
+        typed
+            .typed()
+            .close_after(3)
+            .status(StatusCode::Ok)
+            .header_params(
+                "cache-control",
+                ", ",
+                [("max-age", Some(60)), ("public", None)],
+            )
+            .header_duration_secs("retry-after", Duration::from_secs(120))
+            .alt_svc(br#"h3=":443""#)
+            .body("Sample body");
+        let _ = typed
+            .typed_complete()
+            .insert_header("x-frame-options", "DENY");
+
+        assert_eq!(str_op(&typed.buffer), str_op(&plain.buffer));
+        assert_eq!(typed.close_after, Some(3));
+
+        let mut plain = Response::new(&RespLimits::default());
+        plain.partial(2, 4, 10, "234");
+        let mut typed = Response::new(&RespLimits::default());
+        typed.typed().partial(2, 4, 10, "234");
+        assert_eq!(str_op(&typed.buffer), str_op(&plain.buffer));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Must be first and called only once")]
+    fn typed_after_status() {
+        let mut resp = Response::new(&RespLimits::default());
+        resp.status(StatusCode::Ok);
+        let _ = resp.typed();
+    }
+
     #[test]
     fn full_sequence() {
         let mut resp = Response::new(&RespLimits::default());
@@ -1451,6 +4039,7 @@ mod http09_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "An `HTTP/0.9+` response must use exactly one method")]
     fn double_call() {
         let mut resp = Response::new(&RespLimits::default());
@@ -1461,12 +4050,97 @@ mod http09_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "This method is only for `HTTP/0.9+`")]
     fn http1x_panic() {
         Response::new(&RespLimits::default()).http09("just text");
     }
 }
 
+// `debug` builds panic on these misuses instead, so these run only in the
+// `cargo test --release` step of CI
+#[cfg(all(test, not(debug_assertions)))]
+mod protocol_misuse_tests {
+    use super::*;
+    use crate::tools::*;
+
+    fn response(version: Version, validate_protocol_usage: bool) -> Response {
+        let mut resp = Response::new(&RespLimits {
+            validate_protocol_usage,
+            ..RespLimits::default()
+        });
+        resp.version = version;
+        resp
+    }
+
+    #[test]
+    fn status_for_http09() {
+        let mut resp = response(Version::Http09, true);
+
+        let line = line!() + 1;
+        resp.status(StatusCode::Ok)
+            .header("content-type", "text/plain")
+            .body("Hello");
+
+        assert_eq!(
+            str_op(&resp.buffer),
+            "SERVER_ERROR: 500 Internal Server Error\r\n"
+        );
+        assert!(!resp.keep_alive);
+        let location = resp.misused_at().unwrap();
+        assert_eq!((location.file(), location.line()), (file!(), line));
+    }
+
+    #[test]
+    fn http09_for_http1x() {
+        let rejected =
+            "HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\ncontent-length: 0\r\n\r\n";
+        let calls: [fn(&mut Response) -> Handled; 4] = [
+            |resp| resp.http09("Hello"),
+            |resp| resp.http09_with(|buffer| buffer.extend_from_slice(b"Hello")),
+            |resp| resp.http09_status(StatusCode::Ok),
+            |resp| resp.http09_msg(StatusCode::Ok, "Hello"),
+        ];
+
+        for call in calls {
+            let mut resp = response(Version::Http11, true);
+            call(&mut resp);
+
+            assert_eq!(str_op(&resp.buffer), rejected);
+            assert!(resp.misused_at().is_some());
+
+            // Cleared for the next request
+            resp.reset(&RespLimits::default());
+            assert!(resp.misused_at().is_none());
+        }
+    }
+
+    #[test]
+    fn template_for_http09() {
+        let template = ResponseTemplate::build(|resp| resp.reply(StatusCode::Ok, "Hello"));
+
+        let mut resp = response(Version::Http09, true);
+        resp.from_template(&template);
+        assert_eq!(
+            str_op(&resp.buffer),
+            "SERVER_ERROR: 500 Internal Server Error\r\n"
+        );
+    }
+
+    #[test]
+    fn disabled() {
+        let mut resp = response(Version::Http09, false);
+        resp.status(StatusCode::Ok).body("Hello");
+        assert!(str_op(&resp.buffer).starts_with(" 200 OK\r\n"));
+        assert!(resp.misused_at().is_none());
+
+        let mut resp = response(Version::Http11, false);
+        resp.http09("Hello");
+        assert_eq!(str_op(&resp.buffer), "Hello");
+        assert!(resp.misused_at().is_none());
+    }
+}
+
 #[cfg(test)]
 mod http09_with_tests {
     use super::*;
@@ -1492,6 +4166,7 @@ mod http09_with_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "An `HTTP/0.9+` response must use exactly one method")]
     fn double_call() {
         let mut resp = Response::new(&RespLimits::default());
@@ -1502,6 +4177,7 @@ mod http09_with_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "This method is only for `HTTP/0.9+`")]
     fn http1x_panic() {
         Response::new(&RespLimits::default()).http09_with(|_| {});
@@ -1543,6 +4219,7 @@ mod http09_status_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "An `HTTP/0.9+` response must use exactly one method")]
     fn double_call() {
         let mut resp = Response::new(&RespLimits::default());
@@ -1553,6 +4230,7 @@ mod http09_status_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "This method is only for `HTTP/0.9+`")]
     fn http1x_panic() {
         Response::new(&RespLimits::default()).http09_status(StatusCode::Ok);
@@ -1608,6 +4286,7 @@ mod http09_msg_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "An `HTTP/0.9+` response must use exactly one method")]
     fn double_call() {
         let mut resp = Response::new(&RespLimits::default());
@@ -1618,8 +4297,176 @@ mod http09_msg_tests {
     }
 
     #[test]
+    #[cfg(debug_assertions)]
     #[should_panic(expected = "This method is only for `HTTP/0.9+`")]
     fn http1x_panic() {
         Response::new(&RespLimits::default()).http09_msg(StatusCode::Ok, "");
     }
 }
+
+#[cfg(test)]
+mod write_buffer_ip_tests {
+    use super::*;
+    use crate::tools::*;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    fn written<T: WriteBuffer>(value: T) -> String {
+        let mut buffer = Vec::new();
+        value.write_to(&mut buffer);
+        str_op(&buffer).to_string()
+    }
+
+    #[test]
+    fn ipv4() {
+        let cases = ["0.0.0.0", "127.0.0.1", "192.168.10.255", "255.255.255.255"];
+
+        for case in cases {
+            let ip: Ipv4Addr = case.parse().unwrap();
+            assert_eq!(written(ip), case);
+            assert_eq!(written(IpAddr::V4(ip)), case);
+        }
+    }
+
+    #[test]
+    fn ipv6() {
+        let cases = [
+            "::",
+            "::1",
+            "1::",
+            "2001:db8::1",
+            "2001:db8:0:1:1:1:1:1",
+            "2001:0:0:1::1",
+            "2001:db8::1:0:0:1",
+            "fe80::1:2:3:4",
+            "1:2:3:4:5:6:7:8",
+            "::ffff:192.0.2.1",
+            "::c000:201",
+            "abcd:ef01:2345:6789:abcd:ef01:2345:6789",
+        ];
+
+        for case in cases {
+            let ip: Ipv6Addr = case.parse().unwrap();
+            assert_eq!(written(ip), ip.to_string());
+            assert_eq!(written(IpAddr::V6(ip)), case);
+        }
+    }
+
+    #[test]
+    fn socket_addr() {
+        let cases = [
+            "127.0.0.1:80",
+            "0.0.0.0:0",
+            "[::1]:8080",
+            "[2001:db8::1]:443",
+            "[::ffff:10.0.0.1]:65535",
+        ];
+
+        for case in cases {
+            let addr: SocketAddr = case.parse().unwrap();
+            assert_eq!(written(addr), case);
+        }
+
+        let scoped = SocketAddr::V6(std::net::SocketAddrV6::new(Ipv6Addr::LOCALHOST, 80, 0, 3));
+        assert_eq!(written(scoped), "[::1%3]:80");
+    }
+}
+
+#[cfg(test)]
+mod write_buffer_decimal_tests {
+    use super::*;
+    use crate::{tools::*, Decimal};
+
+    fn written<T: WriteBuffer>(value: T) -> String {
+        let mut buffer = Vec::new();
+        value.write_to(&mut buffer);
+        str_op(&buffer).to_string()
+    }
+
+    #[test]
+    fn scales() {
+        assert_eq!(written(Decimal::<2>(1234)), "12.34");
+        assert_eq!(written(Decimal::<2>(1200)), "12.00");
+        assert_eq!(written(Decimal::<2>(5)), "0.05");
+        assert_eq!(written(Decimal::<2>(0)), "0.00");
+        assert_eq!(written(Decimal::<2>(-1234)), "-12.34");
+        assert_eq!(written(Decimal::<2>(-5)), "-0.05");
+
+        assert_eq!(written(Decimal::<0>(-42)), "-42");
+        assert_eq!(written(Decimal::<0>(0)), "0");
+        assert_eq!(written(Decimal::<1>(10)), "1.0");
+        assert_eq!(written(Decimal::<3>(7000)), "7.000");
+        assert_eq!(written(Decimal::<6>(123)), "0.000123");
+        assert_eq!(written(Decimal::<6>(123_456_789)), "123.456789");
+    }
+
+    #[test]
+    fn extremes() {
+        assert_eq!(
+            written(Decimal::<0>(i128::MAX)),
+            "170141183460469231731687303715884105727"
+        );
+        assert_eq!(
+            written(Decimal::<4>(i128::MIN)),
+            "-17014118346046923173168730371588410.5728"
+        );
+        assert_eq!(
+            written(Decimal::<39>(i128::MAX)),
+            "0.170141183460469231731687303715884105727"
+        );
+        assert_eq!(
+            written(Decimal::<42>(-1)),
+            format!("-0.{}1", "0".repeat(41))
+        );
+    }
+}
+
+#[cfg(test)]
+mod http_date_parse_tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn round_trip() {
+        let secs = [
+            0,
+            784111777,
+            951782400,  // 2000-02-29
+            4102444799, // 2099-12-31 23:59:59
+            1_700_000_000,
+        ];
+        for secs in secs
+            .into_iter()
+            .chain((0..2000).map(|i| i * 86400 * 37 + i))
+        {
+            let time = UNIX_EPOCH + Duration::from_secs(secs);
+            let mut buffer = Vec::new();
+            HttpDate::from(time).write_to(&mut buffer);
+
+            let parsed = HttpDate::parse(&buffer).map(SystemTime::from);
+            assert_eq!(parsed, Some(time), "{}", String::from_utf8_lossy(&buffer));
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let cases: [&[u8]; 9] = [
+            b"",
+            b"Sunday, 06-Nov-94 08:49:37 GMT",
+            b"Sun Nov  6 08:49:37 1994",
+            b"Sun, 06 Nov 1994 08:49:37 UTC",
+            b"Sun, 06 Nov 1994 08:49:37 GMT ",
+            b"Sun, 06 Foo 1994 08:49:37 GMT",
+            b"Sun, 32 Nov 1994 08:49:37 GMT",
+            b"Sun, 06 Nov 1994 24:00:00 GMT",
+            b"Wed, 31 Dec 1969 23:59:59 GMT",
+        ];
+        for value in cases {
+            assert_eq!(
+                HttpDate::parse(value),
+                None,
+                "{}",
+                String::from_utf8_lossy(value)
+            );
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Minimal templates with `{name}` placeholders, rendered straight into the
+//! response body.
+
+use crate::WriteBuffer;
+
+/// A text with `{name}` placeholders, split once at startup into static
+/// chunks and value slots.
+///
+/// - `{name}` - the value, HTML-escaped (`&`, `<`, `>`, `"` and `'`)
+/// - `{name:raw}` - the value as is
+/// - `{{` - a literal `{`
+///
+/// A name is made of ASCII letters, digits and `_`. Anything else in braces
+/// (e.g. `body{margin:0}` in an inline style) is left as text. Each distinct
+/// name is one slot, numbered in the order of first appearance; a name used
+/// twice takes the same value. Rendering doesn't allocate apart from the
+/// growth of the output buffer.
+///
+/// # Examples
+/// ```
+/// # maker_web::docs_rs_helper::run_test(|_, resp| {
+/// use maker_web::{tmpl::Template, StatusCode};
+/// use std::sync::OnceLock;
+///
+/// static PAGE: OnceLock<Template> = OnceLock::new();
+/// let page = PAGE.get_or_init(|| {
+///     Template::new("<h1>Hello, {name}!</h1><p>{unread} new messages</p>{footer:raw}")
+/// });
+///
+/// resp.status(StatusCode::Ok)
+///     .header("content-type", "text/html; charset=utf-8")
+///     .body_template(page, &[&"<script>", &3, &"<footer>maker_web</footer>"])
+///     // <h1>Hello, &lt;script&gt;!</h1><p>3 new messages</p><footer>maker_web</footer>
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Box<[Part]>,
+    names: Box<[&'static str]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    Text(&'static [u8]),
+    Slot { index: usize, raw: bool },
+}
+
+impl Template {
+    /// Splits `source` into text chunks and slots.
+    pub fn new(source: &'static str) -> Self {
+        let mut parts = Vec::new();
+        let mut names: Vec<&'static str> = Vec::new();
+        let source = source.as_bytes();
+
+        let mut text_start = 0;
+        let mut i = 0;
+        while let Some(offset) = source[i..].iter().position(|&b| b == b'{') {
+            let open = i + offset;
+
+            if source.get(open + 1) == Some(&b'{') {
+                push_text(&mut parts, &source[text_start..open + 1]);
+                text_start = open + 2;
+                i = open + 2;
+                continue;
+            }
+            let Some((name, raw, end)) = placeholder(source, open) else {
+                i = open + 1;
+                continue;
+            };
+
+            push_text(&mut parts, &source[text_start..open]);
+            let index = match names.iter().position(|n| *n == name) {
+                Some(index) => index,
+                None => {
+                    names.push(name);
+                    names.len() - 1
+                }
+            };
+            parts.push(Part::Slot { index, raw });
+            text_start = end;
+            i = end;
+        }
+        push_text(&mut parts, &source[text_start..]);
+
+        Self {
+            parts: parts.into(),
+            names: names.into(),
+        }
+    }
+
+    /// Number of values [`render()`](Self::render) expects.
+    #[inline]
+    pub fn slot_count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns the position of the value for `name`, if the template uses it.
+    #[inline]
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| *n == name)
+    }
+
+    /// Appends the template to `out` with `values` in its slots, in the order
+    /// of [`slot()`](Self::slot).
+    ///
+    /// See also [`Response::body_template`](crate::Response::body_template).
+    ///
+    /// # Panics
+    /// Panics in `debug` mode when the number of values isn't
+    /// [`slot_count()`](Self::slot_count). Otherwise missing values are
+    /// written as empty and extra ones are ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::tmpl::Template;
+    ///
+    /// let template = Template::new("<a href=\"/u/{id}\">{name}</a>");
+    ///
+    /// let mut out = Vec::new();
+    /// template.render(&[&42, &"Tom & Jerry"], &mut out);
+    /// assert_eq!(out, b"<a href=\"/u/42\">Tom &amp; Jerry</a>");
+    /// ```
+    #[track_caller]
+    pub fn render(&self, values: &[&dyn WriteBuffer], out: &mut Vec<u8>) {
+        debug_assert_eq!(
+            values.len(),
+            self.names.len(),
+            "The template has the slots {:?}",
+            self.names
+        );
+
+        for part in self.parts.iter() {
+            match *part {
+                Part::Text(text) => out.extend_from_slice(text),
+                Part::Slot { index, raw } => {
+                    let Some(value) = values.get(index) else {
+                        continue;
+                    };
+                    let start = out.len();
+                    value.write_to(out);
+                    if !raw {
+                        escape_html_from(out, start);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[inline]
+fn push_text(parts: &mut Vec<Part>, text: &'static [u8]) {
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+}
+
+// `{name}` or `{name:raw}` at `open`: the name, whether it's raw and the end
+fn placeholder(source: &'static [u8], open: usize) -> Option<(&'static str, bool, usize)> {
+    let close = open + source[open..].iter().position(|&b| b == b'}')?;
+    let inner = &source[open + 1..close];
+
+    let (name, raw) = match inner.strip_suffix(b":raw") {
+        Some(name) => (name, true),
+        None => (inner, false),
+    };
+    let valid = !name.is_empty() && name.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'_');
+
+    // ASCII only, so still valid UTF-8
+    let name = std::str::from_utf8(name).ok().filter(|_| valid)?;
+    Some((name, raw, close + 1))
+}
+
+// Escapes the bytes of `out` after `start` in place, growing it once
+fn escape_html_from(out: &mut Vec<u8>, start: usize) {
+    let extra: usize = out[start..]
+        .iter()
+        .map(|b| match b {
+            b'&' => 4,
+            b'<' | b'>' => 3,
+            b'"' => 5,
+            b'\'' => 4,
+            _ => 0,
+        })
+        .sum();
+    if extra == 0 {
+        return;
+    }
+
+    let old_len = out.len();
+    out.resize(old_len + extra, 0);
+
+    // From the end, so no byte is overwritten before it's moved
+    let mut write = out.len();
+    for read in (start..old_len).rev() {
+        let byte = out[read];
+        let escaped: &[u8] = match byte {
+            b'&' => b"&amp;",
+            b'<' => b"&lt;",
+            b'>' => b"&gt;",
+            b'"' => b"&quot;",
+            b'\'' => b"&#39;",
+            _ => {
+                write -= 1;
+                out[write] = byte;
+                continue;
+            }
+        };
+        write -= escaped.len();
+        out[write..write + escaped.len()].copy_from_slice(escaped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &Template, values: &[&dyn WriteBuffer]) -> String {
+        let mut out = b"prefix:".to_vec();
+        template.render(values, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn escaping() {
+        let template = Template::new("<p title=\"{title}\">{text}</p>");
+        assert_eq!(template.slot_count(), 2);
+
+        let cases = [
+            ("plain", "text", "<p title=\"plain\">text</p>"),
+            (
+                "a \"quoted\" 'title'",
+                "<b>fish & chips</b>",
+                "<p title=\"a &quot;quoted&quot; &#39;title&#39;\">&lt;b&gt;fish &amp; chips&lt;/b&gt;</p>",
+            ),
+            ("", "&amp;", "<p title=\"\">&amp;amp;</p>"),
+        ];
+        for (title, text, expected) in cases {
+            assert_eq!(
+                render(&template, &[&title, &text]),
+                format!("prefix:{expected}")
+            );
+        }
+
+        // Numbers and other `WriteBuffer`s go through the same path
+        let template = Template::new("{a}+{b}={c} is {ok}");
+        assert_eq!(
+            render(&template, &[&1, &2u8, &3u64, &true]),
+            "prefix:1+2=3 is true"
+        );
+    }
+
+    #[test]
+    fn raw_and_literal_braces() {
+        let template = Template::new(
+            "<style>p{margin:0}</style>{{x}} {body:raw}{body}{ bad }{x:html}{body:raw}",
+        );
+        assert_eq!(template.slot_count(), 1);
+        assert_eq!(template.slot("body"), Some(0));
+        assert_eq!(template.slot("x"), None);
+
+        assert_eq!(
+            render(&template, &[&"<i>"]),
+            "prefix:<style>p{margin:0}</style>{x}} <i>&lt;i&gt;{ bad }{x:html}<i>"
+        );
+
+        let template = Template::new("no slots {}");
+        assert_eq!(template.slot_count(), 0);
+        assert_eq!(render(&template, &[]), "prefix:no slots {}");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "The template has the slots [\"a\", \"b\"]")]
+    fn missing_values() {
+        let template = Template::new("{a} and {b}");
+        render(&template, &[&1]);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn missing_values() {
+        let template = Template::new("{a} and {b}");
+        assert_eq!(render(&template, &[&1]), "prefix:1 and ");
+    }
+}
@@ -2,24 +2,24 @@
 
 //! Core HTTP protocol types and utilities
 
-use crate::{errors::ErrorKind, limits::ReqLimits};
-use std::mem;
+use crate::{
+    errors::ErrorKind,
+    limits::ReqLimits,
+    query::{FromRadix, QueryLookup},
+    url, HttpDate,
+};
+use std::{
+    fmt, mem,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 #[inline(always)]
 pub(crate) fn slice_to_usize(bytes: &[u8]) -> Option<usize> {
-    let mut result: usize = 0;
-
-    for &byte in bytes {
-        if !byte.is_ascii_digit() {
-            return None;
-        }
-
-        result = result
-            .checked_mul(10)?
-            .checked_add((byte - b'0') as usize)?;
+    // The same digits and overflow check as the query integers, empty is `0`
+    match bytes {
+        [] => Some(0),
+        bytes => usize::from_radix_10(bytes),
     }
-
-    Some(result)
 }
 
 // METHOD
@@ -87,12 +87,97 @@ impl Method {
             Method::Options => "OPTIONS",
         }
     }
+
+    /// Checks if the method is one of `set`.
+    ///
+    /// For a set checked on every request, build a [`MethodSet`] once instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Method;
+    ///
+    /// assert!(Method::Put.in_set(&[Method::Put, Method::Patch]));
+    /// assert!(!Method::Get.in_set(&[Method::Put, Method::Patch]));
+    /// ```
+    #[inline]
+    pub const fn in_set(self, set: &[Method]) -> bool {
+        MethodSet::new(set).contains(self)
+    }
+
+    #[inline(always)]
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A set of [`Method`]s as a bitmask, for one-instruction membership checks
+/// in routing code.
+///
+/// # Examples
+/// ```
+/// use maker_web::{Method, MethodSet};
+///
+/// const WRITE: MethodSet = MethodSet::new(&[Method::Post, Method::Put, Method::Patch]);
+/// const WITH_DELETE: MethodSet = WRITE.with(Method::Delete);
+///
+/// assert!(WRITE.contains(Method::Patch));
+/// assert!(!WRITE.contains(Method::Delete));
+/// assert!(WITH_DELETE.contains(Method::Delete));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MethodSet(u8);
+
+impl MethodSet {
+    /// The set without methods.
+    pub const EMPTY: Self = Self(0);
+    /// The set of all methods.
+    pub const ALL: Self = Self::new(&[
+        Method::Get,
+        Method::Put,
+        Method::Post,
+        Method::Head,
+        Method::Patch,
+        Method::Delete,
+        Method::Options,
+    ]);
+
+    /// Creates a set of `methods`.
+    #[inline]
+    pub const fn new(methods: &[Method]) -> Self {
+        let mut set = Self::EMPTY;
+        let mut i = 0;
+        while i < methods.len() {
+            set = set.with(methods[i]);
+            i += 1;
+        }
+        set
+    }
+
+    /// Returns the set with `method` added.
+    #[inline]
+    pub const fn with(self, method: Method) -> Self {
+        Self(self.0 | method.bit())
+    }
+
+    /// Checks if `method` is in the set.
+    #[inline]
+    pub const fn contains(self, method: Method) -> bool {
+        self.0 & method.bit() != 0
+    }
+
+    /// Checks if the set has no methods.
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
 }
 
 // VERSION
 
 /// HTTP protocol version
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+///
+/// Versions are ordered by age: `Http09 < Http10 < Http11`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Version {
     /// HTTP/0.9 - The original protocol (1991)
     ///
@@ -114,12 +199,89 @@ pub enum Version {
 }
 
 impl Version {
+    /// The version as written in a request or status line.
+    ///
+    /// `Http09` gives `HTTP/0.9`, although such requests carry no version.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Version;
+    ///
+    /// assert_eq!(Version::Http11.as_str(), "HTTP/1.1");
+    /// assert_eq!(Version::Http10.to_string(), "HTTP/1.0");
+    /// ```
     #[inline]
-    pub const fn as_str(&self) -> &str {
+    pub const fn as_str(self) -> &'static str {
         match self {
             Version::Http11 => "HTTP/1.1",
             Version::Http10 => "HTTP/1.0",
-            Version::Http09 => "HTTP/0.9+",
+            Version::Http09 => "HTTP/0.9",
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// IF_RANGE
+
+/// The value of an `If-Range` header
+/// ([RFC 9110](https://datatracker.ietf.org/doc/html/rfc9110#section-13.1.5)),
+/// see [`Request::if_range`](crate::Request::if_range).
+///
+/// A `Range` request with `If-Range` gets `206 Partial Content` only when the
+/// condition [matches](Self::matches) the current representation, otherwise the
+/// whole representation with `200 OK`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfRange<'a> {
+    /// An entity tag with its quotes, e.g. `"v2"` or `W/"v2"`
+    ETag(&'a [u8]),
+    /// An `IMF-fixdate`, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+    Date(SystemTime),
+    /// Neither an entity tag nor an `IMF-fixdate`; never matches
+    Invalid(&'a [u8]),
+}
+
+impl IfRange<'_> {
+    /// Returns `true` if the representation with these validators may be
+    /// served partially.
+    ///
+    /// Entity tags use the strong comparison: a weak tag (`W/"..."`) never
+    /// matches. A date matches when it equals `last_modified` to the second.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::IfRange;
+    ///
+    /// let etag = Some(&b"\"v2\""[..]);
+    /// assert!(IfRange::ETag(b"\"v2\"").matches(etag, None));
+    /// assert!(!IfRange::ETag(b"\"v1\"").matches(etag, None));
+    /// assert!(!IfRange::ETag(b"W/\"v2\"").matches(Some(b"W/\"v2\""), None));
+    /// ```
+    pub fn matches(&self, etag: Option<&[u8]>, last_modified: Option<SystemTime>) -> bool {
+        match *self {
+            IfRange::ETag(tag) => !tag.starts_with(b"W/") && etag == Some(tag),
+            IfRange::Date(date) => last_modified.is_some_and(|time| {
+                let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+                secs(time) == secs(date)
+            }),
+            IfRange::Invalid(_) => false,
+        }
+    }
+
+    pub(crate) fn parse(value: &[u8]) -> IfRange<'_> {
+        let is_etag = |tag: &[u8]| tag.len() >= 2 && tag[0] == b'"' && tag.ends_with(b"\"");
+
+        if is_etag(value.strip_prefix(b"W/").unwrap_or(value)) {
+            IfRange::ETag(value)
+        } else if let Some(date) = HttpDate::parse(value) {
+            IfRange::Date(date.into())
+        } else {
+            IfRange::Invalid(value)
         }
     }
 }
@@ -159,6 +321,13 @@ macro_rules! set_status_codes {
                 )+ }
             }
 
+            #[inline]
+            pub(crate) const fn from_u16(code: u16) -> Option<Self> {
+                match code { $(
+                    $num => Some(StatusCode::$name),
+                )+ _ => None }
+            }
+
             #[inline]
             pub(crate) const fn as_u16_bytes(&self) -> &[u8] {
                 match self { $(
@@ -176,6 +345,94 @@ macro_rules! set_status_codes {
     }
 }
 
+/// Class of a status code, given by its first digit
+/// ([RFC 9110](https://datatracker.ietf.org/doc/html/rfc9110#section-15)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusClass {
+    /// `1xx`
+    Informational,
+    /// `2xx`
+    Success,
+    /// `3xx`
+    Redirection,
+    /// `4xx`
+    ClientError,
+    /// `5xx`
+    ServerError,
+}
+
+impl StatusClass {
+    /// Returns the class of a numeric code, `None` outside `100..=599`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::StatusClass;
+    ///
+    /// assert_eq!(StatusClass::from_code(429), Some(StatusClass::ClientError));
+    /// assert_eq!(StatusClass::from_code(600), None);
+    /// ```
+    #[inline]
+    pub const fn from_code(code: u16) -> Option<Self> {
+        match code {
+            100..=199 => Some(Self::Informational),
+            200..=299 => Some(Self::Success),
+            300..=399 => Some(Self::Redirection),
+            400..=499 => Some(Self::ClientError),
+            500..=599 => Some(Self::ServerError),
+            _ => None,
+        }
+    }
+}
+
+impl StatusCode {
+    /// Returns the class of the code.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{StatusClass, StatusCode};
+    ///
+    /// assert_eq!(StatusCode::NotFound.class(), StatusClass::ClientError);
+    /// assert!(StatusCode::NotFound.is_client_error());
+    /// ```
+    #[inline]
+    pub const fn class(self) -> StatusClass {
+        match StatusClass::from_code(self as u16) {
+            Some(class) => class,
+            None => unreachable!(),
+        }
+    }
+
+    /// `1xx`, see [`class()`](Self::class).
+    #[inline]
+    pub const fn is_informational(self) -> bool {
+        matches!(self.class(), StatusClass::Informational)
+    }
+
+    /// `2xx`, see [`class()`](Self::class).
+    #[inline]
+    pub const fn is_success(self) -> bool {
+        matches!(self.class(), StatusClass::Success)
+    }
+
+    /// `3xx`, see [`class()`](Self::class).
+    #[inline]
+    pub const fn is_redirection(self) -> bool {
+        matches!(self.class(), StatusClass::Redirection)
+    }
+
+    /// `4xx`, see [`class()`](Self::class).
+    #[inline]
+    pub const fn is_client_error(self) -> bool {
+        matches!(self.class(), StatusClass::ClientError)
+    }
+
+    /// `5xx`, see [`class()`](Self::class).
+    #[inline]
+    pub const fn is_server_error(self) -> bool {
+        matches!(self.class(), StatusClass::ServerError)
+    }
+}
+
 set_status_codes! {
     /// [[RFC9110, Section 15.2.1](https://datatracker.ietf.org/doc/html/rfc9110#section-15.2.1)]
     Continue = (100, "Continue");
@@ -717,6 +974,37 @@ impl Url {
         self.path_segment_str(index).map(|v| v.as_bytes())
     }
 
+    /// Returns the path segment at the given index, percent-decoded into `scratch`.
+    ///
+    /// Borrows the segment when it has nothing to decode, otherwise `scratch`
+    /// is cleared and the decoded segment is written there, so a buffer reused
+    /// across requests doesn't allocate. `+` is kept, an invalid escape is kept
+    /// as it is unless rejected by
+    /// [`ReqLimits::strict_escapes`](crate::limits::ReqLimits::strict_escapes).
+    ///
+    /// # Examples
+    /// ```
+    /// let url = "/api/users/John%20Doe";
+    ///
+    /// // Parsing...
+    ///
+    /// # maker_web::docs_rs_helper::example_url_http1x(url, |req| {
+    /// let mut scratch = Vec::new();
+    /// assert_eq!(req.url().path_segment_decoded(2, &mut scratch), Some("John Doe".as_bytes()));
+    /// assert_eq!(req.url().path_segment_decoded(0, &mut scratch), Some("api".as_bytes()));
+    /// assert_eq!(req.url().path_segment_decoded(3, &mut scratch), None);
+    /// # });
+    /// ```
+    #[inline]
+    pub fn path_segment_decoded<'a>(
+        &'a self,
+        index: usize,
+        scratch: &'a mut Vec<u8>,
+    ) -> Option<&'a [u8]> {
+        let segment = self.path_segment(index)?;
+        Some(url::decode_with(segment, false, scratch))
+    }
+
     /// Returns all path segments as a slice.
     ///
     /// Segments are split by `/` characters and do not include the leading or
@@ -902,6 +1190,180 @@ impl Url {
             .find(|&&(k, _)| k == key)
             .map(|&(_, v)| v)
     }
+
+    /// Returns the value for the specified query parameter key like
+    /// [`query()`](Self::query), percent-decoded into `scratch`.
+    ///
+    /// With `plus_as_space`, `+` is decoded to a space as in HTML forms;
+    /// otherwise it is kept. Borrows the value when it has nothing to decode,
+    /// otherwise `scratch` is cleared and the decoded value is written there,
+    /// so a buffer reused across requests doesn't allocate. An invalid escape
+    /// is kept as it is unless rejected by
+    /// [`ReqLimits::strict_escapes`](crate::limits::ReqLimits::strict_escapes).
+    /// The key is compared with the raw, still encoded keys.
+    ///
+    /// # Examples
+    /// ```
+    /// let url = "/search?q=a%2Bb+c&email=user%40example.com&bad=%G1";
+    ///
+    /// // Parsing...
+    ///
+    /// # maker_web::docs_rs_helper::example_url_http1x(url, |req| {
+    /// let mut scratch = Vec::new();
+    /// let url = req.url();
+    /// assert_eq!(url.query_decoded(b"q", true, &mut scratch), Some("a+b c".as_bytes()));
+    /// assert_eq!(url.query_decoded(b"q", false, &mut scratch), Some("a+b+c".as_bytes()));
+    /// assert_eq!(
+    ///     url.query_decoded(b"email", true, &mut scratch),
+    ///     Some("user@example.com".as_bytes())
+    /// );
+    /// assert_eq!(url.query_decoded(b"bad", true, &mut scratch), Some("%G1".as_bytes()));
+    /// # });
+    /// ```
+    #[inline]
+    pub fn query_decoded<'a>(
+        &'a self,
+        key: &[u8],
+        plus_as_space: bool,
+        scratch: &'a mut Vec<u8>,
+    ) -> Option<&'a [u8]> {
+        let value = self.query(key)?;
+        Some(url::decode_with(value, plus_as_space, scratch))
+    }
+
+    /// Returns every value of a repeated query parameter, in order.
+    ///
+    /// Performs case-sensitive lookup like [`query()`](Self::query),
+    /// without allocating.
+    ///
+    /// # Examples
+    /// ```
+    /// let url = "/posts?tag=rust&sort=new&tag=web";
+    ///
+    /// // Parsing...
+    ///
+    /// # maker_web::docs_rs_helper::example_url_http1x(url, |req| {
+    /// let tags: Vec<&[u8]> = req.url().query_all(b"tag").collect();
+    /// assert_eq!(tags, ["rust".as_bytes(), b"web"]);
+    /// assert_eq!(req.url().query_all(b"page").next(), None);
+    /// # });
+    /// #
+    /// # maker_web::docs_rs_helper::example_url_http09(url, |req| {
+    /// # let tags: Vec<&[u8]> = req.url().query_all(b"tag").collect();
+    /// # assert_eq!(tags, ["rust".as_bytes(), b"web"]);
+    /// # });
+    /// ```
+    #[inline]
+    pub fn query_all<'a>(&'a self, key: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.query_parts
+            .iter()
+            .filter(move |&&(k, _)| k == key)
+            .map(|&(_, v)| v)
+    }
+
+    /// Returns the value for the specified query parameter key,
+    /// with the case sensitivity and duplicate handling of `lookup`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::query::{Pick, QueryLookup};
+    ///
+    /// let url = "/items?Page=2&page=3&sort=name";
+    ///
+    /// // Parsing...
+    ///
+    /// # maker_web::docs_rs_helper::example_url_http1x(url, |req| {
+    /// let lookup = QueryLookup {
+    ///     case_insensitive: true,
+    ///     ..QueryLookup::default()
+    /// };
+    /// assert_eq!(req.url().query_with(b"page", lookup), Some("2".as_bytes()));
+    ///
+    /// let lookup = QueryLookup {
+    ///     case_insensitive: true,
+    ///     pick: Pick::Last,
+    ///     ..QueryLookup::default()
+    /// };
+    /// assert_eq!(req.url().query_with(b"PAGE", lookup), Some("3".as_bytes()));
+    ///
+    /// // The default is the same as `query()`
+    /// assert_eq!(req.url().query_with(b"Page", QueryLookup::default()), Some("2".as_bytes()));
+    /// assert_eq!(req.url().query_with(b"SORT", QueryLookup::default()), None);
+    /// # });
+    /// ```
+    #[inline]
+    pub fn query_with(&self, key: &[u8], lookup: QueryLookup) -> Option<&[u8]> {
+        lookup.find(self.query_parts.iter().copied(), key)
+    }
+
+    /// Returns the specified query parameter parsed as an integer.
+    ///
+    /// `None` if the parameter is missing, `Some(Err(()))` if its value is not
+    /// a decimal integer of type `T` (see [`FromRadix`]). Performs the same
+    /// lookup as [`query()`](Self::query).
+    ///
+    /// # Examples
+    /// ```
+    /// let url = "/items?page=2&limit=20&offset=-5&sort=name";
+    ///
+    /// // Parsing...
+    ///
+    /// # maker_web::docs_rs_helper::example_url_http1x(url, |req| {
+    /// assert_eq!(req.url().query_int::<u32>(b"page"), Some(Ok(2)));
+    /// assert_eq!(req.url().query_int::<u8>(b"limit"), Some(Ok(20)));
+    /// assert_eq!(req.url().query_int::<i64>(b"offset"), Some(Ok(-5)));
+    /// assert_eq!(req.url().query_int::<u64>(b"offset"), Some(Err(())));
+    /// assert_eq!(req.url().query_int::<u32>(b"sort"), Some(Err(())));
+    /// assert_eq!(req.url().query_int::<u32>(b"missing"), None);
+    /// # });
+    /// ```
+    #[inline]
+    #[allow(clippy::result_unit_err)]
+    pub fn query_int<T: FromRadix>(&self, key: &[u8]) -> Option<Result<T, ()>> {
+        self.query(key)
+            .map(|value| T::from_radix_10(value).ok_or(()))
+    }
+}
+
+/// Methods for building URLs relative to the current one
+impl Url {
+    /// Resolves a relative reference against the current path and appends
+    /// the result to `out`.
+    ///
+    /// `.` and `..` segments are removed, a reference starting with `/`
+    /// replaces the whole path, and references with a scheme or authority
+    /// (`https://...`, `//host/...`) are copied unchanged. An empty reference
+    /// gives the current path and query. `relative` is not percent-encoded,
+    /// see the [`url`](crate::url) module for that.
+    ///
+    /// See [[RFC3986, Section 5.2](https://datatracker.ietf.org/doc/html/rfc3986#section-5.2)].
+    ///
+    /// # Examples
+    /// ```
+    /// let url = "/docs/guide/intro?lang=en";
+    ///
+    /// // Parsing...
+    ///
+    /// # maker_web::docs_rs_helper::example_url_http1x(url, |req| {
+    /// let mut out = Vec::new();
+    /// req.url().resolve(b"../api/index", &mut out);
+    /// assert_eq!(out, b"/docs/api/index");
+    ///
+    /// out.clear();
+    /// req.url().resolve(b"setup?lang=de", &mut out);
+    /// assert_eq!(out, b"/docs/guide/setup?lang=de");
+    /// # });
+    /// #
+    /// # maker_web::docs_rs_helper::example_url_http09(url, |req| {
+    /// # let mut out = Vec::new();
+    /// # req.url().resolve(b"../api/index", &mut out);
+    /// # assert_eq!(out, b"/docs/api/index");
+    /// # });
+    /// ```
+    #[inline]
+    pub fn resolve(&self, relative: &[u8], out: &mut Vec<u8>) {
+        url::resolve(self.path(), self.query_full(), relative, out)
+    }
 }
 
 // HEADER
@@ -911,3 +1373,92 @@ pub(crate) struct Header {
     pub(crate) name: &'static str,
     pub(crate) value: &'static str,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_sets() {
+        use Method::*;
+        const METHODS: [Method; 7] = [Get, Put, Post, Head, Patch, Delete, Options];
+
+        let sets: [&[Method]; 4] = [&[], &[Get, Head], &[Post, Put, Patch], &METHODS];
+        for set in sets {
+            let mask = MethodSet::new(set);
+            assert_eq!(mask.is_empty(), set.is_empty());
+
+            for method in METHODS {
+                assert_eq!(mask.contains(method), set.contains(&method), "{method:?}");
+                assert_eq!(method.in_set(set), set.contains(&method), "{method:?}");
+            }
+        }
+
+        assert_eq!(MethodSet::new(&METHODS), MethodSet::ALL);
+        assert_eq!(
+            MethodSet::EMPTY.with(Get).with(Head),
+            MethodSet::new(&[Head, Get, Get])
+        );
+    }
+
+    #[test]
+    fn version_str() {
+        let cases = [
+            (Version::Http09, "HTTP/0.9"),
+            (Version::Http10, "HTTP/1.0"),
+            (Version::Http11, "HTTP/1.1"),
+        ];
+
+        for (version, expected) in cases {
+            assert_eq!(version.as_str(), expected);
+            assert_eq!(version.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn version_order() {
+        let versions = [Version::Http09, Version::Http10, Version::Http11];
+
+        for (i, a) in versions.iter().enumerate() {
+            for (j, b) in versions.iter().enumerate() {
+                assert_eq!(a.cmp(b), i.cmp(&j), "{a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn status_class() {
+        #[rustfmt::skip]
+        let cases = [
+            (99, None),
+            (100, Some(StatusClass::Informational)), (199, Some(StatusClass::Informational)),
+            (200, Some(StatusClass::Success)), (299, Some(StatusClass::Success)),
+            (300, Some(StatusClass::Redirection)), (399, Some(StatusClass::Redirection)),
+            (400, Some(StatusClass::ClientError)), (499, Some(StatusClass::ClientError)),
+            (500, Some(StatusClass::ServerError)), (599, Some(StatusClass::ServerError)),
+            (600, None),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(StatusClass::from_code(code), expected, "{code}");
+        }
+
+        let codes = [
+            (StatusCode::Continue, StatusClass::Informational),
+            (StatusCode::Ok, StatusClass::Success),
+            (StatusCode::PermanentRedirect, StatusClass::Redirection),
+            (StatusCode::BadRequest, StatusClass::ClientError),
+            (StatusCode::InternalServerError, StatusClass::ServerError),
+        ];
+        for (status, class) in codes {
+            assert_eq!(status.class(), class);
+            assert_eq!(
+                status.is_informational(),
+                class == StatusClass::Informational
+            );
+            assert_eq!(status.is_success(), class == StatusClass::Success);
+            assert_eq!(status.is_redirection(), class == StatusClass::Redirection);
+            assert_eq!(status.is_client_error(), class == StatusClass::ClientError);
+            assert_eq!(status.is_server_error(), class == StatusClass::ServerError);
+        }
+    }
+}
@@ -0,0 +1,515 @@
+//! Zero-allocation percent-encoding helpers for building URLs, and decoding
+//! helpers for reading them.
+
+use crate::WriteBuffer;
+use std::borrow::Cow;
+
+/// Set of characters left unescaped by the encoder.
+///
+/// Bytes outside the set are written as `%XX` with uppercase hex digits,
+/// the canonical form from
+/// [[RFC3986, Section 2.1](https://datatracker.ietf.org/doc/html/rfc3986#section-2.1)].
+///
+/// # Examples
+/// ```
+/// use maker_web::url::{Encoded, EncodeSet};
+/// use maker_web::WriteBuffer;
+///
+/// let mut buffer = Vec::new();
+/// Encoded(b"50%25 off", EncodeSet::PATH_SEGMENT.keep_escapes()).write_to(&mut buffer);
+/// assert_eq!(buffer, b"50%25%20off");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EncodeSet {
+    query: bool,
+    keep_escapes: bool,
+}
+
+impl EncodeSet {
+    /// A single path segment: everything except `pchar` is escaped, including `/`.
+    ///
+    /// See [[RFC3986, Section 3.3](https://datatracker.ietf.org/doc/html/rfc3986#section-3.3)].
+    pub const PATH_SEGMENT: Self = Self {
+        query: false,
+        keep_escapes: false,
+    };
+
+    /// A single query key or value: `&`, `=`, `+` and `#` are escaped so the
+    /// result can't break the `key=value&key=value` structure.
+    ///
+    /// See [[RFC3986, Section 3.4](https://datatracker.ietf.org/doc/html/rfc3986#section-3.4)].
+    pub const QUERY_COMPONENT: Self = Self {
+        query: true,
+        keep_escapes: false,
+    };
+
+    /// Leaves valid `%XX` sequences untouched instead of escaping the `%`.
+    ///
+    /// Use it for input that may already be encoded, to avoid turning
+    /// `%20` into `%2520`. A `%` not followed by two hex digits is still escaped.
+    #[inline]
+    pub const fn keep_escapes(mut self) -> Self {
+        self.keep_escapes = true;
+        self
+    }
+
+    #[inline]
+    const fn is_allowed(&self, byte: u8) -> bool {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => true,
+            b'!' | b'$' | b'\'' | b'(' | b')' | b'*' | b',' | b';' | b':' | b'@' => true,
+            b'&' | b'=' | b'+' => !self.query,
+            b'/' | b'?' => self.query,
+            _ => false,
+        }
+    }
+}
+
+/// Percent-encoded view of a byte slice, implementing [`WriteBuffer`].
+///
+/// # Examples
+/// ```
+/// # maker_web::docs_rs_helper::run_test(|_, resp| {
+/// use maker_web::{url::{Encoded, EncodeSet}, StatusCode};
+///
+/// let user_name = "John Smith/admin";
+///
+/// resp.status(StatusCode::Found)
+///     .header("location", Encoded(user_name.as_bytes(), EncodeSet::PATH_SEGMENT))
+///     // location: John%20Smith%2Fadmin
+///     .body("")
+/// # });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Encoded<'a>(pub &'a [u8], pub EncodeSet);
+
+impl WriteBuffer for Encoded<'_> {
+    #[inline]
+    fn write_to(&self, buffer: &mut Vec<u8>) {
+        encode(self.0, self.1, buffer);
+    }
+}
+
+/// Percent-encodes `src` as a single path segment and appends it to `out`.
+///
+/// # Examples
+/// ```
+/// use maker_web::url::encode_path_segment;
+///
+/// let mut out = b"/files/".to_vec();
+/// encode_path_segment("report 2025/Q1.pdf".as_bytes(), &mut out);
+/// assert_eq!(out, b"/files/report%202025%2FQ1.pdf");
+/// ```
+#[inline]
+pub fn encode_path_segment(src: &[u8], out: &mut Vec<u8>) {
+    encode(src, EncodeSet::PATH_SEGMENT, out)
+}
+
+/// Percent-encodes `src` as a single query key or value and appends it to `out`.
+///
+/// # Examples
+/// ```
+/// use maker_web::url::encode_query_component;
+///
+/// let mut out = b"/search?q=".to_vec();
+/// encode_query_component("tom & jerry".as_bytes(), &mut out);
+/// assert_eq!(out, b"/search?q=tom%20%26%20jerry");
+/// ```
+#[inline]
+pub fn encode_query_component(src: &[u8], out: &mut Vec<u8>) {
+    encode(src, EncodeSet::QUERY_COMPONENT, out)
+}
+
+#[inline]
+fn encode(src: &[u8], set: EncodeSet, out: &mut Vec<u8>) {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    out.reserve(src.len());
+
+    for (i, &byte) in src.iter().enumerate() {
+        if set.is_allowed(byte) || (set.keep_escapes && is_escape(&src[i..])) {
+            out.push(byte);
+        } else {
+            out.extend_from_slice(&[b'%', HEX[(byte >> 4) as usize], HEX[(byte & 15) as usize]]);
+        }
+    }
+}
+
+#[inline]
+fn is_escape(src: &[u8]) -> bool {
+    matches!(src, [b'%', a, b, ..] if a.is_ascii_hexdigit() && b.is_ascii_hexdigit())
+}
+
+/// Percent-decodes `src`, borrowing it when there is nothing to decode.
+///
+/// `%XX` is decoded to its byte; with `plus_as_space`, `+` is decoded to a
+/// space as in query strings and forms. An invalid escape (`%G1`, a
+/// truncated `%2`) is kept as it is, reject such requests with
+/// [`ReqLimits::strict_escapes`](crate::limits::ReqLimits::strict_escapes).
+///
+/// See also [`Url::path_segment_decoded`](crate::Url::path_segment_decoded)
+/// and [`Url::query_decoded`](crate::Url::query_decoded).
+///
+/// # Examples
+/// ```
+/// use maker_web::url::decode;
+///
+/// assert_eq!(decode(b"John%20Doe", false), &b"John Doe"[..]);
+/// assert_eq!(decode(b"a%2Bb+c", true), &b"a+b c"[..]);
+/// assert_eq!(decode(b"100%", false), &b"100%"[..]);
+/// ```
+#[inline]
+pub fn decode(src: &[u8], plus_as_space: bool) -> Cow<'_, [u8]> {
+    let special = |b: &u8| *b == b'%' || (plus_as_space && *b == b'+');
+    if !src.iter().any(special) {
+        return Cow::Borrowed(src);
+    }
+
+    let mut out = Vec::with_capacity(src.len());
+    let _ = decode_into(src, &mut out, plus_as_space, false);
+    Cow::Owned(out)
+}
+
+// `decode` into a caller buffer: `src` when there is nothing to decode,
+// otherwise `scratch`, cleared first
+#[inline]
+pub(crate) fn decode_with<'a>(
+    src: &'a [u8],
+    plus_as_space: bool,
+    scratch: &'a mut Vec<u8>,
+) -> &'a [u8] {
+    let special = |b: &u8| *b == b'%' || (plus_as_space && *b == b'+');
+    if !src.iter().any(special) {
+        return src;
+    }
+
+    scratch.clear();
+    let _ = decode_into(src, scratch, plus_as_space, false);
+    scratch
+}
+
+// Appends `src` to `out` decoded; an invalid escape is an error if `strict`,
+// otherwise copied
+#[inline]
+pub(crate) fn decode_into(
+    src: &[u8],
+    out: &mut Vec<u8>,
+    plus_as_space: bool,
+    strict: bool,
+) -> Result<(), ()> {
+    let mut rest = src;
+    while let Some(i) = rest
+        .iter()
+        .position(|&b| b == b'%' || (plus_as_space && b == b'+'))
+    {
+        out.extend_from_slice(&rest[..i]);
+
+        match rest[i..] {
+            [b'+', ..] => {
+                out.push(b' ');
+                rest = &rest[i + 1..];
+            }
+            [b'%', hi, lo, ..] if is_escape(&rest[i..]) => {
+                out.push(hex_value(hi) << 4 | hex_value(lo));
+                rest = &rest[i + 3..];
+            }
+            _ if strict => return Err(()),
+            _ => {
+                out.push(b'%');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.extend_from_slice(rest);
+    Ok(())
+}
+
+// Whether every `%` in `src` starts a valid escape
+#[inline]
+pub(crate) fn has_valid_escapes(src: &[u8]) -> bool {
+    memchr::memchr_iter(b'%', src).all(|i| is_escape(&src[i..]))
+}
+
+// For a byte checked with `is_ascii_hexdigit`
+#[inline]
+const fn hex_value(byte: u8) -> u8 {
+    match byte {
+        b'0'..=b'9' => byte - b'0',
+        b'a'..=b'f' => byte - b'a' + 10,
+        _ => byte - b'A' + 10,
+    }
+}
+
+/// Resolves `relative` against `base_path` and appends the result to `out`.
+///
+/// Follows [[RFC3986, Section 5.2](https://datatracker.ietf.org/doc/html/rfc3986#section-5.2)]
+/// for references without an authority. References with a scheme or an
+/// authority (`//host`) are already absolute and are copied unchanged.
+#[inline]
+pub(crate) fn resolve(
+    base_path: &[u8],
+    base_query: Option<&[u8]>,
+    relative: &[u8],
+    out: &mut Vec<u8>,
+) {
+    if has_scheme(relative) || relative.starts_with(b"//") {
+        out.extend_from_slice(relative);
+        return;
+    }
+
+    let split = relative
+        .iter()
+        .position(|&b| b == b'?' || b == b'#')
+        .unwrap_or(relative.len());
+    let (path, suffix) = relative.split_at(split);
+
+    let start = out.len();
+    out.reserve(base_path.len() + relative.len());
+
+    match path {
+        [b'/', ..] => out.extend_from_slice(path),
+        [] => out.extend_from_slice(base_path),
+        _ => {
+            let dir = base_path
+                .iter()
+                .rposition(|&b| b == b'/')
+                .map_or(&b"/"[..], |i| &base_path[..=i]);
+
+            out.extend_from_slice(dir);
+            out.extend_from_slice(path);
+        }
+    }
+    remove_dot_segments(out, start);
+
+    // Empty path keeps the current query unless the reference has its own
+    if path.is_empty() && !suffix.starts_with(b"?") {
+        out.extend_from_slice(base_query.unwrap_or_default());
+    }
+    out.extend_from_slice(suffix);
+}
+
+// scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." ) ":"
+#[inline]
+fn has_scheme(src: &[u8]) -> bool {
+    match src
+        .iter()
+        .position(|&b| matches!(b, b':' | b'/' | b'?' | b'#'))
+    {
+        Some(i) if src[i] == b':' && i > 0 => {
+            src[0].is_ascii_alphabetic()
+                && src[1..i]
+                    .iter()
+                    .all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'))
+        }
+        _ => false,
+    }
+}
+
+// In place over `buf[start..]`, which always begins with `/`. The output
+// is never longer than the input, so `write` can't overtake `read`.
+#[inline]
+fn remove_dot_segments(buf: &mut Vec<u8>, start: usize) {
+    let end = buf.len();
+    let (mut read, mut write) = (start, start);
+
+    while read < end {
+        let seg_end = memchr::memchr(b'/', &buf[read + 1..end]).map_or(end, |i| read + 1 + i);
+        let is_last = seg_end == end;
+
+        match &buf[read + 1..seg_end] {
+            b"." => {}
+            b".." => {
+                write = buf[start..write]
+                    .iter()
+                    .rposition(|&b| b == b'/')
+                    .map_or(start, |i| start + i);
+            }
+            _ => {
+                buf.copy_within(read..seg_end, write);
+                write += seg_end - read;
+                read = seg_end;
+                continue;
+            }
+        }
+
+        if is_last {
+            buf[write] = b'/';
+            write += 1;
+        }
+        read = seg_end;
+    }
+
+    buf.truncate(write);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::*;
+
+    fn encoded(src: &str, set: EncodeSet) -> String {
+        let mut out = Vec::new();
+        Encoded(src.as_bytes(), set).write_to(&mut out);
+        str_op(&out).to_string()
+    }
+
+    #[test]
+    fn path_segment() {
+        #[rustfmt::skip]
+        let cases = [
+            ("plain-text_1.2~3", "plain-text_1.2~3"),
+            ("a b",              "a%20b"),
+            ("a/b",              "a%2Fb"),
+            ("a?b#c",            "a%3Fb%23c"),
+            ("a&b=c+d",          "a&b=c+d"),
+            ("user@host:80",     "user@host:80"),
+            ("[]{}|\\^`\"<>",    "%5B%5D%7B%7D%7C%5C%5E%60%22%3C%3E"),
+            ("",                 ""),
+        ];
+
+        for (src, expected) in cases {
+            assert_eq!(encoded(src, EncodeSet::PATH_SEGMENT), expected);
+        }
+    }
+
+    #[test]
+    fn query_component() {
+        #[rustfmt::skip]
+        let cases = [
+            ("plain",       "plain"),
+            ("a b",         "a%20b"),
+            ("a&b=c+d",     "a%26b%3Dc%2Bd"),
+            ("a/b?c",       "a/b?c"),
+            ("#frag",       "%23frag"),
+        ];
+
+        for (src, expected) in cases {
+            assert_eq!(encoded(src, EncodeSet::QUERY_COMPONENT), expected);
+        }
+    }
+
+    #[test]
+    fn utf8_multibyte() {
+        assert_eq!(encoded("é", EncodeSet::PATH_SEGMENT), "%C3%A9");
+        assert_eq!(
+            encoded("привет", EncodeSet::QUERY_COMPONENT),
+            "%D0%BF%D1%80%D0%B8%D0%B2%D0%B5%D1%82"
+        );
+        assert_eq!(encoded("🫖", EncodeSet::PATH_SEGMENT), "%F0%9F%AB%96");
+    }
+
+    #[test]
+    fn decoded() {
+        #[rustfmt::skip]
+        let cases = [
+            ("plain",            false, "plain",      true),
+            ("John%20Doe",       false, "John Doe",   false),
+            ("user%40example.com", true, "user@example.com", false),
+            ("a%2bb+c",          true,  "a+b c",      false),
+            ("a%2Bb+c",          false, "a+b+c",      false),
+            ("a+b",              false, "a+b",        true),
+            // Invalid escapes are kept
+            ("%G1x",             false, "%G1x",       false),
+            ("end%2",            false, "end%2",      false),
+            ("%",                true,  "%",          false),
+            ("%%41",             false, "%A",         false),
+            ("%E2%82%AC",        false, "€",          false),
+        ];
+
+        for (src, plus_as_space, expected, borrowed) in cases {
+            let decoded = decode(src.as_bytes(), plus_as_space);
+            assert_eq!(str_op(&decoded), expected, "{src}");
+            assert_eq!(matches!(decoded, Cow::Borrowed(_)), borrowed, "{src}");
+        }
+
+        for (src, valid) in [("a%20b", true), ("a%2", false), ("%G1", false), ("", true)] {
+            assert_eq!(has_valid_escapes(src.as_bytes()), valid, "{src}");
+        }
+    }
+
+    #[test]
+    fn already_encoded() {
+        #[rustfmt::skip]
+        let cases = [
+            ("a%20b",  "a%2520b", "a%20b"),
+            ("100%",   "100%25",  "100%25"),
+            ("%zz",    "%25zz",   "%25zz"),
+            ("%2",     "%252",    "%252"),
+            ("%2f%2F", "%252f%252F", "%2f%2F"),
+        ];
+
+        for (src, double, kept) in cases {
+            assert_eq!(encoded(src, EncodeSet::PATH_SEGMENT), double);
+            assert_eq!(encoded(src, EncodeSet::PATH_SEGMENT.keep_escapes()), kept);
+            assert_eq!(
+                encoded(src, EncodeSet::QUERY_COMPONENT.keep_escapes()),
+                kept
+            );
+        }
+    }
+
+    #[test]
+    fn appends_to_buffer() {
+        let mut out = b"/a/".to_vec();
+        encode_path_segment(b"b c", &mut out);
+        out.push(b'?');
+        encode_query_component(b"k&v", &mut out);
+
+        assert_eq!(str_op(&out), "/a/b%20c?k%26v");
+    }
+
+    fn resolved(relative: &str) -> String {
+        let mut out = Vec::new();
+        resolve(b"/a/b/c", Some(b"?q=1"), relative.as_bytes(), &mut out);
+        str_op(&out).to_string()
+    }
+
+    #[test]
+    fn resolve_relative() {
+        #[rustfmt::skip]
+        let cases = [
+            ("../x",         "/a/x"),
+            ("./y",          "/a/b/y"),
+            ("/abs",         "/abs"),
+            ("d",            "/a/b/d"),
+            ("d/",           "/a/b/d/"),
+            (".",            "/a/b/"),
+            ("..",           "/a/"),
+            ("../..",        "/"),
+            ("../../../../x", "/x"),
+            ("/x/./y/../z",  "/x/z"),
+            ("d?k=v",        "/a/b/d?k=v"),
+            ("?k=v",         "/a/b/c?k=v"),
+            ("#top",         "/a/b/c?q=1#top"),
+            ("",             "/a/b/c?q=1"),
+        ];
+
+        for (relative, expected) in cases {
+            assert_eq!(resolved(relative), expected, "{relative}");
+        }
+    }
+
+    #[test]
+    fn resolve_absolute() {
+        #[rustfmt::skip]
+        let cases = [
+            "https://example.com/a/../b",
+            "mailto:user@example.com",
+            "//cdn.example.com/x",
+        ];
+
+        for relative in cases {
+            assert_eq!(resolved(relative), relative);
+        }
+        assert_eq!(resolved("a:b/c"), "a:b/c");
+        assert_eq!(resolved("./a:b"), "/a/b/a:b");
+    }
+
+    #[test]
+    fn resolve_appends_to_buffer() {
+        let mut out = b"location: ".to_vec();
+        resolve(b"/", None, b"../x", &mut out);
+
+        assert_eq!(str_op(&out), "location: /x");
+    }
+}
@@ -95,34 +95,59 @@
 //! For live statistics, deeper insights, and ongoing project thoughts,
 //! visit the [project website](https://amakesashadev.github.io/maker_web/).
 pub(crate) mod http {
+    pub(crate) mod extensions;
+    pub mod metrics;
+    pub mod parser;
     pub mod query;
     pub(crate) mod request;
     pub(crate) mod response;
+    pub mod tmpl;
     pub(crate) mod types;
+    pub mod url;
 }
+#[cfg(feature = "server")]
 pub(crate) mod server {
     pub(crate) mod connection;
+    pub(crate) mod cors;
+    pub(crate) mod hot_swap;
+    pub(crate) mod idempotency;
+    pub(crate) mod rate_limit;
+    pub(crate) mod router;
     pub(crate) mod server_impl;
 }
+#[cfg(feature = "server")]
+pub mod debug;
 pub(crate) mod errors;
 pub mod limits;
 
-pub use crate::{
-    http::{
-        query,
-        request::Request,
-        response::{
-            write::{BodyWriter, WriteBuffer},
-            Handled, Response,
-        },
-        types::{Method, StatusCode, Url, Version},
+pub use crate::http::{
+    extensions::Extensions,
+    metrics, parser, query,
+    request::Request,
+    response::{
+        write::{BodyWriter, Decimal, HttpDate, WriteBuffer, YieldingBodyWriter},
+        BodyOverflow, Handled, Response, ResponseClean, ResponseComplete, ResponseHeaders,
+        ResponseTemplate,
     },
-    server::{
-        connection::{ConnectionData, ConnectionFilter},
-        server_impl::{Handler, Server, ServerBuilder},
+    tmpl,
+    types::{IfRange, Method, MethodSet, StatusClass, StatusCode, Url, Version},
+    url,
+};
+#[cfg(feature = "server")]
+pub use crate::server::{
+    connection::{
+        CancelToken, CloseReason, ConnInfo, ConnectionData, ConnectionFilter, EffectiveLimits,
+        FilterError, FilterFailurePolicy,
     },
+    cors::Cors,
+    hot_swap::HotSwap,
+    idempotency::IdempotencyStore,
+    rate_limit::IpRateLimit,
+    router::{HostRouter, NotFound, Router},
+    server_impl::{BuildError, Handler, ResolvedConfig, Server, ServerBuilder},
 };
 
+#[cfg(feature = "server")]
 #[doc(hidden)]
 pub mod docs_rs_helper {
     use crate::{
@@ -152,6 +177,64 @@ pub mod docs_rs_helper {
     }
 }
 
+// For `benches/parser.rs` and `benches/response.rs`
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod bench_helper {
+    use crate::{
+        limits::{Http09Limits, ReqLimits, RespLimits},
+        server::connection::{def_handler::DefHandler, HttpConnection},
+        Handled, Request, Response,
+    };
+
+    pub struct RespBench(Response, RespLimits);
+
+    impl Default for RespBench {
+        fn default() -> Self {
+            let limits = RespLimits::default();
+            Self(Response::new(&limits), limits)
+        }
+    }
+
+    impl RespBench {
+        /// Builds a response on a freshly reset `Response`, returns its size
+        #[inline]
+        pub fn run<F: FnOnce(&mut Response) -> Handled>(&mut self, f: F) -> usize {
+            self.0.reset(&self.1);
+            f(&mut self.0);
+            self.0.buffer().len()
+        }
+    }
+
+    pub struct ParseBench(HttpConnection<DefHandler, ()>);
+
+    impl ParseBench {
+        pub fn new<V: AsRef<[u8]>>(request: V, http_09: bool) -> Self {
+            let mut t = HttpConnection::from_req(request);
+            if http_09 {
+                t.http_09_limits = Some(Http09Limits::default());
+            }
+
+            Self(t)
+        }
+
+        pub fn with_limits<V: AsRef<[u8]>>(request: V, limits: ReqLimits) -> Self {
+            Self(HttpConnection::from_req_with(request, limits))
+        }
+
+        #[inline]
+        pub fn request(&self) -> &Request {
+            &self.0.request
+        }
+
+        #[inline]
+        pub fn parse(&mut self) -> bool {
+            self.0.request.reset();
+            self.0.parse_request().is_ok()
+        }
+    }
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! impt_default_handler {
@@ -51,7 +51,8 @@
 //! }
 //! ```
 
-use std::time::Duration;
+use crate::StatusCode;
+use std::{net::IpAddr, time::Duration};
 
 /// Controls server-level concurrency, queueing, and performance behavior.
 ///
@@ -98,11 +99,19 @@ pub struct ServerLimits {
     ///
     /// All accepted connections first go into this queue. Worker processes select
     /// connections from here. If the queue becomes full, new connections receive immediate
-    /// HTTP `503` responses.
+    /// HTTP `503` responses, or are not accepted at all with [`Admission::Backpressure`].
     ///
     /// For more information, see [Connection management](#connection-management).
     pub max_pending_connections: usize,
 
+    /// What happens to new connections once the queue is full
+    /// (default: [`Admission::Queue`])
+    pub admission: Admission,
+
+    /// How accepted connections are assigned to workers
+    /// (default: [`Dispatch::RoundRobin`])
+    pub dispatch: Dispatch,
+
     /// Strategy for worker task waiting behavior (default: `Sleep(50μs)`)
     ///
     /// Controls how worker tasks wait when connection buffers are empty
@@ -113,12 +122,44 @@ pub struct ServerLimits {
     /// Dedicated handlers for queue overflow responses (default: `1`).
     ///
     /// When the connection queue becomes full, these handlers immediately send
-    /// responses with the [503](crate::StatusCode::ServiceUnavailable) code. Using
+    /// responses with the [`overload_status`](#structfield.overload_status) code. Using
     /// multiple handlers prevents bottlenecks in scenarios with a large volume of
     /// rejected requests. Set to 0 to silently close the connection (not recommended
     /// for production HTTP servers).
     pub count_503_handlers: usize,
 
+    /// Write connection filter rejections from the overflow handlers (default: `false`)
+    ///
+    /// By default a worker writes the rejection of its [connection
+    /// filter](crate::ServerBuilder::conn_filter) itself, which can take up to
+    /// [`socket_write_timeout`](ConnLimits::socket_write_timeout) for a client
+    /// that does not read. With `true` the worker copies the response, hands the
+    /// connection to the [`count_503_handlers`](#structfield.count_503_handlers)
+    /// and goes back to its queue, so a flood of banned clients does not slow
+    /// down the others. The same applies to the `503` of
+    /// [`FilterFailurePolicy::FailClosed`](crate::FilterFailurePolicy::FailClosed).
+    /// Ignored with `count_503_handlers: 0`.
+    pub filter_rejects_use_alarmists: bool,
+
+    /// Maximum number of responses per second written by the overflow handlers
+    /// (default: `None`, unlimited)
+    ///
+    /// Shared by all [`count_503_handlers`](#structfield.count_503_handlers),
+    /// with bursts of up to one second of responses. Connections over the rate
+    /// are closed without a response, so a rejection flood costs at most this
+    /// many writes per second and leaves the CPU to the workers. Applies to
+    /// filter rejections handed over by `filter_rejects_use_alarmists` too.
+    /// `Some(0)` closes every overflow connection silently.
+    pub alarmist_max_rate: Option<u32>,
+
+    /// Status sent on queue overflow (default: [`503`](crate::StatusCode::ServiceUnavailable))
+    ///
+    /// Use [`429`](crate::StatusCode::TooManyRequests) if clients should slow
+    /// down rather than treat the server as unavailable. With `json_errors`,
+    /// statuses other than `503` get the body
+    /// `{"error":"Server overloaded","code":"OVERLOADED"}`.
+    pub overload_status: StatusCode,
+
     /// Format for error responses (default: `true`)
     ///
     /// # Examples
@@ -139,6 +180,27 @@ pub struct ServerLimits {
     /// \r
     /// ```
     pub json_errors: bool,
+    /// Adds where a request failed to parse to JSON errors (default: `false`)
+    ///
+    /// The body gets the offset of the rejected element (method, target,
+    /// version, header line or body) from the start of the request and up
+    /// to 32 bytes around it, with unprintable bytes escaped:
+    /// ```text
+    /// {"error":"Invalid header format","code":"INVALID_HEADER","offset":16,"excerpt":"HTTP/1.1\u000d\u000abad header\u000d\u000a\u000d\u000a"}
+    /// ```
+    /// Only with `json_errors`. The excerpt echoes the client's own bytes,
+    /// which can include credentials from its headers: enable it for debugging.
+    pub verbose_parse_errors: bool,
+
+    /// Maximum estimated memory of all workers, in bytes (default: `Some(1 GiB)`)
+    ///
+    /// The estimate is [`ResolvedConfig::estimated_memory`](crate::ResolvedConfig::estimated_memory):
+    /// `max_connections` times the request buffer
+    /// ([`ReqLimits::estimated_buffer_size`]) plus the initial response capacity.
+    /// Above it [`try_build`](crate::ServerBuilder::try_build) returns
+    /// [`BuildError::MemoryCeiling`](crate::BuildError::MemoryCeiling) instead of
+    /// allocating it at startup. `None` disables the check.
+    pub memory_ceiling: Option<usize>,
 
     #[doc(hidden)]
     #[allow(dead_code)]
@@ -150,15 +212,65 @@ impl Default for ServerLimits {
         Self {
             max_connections: 100,
             max_pending_connections: 250,
+            admission: Admission::Queue,
+            dispatch: Dispatch::RoundRobin,
             wait_strategy: WaitStrategy::Sleep(Duration::from_micros(50)),
             count_503_handlers: 1,
+            filter_rejects_use_alarmists: false,
+            alarmist_max_rate: None,
+            overload_status: StatusCode::ServiceUnavailable,
             json_errors: true,
+            verbose_parse_errors: false,
+            memory_ceiling: Some(1 << 30),
 
             _priv: (),
         }
     }
 }
 
+/// Admission of new connections when the queue is full, see
+/// [`ServerLimits::admission`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Admission {
+    /// Connections are always accepted; once the queue is full they get
+    /// the [`overload_status`](ServerLimits::overload_status) response
+    Queue,
+    /// The server stops calling `accept()` while the queue is full.
+    ///
+    /// New connections wait in the OS backlog (see `listen(2)`) and no work
+    /// is spent on rejecting them; clients see a slow connect or a refused
+    /// connection once the backlog is full too. The queue is checked with the
+    /// [`wait_strategy`](ServerLimits::wait_strategy). With
+    /// `max_pending_connections: 0` one connection is still queued.
+    ///
+    /// With [`Dispatch::PeerIpHash`] the queue of a client is only known once
+    /// it is accepted, so `accept()` stops only while every queue is full; a
+    /// connection accepted for a full queue gets the
+    /// [`overload_status`](ServerLimits::overload_status) response, and one
+    /// busy client never holds up the clients of other workers.
+    Backpressure,
+}
+
+/// Assignment of accepted connections to workers, see
+/// [`ServerLimits::dispatch`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispatch {
+    /// All workers take connections from one shared queue, the next free
+    /// worker gets the next connection
+    RoundRobin,
+    /// Each worker has its own queue and a client IP always lands on the same
+    /// worker, chosen by a hash of the IP (IPv4-mapped IPv6 addresses as IPv4).
+    ///
+    /// Keeps per-client state in [`ConnectionData`](crate::ConnectionData)
+    /// warm across connections. Workers don't take connections from other
+    /// queues, so one busy client delays the other clients of its worker even
+    /// when the rest are idle: tail latency is traded for locality.
+    /// [`max_pending_connections`](ServerLimits::max_pending_connections) is
+    /// split evenly between the queues. The worker is reported by
+    /// [`ConnInfo::worker`](crate::ConnInfo::worker).
+    PeerIpHash,
+}
+
 /// Strategy for worker task waiting when no connections are available
 ///
 /// Different strategies optimize for different workload patterns.
@@ -230,6 +342,21 @@ pub struct ConnLimits {
     /// If data can't be written in time, connection is terminated.
     /// Applies to individual write operations.
     pub socket_write_timeout: Duration,
+    /// Minimum write throughput in bytes per second (default: `None`)
+    ///
+    /// By default a whole response must be written within `socket_write_timeout`,
+    /// which is too short for large bodies on slow links. With a rate, each
+    /// write gets `socket_write_timeout` instead, and the connection is closed
+    /// when less than `rate` bytes were written during any second of the
+    /// response, so a client reading at a trickle can't hold a worker.
+    pub min_write_rate: Option<u64>,
+    /// Size of the segments a response is written in (default: `None`)
+    ///
+    /// With a size, each segment is written and flushed under its own
+    /// `socket_write_timeout`, so a slow client that keeps reading gets a
+    /// large response while a stalled one is still cut off after one timeout.
+    /// Ignored when `min_write_rate` is set, which already times each write.
+    pub write_segment_size: Option<usize>,
 
     /// Maximum number of requests allowed per connection (default: `100`)
     ///
@@ -249,6 +376,52 @@ pub struct ConnLimits {
     /// excluding I/O operations without this limit).
     pub connection_lifetime: Duration,
 
+    /// Maximum number of pipelined requests processed from a single read (default: `16`)
+    ///
+    /// A client may send several requests in one packet. After this many of them
+    /// are answered from the same buffer, the worker yields to the runtime before
+    /// continuing, so one large batch can't monopolize it. Doesn't limit the total
+    /// number of requests, see `max_requests_per_connection` for that.
+    pub max_pipelined_requests: usize,
+
+    /// Maximum number of unread bytes discarded before closing a connection (default: `16 KB`)
+    ///
+    /// After the last response, the server shuts down its side of the connection
+    /// and reads away what the client still sends (e.g. trailing bytes after a
+    /// `Connection: close` request) until the client closes its side. Closing a
+    /// socket with unread data makes the OS send `RST`, which can destroy the
+    /// response before the client reads it. The worker waits for the client at
+    /// most `socket_write_timeout`. Set to `0` to close right after the shutdown.
+    pub close_drain_size: usize,
+
+    /// Requests pipelined after the last one allowed by `max_requests_per_connection`
+    /// (default: [`PipelinedOverflow::Discard`])
+    ///
+    /// A client may have sent more requests in the same packet as the last
+    /// one the connection is allowed to serve.
+    pub pipelined_overflow_policy: PipelinedOverflow,
+
+    /// Resets connections that are dropped without a response (default: `false`)
+    ///
+    /// Applies only to the silent paths: queue overflow with
+    /// `count_503_handlers: 0` and connections rejected by the
+    /// [`ConnectionFilter`](crate::ConnectionFilter) with
+    /// [`close_without_response()`](crate::Response::close_without_response).
+    /// `SO_LINGER` is set to zero before closing, so the OS sends `RST` and
+    /// frees the socket at once instead of keeping it in `TIME_WAIT`, which
+    /// matters during floods. Connections that received a response are
+    /// always closed normally.
+    pub abortive_close_on_reject: bool,
+    /// Fires the [`CancelToken`](crate::CancelToken) when the client sends
+    /// `EOF` while its request is handled (default: `false`)
+    ///
+    /// A reset connection always fires it. `EOF` is ambiguous: a client that
+    /// closed the connection sends it, but so does one that only half-closes
+    /// its write side after the request and still waits for the response
+    /// (e.g. `curl --no-keepalive`). Enable this only if your clients never
+    /// half-close, otherwise their requests are cancelled while they're alive.
+    pub cancel_on_eof: bool,
+
     #[doc(hidden)]
     #[allow(dead_code)]
     pub _priv: (),
@@ -260,14 +433,35 @@ impl Default for ConnLimits {
         Self {
             socket_read_timeout: Duration::from_secs(2),
             socket_write_timeout: Duration::from_secs(3),
+            min_write_rate: None,
+            write_segment_size: None,
             connection_lifetime: Duration::from_secs(120),
             max_requests_per_connection: 100,
+            max_pipelined_requests: 16,
+            close_drain_size: 16 * 1024,
+            pipelined_overflow_policy: PipelinedOverflow::Discard,
+            abortive_close_on_reject: false,
+            cancel_on_eof: false,
 
             _priv: (),
         }
     }
 }
 
+/// Handling of requests pipelined after the last request a connection may serve,
+/// see [`ConnLimits::pipelined_overflow_policy`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PipelinedOverflow {
+    /// The last request is answered with `connection: close`, the requests
+    /// after it are dropped unparsed and unanswered
+    Discard,
+    /// The last request is answered as usual; if more requests are already
+    /// buffered, the first of them gets `503 Service Unavailable` and the
+    /// connection is closed. Clients can tell the rejected requests apart
+    /// from lost ones and retry them on a new connection.
+    RejectWith503,
+}
+
 /// Configuration for `HTTP/0.9+` protocol support
 ///
 /// HTTP/0.9+ is an optimized protocol variant for high-performance scenarios
@@ -448,6 +642,15 @@ pub struct Http09Limits {
     /// - Very long (5+ minutes): not recommended outside controlled environments
     pub connection_lifetime: Duration,
 
+    /// Reject `HTTP/0.9+` requests until an `HTTP/1.X` request has been
+    /// handled on the same connection (default: `false`)
+    ///
+    /// Enforces the hybrid pattern above, e.g. authentication over `HTTP/1.X`
+    /// before the `HTTP/0.9+` requests. Rejected requests get
+    /// `ERROR: "403 Forbidden"` and the connection is closed. Handlers can check
+    /// the same condition with [`ConnInfo`](crate::ConnInfo).
+    pub require_prior_http1x: bool,
+
     #[doc(hidden)]
     #[allow(dead_code)]
     pub _priv: (),
@@ -458,6 +661,7 @@ impl Default for Http09Limits {
         Self {
             max_requests_per_connection: 250,
             connection_lifetime: Duration::from_secs(30),
+            require_prior_http1x: false,
             _priv: (),
         }
     }
@@ -539,6 +743,25 @@ pub struct ReqLimits {
     /// Prevents query parameter explosion attacks.
     /// Increase for complex filtering APIs with many parameters.
     pub url_query_parts: usize,
+    /// Parses the query string of the URL (default: `true`)
+    ///
+    /// With `false` the path still ends at `?`, but the query is neither
+    /// checked nor split into parameters: [`Url::query_full`](crate::Url::query_full)
+    /// returns `None`, [`Url::query`](crate::Url::query) finds nothing and the
+    /// query is only visible in [`Url::target`](crate::Url::target).
+    /// [`url_query_size`](Self::url_query_size) is ignored, while
+    /// [`url_query_parts`](Self::url_query_parts) still limits
+    /// [`Request::body_form`](crate::Request::body_form).
+    /// For hot endpoints that never read query parameters.
+    pub parse_query: bool,
+    /// Rejects a URL with an invalid percent-escape (`%G1`, a truncated `%2`)
+    /// with `400 INVALID_ESCAPE` (default: `false`)
+    ///
+    /// Without it such escapes are kept as they are by
+    /// [`Url::path_segment_decoded`](crate::Url::path_segment_decoded),
+    /// [`Url::query_decoded`](crate::Url::query_decoded) and
+    /// [`url::decode`](crate::url::decode).
+    pub strict_escapes: bool,
 
     /// Maximum number of headers per request (default: `16 headers`)
     ///
@@ -555,12 +778,87 @@ pub struct ReqLimits {
     /// Fits most headers including JWT tokens, cookies, and UUIDs.
     /// Increase for large cookies or complex authentication tokens.
     pub header_value_size: usize,
+    /// Headers stored in dedicated slots instead of the general list (default: `None`)
+    ///
+    /// [`Request::header`](crate::Request::header) scans all headers linearly.
+    /// Names listed here (case-insensitive) are resolved to their slot once at
+    /// startup and found through a hash of their length and first and last
+    /// bytes, so reading them costs the same with 4 or 64 headers. Lookups of
+    /// other names pay one hash before the scan. Lookup results are the same
+    /// with or without this setting, and captured headers still count towards
+    /// `header_count`.
+    ///
+    /// # Example
+    /// ```
+    /// use maker_web::limits::ReqLimits;
+    ///
+    /// let limits = ReqLimits {
+    ///     header_count: 64,
+    ///     captured_headers: Some(&[b"host", b"authorization", b"x-request-id"]),
+    ///     ..ReqLimits::default()
+    /// };
+    /// ```
+    pub captured_headers: Option<&'static [&'static [u8]]>,
+    /// Rejects a `Host` header with invalid syntax with `400` (default: `false`)
+    ///
+    /// The value must be a host name, an IPv4 address or a bracketed IPv6
+    /// address, optionally followed by `:port`, as in
+    /// [RFC 3986](https://datatracker.ietf.org/doc/html/rfc3986#section-3.2.2).
+    /// An empty value is allowed.
+    pub validate_authority: bool,
+    /// Rejects `HTTP/1.1` requests without a `Host` header with `400` (default: `true`)
+    ///
+    /// [RFC 9112](https://datatracker.ietf.org/doc/html/rfc9112#section-3.2)
+    /// requires the header on `HTTP/1.1`. `HTTP/1.0` and `HTTP/0.9+` requests
+    /// may omit it.
+    pub require_host_http11: bool,
+    /// Headers dropped from requests of untrusted peers (default: `None`)
+    ///
+    /// Matching headers (case-insensitive) are not stored, so
+    /// [`Request::header`](crate::Request::header) never returns them, unless the
+    /// client address is in [`trusted_proxies`](Self::trusted_proxies). Use this for
+    /// headers that only your proxy may set, like `x-internal-auth`. Headers the
+    /// parser interprets itself (`Content-Length`, `Connection`, `Transfer-Encoding`)
+    /// can't be stripped, and stripped headers don't count towards `header_count`.
+    ///
+    /// # Example
+    /// ```
+    /// use maker_web::limits::ReqLimits;
+    /// use std::net::{IpAddr, Ipv4Addr};
+    ///
+    /// const PROXIES: &[IpAddr] = &[IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))];
+    ///
+    /// let limits = ReqLimits {
+    ///     strip_headers: Some(&[b"x-internal-auth", b"x-real-ip"]),
+    ///     trusted_proxies: PROXIES,
+    ///     ..ReqLimits::default()
+    /// };
+    /// ```
+    pub strip_headers: Option<&'static [&'static [u8]]>,
+    /// Peers whose requests keep the [`strip_headers`](Self::strip_headers) and whose
+    /// `X-Forwarded-Proto` is used by [`Request::is_secure`](crate::Request::is_secure)
+    /// (default: empty)
+    pub trusted_proxies: &'static [IpAddr],
 
     /// Maximum request body size in bytes (default: `4 KB`)
     ///
     /// Suitable for API requests with JSON payloads. Increase for file uploads
     /// or large data submissions. Set based on your expected payload sizes.
     pub body_size: usize,
+    /// What to do with a body on `GET`, `HEAD`, `DELETE` and `OPTIONS` requests
+    /// (default: [`BodyPolicy::Allow`])
+    ///
+    /// These methods have no defined body semantics, and some proxies and caches
+    /// refuse to forward such requests. Only a non-zero `Content-Length` counts
+    /// as a body.
+    pub body_on_get_head: BodyPolicy,
+
+    /// How much of the request buffer is zeroed when it is reset (default: [`ZeroPolicy::UsedPrefix`])
+    ///
+    /// The buffer is reset between connections and whenever all read requests
+    /// have been answered. Parsing never looks past the bytes of the current read,
+    /// so this only controls how long old request data stays in memory.
+    pub zero_on_reset: ZeroPolicy,
 
     #[doc(hidden)]
     #[allow(dead_code)]
@@ -575,12 +873,21 @@ impl Default for ReqLimits {
             url_parts: 8,        // /api/users/123
             url_query_size: 128, // Enough for: ?sort=name&debug
             url_query_parts: 8,  // ?sort=name&debug
+            parse_query: true,
+            strict_escapes: false,
 
             header_count: 16,       // Typical: 10-12 browser headers + 4-6 custom
             header_name_size: 64,   // Fits: x-custom-auth-token-header-name
             header_value_size: 512, // Fits most JWT tokens and cookies
+            captured_headers: None,
+            validate_authority: false,
+            require_host_http11: true,
+            strip_headers: None,
+            trusted_proxies: &[],
 
             body_size: 4 * 1024, // Good for JSON API requests, not file uploads
+            body_on_get_head: BodyPolicy::Allow,
+            zero_on_reset: ZeroPolicy::UsedPrefix,
 
             precalc: ReqLimitsPrecalc::default(),
         }
@@ -621,10 +928,21 @@ impl ReqLimits {
         self.precalc.h_line = self.h_line();
         self.precalc.buffer = self.buffer();
         self.precalc.req_without_body = self.precalc.buffer - self.body_size;
+        self.precalc.captured = CapturedSlots::new(self.captured_headers.unwrap_or_default());
 
         self
     }
 
+    // Limits of the parser fixtures in the tests and benches: most of them are
+    // a bare request line, `require_host_http11` has its own test
+    #[cfg_attr(not(any(test, feature = "server")), allow(dead_code))]
+    pub(crate) fn bare() -> Self {
+        Self {
+            require_host_http11: false,
+            ..Self::default()
+        }
+    }
+
     #[inline(always)]
     // First line + Header * N + "\r\n" + Body
     fn buffer(&self) -> usize {
@@ -656,6 +974,36 @@ impl ReqLimits {
     }
 }
 
+/// Handling of a body on methods without body semantics,
+/// see [`ReqLimits::body_on_get_head`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyPolicy {
+    /// The body is read and available in [`Request::body`](crate::Request::body)
+    Allow,
+    /// The request is rejected with `400 Bad Request` (`BODY_NOT_ALLOWED`)
+    Reject,
+    /// The body is read and discarded, [`Request::body`](crate::Request::body)
+    /// returns `None`
+    Ignore,
+}
+
+/// Zeroing of the request buffer on reset, see [`ReqLimits::zero_on_reset`]
+///
+/// Workers reuse their buffers, so without zeroing the bytes of earlier requests
+/// stay in memory until they are overwritten.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZeroPolicy {
+    /// Nothing is zeroed, maximum throughput
+    None,
+    /// Only the bytes written since the last reset are zeroed.
+    ///
+    /// Leaves the buffer in the same state as [`Full`](ZeroPolicy::Full)
+    /// for the cost of the data actually received.
+    UsedPrefix,
+    /// The whole buffer is zeroed, regardless of how much of it was used
+    Full,
+}
+
 #[doc(hidden)]
 #[derive(Debug, Clone, Default)]
 pub struct ReqLimitsPrecalc {
@@ -663,6 +1011,64 @@ pub struct ReqLimitsPrecalc {
     pub(crate) first_line: usize,
     pub(crate) req_without_body: usize,
     pub(crate) h_line: usize,
+    pub(crate) captured: CapturedSlots,
+}
+
+/// The slots of [`ReqLimits::captured_headers`] by a hash of the name, so a
+/// lookup compares at most the names sharing its bucket (usually none or one)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct CapturedSlots {
+    names: &'static [&'static [u8]],
+    // Empty without captured headers
+    buckets: Box<[Box<[usize]>]>,
+}
+
+impl CapturedSlots {
+    fn new(names: &'static [&'static [u8]]) -> Self {
+        if names.is_empty() {
+            return Self::default();
+        }
+
+        let mut buckets = vec![Vec::new(); (names.len() * 2).next_power_of_two()];
+        let mask = buckets.len() - 1;
+        for (slot, name) in names.iter().enumerate() {
+            // A repeated name keeps its first slot, like the linear search did
+            if !names[..slot].iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                buckets[Self::bucket(name, mask)].push(slot);
+            }
+        }
+
+        Self {
+            names,
+            buckets: buckets.into_iter().map(Vec::into_boxed_slice).collect(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    #[inline(always)]
+    pub(crate) fn slot(&self, name: &[u8]) -> Option<usize> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
+        let bucket = &self.buckets[Self::bucket(name, self.buckets.len() - 1)];
+        bucket
+            .iter()
+            .copied()
+            .find(|&slot| self.names[slot].eq_ignore_ascii_case(name))
+    }
+
+    // Case-insensitive: length, first and last byte
+    #[inline(always)]
+    fn bucket(name: &[u8], mask: usize) -> usize {
+        let byte = |b: Option<&u8>| usize::from(b.map_or(0, u8::to_ascii_lowercase));
+        let hash = name.len().wrapping_mul(31) ^ (byte(name.first()) << 2) ^ byte(name.last());
+        hash & mask
+    }
 }
 
 /// Configuration for response processing and memory allocation limits.
@@ -679,7 +1085,8 @@ pub struct ReqLimitsPrecalc {
 /// # let mut buffer: Vec<()> = Vec::with_capacity(limits.default_capacity);
 /// #
 /// // `buffer` is Vec
-/// if buffer.capacity() > limits.max_capacity {
+/// let capacity = buffer.capacity();
+/// if capacity > limits.max_capacity || capacity < limits.default_capacity {
 ///     buffer = Vec::with_capacity(limits.default_capacity);
 /// } else {
 ///     buffer.clear();
@@ -687,6 +1094,8 @@ pub struct ReqLimitsPrecalc {
 /// ```
 ///
 /// When the server starts, buffers are created with a capacity equal to `default_capacity`.
+/// A buffer only falls under it when a cancelled
+/// [`body_with_async`](crate::Response::body_with_async) builder took it.
 #[derive(Debug, Clone)]
 pub struct RespLimits {
     /// Initial buffer capacity allocated for responses (default: `1024 B`)
@@ -695,6 +1104,39 @@ pub struct RespLimits {
     //
     // Note: If the response exceeds `max_capacity * 2`, it may be sent in 1 or more `syscall`
     pub max_capacity: usize,
+    /// Maximum body size of a single `HTTP/1.X` response (default: `usize::MAX`)
+    ///
+    /// A larger body is replaced with an empty `500 Internal Server Error` and
+    /// the connection is closed. Bodies that do not fit into the 10-digit
+    /// `content-length` (over `9_999_999_999 B`) are always handled this way.
+    pub hard_max_body: usize,
+    /// Maximum size of a whole `HTTP/1.X` response, status line and headers
+    /// included (default: `None`, no limit)
+    ///
+    /// A larger response is replaced with an empty `500 Internal Server Error`
+    /// and the connection is closed. Unlike `max_capacity`, which only decides
+    /// whether the buffer is kept, this limit is enforced.
+    pub max_response_size: Option<usize>,
+    /// Replaces a response built with methods for the wrong protocol with
+    /// `500 Internal Server Error` in `release` builds (default: `true`)
+    ///
+    /// E.g. [`status()`](crate::Response::status) for an `HTTP/0.9+` request
+    /// or [`http09()`](crate::Response::http09) for an `HTTP/1.X` one. The call
+    /// site is kept in [`Response::misused_at`](crate::Response::misused_at).
+    /// `debug` builds panic instead. With `false` such responses are sent as
+    /// written, which is not valid for the client's protocol.
+    pub validate_protocol_usage: bool,
+    /// Announces the connection limits to clients (default: `false`)
+    ///
+    /// Responses that keep the connection open get
+    /// `keep-alive: timeout=N, max=M`, where `N` is
+    /// [`ConnLimits::socket_read_timeout`] in whole seconds and `M` is the
+    /// number of requests the connection may still serve after this one
+    /// ([`ConnLimits::max_requests_per_connection`] or
+    /// [`Response::close_after`](crate::Response::close_after)). Clients and
+    /// proxies that honor it close idle connections before the server does,
+    /// instead of sending a request into a closing socket.
+    pub emit_keep_alive_hint: bool,
 
     #[doc(hidden)]
     #[allow(dead_code)]
@@ -706,6 +1148,10 @@ impl Default for RespLimits {
         Self {
             default_capacity: 1024,
             max_capacity: 8 * 1024,
+            hard_max_body: usize::MAX,
+            max_response_size: None,
+            validate_protocol_usage: true,
+            emit_keep_alive_hint: false,
 
             _priv: (),
         }
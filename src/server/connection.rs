@@ -1,16 +1,41 @@
 use crate::{
-    errors::ErrorKind,
+    debug::{Direction, RequestMirror, VersionCounters, Watermarks, WireTap},
+    errors::{ErrorContext, ErrorKind, VERBOSE_LEN},
     http::{
         request::{Parser, Request},
-        response::Response,
+        response::{Outcome, Response},
         types::Version,
     },
-    limits::{ConnLimits, Http09Limits, ReqLimits, RespLimits, ServerLimits},
-    server::server_impl::{AllLimits, Handler},
+    limits::{ConnLimits, Http09Limits, PipelinedOverflow, ReqLimits, RespLimits, ServerLimits},
+    server::{
+        cors::Cors,
+        server_impl::{AllLimits, Handler},
+    },
     Handled,
 };
-use std::{future::Future, io, net::SocketAddr, sync::Arc, time::Instant};
-use tokio::{io::AsyncWriteExt, net::TcpStream, time::sleep};
+use std::{
+    fmt,
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+    sync::Notify,
+    task::yield_now,
+    time::{sleep, timeout},
+};
+
+// Interim response to `Expect: 100-continue`
+const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
 
 pub(crate) struct HttpConnection<H: Handler<S>, S: ConnectionData> {
     handler: Arc<H>,
@@ -26,12 +51,18 @@ pub(crate) struct HttpConnection<H: Handler<S>, S: ConnectionData> {
     pub(crate) http_09_limits: Option<Http09Limits>,
     pub(crate) req_limits: ReqLimits,
     pub(crate) resp_limits: RespLimits,
+
+    pub(crate) mirror: Option<Arc<RequestMirror>>,
+    pub(crate) watermarks: Option<Arc<Watermarks>>,
+    pub(crate) version_counters: Option<Arc<VersionCounters>>,
+    pub(crate) preflight: Option<Arc<Cors>>,
+    pub(crate) wire_tap: Option<Arc<WireTap>>,
 }
 
 impl<H: Handler<S>, S: ConnectionData> HttpConnection<H, S> {
     #[inline]
     pub(crate) fn new(handler: Arc<H>, limits: AllLimits) -> Self {
-        Self {
+        let mut conn = Self {
             handler,
             connection_data: S::new(),
 
@@ -45,12 +76,65 @@ impl<H: Handler<S>, S: ConnectionData> HttpConnection<H, S> {
             http_09_limits: limits.2,
             req_limits: limits.3,
             resp_limits: limits.4,
+
+            mirror: None,
+            watermarks: None,
+            version_counters: None,
+            preflight: None,
+            wire_tap: None,
+        };
+        conn.request.conn_info.set_limits(EffectiveLimits {
+            req: conn.req_limits.clone(),
+            conn: conn.conn_limits.clone(),
+            resp: conn.resp_limits.clone(),
+            _priv: (),
+        });
+
+        conn
+    }
+
+    #[inline]
+    pub(crate) fn parse_request(&mut self) -> Result<(), ErrorKind> {
+        self.parser.parse_request(
+            &mut self.request,
+            &self.req_limits,
+            self.http_09_limits.is_some(),
+        )
+    }
+
+    // Parses the buffered request, reading again while its head or body is
+    // incomplete, e.g. the tail of a pipelined read. Only a request cut short
+    // by EOF (or too large for the buffer) is an error. A client waiting for
+    // `100 Continue` gets it (or 417) before the body is read
+    #[inline]
+    async fn read_request<T: Transport>(&mut self, stream: &mut T) -> Result<(), ErrorKind> {
+        let mut continued = false;
+        loop {
+            let error = match self.parse_request() {
+                Err(error @ ErrorKind::BodyMismatch { .. }) => error,
+                Err(error) if self.parser.head_incomplete() => error,
+                result => return result,
+            };
+
+            let body_pending = matches!(error, ErrorKind::BodyMismatch { .. });
+            if body_pending && !continued && self.request.expects_continue() {
+                if !self.handler.expect_continue(&self.request) {
+                    return Err(ErrorKind::ExpectationFailed);
+                }
+                self.conn_limits.write_bytes(stream, CONTINUE).await?;
+                continued = true;
+            }
+
+            let timeout = self.conn_limits.socket_read_timeout;
+            if self.parser.fill_more(stream, timeout).await? == 0 {
+                return Err(error);
+            }
+            self.request.reset();
         }
     }
 
     #[inline]
     fn reset_request_response(&mut self) {
-        self.parser.reset();
         self.request.reset();
         self.response.reset(&self.resp_limits);
     }
@@ -58,123 +142,549 @@ impl<H: Handler<S>, S: ConnectionData> HttpConnection<H, S> {
 
 impl<H: Handler<S>, S: ConnectionData> HttpConnection<H, S> {
     #[inline]
-    pub(crate) async fn run(
+    pub(crate) async fn run<T: Transport>(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut T,
         client_addr: SocketAddr,
         server_addr: SocketAddr,
+        queue_wait: Duration,
+    ) -> Result<(), io::Error> {
+        let tapped = (self.wire_tap.clone()).and_then(|tap| Some((tap.sample()?, tap)));
+        match tapped {
+            Some((connection, tap)) => {
+                let mut stream = Tapped {
+                    stream,
+                    tap: &tap,
+                    connection,
+                };
+                (self.run_stream(&mut stream, client_addr, server_addr, queue_wait)).await
+            }
+            None => (self.run_stream(stream, client_addr, server_addr, queue_wait)).await,
+        }
+    }
+
+    #[inline]
+    async fn run_stream<T: Transport>(
+        &mut self,
+        stream: &mut T,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        queue_wait: Duration,
     ) -> Result<(), io::Error> {
         self.request.client_addr = client_addr;
         self.request.server_addr = server_addr;
+        self.request.conn_info.reset(queue_wait);
 
-        match self.impl_run(stream).await {
-            Ok(()) => Ok(()),
-            Err(ErrorKind::Io(e)) => Err(e.0),
+        let (reason, result) = match self.impl_run(stream).await {
+            Ok(reason) => (reason, Ok(())),
+            Err(ErrorKind::ReadTimeout) => (CloseReason::ReadTimeout, Ok(())),
+            Err(ErrorKind::Io(e)) => (CloseReason::Io(e.0.kind()), Err(e.0)),
             Err(error) => {
-                self.conn_limits
+                if let Some(counters) = &self.version_counters {
+                    counters.record_error(self.request.version());
+                }
+                if let Some(mirror) = &self.mirror {
+                    mirror.record(
+                        self.parser.request_bytes(),
+                        error.as_http(self.request.version(), false),
+                        client_addr,
+                        None,
+                    );
+                }
+
+                let context = (self.server_limits.verbose_parse_errors)
+                    .then(|| self.parser.error_context())
+                    .flatten();
+                let sent = self
+                    .conn_limits
                     .send_error(
                         stream,
                         error,
                         self.request.version(),
                         self.server_limits.json_errors,
+                        context,
                     )
-                    .await
+                    .await;
+
+                match sent {
+                    Ok(()) => {
+                        let _ = self.conn_limits.drain_and_close(stream).await;
+                        (CloseReason::Closed, Ok(()))
+                    }
+                    Err(e) => (CloseReason::Io(e.kind()), Err(e)),
+                }
             }
-        }
+        };
+
+        self.handler.connection_closed(client_addr, reason);
+        result
+    }
+
+    /// Serves a connection over any [`Transport`], e.g. `tokio::io::duplex`
+    #[cfg(test)]
+    pub(crate) async fn run_on<T: Transport>(&mut self, stream: &mut T) -> Result<(), io::Error> {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        self.run(stream, addr, addr, Duration::ZERO).await
     }
 
     #[inline]
-    pub(crate) async fn impl_run(&mut self, stream: &mut TcpStream) -> Result<(), ErrorKind> {
-        self.optimize_socket(stream)?;
+    pub(crate) async fn impl_run<T: Transport>(
+        &mut self,
+        stream: &mut T,
+    ) -> Result<CloseReason, ErrorKind> {
+        stream.optimize(
+            self.req_limits.precalc.buffer,
+            self.resp_limits.max_capacity * 2,
+        )?;
 
         self.connection.reset();
         self.connection_data.reset();
+        self.parser.reset();
+        self.request.cancel.reset();
+        // `is_expired` reads the previous connection's response otherwise
+        self.reset_request_response();
 
         while !self.is_expired()? {
             self.reset_request_response();
 
-            if self
-                .parser
-                .fill_buffer(stream, self.conn_limits.socket_read_timeout)
-                .await?
-                == 0
+            if self.parser.is_empty()
+                && self
+                    .parser
+                    .fill_buffer(stream, self.conn_limits.socket_read_timeout)
+                    .await?
+                    == 0
             {
-                break;
+                let _ = self.conn_limits.drain_and_close(stream).await;
+                return Ok(CloseReason::ClientClosed);
             }
 
-            self.parse_request()?;
+            self.read_request(stream).await?;
+            if let Some(watermarks) = &self.watermarks {
+                watermarks.record(self.parser.request_bytes(), self.request.header_count());
+            }
+            if let Some(counters) = &self.version_counters {
+                counters.record_request(self.request.version());
+            }
+            self.check_prior_http1x()?;
             self.response.synchronization_with_request(&self.request);
+            self.close_on_last_request();
+            if self.resp_limits.emit_keep_alive_hint {
+                // `max_requests()` follows this request's version, which may
+                // have a lower limit than the one `is_expired` just checked
+                let remaining = (self.max_requests())
+                    .saturating_sub(self.connection.request_count)
+                    .saturating_sub(1);
+                let timeout = self.conn_limits.socket_read_timeout.as_secs();
+                self.response.keep_alive_hint(timeout, remaining);
+            }
 
-            self.handler
-                .handle(&mut self.connection_data, &self.request, &mut self.response)
-                .await;
+            let started = self.mirror.as_ref().map(|_| Instant::now());
+            let preflight = (self.preflight.as_ref())
+                .and_then(|cors| cors.preflight(&self.request, &mut self.response));
+            let handled = match preflight {
+                Some(handled) => handled,
+                None => {
+                    let handle = self.handler.handle(
+                        &mut self.connection_data,
+                        &self.request,
+                        &mut self.response,
+                    );
+                    let cancel_on_eof = self.conn_limits.cancel_on_eof;
+                    watch_disconnect(stream, &self.request.cancel, cancel_on_eof, handle).await
+                }
+            };
+            if handled.outcome() == Outcome::Complete {
+                self.handler.post_process(&self.request, &mut self.response);
+            }
 
-            self.conn_limits
-                .write_bytes(stream, self.response.buffer())
-                .await?;
+            if let (Some(mirror), Some(started)) = (&self.mirror, started) {
+                mirror.record(
+                    self.parser.request_bytes(),
+                    self.response.buffer(),
+                    self.request.client_addr,
+                    Some(started.elapsed()),
+                );
+            }
+
+            match handled.outcome() {
+                Outcome::Complete => {
+                    self.conn_limits
+                        .write_bytes(stream, self.response.buffer())
+                        .await?
+                }
+                Outcome::NoResponse => {}
+            }
+            self.request.conn_info.record(self.request.version());
+            if let Some(remaining) = self.response.close_after {
+                self.connection.close_after(remaining);
+            }
 
             if !self.response.keep_alive {
                 break;
             }
 
             self.connection.request_count += 1;
+            if self.connection.request_count >= self.max_requests() && self.parser.has_pending() {
+                // Only `RejectWith503` gets here, `Discard` closed on the last request
+                return Err(ErrorKind::ServiceUnavailable);
+            }
+
+            if self.next_in_buffer() == Pipeline::Yield {
+                yield_now().await;
+            }
         }
 
-        Ok(())
+        // Best effort, the response has already been written
+        let _ = self.conn_limits.drain_and_close(stream).await;
+
+        Ok(CloseReason::Closed)
     }
 
     #[inline]
-    fn optimize_socket(&self, stream: &TcpStream) -> io::Result<()> {
-        use socket2::SockRef;
+    fn check_prior_http1x(&self) -> Result<(), ErrorKind> {
+        match &self.http_09_limits {
+            Some(limits)
+                if limits.require_prior_http1x
+                    && self.request.version() == Version::Http09
+                    && !self.request.conn_info.has_prior_http1x() =>
+            {
+                Err(ErrorKind::Http09WithoutHttp1x)
+            }
+            _ => Ok(()),
+        }
+    }
 
-        let socket = SockRef::from(&stream);
+    // The last request a connection may serve announces `connection: close`,
+    // unless a `503` for the requests pipelined after it follows
+    #[inline]
+    fn close_on_last_request(&mut self) {
+        let reject = self.conn_limits.pipelined_overflow_policy == PipelinedOverflow::RejectWith503
+            && self.parser.has_pending();
 
-        socket.set_tcp_nodelay(true)?;
+        if self.connection.request_count + 1 >= self.max_requests() && !reject {
+            self.response.require_close();
+        }
+    }
 
-        socket.set_recv_buffer_size(self.req_limits.precalc.buffer)?;
-        socket.set_send_buffer_size(self.resp_limits.max_capacity * 2)?;
+    #[inline]
+    fn max_requests(&self) -> usize {
+        let max = match (self.response.version, &self.http_09_limits) {
+            (Version::Http09, Some(limits)) => limits.max_requests_per_connection,
+            _ => self.conn_limits.max_requests_per_connection,
+        };
+        max.min(self.connection.close_at)
+    }
 
-        #[cfg(target_os = "linux")]
-        {
-            socket.set_tcp_quickack(true)?;
+    // Decides where the next request comes from: the rest of the current
+    // buffer (pipelining) or a new read
+    #[inline]
+    pub(crate) fn next_in_buffer(&mut self) -> Pipeline {
+        if !self.parser.advance() {
+            self.connection.pipelined = 0;
+            return Pipeline::Read;
         }
 
-        Ok(())
+        self.connection.pipelined += 1;
+        if self.connection.pipelined >= self.conn_limits.max_pipelined_requests {
+            self.connection.pipelined = 0;
+            return Pipeline::Yield;
+        }
+
+        Pipeline::Next
     }
 }
 
 impl ConnLimits {
     #[inline]
-    pub(crate) async fn send_error(
+    pub(crate) async fn send_error<T: AsyncWrite + Unpin>(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut T,
         error: ErrorKind,
         version: Version,
         json_errors: bool,
+        context: Option<ErrorContext>,
     ) -> Result<(), io::Error> {
-        self.write_bytes(stream, error.as_http(version, json_errors))
+        match context {
+            Some(context) if json_errors => {
+                let mut buffer = [0; VERBOSE_LEN];
+                let response = error.as_verbose_http(version, &context, &mut buffer);
+                self.write_bytes(stream, response).await
+            }
+            _ => {
+                self.write_bytes(stream, error.as_http(version, json_errors))
+                    .await
+            }
+        }
+    }
+
+    // Half-closes the connection and discards what the client still sends until
+    // its `FIN`, so closing the socket doesn't turn into `RST` and destroy the
+    // unacknowledged tail of the response
+    #[inline]
+    pub(crate) async fn drain_and_close<T: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: &mut T,
+    ) -> Result<(), io::Error> {
+        stream.shutdown().await?;
+
+        let drain = async {
+            let mut buffer = [0; 512];
+            let mut left = self.close_drain_size;
+            while left > 0 {
+                match stream.read(&mut buffer[..left.min(512)]).await? {
+                    0 => break,
+                    n => left -= n,
+                }
+            }
+            Ok(())
+        };
+
+        // A client that never closes its side only costs the timeout
+        timeout(self.socket_write_timeout, drain)
             .await
+            .unwrap_or(Ok(()))
     }
 
+    // Drops a connection that gets no response at all
     #[inline]
-    pub(crate) async fn write_bytes(
+    pub(crate) fn drop_silently(&self, stream: TcpStream) {
+        if self.abortive_close_on_reject {
+            // Zero linger: closing sends `RST` and skips `TIME_WAIT`
+            let _ = socket2::SockRef::from(&stream).set_linger(Some(Duration::ZERO));
+        }
+        drop(stream);
+    }
+
+    #[inline]
+    pub(crate) async fn write_bytes<T: AsyncWrite + Unpin>(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut T,
         response: &[u8],
     ) -> Result<(), io::Error> {
+        if let Some(rate) = self.min_write_rate {
+            return self.write_at_rate(stream, response, rate).await;
+        }
+        let size = self.write_segment_size.unwrap_or(response.len());
+
+        for segment in response.chunks(size.max(1)) {
+            let write = async {
+                stream.write_all(segment).await?;
+                stream.flush().await
+            };
+            tokio::select! {
+                result = write => result?,
+                _ = sleep(self.socket_write_timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "write timeout"))
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // `min_write_rate`: the bytes written are checked every second,
+    // a single write still ends after `socket_write_timeout`
+    async fn write_at_rate<T: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut T,
+        mut response: &[u8],
+        rate: u64,
+    ) -> Result<(), io::Error> {
+        const WINDOW: Duration = Duration::from_secs(1);
+        let (mut window_start, mut written) = (Instant::now(), 0);
+
+        while !response.is_empty() {
+            let wait = WINDOW.saturating_sub(window_start.elapsed());
+            let wait = wait.min(self.socket_write_timeout);
+
+            match timeout(wait, stream.write(response)).await {
+                Ok(Ok(0)) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(Ok(n)) => {
+                    response = &response[n..];
+                    written += n as u64;
+                }
+                Ok(Err(error)) => return Err(error),
+                Err(_) if wait == self.socket_write_timeout => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "write timeout"))
+                }
+                Err(_) => {}
+            }
+
+            // A response sent in full is never too slow
+            if !response.is_empty() && window_start.elapsed() >= WINDOW {
+                if written < rate {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "write rate too low",
+                    ));
+                }
+                (window_start, written) = (Instant::now(), 0);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Runs the handler while watching the socket, so `CancelToken` fires when the
+// client disconnects. The handler itself is never dropped early.
+#[inline]
+async fn watch_disconnect<T: Transport, F: Future<Output = Handled>>(
+    stream: &T,
+    token: &CancelToken,
+    cancel_on_eof: bool,
+    handle: F,
+) -> Handled {
+    tokio::pin!(handle);
+    let mut watching = true;
+
+    loop {
         tokio::select! {
-            result = stream.write_all(response) => result,
-            _ = sleep(self.socket_write_timeout) => {
-                Err(io::Error::new(io::ErrorKind::TimedOut, "write timeout"))
-            },
+            biased;
+            handled = &mut handle => return handled,
+            event = stream.peer_event(), if watching => {
+                // Stop after the first event: new data (pipelining) isn't read here,
+                // and `EOF` stays readable, so peeking again would spin
+                watching = false;
+                match event {
+                    PeerEvent::Reset => token.cancel(),
+                    PeerEvent::Eof if cancel_on_eof => token.cancel(),
+                    PeerEvent::Eof | PeerEvent::Data => {}
+                }
+            }
         }
     }
 }
 
+/// The stream a connection is served over: a `TcpStream`, or an in-memory
+/// stream in tests (see `HttpConnection::run_on`).
+pub(crate) trait Transport: AsyncRead + AsyncWrite + Unpin {
+    /// Applies socket options for the given buffer sizes
+    fn optimize(&self, recv_buffer: usize, send_buffer: usize) -> io::Result<()>;
+
+    /// Resolves once the peer has sent more data, `EOF` or a reset
+    async fn peer_event(&self) -> PeerEvent;
+}
+
+/// What [`Transport::peer_event`] saw on the socket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PeerEvent {
+    Data,
+    // Either a half-close (the client still waits for the response) or a
+    // full close, TCP doesn't tell them apart
+    Eof,
+    // The connection is gone, e.g. `RST`
+    Reset,
+}
+
+impl Transport for TcpStream {
+    #[inline]
+    fn optimize(&self, recv_buffer: usize, send_buffer: usize) -> io::Result<()> {
+        use socket2::SockRef;
+
+        let socket = SockRef::from(self);
+
+        socket.set_tcp_nodelay(true)?;
+
+        socket.set_recv_buffer_size(recv_buffer)?;
+        socket.set_send_buffer_size(send_buffer)?;
+
+        #[cfg(target_os = "linux")]
+        {
+            socket.set_tcp_quickack(true)?;
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    async fn peer_event(&self) -> PeerEvent {
+        let mut byte = [0; 1];
+        match self.peek(&mut byte).await {
+            Ok(0) => PeerEvent::Eof,
+            Ok(_) => PeerEvent::Data,
+            Err(_) => PeerEvent::Reset,
+        }
+    }
+}
+
+// No socket options and no way to peek: `CancelToken` never fires
+#[cfg(test)]
+impl Transport for tokio::io::DuplexStream {
+    fn optimize(&self, _: usize, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn peer_event(&self) -> PeerEvent {
+        std::future::pending().await
+    }
+}
+
+// Records every read and write of a connection sampled by a `WireTap`
+struct Tapped<'a, T> {
+    stream: &'a mut T,
+    tap: &'a WireTap,
+    connection: u64,
+}
+
+impl<T: Transport> AsyncRead for Tapped<'_, T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let start = buf.filled().len();
+        let result = Pin::new(&mut *this.stream).poll_read(cx, buf);
+
+        let read = &buf.filled()[start..];
+        if !read.is_empty() {
+            this.tap.record(Direction::Inbound, this.connection, read);
+        }
+        result
+    }
+}
+
+impl<T: Transport> AsyncWrite for Tapped<'_, T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut *this.stream).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(n @ 1..)) = result {
+            this.tap
+                .record(Direction::Outbound, this.connection, &buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+impl<T: Transport> Transport for Tapped<'_, T> {
+    #[inline]
+    fn optimize(&self, recv_buffer: usize, send_buffer: usize) -> io::Result<()> {
+        self.stream.optimize(recv_buffer, send_buffer)
+    }
+
+    #[inline]
+    async fn peer_event(&self) -> PeerEvent {
+        self.stream.peer_event().await
+    }
+}
+
 macro_rules! is_expired {
     ($self:expr, $limits:expr) => {
         Ok(!$self.response.keep_alive
             || $self.connection.request_count >= $limits.max_requests_per_connection
+            || $self.connection.request_count >= $self.connection.close_at
             || $self.connection.created.elapsed() > $limits.connection_lifetime)
     };
 }
@@ -194,6 +704,10 @@ impl<H: Handler<S>, S: ConnectionData> HttpConnection<H, S> {
 pub(crate) struct Connection {
     created: Instant,
     request_count: usize,
+    // Requests served from the current buffer since the last read or yield
+    pipelined: usize,
+    // Request count at which `Response::close_after` ends the connection
+    close_at: usize,
 }
 
 impl Connection {
@@ -202,6 +716,8 @@ impl Connection {
         Self {
             created: Instant::now(),
             request_count: 0,
+            pipelined: 0,
+            close_at: usize::MAX,
         }
     }
 
@@ -209,9 +725,28 @@ impl Connection {
     pub(crate) fn reset(&mut self) {
         self.created = Instant::now();
         self.request_count = 0;
+        self.pipelined = 0;
+        self.close_at = usize::MAX;
+    }
+
+    // Called before `request_count` counts the current request
+    #[inline]
+    fn close_after(&mut self, remaining: usize) {
+        let close_at = self
+            .request_count
+            .saturating_add(1)
+            .saturating_add(remaining);
+        self.close_at = self.close_at.min(close_at);
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Pipeline {
+    Read,
+    Next,
+    Yield,
+}
+
 //
 
 /// Managing user session data stored between requests within a single HTTP connection.
@@ -465,6 +1000,378 @@ pub trait ConnectionFilter: Sync + Send + 'static {
     ) -> impl Future<Output = Result<(), Handled>> + Send {
         async { Ok(()) }
     }
+
+    /// Asynchronous connection inspection that can report its own failure.
+    ///
+    /// Called instead of [`filter_async`](Self::filter_async); the default
+    /// implementation calls it and treats `Err` as [`FilterError::Rejected`].
+    /// Override it when the check depends on infrastructure that may be down
+    /// (database, external API) and return [`FilterError::Unavailable`] in that
+    /// case. What happens next is decided by [`FilterFailurePolicy`].
+    ///
+    /// # Examples
+    /// ```
+    /// use std::net::SocketAddr;
+    /// use maker_web::{ConnectionFilter, FilterError, Response, Handled, StatusCode};
+    ///
+    /// # struct DatabaseClient;
+    /// #
+    /// # impl DatabaseClient {
+    /// #     async fn is_banned(&self, _: SocketAddr) -> Result<bool, ()> {
+    /// #         Ok(false)
+    /// #     }
+    /// # }
+    /// #
+    /// struct MyConnFilter {
+    ///     db: DatabaseClient,
+    /// }
+    ///
+    /// impl ConnectionFilter for MyConnFilter {
+    ///     fn filter(&self, _: SocketAddr, _: SocketAddr, _: &mut Response) -> Result<(), Handled> {
+    ///         Ok(())
+    ///     }
+    ///
+    ///     async fn try_filter_async(
+    ///         &self,
+    ///         client_addr: SocketAddr,
+    ///         _: SocketAddr,
+    ///         err_resp: &mut Response,
+    ///     ) -> Result<(), FilterError> {
+    ///         match self.db.is_banned(client_addr).await {
+    ///             Ok(false) => Ok(()),
+    ///             Ok(true) => Err(err_resp.status(StatusCode::Forbidden).body("Banned").into()),
+    ///             Err(_) => Err(FilterError::Unavailable),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn try_filter_async(
+        &self,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        error_response: &mut Response,
+    ) -> impl Future<Output = Result<(), FilterError>> + Send {
+        async move {
+            self.filter_async(client_addr, server_addr, error_response)
+                .await
+                .map_err(FilterError::Rejected)
+        }
+    }
+
+    /// Called when [`try_filter_async`](Self::try_filter_async) returns
+    /// [`FilterError::Unavailable`], before the [`FilterFailurePolicy`] is applied.
+    ///
+    /// Use it to log or count filter failures. Does nothing by default.
+    fn filter_unavailable(
+        &self,
+        #[allow(unused_variables)] client_addr: SocketAddr,
+        #[allow(unused_variables)] server_addr: SocketAddr,
+    ) {
+    }
+}
+
+/// Result of a failed [`ConnectionFilter::try_filter_async`] check.
+#[derive(Debug)]
+pub enum FilterError {
+    /// The connection was rejected on purpose, the error response is sent
+    Rejected(Handled),
+    /// The filter couldn't make a decision (e.g. its database is down),
+    /// handled according to [`FilterFailurePolicy`]
+    Unavailable,
+}
+
+impl From<Handled> for FilterError {
+    #[inline(always)]
+    fn from(value: Handled) -> Self {
+        FilterError::Rejected(value)
+    }
+}
+
+/// What to do with a connection when the filter returns
+/// [`FilterError::Unavailable`].
+///
+/// Set with [`ServerBuilder::filter_failure_policy`](crate::ServerBuilder::filter_failure_policy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterFailurePolicy {
+    /// Accept the connection as if the filter passed it
+    FailOpen,
+    /// Reject the connection with `503 Service Unavailable` (default)
+    #[default]
+    FailClosed,
+}
+
+/// Why a connection ended, see [`Handler::connection_closed`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CloseReason {
+    /// The server closed the connection after its last response: keep-alive
+    /// is off, a connection limit was reached, or the request was invalid
+    /// and got an error response.
+    Closed,
+    /// The client closed the connection cleanly between requests (`EOF`).
+    ClientClosed,
+    /// No request arrived within
+    /// [`socket_read_timeout`](crate::limits::ConnLimits::socket_read_timeout),
+    /// e.g. an idle keep-alive client or a slowloris attack.
+    ReadTimeout,
+    /// Any other I/O error, including a write timeout.
+    Io(io::ErrorKind),
+}
+
+/// Signals that the client disconnected while its request was being handled.
+///
+/// Available in the handler via [`Request::cancel_token`](crate::Request::cancel_token).
+/// The server never stops a handler on its own; long-running handlers can
+/// check the token and give up early, e.g. with [`tokio::select!`].
+///
+/// A disconnect is detected when the connection is reset (e.g. the client
+/// closed it with unread data, or its host is gone) during [`Handler::handle`].
+/// A plain close only sends `EOF`, which is also what a client that
+/// half-closes the socket after sending its request (`shutdown(Write)`,
+/// `curl --no-keepalive`) looks like while it still waits for the response,
+/// so `EOF` fires the token only with
+/// [`ConnLimits::cancel_on_eof`](crate::limits::ConnLimits::cancel_on_eof).
+///
+/// # Examples
+/// ```
+/// use maker_web::{Handled, Handler, Request, Response, StatusCode};
+/// use std::time::Duration;
+///
+/// struct MyHandler;
+///
+/// impl Handler for MyHandler {
+///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+///         tokio::select! {
+///             _ = req.cancel_token().cancelled() => resp.close_without_response(),
+///             _ = tokio::time::sleep(Duration::from_secs(5)) => {
+///                 resp.status(StatusCode::Ok).body("upstream answered")
+///             }
+///         }
+///     }
+/// }
+/// ```
+#[derive(Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    /// Returns `true` if the client has disconnected.
+    #[inline(always)]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Waits until the client disconnects.
+    ///
+    /// Never completes if the client stays connected until the response.
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before the check, so `notify_waiters` can't be missed
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        self.notify.notify_waiters();
+    }
+
+    #[inline(always)]
+    pub(crate) fn reset(&self) {
+        self.cancelled.store(false, Ordering::Release);
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+// `Request` is `Clone` and `PartialEq`; only the state is copied and compared
+impl Clone for CancelToken {
+    fn clone(&self) -> Self {
+        Self {
+            cancelled: AtomicBool::new(self.is_cancelled()),
+            notify: Notify::new(),
+        }
+    }
+}
+
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_cancelled() == other.is_cancelled()
+    }
+}
+
+/// Protocol versions of the requests handled so far on a connection,
+/// and how long the connection waited for a worker.
+///
+/// Only requests that received a response are counted, the current request
+/// is not. Counters start at zero with every new connection.
+///
+/// # Examples
+/// ```
+/// use maker_web::{Handled, Handler, Request, Response, StatusCode, Version};
+///
+/// struct MyHandler;
+///
+/// impl Handler for MyHandler {
+///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+///         // `HTTP/0.9+` requests are served only after the `HTTP/1.X` login
+///         if req.version() == Version::Http09 && !req.conn_info().has_prior_http1x() {
+///             return resp.http09_msg(StatusCode::Unauthorized, "login first");
+///         }
+///
+///         // ...
+/// #       resp.http09("")
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConnInfo {
+    http1x_requests: usize,
+    http09_requests: usize,
+    queue_wait: Duration,
+    worker: usize,
+    limits: Arc<EffectiveLimits>,
+}
+
+// Limits and worker are the same for all requests of a worker, only the counters are compared
+impl PartialEq for ConnInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.http1x_requests == other.http1x_requests
+            && self.http09_requests == other.http09_requests
+            && self.queue_wait == other.queue_wait
+    }
+}
+
+/// The limits a connection runs with, defaults applied.
+///
+/// Returned by [`ConnInfo::limits`]; the same values as in
+/// [`ResolvedConfig`](crate::ResolvedConfig).
+#[derive(Debug, Clone, Default)]
+pub struct EffectiveLimits {
+    /// Effective [`ReqLimits`]
+    pub req: ReqLimits,
+    /// Effective [`ConnLimits`]
+    pub conn: ConnLimits,
+    /// Effective [`RespLimits`]
+    pub resp: RespLimits,
+
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub _priv: (),
+}
+
+impl ConnInfo {
+    /// Returns `true` if an `HTTP/1.X` request has been handled
+    /// earlier on this connection.
+    #[inline(always)]
+    pub const fn has_prior_http1x(&self) -> bool {
+        self.http1x_requests > 0
+    }
+
+    /// Number of `HTTP/1.0` and `HTTP/1.1` requests handled on this connection.
+    #[inline(always)]
+    pub const fn http1x_requests(&self) -> usize {
+        self.http1x_requests
+    }
+
+    /// Number of `HTTP/0.9+` requests handled on this connection.
+    #[inline(always)]
+    pub const fn http09_requests(&self) -> usize {
+        self.http09_requests
+    }
+
+    /// Time the connection spent in the pending queue between being accepted
+    /// and being taken by a worker.
+    ///
+    /// Grows when all workers are busy, see
+    /// [`ServerLimits::max_pending_connections`].
+    #[inline(always)]
+    pub const fn queue_wait(&self) -> Duration {
+        self.queue_wait
+    }
+
+    /// Index of the worker serving this connection, from `0` to
+    /// [`max_connections`](crate::limits::ServerLimits::max_connections) `- 1`.
+    ///
+    /// With [`Dispatch::PeerIpHash`](crate::limits::Dispatch::PeerIpHash)
+    /// it is the same for every connection from one client IP.
+    #[inline(always)]
+    pub const fn worker(&self) -> usize {
+        self.worker
+    }
+
+    /// The limits of this connection, e.g. to advertise them to clients.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Handler, Request, Response, StatusCode};
+    ///
+    /// struct MyHandler;
+    ///
+    /// impl Handler for MyHandler {
+    ///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+    ///         match req.url().path() {
+    ///             b"/limits" => {
+    ///                 let limits = req.conn_info().limits();
+    ///
+    ///                 resp.status(StatusCode::Ok)
+    ///                     .header("content-type", "application/json")
+    ///                     .body_with(|writer| {
+    ///                         writer.write(r#"{"max_body_size":"#);
+    ///                         writer.write(limits.req.body_size);
+    ///                         writer.write(r#","max_headers":"#);
+    ///                         writer.write(limits.req.header_count);
+    ///                         writer.write(r#","max_requests_per_connection":"#);
+    ///                         writer.write(limits.conn.max_requests_per_connection);
+    ///                         writer.write("}");
+    ///                     })
+    ///             }
+    ///             _ => resp.reply(StatusCode::NotFound, "Not Found"),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    #[inline(always)]
+    pub fn limits(&self) -> &EffectiveLimits {
+        &self.limits
+    }
+
+    #[inline(always)]
+    pub(crate) fn set_limits(&mut self, limits: EffectiveLimits) {
+        self.limits = Arc::new(limits);
+    }
+
+    #[inline(always)]
+    pub(crate) fn set_worker(&mut self, worker: usize) {
+        self.worker = worker;
+    }
+
+    #[inline(always)]
+    pub(crate) fn record(&mut self, version: Version) {
+        match version {
+            Version::Http09 => self.http09_requests += 1,
+            _ => self.http1x_requests += 1,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn reset(&mut self, queue_wait: Duration) {
+        self.http1x_requests = 0;
+        self.http09_requests = 0;
+        self.queue_wait = queue_wait;
+    }
 }
 
 impl ConnectionFilter for () {
@@ -477,7 +1384,7 @@ impl ConnectionFilter for () {
 
 // For tests
 #[doc(hidden)]
-mod def_handler {
+pub(crate) mod def_handler {
     use super::*;
     use crate::{Handled, StatusCode};
 
@@ -492,7 +1399,12 @@ mod def_handler {
     impl HttpConnection<DefHandler, ()> {
         #[inline]
         pub(crate) fn from_req<V: AsRef<[u8]>>(value: V) -> Self {
-            let req_limits = ReqLimits::default().precalculate();
+            Self::from_req_with(value, ReqLimits::bare())
+        }
+
+        #[inline]
+        pub(crate) fn from_req_with<V: AsRef<[u8]>>(value: V, req_limits: ReqLimits) -> Self {
+            let req_limits = req_limits.precalculate();
             let resp_limits = RespLimits::default();
 
             Self {
@@ -509,7 +1421,674 @@ mod def_handler {
                 http_09_limits: None,
                 req_limits,
                 resp_limits,
+
+                mirror: None,
+                watermarks: None,
+                version_counters: None,
+                preflight: None,
+                wire_tap: None,
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{def_handler::DefHandler, *};
+    use crate::StatusCode;
+    use tokio::io::{duplex, DuplexStream};
+
+    const OK: &str = "HTTP/1.1 200 OK\r\ncontent-length: 0000000004\r\n\r\ntest";
+    const OK_CLOSE: &str =
+        "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 0000000004\r\n\r\ntest";
+
+    fn conn(conn_limits: ConnLimits) -> HttpConnection<DefHandler, ()> {
+        conn_with(DefHandler, conn_limits)
+    }
+
+    fn conn_with<H: Handler>(handler: H, conn_limits: ConnLimits) -> HttpConnection<H, ()> {
+        let limits = (
+            ServerLimits::default(),
+            conn_limits,
+            None,
+            ReqLimits::bare().precalculate(),
+            RespLimits::default(),
+        );
+        HttpConnection::new(Arc::new(handler), limits)
+    }
+
+    // Serves the connection while `client` talks to it
+    async fn serve<H, C, F>(mut conn: HttpConnection<H, ()>, client: C) -> String
+    where
+        H: Handler,
+        C: FnOnce(DuplexStream) -> F,
+        F: Future<Output = DuplexStream>,
+    {
+        let (client_side, mut server_side) = duplex(4096);
+        let client = async move {
+            let mut stream = client(client_side).await;
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await.unwrap();
+            String::from_utf8(response).unwrap()
+        };
+
+        let (result, response) = tokio::join!(conn.run_on(&mut server_side), client);
+        result.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn keep_alive() {
+        let response = serve(conn(ConnLimits::default()), |mut stream| async {
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+            let mut first = vec![0; OK.len()];
+            stream.read_exact(&mut first).await.unwrap();
+            assert_eq!(first, OK.as_bytes());
+
+            // The same connection serves the next request
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            stream
+        })
+        .await;
+
+        assert_eq!(response, OK_CLOSE);
+    }
+
+    #[tokio::test]
+    async fn pipelining() {
+        let response = serve(conn(ConnLimits::default()), |mut stream| async {
+            let requests = "GET / HTTP/1.1\r\n\r\n".repeat(2) + "GET / HTTP/1.0\r\n\r\n";
+            stream.write_all(requests.as_bytes()).await.unwrap();
+            stream
+        })
+        .await;
+
+        let http10_close =
+            "HTTP/1.0 200 OK\r\nconnection: close\r\ncontent-length: 0000000004\r\n\r\ntest";
+        assert_eq!(response, format!("{OK}{OK}{http10_close}"));
+    }
+
+    #[tokio::test]
+    async fn pipelined_partial_request() {
+        let response = serve(conn(ConnLimits::default()), |mut stream| async {
+            // Two requests and the start of a third in one read
+            let first = "GET / HTTP/1.1\r\n\r\n".repeat(2) + "GET / HT";
+            stream.write_all(first.as_bytes()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            stream.write_all(b"TP/1.1\r\nconnection: ").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            stream.write_all(b"close\r\n\r\n").await.unwrap();
+            stream
+        })
+        .await;
+
+        assert_eq!(response, format!("{OK}{OK}{OK_CLOSE}"));
+    }
+
+    #[tokio::test]
+    async fn truncated_head() {
+        let mut conn = conn(ConnLimits::default());
+        let (mut client, mut server_side) = duplex(4096);
+        let client = async move {
+            client
+                .write_all(b"GET / HTTP/1.1\r\nhost: a")
+                .await
+                .unwrap();
+            client.shutdown().await.unwrap();
+            let mut response = Vec::new();
+            client.read_to_end(&mut response).await.unwrap();
+            response
+        };
+
+        let server = async move { conn.run_on(&mut server_side).await };
+        let (result, response) = tokio::join!(server, client);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn last_allowed_request() {
+        let limits = ConnLimits {
+            max_requests_per_connection: 2,
+            ..ConnLimits::default()
+        };
+        let response = serve(conn(limits), |mut stream| async {
+            let requests = "GET / HTTP/1.1\r\n\r\n".repeat(3);
+            stream.write_all(requests.as_bytes()).await.unwrap();
+            stream
+        })
+        .await;
+
+        // The third request is discarded
+        assert_eq!(response, format!("{OK}{OK_CLOSE}"));
+    }
+
+    #[tokio::test]
+    async fn error_response() {
+        let response = serve(conn(ConnLimits::default()), |mut stream| async {
+            stream
+                .write_all(b"GET / HTTP/1.1\r\n\r\nGET //x HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap();
+            stream
+        })
+        .await;
+
+        let error = str::from_utf8(ErrorKind::DoubleSlash.as_http(Version::Http11, true));
+        assert_eq!(response, format!("{OK}{}", error.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn client_closed() {
+        let response = serve(conn(ConnLimits::default()), |mut stream| async {
+            stream.shutdown().await.unwrap();
+            stream
+        })
+        .await;
+
+        assert_eq!(response, "");
+    }
+
+    struct EchoBody;
+
+    impl Handler for EchoBody {
+        async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+            resp.status(StatusCode::Ok)
+                .body(req.body().unwrap_or_default())
+        }
+    }
+
+    #[tokio::test]
+    async fn body_over_several_reads() {
+        let response = serve(
+            conn_with(EchoBody, ConnLimits::default()),
+            |mut stream| async {
+                let parts = [
+                    "POST / HTTP/1.1\r\ncontent-length: 11\r\n\r\nhel",
+                    "lo ",
+                    "world",
+                ];
+                for part in parts {
+                    stream.write_all(part.as_bytes()).await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                stream
+                    .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+                stream
+            },
+        )
+        .await;
+
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\ncontent-length: 0000000011\r\n\r\nhello world\
+             HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 0000000000\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn body_larger_than_one_read() {
+        let body = "j".repeat(3 * 1024);
+        let head = format!(
+            "POST / HTTP/1.1\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+            body.len()
+        );
+        let (half, rest) = body.split_at(1500);
+        let conn = conn_with(EchoBody, ConnLimits::default());
+        let response = serve(conn, |mut stream| async move {
+            stream
+                .write_all(format!("{head}{half}").as_bytes())
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            stream.write_all(rest.as_bytes()).await.unwrap();
+            stream
+        })
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with(&format!("\r\n\r\n{body}")));
+    }
+
+    #[tokio::test]
+    async fn body_over_limit() {
+        let limits = ReqLimits::default();
+        let response = serve(
+            conn_with(EchoBody, ConnLimits::default()),
+            |mut stream| async move {
+                let head = format!(
+                    "POST / HTTP/1.1\r\ncontent-length: {}\r\n\r\n",
+                    limits.body_size + 1
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream
+            },
+        )
+        .await;
+
+        let error = ErrorKind::BodyTooLarge;
+        assert_eq!(response.as_bytes(), error.as_http(Version::Http11, true));
+    }
+
+    #[tokio::test]
+    async fn expect_continue() {
+        const HEAD: &[u8] = b"POST / HTTP/1.1\r\nexpect: 100-Continue\r\ncontent-length: 5\r\n\r\n";
+
+        let response = serve(
+            conn_with(EchoBody, ConnLimits::default()),
+            |mut stream| async {
+                stream.write_all(HEAD).await.unwrap();
+
+                // The body is only sent after the interim response
+                let mut interim = [0; CONTINUE.len()];
+                stream.read_exact(&mut interim).await.unwrap();
+                assert_eq!(interim, CONTINUE);
+
+                stream.write_all(b"hello").await.unwrap();
+                stream
+                    .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+                stream
+            },
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains("\r\n\r\nhello"), "{response}");
+        assert!(!response.contains("100 Continue"), "{response}");
+
+        struct Refuse;
+
+        impl Handler for Refuse {
+            async fn handle(&self, _: &mut (), _: &Request, _: &mut Response) -> Handled {
+                unreachable!()
+            }
+
+            fn expect_continue(&self, _: &Request) -> bool {
+                false
+            }
+        }
+
+        let response = serve(
+            conn_with(Refuse, ConnLimits::default()),
+            |mut stream| async {
+                stream.write_all(HEAD).await.unwrap();
+                stream
+            },
+        )
+        .await;
+        let error = ErrorKind::ExpectationFailed;
+        assert_eq!(response.as_bytes(), error.as_http(Version::Http11, true));
+    }
+
+    #[tokio::test]
+    async fn verbose_parse_errors() {
+        const BAD: &[u8] = b"GET / HTTP/1.1\r\nx-id: \"1\"\r\nbad header\r\n\r\n";
+
+        let send = |verbose, json| async move {
+            let mut conn = conn(ConnLimits::default());
+            conn.server_limits.verbose_parse_errors = verbose;
+            conn.server_limits.json_errors = json;
+            serve(conn, |mut stream| async move {
+                stream.write_all(BAD).await.unwrap();
+                stream
+            })
+            .await
+        };
+
+        let body = r#"{"error":"Invalid header format","code":"INVALID_HEADER","offset":27,"excerpt":"d: \"1\"\u000d\u000abad header\u000d\u000a\u000d\u000a"}"#;
+        assert_eq!(
+            send(true, true).await,
+            format!(
+                "HTTP/1.1 400 Bad Request\r\nconnection: close\r\ncontent-length: {}\r\n\
+                 content-type: application/json\r\n\r\n{body}",
+                body.len()
+            )
+        );
+
+        // Off by default, and never without a JSON body
+        let plain = |json| str::from_utf8(ErrorKind::InvalidHeader.as_http(Version::Http11, json));
+        assert_eq!(send(false, true).await, plain(true).unwrap());
+        assert_eq!(send(true, false).await, plain(false).unwrap());
+    }
+
+    #[tokio::test]
+    async fn truncated_body() {
+        let mut conn = conn_with(EchoBody, ConnLimits::default());
+        let (mut client, mut server_side) = duplex(4096);
+        let client = async move {
+            client
+                .write_all(b"POST / HTTP/1.1\r\ncontent-length: 10\r\n\r\nhello")
+                .await
+                .unwrap();
+            client.shutdown().await.unwrap();
+            let mut response = Vec::new();
+            client.read_to_end(&mut response).await.unwrap();
+            response
+        };
+
+        // The server side is dropped on error, like the socket in `launch`
+        let server = async move { conn.run_on(&mut server_side).await };
+        let (result, response) = tokio::join!(server, client);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keep_alive_hint() {
+        let mut conn = conn(ConnLimits {
+            max_requests_per_connection: 3,
+            ..ConnLimits::default()
+        });
+        conn.resp_limits.emit_keep_alive_hint = true;
+
+        let response = serve(conn, |mut stream| async {
+            let requests = "GET / HTTP/1.1\r\n\r\n".repeat(2) + "GET / HTTP/1.0\r\n\r\n";
+            stream.write_all(requests.as_bytes()).await.unwrap();
+            stream
+        })
+        .await;
+
+        // The last request closes the connection, so it gets no hint
+        let hinted = |max| {
+            format!(
+                "HTTP/1.1 200 OK\r\nkeep-alive: timeout=2, max={max}\r\n\
+                 content-length: 0000000004\r\n\r\ntest"
+            )
+        };
+        let close =
+            "HTTP/1.0 200 OK\r\nconnection: close\r\ncontent-length: 0000000004\r\n\r\ntest";
+        assert_eq!(response, format!("{}{}{close}", hinted(2), hinted(1)));
+    }
+
+    #[tokio::test]
+    async fn keep_alive_hint_http09() {
+        struct AnyVersion;
+
+        impl Handler for AnyVersion {
+            async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+                match req.version() {
+                    Version::Http09 => resp.http09("test"),
+                    _ => resp.status(StatusCode::Ok).body("test"),
+                }
+            }
+        }
+
+        let mut conn = conn_with(
+            AnyVersion,
+            ConnLimits {
+                max_requests_per_connection: 10,
+                ..ConnLimits::default()
+            },
+        );
+        conn.http_09_limits = Some(Http09Limits {
+            max_requests_per_connection: 2,
+            ..Http09Limits::default()
+        });
+        conn.resp_limits.emit_keep_alive_hint = true;
+
+        // The `HTTP/0.9+` request is already over its own, lower limit
+        let response = serve(conn, |mut stream| async {
+            let requests = "GET / HTTP/1.1\r\n\r\n".repeat(4) + "GET /x\r\n";
+            stream.write_all(requests.as_bytes()).await.unwrap();
+            stream
+        })
+        .await;
+
+        let hinted = |max| {
+            format!(
+                "HTTP/1.1 200 OK\r\nkeep-alive: timeout=2, max={max}\r\n\
+                 content-length: 0000000004\r\n\r\ntest"
+            )
+        };
+        let expected: String = (6..=9).rev().map(hinted).collect();
+        assert_eq!(response, expected + "test");
+    }
+
+    #[tokio::test]
+    async fn close_after() {
+        struct WindDown;
+
+        impl Handler for WindDown {
+            async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+                if req.url().path() == b"/wind-down" {
+                    resp.close_after(2);
+                }
+                resp.status(StatusCode::Ok).body("test")
+            }
+        }
+
+        let send = |requests: &'static [&'static str]| {
+            serve(
+                conn_with(WindDown, ConnLimits::default()),
+                move |mut stream| async move {
+                    for path in requests {
+                        let request = format!("GET {path} HTTP/1.1\r\n\r\n");
+                        stream.write_all(request.as_bytes()).await.unwrap();
+                    }
+                    stream
+                },
+            )
+        };
+
+        // Two more requests, the last with `connection: close`; the rest is discarded
+        let response = send(&["/", "/wind-down", "/", "/", "/"]).await;
+        assert_eq!(response, format!("{OK}{OK}{OK}{OK_CLOSE}"));
+
+        // A later call doesn't move the end further
+        let response = send(&["/wind-down", "/wind-down", "/", "/"]).await;
+        assert_eq!(response, format!("{OK}{OK}{OK_CLOSE}"));
+    }
+
+    #[tokio::test]
+    async fn version_counters() {
+        struct AnyVersion;
+
+        impl Handler for AnyVersion {
+            async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+                match req.version() {
+                    Version::Http09 => resp.http09("test"),
+                    _ => resp.status(StatusCode::Ok).body("test"),
+                }
+            }
+        }
+
+        let counters = Arc::new(VersionCounters::new());
+        let send = |requests: &'static str| {
+            let mut conn = conn_with(AnyVersion, ConnLimits::default());
+            conn.http_09_limits = Some(Http09Limits::default());
+            conn.version_counters = Some(counters.clone());
+
+            serve(conn, move |mut stream| async move {
+                stream.write_all(requests.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+                stream
+            })
+        };
+
+        send("GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\nGET / HTTP/1.0\r\n\r\n").await;
+        send("GET /x\r\n").await;
+        send("GET /y\r\n").await;
+        let snapshot = counters.snapshot();
+        assert_eq!(snapshot.requests_http11, 2);
+        assert_eq!(snapshot.requests_http10, 1);
+        assert_eq!(snapshot.requests_http09, 2);
+        assert_eq!(snapshot.errors_http11, 0);
+
+        // Errors are counted under the version of the rejected request
+        send("GET / HTTP/1.0\r\nbad header\r\n\r\n").await;
+        send("GET / HTTP/2.0\r\n\r\n").await;
+        let snapshot = counters.snapshot();
+        assert_eq!(snapshot.errors_http10, 1);
+        assert_eq!(snapshot.errors_http11, 1);
+        assert_eq!(snapshot.errors_http09, 0);
+        assert_eq!(snapshot.requests_http10, 1);
+    }
+
+    #[tokio::test]
+    async fn min_write_rate() {
+        let limits = ConnLimits {
+            min_write_rate: Some(10_000),
+            ..ConnLimits::default()
+        };
+        let response = vec![b'x'; 64 * 1024];
+
+        // A client reading about 1 KB/s is cut off after the first second
+        let (mut client, mut server) = duplex(1024);
+        let reader = tokio::spawn(async move {
+            let mut buf = [0; 64];
+            while client.read(&mut buf).await.is_ok_and(|n| n != 0) {
+                sleep(Duration::from_millis(50)).await;
+            }
+        });
+        let started = Instant::now();
+        let err = limits
+            .write_bytes(&mut server, &response)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        drop(server);
+        reader.await.unwrap();
+
+        // A fast one gets the whole response
+        let (mut client, mut server) = duplex(1024);
+        let reader = tokio::spawn(async move {
+            let mut received = Vec::new();
+            client.read_to_end(&mut received).await.unwrap();
+            received.len()
+        });
+        limits.write_bytes(&mut server, &response).await.unwrap();
+        drop(server);
+        assert_eq!(reader.await.unwrap(), response.len());
+    }
+
+    #[tokio::test]
+    async fn min_write_rate_finished() {
+        use std::{
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        // Takes all but the last byte at once, then blocks past the window
+        // for the last one
+        struct SlowFinish(usize);
+
+        impl AsyncWrite for SlowFinish {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                if buf.len() == 1 {
+                    std::thread::sleep(Duration::from_millis(1100));
+                }
+                let n = buf.len().max(2) - 1;
+                self.0 += n;
+                Poll::Ready(Ok(n))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let limits = ConnLimits {
+            min_write_rate: Some(10_000),
+            ..ConnLimits::default()
+        };
+        let mut stream = SlowFinish(0);
+        limits.write_bytes(&mut stream, &[b'x'; 64]).await.unwrap();
+        assert_eq!(stream.0, 64);
+    }
+
+    #[tokio::test]
+    async fn write_segment_size() {
+        let limits = ConnLimits {
+            socket_write_timeout: Duration::from_millis(100),
+            write_segment_size: Some(1024),
+            ..ConnLimits::default()
+        };
+        let response = vec![b'x'; 16 * 1024];
+
+        // About 1 KB every 20 ms: 320 ms in total, each segment within the timeout
+        let slow_reader = |mut client: DuplexStream| async move {
+            let mut received = Vec::new();
+            let mut buf = [0; 1024];
+            loop {
+                match client.read(&mut buf).await.unwrap() {
+                    0 => return received.len(),
+                    n => received.extend_from_slice(&buf[..n]),
+                }
+                sleep(Duration::from_millis(20)).await;
+            }
+        };
+
+        let (client, mut server) = duplex(1024);
+        let reader = tokio::spawn(slow_reader(client));
+        limits.write_bytes(&mut server, &response).await.unwrap();
+        drop(server);
+        assert_eq!(reader.await.unwrap(), response.len());
+
+        // The same client misses the timeout for the whole response
+        let (client, mut server) = duplex(1024);
+        let reader = tokio::spawn(slow_reader(client));
+        let whole = ConnLimits {
+            write_segment_size: None,
+            ..limits.clone()
+        };
+        let err = whole.write_bytes(&mut server, &response).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        drop(server);
+        reader.await.unwrap();
+
+        // A stalled client is cut off after one timeout
+        let (_client, mut server) = duplex(1024);
+        let started = Instant::now();
+        let err = limits
+            .write_bytes(&mut server, &response)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn pipelined_batch_is_capped() {
+        let mut t = HttpConnection::from_req("GET /p HTTP/1.1\r\n\r\n".repeat(40));
+        let max = t.conn_limits.max_pipelined_requests;
+
+        let mut steps = Vec::new();
+        loop {
+            t.request.reset();
+            assert_eq!(t.parse_request(), Ok(()));
+            assert_eq!(t.request.url().target_str(), "/p");
+
+            let step = t.next_in_buffer();
+            steps.push(step);
+            if step == Pipeline::Read {
+                break;
+            }
+        }
+
+        assert_eq!(steps.len(), 40);
+        for (i, step) in steps.iter().enumerate() {
+            let expected = match i + 1 {
+                40 => Pipeline::Read,
+                n if n % max == 0 => Pipeline::Yield,
+                _ => Pipeline::Next,
+            };
+            assert_eq!(*step, expected, "request {}", i + 1);
+        }
+        assert!(t.parser.is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    http::{request::Request, response::Response},
+    Handled, Method, StatusCode,
+};
+
+/// CORS settings for answering preflight requests, see
+/// [`ServerBuilder::auto_preflight`](crate::ServerBuilder::auto_preflight).
+///
+/// A preflight is an `OPTIONS` request with `origin` and
+/// `access-control-request-method` headers, sent by browsers before a
+/// cross-origin request. It gets `204 No Content` with the allow headers
+/// below, or `403 Forbidden` without them when its origin isn't allowed.
+/// Only the preflight is answered: the actual requests reach the handler,
+/// which adds `access-control-allow-origin` to its responses itself.
+///
+/// # Examples
+/// ```
+/// use maker_web::Cors;
+///
+/// let cors = Cors {
+///     allow_origins: &["https://app.example.com"],
+///     allow_headers: "content-type, x-request-id",
+///     ..Cors::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cors {
+    /// Allowed origins, compared exactly; `"*"` allows any (default: `&["*"]`)
+    pub allow_origins: &'static [&'static str],
+    /// Value of `access-control-allow-methods` (default: `"GET, POST, PUT, PATCH, DELETE"`)
+    pub allow_methods: &'static str,
+    /// Value of `access-control-allow-headers` (default: `"content-type, authorization"`)
+    pub allow_headers: &'static str,
+    /// Seconds browsers may cache the preflight result (default: `Some(600)`)
+    pub max_age: Option<u32>,
+
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub _priv: (),
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            allow_origins: &["*"],
+            allow_methods: "GET, POST, PUT, PATCH, DELETE",
+            allow_headers: "content-type, authorization",
+            max_age: Some(600),
+
+            _priv: (),
+        }
+    }
+}
+
+impl Cors {
+    // Answers `request` if it is a preflight, before the handler runs
+    #[inline]
+    pub(crate) fn preflight(&self, request: &Request, response: &mut Response) -> Option<Handled> {
+        if request.method() != Method::Options
+            || request.header(b"access-control-request-method").is_none()
+        {
+            return None;
+        }
+        let origin = request.header(b"origin")?;
+
+        let any = self.allow_origins.contains(&"*");
+        if !any && !self.allow_origins.iter().any(|o| o.as_bytes() == origin) {
+            return Some(response.status(StatusCode::Forbidden).body(""));
+        }
+
+        response.status(StatusCode::NoContent);
+        match any {
+            true => response.header("access-control-allow-origin", "*"),
+            false => response
+                .header("access-control-allow-origin", origin)
+                .header("vary", "origin"),
+        };
+        response
+            .header("access-control-allow-methods", self.allow_methods)
+            .header("access-control-allow-headers", self.allow_headers);
+        if let Some(max_age) = self.max_age {
+            response.header("access-control-max-age", max_age);
+        }
+        Some(response.body(""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{server::connection::def_handler::DefHandler, Server};
+    use std::{net::SocketAddr, sync::Arc};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::Notify,
+    };
+
+    async fn launch(cors: Cors) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .auto_preflight(cors)
+            .build();
+
+        let ready = Arc::new(Notify::new());
+        tokio::spawn(server.launch_notified(ready.clone()));
+        ready.notified().await;
+        addr
+    }
+
+    async fn send(addr: SocketAddr, headers: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request =
+            format!("OPTIONS /missing HTTP/1.1\r\nHost: a\r\n{headers}connection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        String::from_utf8(response).unwrap()
+    }
+
+    const PREFLIGHT: &str = "Access-Control-Request-Method: POST\r\n";
+
+    #[tokio::test]
+    async fn preflight() {
+        let addr = launch(Cors::default()).await;
+        let resp = send(addr, &format!("Origin: https://a.test\r\n{PREFLIGHT}")).await;
+        assert_eq!(
+            resp,
+            "HTTP/1.1 204 No Content\r\n\
+             access-control-allow-origin: *\r\n\
+             access-control-allow-methods: GET, POST, PUT, PATCH, DELETE\r\n\
+             access-control-allow-headers: content-type, authorization\r\n\
+             access-control-max-age: 600\r\n\
+             connection: close\r\ncontent-length: 0000000000\r\n\r\n"
+        );
+
+        let addr = launch(Cors {
+            allow_origins: &["https://a.test"],
+            max_age: None,
+            ..Cors::default()
+        })
+        .await;
+        let resp = send(addr, &format!("Origin: https://a.test\r\n{PREFLIGHT}")).await;
+        assert!(resp.starts_with("HTTP/1.1 204 No Content\r\n"), "{resp}");
+        assert!(resp.contains("access-control-allow-origin: https://a.test\r\nvary: origin\r\n"));
+        assert!(!resp.contains("access-control-max-age"));
+    }
+
+    #[tokio::test]
+    async fn plain_options_reach_handler() {
+        let addr = launch(Cors::default()).await;
+
+        for headers in ["", "Origin: https://a.test\r\n", PREFLIGHT] {
+            let resp = send(addr, headers).await;
+            assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+            assert!(resp.ends_with("\r\n\r\ntest"), "{resp}");
+        }
+    }
+
+    #[tokio::test]
+    async fn disallowed_origin() {
+        let addr = launch(Cors {
+            allow_origins: &["https://a.test"],
+            ..Cors::default()
+        })
+        .await;
+
+        for origin in ["https://b.test", "https://a.test.evil", "http://a.test"] {
+            let resp = send(addr, &format!("Origin: {origin}\r\n{PREFLIGHT}")).await;
+            assert!(resp.starts_with("HTTP/1.1 403 Forbidden\r\n"), "{resp}");
+            assert!(!resp.contains("access-control-"), "{resp}");
+        }
+    }
+}
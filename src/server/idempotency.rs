@@ -0,0 +1,277 @@
+use crate::{
+    http::{
+        request::Request,
+        response::{Response, ResponseTemplate},
+    },
+    server::{
+        connection::{CloseReason, ConnectionData},
+        server_impl::Handler,
+    },
+    Handled,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Expired entries forgotten by each new response
+const SWEEP: usize = 4;
+
+/// Replays the cached response for a repeated
+/// [`Idempotency-Key`](Request::idempotency_key) instead of calling the handler again.
+///
+/// The first request with a key is handled as usual and its response is kept
+/// for `ttl`; repeats within `ttl` get a copy of it (adjusted to their version
+/// and keep-alive). Requests without the key always go to the handler.
+/// [`Handler::post_process`] runs for every response, replayed or not.
+///
+/// Keys are global, not per path or client: use keys unique per operation
+/// (e.g. UUIDs). Every complete response is cached, errors included. Requests
+/// with the same key that arrive while the first one is still being handled
+/// are not deduplicated. At most `max_entries` responses are kept; when the
+/// store is full, new responses are not cached until old ones expire. Each
+/// new response forgets a few expired ones, so every request does a bounded
+/// amount of work.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{Handled, Handler, IdempotencyStore, Request, Response, Server, StatusCode};
+/// use std::time::Duration;
+/// use tokio::net::TcpListener;
+///
+/// struct Payments;
+///
+/// impl Handler for Payments {
+///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+///         // Charge the card once
+///         resp.status(StatusCode::Created).body(r#"{"status":"paid"}"#)
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     .handler(IdempotencyStore::new(Payments, Duration::from_secs(24 * 3600), 10_000))
+///     .build()
+///     .launch()
+///     .await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct IdempotencyStore<H> {
+    handler: H,
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<Entries>,
+}
+
+// When the response was cached, and the response
+type Entry = (Instant, ResponseTemplate);
+
+#[derive(Debug, Default)]
+struct Entries {
+    map: HashMap<Arc<[u8]>, Entry>,
+    // Keys in the order they were cached, so the oldest expire first; a key
+    // removed or cached again since then is skipped
+    order: VecDeque<(Instant, Arc<[u8]>)>,
+}
+
+impl<H> IdempotencyStore<H> {
+    /// Creates a store that keeps up to `max_entries` responses of `handler` for `ttl`.
+    pub fn new(handler: H, ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            handler,
+            ttl,
+            max_entries,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Number of cached responses, expired ones included until they are evicted.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    /// Returns `true` if no responses are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Writes the cached response, `None` if there is none (or it's expired)
+    fn replay(&self, key: &[u8], response: &mut Response) -> Option<Handled> {
+        let mut entries = self.entries.lock().unwrap();
+        let (created, template) = entries.map.get(key)?;
+
+        if created.elapsed() < self.ttl {
+            return Some(response.from_template(template));
+        }
+        entries.map.remove(key);
+        None
+    }
+
+    fn store(&self, key: &[u8], template: ResponseTemplate) {
+        let mut entries = self.entries.lock().unwrap();
+        let Entries { map, order } = &mut *entries;
+
+        for _ in 0..SWEEP {
+            match order.front() {
+                Some((created, _)) if created.elapsed() >= self.ttl => {}
+                _ => break,
+            }
+            let Some((created, old)) = order.pop_front() else {
+                break;
+            };
+            if map.get(&old).is_some_and(|(at, _)| *at == created) {
+                map.remove(&old);
+            }
+        }
+
+        if map.len() >= self.max_entries {
+            return;
+        }
+        let (key, now) = (Arc::<[u8]>::from(key), Instant::now());
+        order.push_back((now, key.clone()));
+        map.insert(key, (now, template));
+    }
+}
+
+impl<H: Handler<S>, S: ConnectionData> Handler<S> for IdempotencyStore<H> {
+    async fn handle(&self, data: &mut S, request: &Request, response: &mut Response) -> Handled {
+        let Some(key) = request.idempotency_key() else {
+            return self.handler.handle(data, request, response).await;
+        };
+        if let Some(handled) = self.replay(key, response) {
+            return handled;
+        }
+
+        let handled = self.handler.handle(data, request, response).await;
+
+        // `close()` by the handler, not by the client's `connection: close`
+        let close = request.is_keep_alive() && !response.keep_alive;
+        if let Some(template) = ResponseTemplate::capture(response, close) {
+            self.store(key, template);
+        }
+        handled
+    }
+
+    #[inline]
+    fn post_process(&self, request: &Request, response: &mut Response) {
+        self.handler.post_process(request, response)
+    }
+
+    #[inline]
+    fn expect_continue(&self, request: &Request) -> bool {
+        self.handler.expect_continue(request)
+    }
+
+    #[inline]
+    fn connection_closed(&self, client_addr: SocketAddr, reason: CloseReason) {
+        self.handler.connection_closed(client_addr, reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{server::connection::HttpConnection, tools::str_op, StatusCode};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl Handler for Counter {
+        async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+            let n = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            resp.status(StatusCode::Created)
+                .header("x-call", n)
+                .body("paid")
+        }
+    }
+
+    async fn send(store: &IdempotencyStore<Counter>, req: &str) -> String {
+        let mut t = HttpConnection::from_req(req);
+        t.parse_request().unwrap();
+        t.response.synchronization_with_request(&t.request);
+
+        store.handle(&mut (), &t.request, &mut t.response).await;
+        str_op(t.response.buffer()).to_string()
+    }
+
+    const KEY_A: &str = "POST /pay HTTP/1.1\r\nIdempotency-Key: a\r\n\r\n";
+    const KEY_B: &str = "POST /pay HTTP/1.1\r\nIdempotency-Key: b\r\n\r\n";
+
+    #[test]
+    fn idempotency_key() {
+        let mut t = HttpConnection::from_req(KEY_A);
+        t.parse_request().unwrap();
+        assert_eq!(t.request.idempotency_key(), Some(&b"a"[..]));
+
+        let mut t = HttpConnection::from_req("POST /pay HTTP/1.1\r\n\r\n");
+        t.parse_request().unwrap();
+        assert_eq!(t.request.idempotency_key(), None);
+    }
+
+    #[tokio::test]
+    async fn repeated_key() {
+        let store = IdempotencyStore::new(Counter::default(), Duration::from_secs(60), 16);
+
+        let first = send(&store, KEY_A).await;
+        assert_eq!(
+            first,
+            "HTTP/1.1 201 Created\r\nx-call: 1\r\ncontent-length: 0000000004\r\n\r\npaid"
+        );
+        assert_eq!(send(&store, KEY_A).await, first);
+
+        // Adjusted to the version and keep-alive of the repeat
+        assert_eq!(
+            send(&store, "POST /pay HTTP/1.0\r\nIdempotency-Key: a\r\n\r\n").await,
+            "HTTP/1.0 201 Created\r\nx-call: 1\r\nconnection: close\r\ncontent-length: 0000000004\r\n\r\npaid"
+        );
+
+        assert!(send(&store, KEY_B).await.contains("x-call: 2"));
+        assert!(send(&store, "POST /pay HTTP/1.1\r\n\r\n")
+            .await
+            .contains("x-call: 3"));
+        assert!(send(&store, "POST /pay HTTP/1.1\r\n\r\n")
+            .await
+            .contains("x-call: 4"));
+        assert_eq!(store.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn expiry_and_capacity() {
+        let store = IdempotencyStore::new(Counter::default(), Duration::from_millis(50), 1);
+
+        assert!(send(&store, KEY_A).await.contains("x-call: 1"));
+        // Full: `b` is handled but not cached
+        assert!(send(&store, KEY_B).await.contains("x-call: 2"));
+        assert!(send(&store, KEY_B).await.contains("x-call: 3"));
+        assert!(send(&store, KEY_A).await.contains("x-call: 1"));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(send(&store, KEY_A).await.contains("x-call: 4"));
+        assert!(send(&store, KEY_A).await.contains("x-call: 4"));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn bounded_sweep() {
+        let store = IdempotencyStore::new(Counter::default(), Duration::from_millis(50), 8);
+        let key = |i| format!("POST /pay HTTP/1.1\r\nIdempotency-Key: {i}\r\n\r\n");
+
+        for i in 0..8 {
+            send(&store, &key(i)).await;
+        }
+        assert_eq!(store.len(), 8);
+
+        // A new response forgets only the oldest expired ones
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        send(&store, &key(8)).await;
+        assert_eq!(store.len(), 8 - SWEEP + 1);
+        send(&store, &key(9)).await;
+        assert_eq!(store.len(), 8 - 2 * SWEEP + 2);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{
+    http::{request::Request, response::Response},
+    server::{
+        connection::{CloseReason, ConnectionData},
+        server_impl::{peer_shard, Handler},
+    },
+    Handled, StatusCode,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::Mutex,
+    time::Instant,
+};
+
+const SHARDS: usize = 16;
+// Tracked IPs checked for a refilled bucket when a new IP arrives
+const SWEEP: usize = 4;
+
+/// Limits the request rate of each client IP across all of its connections,
+/// answering requests over the limit with `429 Too Many Requests`.
+///
+/// Counting requests instead of connections matters because a client can
+/// open many connections, each within per-connection limits. Every IP gets a
+/// bucket of `burst` requests refilled at `per_second`; a request that finds
+/// it empty never reaches the handler and gets a `retry-after` header with
+/// the seconds until the next one is allowed. IPv6 clients are counted per
+/// `/64`, which is usually a single host or network.
+///
+/// The counters are split into shards by IP, each behind its own lock, so
+/// workers rarely wait for each other. At most `max_ips` IPs are tracked:
+/// each new IP first forgets a few tracked ones whose bucket has refilled
+/// (which loses nothing), and when the store is still full, the IP tracked
+/// the longest is forgotten and starts over with a full bucket. Every request
+/// does a bounded amount of work. Behind a reverse proxy all requests come
+/// from the proxy address.
+///
+/// [`Handler::post_process`] runs for every response, rejected or not.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{Handled, Handler, IpRateLimit, Request, Response, Server, StatusCode};
+/// use tokio::net::TcpListener;
+///
+/// struct Api;
+///
+/// impl Handler for Api {
+///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+///         resp.status(StatusCode::Ok).body("ok")
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     // 20 requests per second, bursts of up to 50, for 100 000 clients
+///     .handler(IpRateLimit::new(Api, 20, 50, 100_000))
+///     .build()
+///     .launch()
+///     .await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct IpRateLimit<H> {
+    handler: H,
+    start: Instant,
+    gcra: Gcra,
+    max_per_shard: usize,
+    shards: Box<[Mutex<Shard>]>,
+}
+
+#[derive(Debug, Default)]
+struct Shard {
+    // When each IP's bucket is full again, in nanoseconds since `start`
+    full_at: HashMap<IpAddr, u64>,
+    // The same IPs, swept from the front
+    order: VecDeque<IpAddr>,
+}
+
+impl Shard {
+    // Forgets the refilled buckets among the first `SWEEP` IPs, the others
+    // move to the back so the next sweep checks different ones
+    fn sweep(&mut self, now: u64) {
+        for _ in 0..SWEEP.min(self.order.len()) {
+            let Some(ip) = self.order.pop_front() else {
+                break;
+            };
+            match self.full_at[&ip] > now {
+                true => self.order.push_back(ip),
+                false => {
+                    self.full_at.remove(&ip);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, ip: IpAddr, full_at: u64, max: usize) {
+        if self.full_at.len() >= max {
+            if let Some(oldest) = self.order.pop_front() {
+                self.full_at.remove(&oldest);
+            }
+        }
+        self.full_at.insert(ip, full_at);
+        self.order.push_back(ip);
+    }
+}
+
+impl<H> IpRateLimit<H> {
+    /// Allows each client IP `per_second` requests to `handler`, up to `burst`
+    /// at once, tracking at most `max_ips` clients. A `burst` of `0` is `1`.
+    ///
+    /// # Panics
+    /// Panics if `per_second` or `max_ips` is `0`.
+    #[track_caller]
+    pub fn new(handler: H, per_second: u32, burst: u32, max_ips: usize) -> Self {
+        assert!(per_second > 0, "`per_second` must be at least 1");
+        assert!(max_ips > 0, "`max_ips` must be at least 1");
+        Self {
+            handler,
+            start: Instant::now(),
+            gcra: Gcra::new(per_second, burst.max(1)),
+            max_per_shard: max_ips.div_ceil(SHARDS),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    /// Number of tracked IPs, those whose bucket has refilled included until
+    /// they are forgotten.
+    pub fn len(&self) -> usize {
+        let len = |shard: &Mutex<Shard>| shard.lock().unwrap().full_at.len();
+        self.shards.iter().map(len).sum()
+    }
+
+    /// Returns `true` if no IPs are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Takes a request from the bucket of `ip`, or returns the nanoseconds
+    // until one is available
+    fn acquire(&self, ip: IpAddr) -> Result<(), u64> {
+        let ip = client_key(ip);
+        let now = self.start.elapsed().as_nanos() as u64;
+        let mut shard = self.shards[peer_shard(ip, SHARDS)].lock().unwrap();
+
+        match shard.full_at.get_mut(&ip) {
+            Some(full_at) => *full_at = self.gcra.acquire(*full_at, now)?,
+            None => {
+                // A refilled bucket is the same as no entry
+                let full_at = self.gcra.acquire(0, now)?;
+                shard.sweep(now);
+                shard.insert(ip, full_at, self.max_per_shard);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A token bucket kept as the time it is full again (GCRA), in nanoseconds
+/// on the caller's clock
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Gcra {
+    // Nanoseconds per token and per full bucket, an empty bucket allows nothing
+    interval: u64,
+    burst: u64,
+}
+
+impl Gcra {
+    /// Refills `per_second` tokens a second, up to `burst`
+    pub(crate) fn new(per_second: u32, burst: u32) -> Self {
+        let interval = 1_000_000_000 / u64::from(per_second.max(1));
+        Self {
+            interval,
+            burst: interval * u64::from(burst),
+        }
+    }
+
+    /// Takes a token from the bucket full again at `full_at`: the new
+    /// `full_at`, or the nanoseconds until a token is available
+    #[inline]
+    pub(crate) fn acquire(&self, full_at: u64, now: u64) -> Result<u64, u64> {
+        let next = full_at.max(now) + self.interval;
+        match next - now > self.burst {
+            true => Err(next - now - self.burst),
+            false => Ok(next),
+        }
+    }
+}
+
+// IPv4 (mapped ones too) as is, IPv6 by its `/64` prefix
+#[inline]
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(ip) => {
+            let prefix = u128::from(ip) & !(u128::MAX >> 64);
+            IpAddr::V6(Ipv6Addr::from(prefix))
+        }
+        ip => ip,
+    }
+}
+
+impl<H: Handler<S>, S: ConnectionData> Handler<S> for IpRateLimit<H> {
+    async fn handle(&self, data: &mut S, request: &Request, response: &mut Response) -> Handled {
+        match self.acquire(request.client_addr().ip()) {
+            Ok(()) => self.handler.handle(data, request, response).await,
+            Err(wait) => response
+                .status(StatusCode::TooManyRequests)
+                .header("retry-after", wait.div_ceil(1_000_000_000).max(1))
+                .body("Too Many Requests"),
+        }
+    }
+
+    #[inline]
+    fn post_process(&self, request: &Request, response: &mut Response) {
+        self.handler.post_process(request, response)
+    }
+
+    #[inline]
+    fn expect_continue(&self, request: &Request) -> bool {
+        self.handler.expect_continue(request)
+    }
+
+    #[inline]
+    fn connection_closed(&self, client_addr: SocketAddr, reason: CloseReason) {
+        self.handler.connection_closed(client_addr, reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Server;
+    use std::{
+        net::Ipv4Addr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::Notify,
+    };
+
+    struct Counter(Arc<AtomicUsize>);
+
+    impl Handler for Counter {
+        async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            resp.status(StatusCode::Ok).body("ok")
+        }
+    }
+
+    async fn launch(limit: IpRateLimit<Counter>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Server::builder().listener(listener).handler(limit).build();
+
+        let ready = Arc::new(Notify::new());
+        tokio::spawn(server.launch_notified(ready.clone()));
+        ready.notified().await;
+        port
+    }
+
+    async fn connect(port: u16) -> TcpStream {
+        TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap()
+    }
+
+    async fn send(stream: &mut TcpStream) -> String {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = vec![0; 256];
+        let n = stream.read(&mut response).await.unwrap();
+        String::from_utf8(response[..n].to_vec()).unwrap()
+    }
+
+    const LIMITED: &str = "HTTP/1.1 429 Too Many Requests\r\nretry-after: 1\r\n";
+
+    #[tokio::test]
+    async fn spread_over_connections() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let port = launch(IpRateLimit::new(Counter(calls.clone()), 1, 3, 1024)).await;
+
+        // One request per connection, every connection within any per-connection limit
+        let mut streams = Vec::new();
+        for _ in 0..3 {
+            let mut stream = connect(port).await;
+            assert!(send(&mut stream).await.ends_with("\r\n\r\nok"));
+            streams.push(stream);
+        }
+        for stream in &mut streams {
+            let resp = send(stream).await;
+            assert!(resp.starts_with(LIMITED), "{resp}");
+        }
+        let resp = send(&mut connect(port).await).await;
+        assert!(resp.starts_with(LIMITED), "{resp}");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn buckets() {
+        let limit = IpRateLimit::new(Counter(Arc::default()), 1, 2, SHARDS);
+        let a: IpAddr = Ipv4Addr::new(10, 0, 0, 1).into();
+
+        assert_eq!(limit.acquire(a), Ok(()));
+        assert_eq!(limit.acquire(a), Ok(()));
+        assert!(limit.acquire(a).is_err_and(|wait| wait > 0));
+        // Another IP has its own bucket
+        assert_eq!(limit.acquire(Ipv4Addr::new(10, 0, 0, 2).into()), Ok(()));
+        // A mapped IPv4 is the IPv4
+        assert!(limit.acquire("::ffff:10.0.0.1".parse().unwrap()).is_err());
+
+        // The same `/64` is one client
+        let v6 = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(limit.acquire(v6("2001:db8::1")), Ok(()));
+        assert_eq!(limit.acquire(v6("2001:db8::ffff:1")), Ok(()));
+        assert!(limit.acquire(v6("2001:db8::2")).is_err());
+        assert_eq!(limit.acquire(v6("2001:db8:0:1::1")), Ok(()));
+
+        // Refilled after one interval
+        let limit = IpRateLimit::new(Counter(Arc::default()), 1000, 1, SHARDS);
+        assert_eq!(limit.acquire(a), Ok(()));
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(limit.acquire(a), Ok(()));
+    }
+
+    // Distinct IPs that land in the same shard
+    fn same_shard(count: usize) -> Vec<IpAddr> {
+        let first = peer_shard(Ipv4Addr::new(10, 0, 0, 0).into(), SHARDS);
+        (0..=255)
+            .map(|last| IpAddr::from(Ipv4Addr::new(10, 0, 0, last)))
+            .filter(|ip| peer_shard(*ip, SHARDS) == first)
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn full_store() {
+        // One IP per shard
+        let limit = IpRateLimit::new(Counter(Arc::default()), 1, 1, SHARDS);
+        let ips = same_shard(2);
+
+        assert_eq!(limit.acquire(ips[0]), Ok(()));
+        assert!(limit.acquire(ips[0]).is_err());
+
+        // A new IP isn't locked out, the oldest one is forgotten instead
+        assert_eq!(limit.acquire(ips[1]), Ok(()));
+        assert_eq!(limit.len(), 1);
+        assert_eq!(limit.acquire(ips[0]), Ok(()));
+        assert!(limit.acquire(ips[1]).is_ok());
+    }
+
+    #[test]
+    fn sweep_refilled() {
+        let limit = IpRateLimit::new(Counter(Arc::default()), 1000, 1, SHARDS * 8);
+        let ips = same_shard(SWEEP + 1);
+
+        for ip in &ips[..SWEEP] {
+            assert_eq!(limit.acquire(*ip), Ok(()));
+        }
+        assert_eq!(limit.len(), SWEEP);
+
+        // Refilled buckets are forgotten by the next new IP
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(limit.acquire(ips[SWEEP]), Ok(()));
+        assert_eq!(limit.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "`max_ips` must be at least 1")]
+    fn zero_max_ips() {
+        IpRateLimit::new(Counter(Arc::default()), 1, 1, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "`per_second` must be at least 1")]
+    fn zero_rate() {
+        IpRateLimit::new(Counter(Arc::default()), 0, 1, SHARDS);
+    }
+}
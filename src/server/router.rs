@@ -0,0 +1,410 @@
+use crate::{
+    http::{metrics, request::Request, response::Response},
+    server::{
+        connection::{CloseReason, ConnectionData},
+        server_impl::Handler,
+    },
+    Handled, StatusCode, Url,
+};
+use std::net::SocketAddr;
+
+/// Dispatches requests to handlers by the `Host` header (virtual hosting).
+///
+/// A host is matched case-insensitively and without its port: first the
+/// exact hosts, then the wildcards (`*.example.com`, the longest suffix wins),
+/// then the default handler. A wildcard matches any subdomain, but not
+/// `example.com` itself. Requests without `Host` (e.g. `HTTP/1.0` or
+/// `HTTP/0.9+`) go to the default handler.
+///
+/// All handlers have the same type, so no request pays for dynamic dispatch;
+/// use an `enum` to route to different handler types.
+/// [`Handler::connection_closed`] is called on the default handler.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{Handled, Handler, HostRouter, Request, Response, Server, StatusCode};
+/// use tokio::net::TcpListener;
+///
+/// enum Site {
+///     Api,
+///     Tenant,
+///     NotFound,
+/// }
+///
+/// impl Handler for Site {
+///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+///         match self {
+///             Site::Api => resp.status(StatusCode::Ok).body(r#"{"ok":true}"#),
+///             Site::Tenant => resp.status(StatusCode::Ok).body(req.header(b"host").unwrap()),
+///             Site::NotFound => resp.status(StatusCode::NotFound).body("unknown host"),
+///         }
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = HostRouter::new(Site::NotFound)
+///     .route("api.example.com", Site::Api)
+///     .route("*.example.com", Site::Tenant);
+///
+/// Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     .handler(router)
+///     .build()
+///     .launch()
+///     .await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct HostRouter<H> {
+    exact: Vec<(Box<[u8]>, H)>,
+    // Suffixes with the leading dot, longest first
+    wildcard: Vec<(Box<[u8]>, H)>,
+    default: H,
+}
+
+impl<H> HostRouter<H> {
+    /// Creates a router that sends every request to `default`.
+    pub fn new(default: H) -> Self {
+        Self {
+            exact: Vec::new(),
+            wildcard: Vec::new(),
+            default,
+        }
+    }
+
+    /// Routes requests for `host` to `handler`.
+    ///
+    /// `host` is either exact (`api.example.com`) or a wildcard
+    /// (`*.example.com`). A later route for the same host is ignored.
+    pub fn route(mut self, host: &str, handler: H) -> Self {
+        let host = host.to_ascii_lowercase().into_bytes();
+
+        match host.strip_prefix(b"*") {
+            Some(suffix) => {
+                debug_assert!(
+                    suffix.starts_with(b"."),
+                    "A wildcard must have the form `*.example.com`"
+                );
+                let at = self
+                    .wildcard
+                    .partition_point(|(s, _)| s.len() >= suffix.len());
+                self.wildcard.insert(at, (suffix.into(), handler));
+            }
+            None => self.exact.push((host.into(), handler)),
+        }
+        self
+    }
+
+    /// Returns the handler for a `Host` value (with or without port).
+    pub fn select(&self, host: Option<&[u8]>) -> &H {
+        let Some(host) = host.map(strip_port) else {
+            return &self.default;
+        };
+
+        let exact = self
+            .exact
+            .iter()
+            .find(|(h, _)| h.eq_ignore_ascii_case(host));
+        let wildcard = || {
+            self.wildcard.iter().find(|(suffix, _)| {
+                host.len() > suffix.len()
+                    && host[host.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            })
+        };
+
+        match exact.or_else(wildcard) {
+            Some((_, handler)) => handler,
+            None => &self.default,
+        }
+    }
+}
+
+// `example.com:8080` -> `example.com`, `[::1]:80` -> `[::1]`
+#[inline]
+fn strip_port(host: &[u8]) -> &[u8] {
+    match host.iter().rposition(|&b| b == b':') {
+        Some(colon) if !host[colon..].contains(&b']') => &host[..colon],
+        _ => host,
+    }
+}
+
+impl<H: Handler<S>, S: ConnectionData> Handler<S> for HostRouter<H> {
+    #[inline]
+    async fn handle(&self, data: &mut S, request: &Request, response: &mut Response) -> Handled {
+        self.select(request.header(b"host"))
+            .handle(data, request, response)
+            .await
+    }
+
+    #[inline]
+    fn post_process(&self, request: &Request, response: &mut Response) {
+        self.select(request.header(b"host"))
+            .post_process(request, response)
+    }
+
+    #[inline]
+    fn expect_continue(&self, request: &Request) -> bool {
+        self.select(request.header(b"host"))
+            .expect_continue(request)
+    }
+
+    // A connection may serve several hosts, it's reported to the default handler
+    #[inline]
+    fn connection_closed(&self, client_addr: SocketAddr, reason: CloseReason) {
+        self.default.connection_closed(client_addr, reason)
+    }
+}
+
+/// Dispatches requests to handlers by the URL path, with a fallback for
+/// paths that match no route.
+///
+/// Routes use the patterns of [`PathTemplater`](crate::metrics::PathTemplater)
+/// (`/api/users/:id`, `/static/*`) and are tried in order, the first match
+/// wins. The method is not matched, the route handler checks it. Requests
+/// that match no route go to the fallback, [`NotFound`] unless set with
+/// [`fallback()`](Self::fallback). Matching doesn't allocate.
+///
+/// As with [`HostRouter`], all routes have the same handler type;
+/// [`Handler::connection_closed`] is called on the fallback.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{Handled, Handler, Request, Response, Router, Server, StatusCode};
+/// use tokio::net::TcpListener;
+///
+/// enum Api {
+///     Users,
+///     User,
+/// }
+///
+/// impl Handler for Api {
+///     async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+///         match self {
+///             Api::Users => resp.status(StatusCode::Ok).body("[]"),
+///             Api::User => resp.status(StatusCode::Ok).body(req.url().path_segments()[2]),
+///         }
+///     }
+/// }
+///
+/// struct Missing;
+///
+/// impl Handler for Missing {
+///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+///         resp.status(StatusCode::NotFound)
+///             .header("content-type", "application/json")
+///             .body(r#"{"error":"no such endpoint"}"#)
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = Router::new()
+///     .route("/api/users", Api::Users)
+///     .route("/api/users/:id", Api::User)
+///     .fallback(Missing);
+///
+/// Server::builder()
+///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+///     .handler(router)
+///     .build()
+///     .launch()
+///     .await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Router<H, F = NotFound> {
+    routes: Vec<(&'static str, H)>,
+    fallback: F,
+}
+
+impl<H> Router<H> {
+    /// Creates a router without routes, answering every request with [`NotFound`].
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            fallback: NotFound,
+        }
+    }
+}
+
+impl<H> Default for Router<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H, F> Router<H, F> {
+    /// Routes requests whose path matches `pattern` to `handler`.
+    pub fn route(mut self, pattern: &'static str, handler: H) -> Self {
+        self.routes.push((pattern, handler));
+        self
+    }
+
+    /// Sends requests that match no route to `fallback` instead of [`NotFound`].
+    ///
+    /// It gets the full request and response like any handler, e.g. to serve
+    /// an SPA's `index.html` or a JSON `404`.
+    pub fn fallback<G>(self, fallback: G) -> Router<H, G> {
+        Router {
+            routes: self.routes,
+            fallback,
+        }
+    }
+
+    /// Returns the handler of the first route matching the path of `url`,
+    /// `None` if the request goes to the fallback.
+    pub fn select(&self, url: &Url) -> Option<&H> {
+        let path = url.path_segments_str();
+
+        self.routes
+            .iter()
+            .find(|(pattern, _)| metrics::matches(pattern, path))
+            .map(|(_, handler)| handler)
+    }
+}
+
+impl<H: Handler<S>, F: Handler<S>, S: ConnectionData> Handler<S> for Router<H, F> {
+    #[inline]
+    async fn handle(&self, data: &mut S, request: &Request, response: &mut Response) -> Handled {
+        match self.select(request.url()) {
+            Some(handler) => handler.handle(data, request, response).await,
+            None => self.fallback.handle(data, request, response).await,
+        }
+    }
+
+    #[inline]
+    fn post_process(&self, request: &Request, response: &mut Response) {
+        match self.select(request.url()) {
+            Some(handler) => handler.post_process(request, response),
+            None => self.fallback.post_process(request, response),
+        }
+    }
+
+    #[inline]
+    fn expect_continue(&self, request: &Request) -> bool {
+        match self.select(request.url()) {
+            Some(handler) => handler.expect_continue(request),
+            None => self.fallback.expect_continue(request),
+        }
+    }
+
+    // A connection may serve several routes, it's reported to the fallback
+    #[inline]
+    fn connection_closed(&self, client_addr: SocketAddr, reason: CloseReason) {
+        self.fallback.connection_closed(client_addr, reason)
+    }
+}
+
+/// The default fallback of [`Router`]: `404 Not Found` with the body `Not Found`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotFound;
+
+impl<S: ConnectionData> Handler<S> for NotFound {
+    #[inline]
+    async fn handle(&self, _: &mut S, _: &Request, response: &mut Response) -> Handled {
+        response.reply(StatusCode::NotFound, "Not Found")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{server::connection::HttpConnection, tools::str_op, StatusCode};
+
+    #[derive(Debug, PartialEq)]
+    struct Named(&'static str);
+
+    impl Handler for Named {
+        async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+            resp.status(StatusCode::Ok).body(self.0)
+        }
+    }
+
+    fn router() -> HostRouter<Named> {
+        HostRouter::new(Named("default"))
+            .route("api.example.com", Named("api"))
+            .route("admin.example.com", Named("admin"))
+            .route("*.example.com", Named("tenant"))
+            .route("*.eu.example.com", Named("eu"))
+    }
+
+    #[test]
+    fn select() {
+        let router = router();
+
+        #[rustfmt::skip]
+        let cases: [(Option<&[u8]>, &str); 11] = [
+            (Some(b"api.example.com"), "api"),
+            (Some(b"API.Example.COM:8080"), "api"),
+            (Some(b"admin.example.com"), "admin"),
+            (Some(b"shop.example.com"), "tenant"),
+            (Some(b"a.b.example.com:443"), "tenant"),
+            (Some(b"shop.eu.example.com"), "eu"),
+            (Some(b"example.com"), "default"),
+            (Some(b"api.example.org"), "default"),
+            (Some(b"[::1]:80"), "default"),
+            (Some(b""), "default"),
+            (None, "default"),
+        ];
+
+        for (host, expected) in cases {
+            assert_eq!(router.select(host), &Named(expected), "{host:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch() {
+        let router = router();
+
+        for (host, expected) in [
+            ("api.example.com", "api"),
+            ("admin.example.com", "admin"),
+            ("other.org", "default"),
+        ] {
+            let mut t = HttpConnection::from_req(format!("GET / HTTP/1.1\r\nHost: {host}\r\n\r\n"));
+            t.parse_request().unwrap();
+
+            router.handle(&mut (), &t.request, &mut t.response).await;
+            assert!(str_op(t.response.buffer()).ends_with(expected));
+        }
+    }
+
+    async fn route(router: &Router<Named, impl Handler>, path: &str) -> String {
+        let mut t = HttpConnection::from_req(format!("GET {path} HTTP/1.1\r\nHost: a\r\n\r\n"));
+        t.parse_request().unwrap();
+
+        router.handle(&mut (), &t.request, &mut t.response).await;
+        str_op(t.response.buffer()).to_string()
+    }
+
+    #[tokio::test]
+    async fn fallback() {
+        let router = Router::new()
+            .route("/api/users", Named("users"))
+            .route("/api/users/:id", Named("user"))
+            .route("/static/*", Named("static"));
+
+        for (path, expected) in [
+            ("/api/users", "users"),
+            ("/api/users/42?full=1", "user"),
+            ("/static/css/site.css", "static"),
+        ] {
+            let resp = route(&router, path).await;
+            assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+            assert!(resp.ends_with(expected), "{path}: {resp}");
+        }
+
+        // Unmatched paths get the default `404`
+        for path in ["/", "/api", "/api/users/42/posts"] {
+            let resp = route(&router, path).await;
+            assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"), "{resp}");
+            assert!(resp.ends_with("\r\n\r\nNot Found"), "{resp}");
+        }
+
+        // Or the configured one, only for them
+        let router = router.fallback(Named("fallback"));
+        assert!(route(&router, "/api").await.ends_with("fallback"));
+        assert!(route(&router, "/api/users/7").await.ends_with("user"));
+    }
+}
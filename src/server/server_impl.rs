@@ -1,22 +1,38 @@
 use crate::{
+    debug::{RequestMirror, VersionCounters, Watermarks, WireTap},
     errors::ErrorKind,
     http::{
         request::Request,
         response::{Handled, Response},
     },
-    limits::{ConnLimits, Http09Limits, ReqLimits, RespLimits, ServerLimits, WaitStrategy},
-    server::connection::{ConnectionData, HttpConnection},
-    ConnectionFilter, Version,
+    limits::{
+        Admission, ConnLimits, Dispatch, Http09Limits, ReqLimits, RespLimits, ServerLimits,
+        WaitStrategy,
+    },
+    server::{
+        connection::{CloseReason, ConnectionData, HttpConnection},
+        cors::Cors,
+        rate_limit::Gcra,
+    },
+    ConnectionFilter, FilterError, FilterFailurePolicy, StatusCode, Version, WriteBuffer,
 };
 use crossbeam::queue::SegQueue;
 use std::{
+    borrow::Cow,
+    fmt,
     future::Future,
+    io,
     marker::{PhantomData, Send, Sync},
-    net::SocketAddr,
-    sync::Arc,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 use tokio::{
     net::{TcpListener, TcpStream},
+    sync::Notify,
     task::yield_now,
     time::sleep as tokio_sleep,
 };
@@ -103,6 +119,107 @@ where
         request: &Request,
         response: &mut Response,
     ) -> impl Future<Output = Handled> + Send;
+
+    /// Post-processes every response after [`handle`](Handler::handle) and
+    /// before it is written. Does nothing by default.
+    ///
+    /// The place for cross-cutting changes applied to all responses, such as
+    /// security headers via [`Response::insert_header`]. Not called when
+    /// the handler closed the connection with
+    /// [`close_without_response()`](Response::close_without_response).
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Handler, Request, Response, StatusCode, Version};
+    ///
+    /// struct MyHandler;
+    ///
+    /// impl Handler for MyHandler {
+    ///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+    ///         resp.status(StatusCode::Ok).body("Hello")
+    ///     }
+    ///
+    ///     fn post_process(&self, req: &Request, resp: &mut Response) {
+    ///         if req.version() != Version::Http09 {
+    ///             resp.insert_header("x-content-type-options", "nosniff");
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn post_process(
+        &self,
+        #[allow(unused_variables)] request: &Request,
+        #[allow(unused_variables)] response: &mut Response,
+    ) {
+    }
+
+    /// Decides from the head of a request with `Expect: 100-continue` whether
+    /// its body is wanted. Accepts every request by default.
+    ///
+    /// Called before the body is read, with the method, URL and headers
+    /// parsed but no body. On `true` the server sends `100 Continue` and
+    /// reads the body, on `false` it answers `417 Expectation Failed` and
+    /// closes the connection. Requests whose `Content-Length` is over
+    /// [`body_size`](crate::limits::ReqLimits::body_size) get `413` without
+    /// reaching it.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Handled, Handler, Request, Response, StatusCode};
+    ///
+    /// struct Uploads;
+    ///
+    /// impl Handler for Uploads {
+    ///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+    ///         resp.status(StatusCode::Created).body("stored")
+    ///     }
+    ///
+    ///     // Unauthenticated uploads don't need to be sent at all
+    ///     fn expect_continue(&self, req: &Request) -> bool {
+    ///         req.header(b"authorization").is_some()
+    ///     }
+    /// }
+    /// ```
+    fn expect_continue(&self, #[allow(unused_variables)] request: &Request) -> bool {
+        true
+    }
+
+    /// Called once a connection handed to a worker has ended, with the
+    /// reason. Does nothing by default.
+    ///
+    /// Separates clients that closed the connection from ones reaped by
+    /// [`socket_read_timeout`](crate::limits::ConnLimits::socket_read_timeout),
+    /// e.g. for metrics. Connections rejected by the
+    /// [`ConnectionFilter`] are not reported.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{CloseReason, Handled, Handler, Request, Response, StatusCode};
+    /// use std::{net::SocketAddr, sync::atomic::{AtomicUsize, Ordering}};
+    ///
+    /// #[derive(Default)]
+    /// struct MyHandler {
+    ///     timeouts: AtomicUsize,
+    /// }
+    ///
+    /// impl Handler for MyHandler {
+    ///     async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+    ///         resp.status(StatusCode::Ok).body("Hello")
+    ///     }
+    ///
+    ///     fn connection_closed(&self, _: SocketAddr, reason: CloseReason) {
+    ///         if reason == CloseReason::ReadTimeout {
+    ///             self.timeouts.fetch_add(1, Ordering::Relaxed);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn connection_closed(
+        &self,
+        #[allow(unused_variables)] client_addr: SocketAddr,
+        #[allow(unused_variables)] reason: CloseReason,
+    ) {
+    }
 }
 
 /// An HTTP server that processes incoming connections and requests.
@@ -136,9 +253,10 @@ where
 /// ```
 pub struct Server {
     listener: TcpListener,
-    stream_queue: TcpQueue,
-    error_queue: TcpQueue,
-    server_limits: ServerLimits,
+    // One shared queue, or one per worker with `Dispatch::PeerIpHash`
+    stream_queues: Box<[TcpQueue]>,
+    error_queue: AlarmQueue,
+    config: ResolvedConfig,
 }
 
 impl Server {
@@ -169,6 +287,13 @@ impl Server {
             listener: None,
             handler: None,
             connection_filter: Arc::new(()),
+            filter_failure_policy: FilterFailurePolicy::default(),
+            on_ready: None,
+            request_mirror: None,
+            watermarks: None,
+            version_counters: None,
+            preflight: None,
+            wire_tap: None,
             _marker: PhantomData,
 
             server_limits: None,
@@ -200,29 +325,125 @@ impl Server {
     /// ```
     #[inline]
     pub async fn launch(self) {
+        self.accept_loop(None).await
+    }
+
+    /// Starts the server like [`launch()`](Server::launch) and calls
+    /// [`notify_one()`](Notify::notify_one) on `ready` once the listener
+    /// is being polled.
+    ///
+    /// Workers are already spawned by [`ServerBuilder::build`], so after the
+    /// signal connections are accepted without a race. The permit of `ready`
+    /// is stored, waiting after the signal is fine too.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::Server;
+    /// use std::sync::Arc;
+    /// use tokio::{net::TcpListener, sync::Notify};
+    ///
+    /// let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    /// let addr = listener.local_addr().unwrap();
+    /// let server = Server::builder()
+    ///     .listener(listener)
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .build();
+    ///
+    /// let ready = Arc::new(Notify::new());
+    /// tokio::spawn(server.launch_notified(ready.clone()));
+    /// ready.notified().await;
+    ///
+    /// // The server is accepting
+    /// let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    /// # }
+    /// ```
+    #[inline]
+    pub async fn launch_notified(self, ready: Arc<Notify>) {
+        self.accept_loop(Some(ready)).await
+    }
+
+    #[inline]
+    async fn accept_loop(self, ready: Option<Arc<Notify>>) {
+        if let Some(ready) = ready {
+            ready.notify_one();
+        }
+
+        let server_limits = &self.config.server_limits;
+        let backpressure = server_limits.admission == Admission::Backpressure;
+        let shards = self.stream_queues.len();
+        let pending = server_limits.max_pending_connections.div_ceil(shards);
+        let limit = match backpressure {
+            true => pending.max(1),
+            false => pending,
+        };
+
         loop {
+            // The queue of a sharded connection is only known after `accept()`,
+            // so only waiting for all of them never stalls the other clients
+            let full = || self.stream_queues.iter().all(|queue| queue.len() >= limit);
+            while backpressure && full() {
+                Self::wait(&server_limits.wait_strategy).await;
+            }
+
             let Ok(value) = self.listener.accept().await else {
                 continue;
             };
 
-            match self.stream_queue.len() < self.server_limits.max_pending_connections {
-                true => self.stream_queue.push(value),
-                false => self.error_queue.push(value),
+            let (stream, addr) = value;
+            let queue = &self.stream_queues[peer_shard(addr.ip(), shards)];
+            match queue.len() < limit {
+                true => queue.push((stream, addr, Instant::now())),
+                false => self.error_queue.push((stream, Canned::Overload)),
             }
         }
     }
 
+    /// Returns the configuration the server is actually running with.
+    ///
+    /// The same value is passed to [`ServerBuilder::on_ready`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::Server;
+    /// use tokio::net::TcpListener;
+    ///
+    /// let server = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .build();
+    ///
+    /// println!("Request buffer: {} bytes", server.config().buffer_size);
+    /// # }
+    /// ```
+    #[inline(always)]
+    pub fn config(&self) -> &ResolvedConfig {
+        &self.config
+    }
+
     #[inline]
-    async fn get_stream(queue: &TcpQueue, wait: &WaitStrategy) -> (TcpStream, SocketAddr) {
+    async fn get_stream<T>(queue: &SegQueue<T>, wait: &WaitStrategy) -> T {
         loop {
             if let Some(value) = queue.pop() {
                 return value;
             }
 
-            match wait {
-                WaitStrategy::Yield => yield_now().await,
-                WaitStrategy::Sleep(time) => tokio_sleep(*time).await,
-            }
+            Self::wait(wait).await;
+        }
+    }
+
+    #[inline]
+    async fn wait(wait: &WaitStrategy) {
+        match wait {
+            WaitStrategy::Yield => yield_now().await,
+            WaitStrategy::Sleep(time) => tokio_sleep(*time).await,
         }
     }
 }
@@ -245,6 +466,13 @@ where
     listener: Option<TcpListener>,
     handler: Option<Arc<H>>,
     connection_filter: Arc<F>,
+    filter_failure_policy: FilterFailurePolicy,
+    on_ready: Option<OnReady>,
+    request_mirror: Option<Arc<RequestMirror>>,
+    watermarks: Option<Arc<Watermarks>>,
+    version_counters: Option<Arc<VersionCounters>>,
+    preflight: Option<Arc<Cors>>,
+    wire_tap: Option<Arc<WireTap>>,
     _marker: PhantomData<S>,
 
     server_limits: Option<ServerLimits>,
@@ -285,6 +513,88 @@ where
         self
     }
 
+    /// Binds a TCP listener to `addr` with a listen queue of `backlog`
+    /// connections and sets it like [`listener`](Self::listener).
+    ///
+    /// [`TcpListener::bind`] always uses a backlog of 1024; under bursts of
+    /// new connections a larger one (up to the OS limit, e.g. `somaxconn`
+    /// on Linux) avoids dropped `SYN`s. Must be called inside a `tokio` runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::Server;
+    ///
+    /// let server = Server::builder()
+    ///     .bind("0.0.0.0:8080".parse().unwrap(), 8192)
+    ///     .unwrap()
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .build();
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// Any error from creating, binding or listening on the socket.
+    pub fn bind(self, addr: SocketAddr, backlog: u32) -> io::Result<Self> {
+        use socket2::{Domain, Socket, Type};
+
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        // Same as `TcpListener::bind`
+        #[cfg(not(windows))]
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(backlog.min(i32::MAX as u32) as i32)?;
+
+        Ok(self.listener(TcpListener::from_std(socket.into())?))
+    }
+
+    /// Takes over an already bound and listening TCP socket by its file
+    /// descriptor and sets it like [`listener`](Self::listener).
+    ///
+    /// For sockets inherited from systemd socket activation or from a parent
+    /// process during a binary upgrade: the socket stays open across the
+    /// restart, so connections wait in its backlog instead of being refused.
+    /// The socket is switched to non-blocking mode. Must be called inside a
+    /// `tokio` runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::Server;
+    ///
+    /// // systemd passes the first socket as fd 3 (`LISTEN_FDS_START`)
+    /// // SAFETY: fd 3 is the listening socket from `ListenStream=`, used only here
+    /// let server = unsafe { Server::builder().listener_from_fd(3) }
+    ///     .unwrap()
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .build();
+    /// # }
+    /// ```
+    ///
+    /// # Safety
+    /// `fd` must be an open TCP socket in the listening state, and ownership
+    /// of it passes to the server: nothing else may use or close it.
+    ///
+    /// # Errors
+    /// Any error from switching the socket to non-blocking mode or
+    /// registering it with the `tokio` runtime.
+    #[cfg(unix)]
+    pub unsafe fn listener_from_fd(self, fd: std::os::unix::io::RawFd) -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        listener.set_nonblocking(true)?;
+
+        Ok(self.listener(TcpListener::from_std(listener)?))
+    }
+
     /// Sets the request handler that will process incoming requests.
     ///
     /// **This is a required component.**
@@ -378,6 +688,13 @@ where
             listener: self.listener,
             handler: self.handler,
             connection_filter: Arc::new(filter),
+            filter_failure_policy: self.filter_failure_policy,
+            on_ready: self.on_ready,
+            request_mirror: self.request_mirror,
+            watermarks: self.watermarks,
+            version_counters: self.version_counters,
+            preflight: self.preflight,
+            wire_tap: self.wire_tap,
             _marker: self._marker,
             server_limits: self.server_limits,
             request_limits: self.request_limits,
@@ -387,6 +704,35 @@ where
         }
     }
 
+    /// Sets what happens to a connection when the connection filter can't
+    /// make a decision (default: [`FailClosed`](FilterFailurePolicy::FailClosed)).
+    ///
+    /// Applies when [`ConnectionFilter::try_filter_async`] returns
+    /// [`FilterError::Unavailable`]. With `FailOpen` a filter outage doesn't
+    /// take the service down, at the cost of letting unchecked clients in.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::{Server, FilterFailurePolicy};
+    /// use tokio::net::TcpListener;
+    ///
+    /// let server = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .filter_failure_policy(FilterFailurePolicy::FailOpen)
+    ///     .build();
+    /// # }
+    /// ```
+    #[inline(always)]
+    pub fn filter_failure_policy(mut self, policy: FilterFailurePolicy) -> Self {
+        self.filter_failure_policy = policy;
+        self
+    }
+
     /// Configures request parsing and processing limits.
     ///
     /// # Examples
@@ -556,17 +902,42 @@ where
         self
     }
 
-    /// Finalizes the builder and constructs a [`Server`] instance.
+    /// Sets a callback that receives the resolved configuration once the
+    /// server is built.
     ///
-    /// # Panics
+    /// Called at the end of [`build()`](Self::build), after defaults are applied
+    /// and workers are spawned. Useful for logging the limits the server actually
+    /// runs with. The same data is available later via [`Server::config`].
     ///
-    /// Error messages:
-    /// - ``The `listener` method must be called to create``
-    /// - ``The `handler` method must be called to create``
+    /// # Examples
     ///
-    /// Panics when:
-    /// - The `listener` method was not called.
-    /// - The `handler` method was not called.
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::Server;
+    /// use tokio::net::TcpListener;
+    ///
+    /// let server = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .on_ready(|config| println!("{config}"))
+    ///     .build();
+    /// # }
+    /// ```
+    #[inline(always)]
+    pub fn on_ready<C>(mut self, callback: C) -> Self
+    where
+        C: FnOnce(&ResolvedConfig) + Send + 'static,
+    {
+        self.on_ready = Some(Box::new(callback));
+        self
+    }
+
+    /// Records recent requests into a [`RequestMirror`] for debugging.
+    ///
+    /// Keep a clone of the `Arc` to read [`snapshot()`](RequestMirror::snapshot),
+    /// e.g. from an admin endpoint of your handler.
     ///
     /// # Examples
     ///
@@ -574,125 +945,518 @@ where
     /// # maker_web::impt_default_handler!{ MyStruct }
     /// # #[tokio::main]
     /// # async fn main() {
+    /// use maker_web::{debug::RequestMirror, Server};
+    /// use std::sync::Arc;
     /// use tokio::net::TcpListener;
-    /// use maker_web::Server;
+    ///
+    /// let mirror = Arc::new(RequestMirror::new(50, 512, |status| status >= 400));
     ///
     /// let server = Server::builder()
     ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
     ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .request_mirror(mirror.clone())
     ///     .build();
-    ///
-    /// // Yes, 3 identical examples, for you, in case you suddenly get lost :)
-    /// #
-    /// # // No, really. Documentation can be difficult for beginners.
     /// # }
     /// ```
-    #[inline]
-    #[track_caller]
-    pub fn build(self) -> Server {
-        let (listener, handler, filter, limits) = self.get_all_parts();
-
-        let stream_queue = Arc::new(SegQueue::new());
-        let error_queue = Arc::new(SegQueue::new());
-
-        for _ in 0..limits.0.max_connections {
-            Self::spawn_worker(&stream_queue, &limits, &filter, &handler);
-        }
-        if limits.0.count_503_handlers != 0 {
-            for _ in 0..limits.0.count_503_handlers {
-                Self::spawn_alarmist(&error_queue, &limits);
-            }
-        } else {
-            Self::spawn_quiet_alarmist(&error_queue, &limits);
-        }
-
-        Server {
-            listener,
-            stream_queue,
-            error_queue,
-            server_limits: limits.0,
-        }
+    #[inline(always)]
+    pub fn request_mirror(mut self, mirror: Arc<RequestMirror>) -> Self {
+        self.request_mirror = Some(mirror);
+        self
     }
 
-    #[inline]
-    fn spawn_worker(queue: &TcpQueue, limits: &AllLimits, filter: &Arc<F>, handler: &Arc<H>) {
-        let queue = queue.clone();
-        let filter = filter.clone();
-        let mut conn = HttpConnection::new(handler.clone(), limits.clone());
-
-        tokio::spawn(async move {
-            loop {
-                let (mut stream, c_addr) =
-                    Server::get_stream(&queue, &conn.server_limits.wait_strategy).await;
-
-                let Ok(s_addr) = stream.local_addr() else {
-                    continue;
-                };
-
-                if filter.filter(c_addr, s_addr, &mut conn.response).is_err()
-                    || filter
-                        .filter_async(c_addr, s_addr, &mut conn.response)
-                        .await
-                        .is_err()
-                {
-                    let _ = conn
-                        .conn_limits
-                        .write_bytes(&mut stream, conn.response.buffer())
-                        .await;
-
-                    conn.response.reset(&conn.resp_limits);
-                    continue;
-                }
-
-                let _ = conn.run(&mut stream, c_addr, s_addr).await;
-            }
-        });
+    /// Tracks the largest request head, body and header count in [`Watermarks`].
+    ///
+    /// Keep a clone of the `Arc` to read [`snapshot()`](Watermarks::snapshot)
+    /// and tune [`ReqLimits`] from real traffic.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::{debug::Watermarks, Server};
+    /// use std::sync::Arc;
+    /// use tokio::net::TcpListener;
+    ///
+    /// let watermarks = Arc::new(Watermarks::new());
+    ///
+    /// let server = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .watermarks(watermarks.clone())
+    ///     .build();
+    /// # }
+    /// ```
+    #[inline(always)]
+    pub fn watermarks(mut self, watermarks: Arc<Watermarks>) -> Self {
+        self.watermarks = Some(watermarks);
+        self
+    }
+
+    /// Counts requests and error responses per protocol version.
+    ///
+    /// See [`VersionCounters`] for what is counted and the cost.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::{debug::VersionCounters, Server};
+    /// use std::sync::Arc;
+    /// use tokio::net::TcpListener;
+    ///
+    /// let counters = Arc::new(VersionCounters::new());
+    ///
+    /// let server = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .version_counters(counters.clone())
+    ///     .build();
+    /// # }
+    /// ```
+    #[inline(always)]
+    pub fn version_counters(mut self, counters: Arc<VersionCounters>) -> Self {
+        self.version_counters = Some(counters);
+        self
+    }
+
+    /// Captures the raw traffic of sampled connections with a [`WireTap`].
+    ///
+    /// The tap is flushed to its file every 100 ms by a background task.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::{debug::WireTap, Server};
+    /// use std::sync::Arc;
+    /// use tokio::net::TcpListener;
+    ///
+    /// let tap = Arc::new(WireTap::new("wire.tap", 16 * 1024 * 1024, 1000));
+    ///
+    /// let server = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .wire_tap(tap)
+    ///     .build();
+    /// # }
+    /// ```
+    #[inline(always)]
+    pub fn wire_tap(mut self, tap: Arc<WireTap>) -> Self {
+        self.wire_tap = Some(tap);
+        self
+    }
+
+    /// Answers CORS preflight requests before the handler runs, for any path.
+    ///
+    /// See [`Cors`] for what counts as a preflight and how it is answered.
+    /// Other `OPTIONS` requests go to the handler as usual.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::{Cors, Server};
+    /// use tokio::net::TcpListener;
+    ///
+    /// let server = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .auto_preflight(Cors {
+    ///         allow_origins: &["https://app.example.com"],
+    ///         ..Cors::default()
+    ///     })
+    ///     .build();
+    /// # }
+    /// ```
+    #[inline(always)]
+    pub fn auto_preflight(mut self, cors: Cors) -> Self {
+        self.preflight = Some(Arc::new(cors));
+        self
+    }
+
+    /// Returns a copy of the builder without a listener, to run the same
+    /// configuration on several ports.
+    ///
+    /// The handler, connection filter, request mirror, watermarks, version
+    /// counters, preflight settings and wire tap are shared (`Arc`), the limits and the filter failure policy
+    /// are copied. The [`on_ready`](Self::on_ready) callback is not copied. Each fork needs its
+    /// own [`listener`](Self::listener) and builds its own workers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::{Server, limits::ServerLimits};
+    /// use tokio::net::TcpListener;
+    ///
+    /// let template = Server::builder()
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .server_limits(ServerLimits {
+    ///         max_connections: 500,
+    ///         ..ServerLimits::default()
+    ///     });
+    ///
+    /// let internal = template
+    ///     .fork()
+    ///     .listener(TcpListener::bind("127.0.0.1:8081").await.unwrap())
+    ///     .build();
+    /// let public = template
+    ///     .listener(TcpListener::bind("0.0.0.0:8080").await.unwrap())
+    ///     .build();
+    /// # }
+    /// ```
+    pub fn fork(&self) -> Self {
+        Self {
+            listener: None,
+            handler: self.handler.clone(),
+            connection_filter: self.connection_filter.clone(),
+            filter_failure_policy: self.filter_failure_policy,
+            on_ready: None,
+            request_mirror: self.request_mirror.clone(),
+            watermarks: self.watermarks.clone(),
+            version_counters: self.version_counters.clone(),
+            preflight: self.preflight.clone(),
+            wire_tap: self.wire_tap.clone(),
+            _marker: PhantomData,
+            server_limits: self.server_limits.clone(),
+            request_limits: self.request_limits.clone(),
+            response_limits: self.response_limits.clone(),
+            connection_limits: self.connection_limits.clone(),
+            http_09_limits: self.http_09_limits.clone(),
+        }
+    }
+
+    /// Finalizes the builder and constructs a [`Server`] instance.
+    ///
+    /// # Panics
+    ///
+    /// Error messages:
+    /// - ``The `listener` method must be called to create``
+    /// - ``The `handler` method must be called to create``
+    /// - `Estimated memory ... exceeds the ceiling ...`
+    ///
+    /// Panics when:
+    /// - The `listener` method was not called.
+    /// - The `handler` method was not called.
+    /// - The workers would need more than [`ServerLimits::memory_ceiling`].
+    ///
+    /// Use [`try_build`](Self::try_build) to get these as a [`BuildError`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use tokio::net::TcpListener;
+    /// use maker_web::Server;
+    ///
+    /// let server = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .build();
+    ///
+    /// // Yes, 3 identical examples, for you, in case you suddenly get lost :)
+    /// #
+    /// # // No, really. Documentation can be difficult for beginners.
+    /// # }
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn build(self) -> Server {
+        match self.try_build() {
+            Ok(server) => server,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Finalizes the builder like [`build`](Self::build), returning an error
+    /// instead of panicking.
+    ///
+    /// Nothing is spawned or allocated for the workers when it fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # maker_web::impt_default_handler!{ MyStruct }
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use maker_web::{limits::ReqLimits, BuildError, Server};
+    /// use tokio::net::TcpListener;
+    ///
+    /// let result = Server::builder()
+    ///     .listener(TcpListener::bind("127.0.0.1:8080").await.unwrap())
+    ///     .handler(MyStruct) // structure with Handler implementation
+    ///     .request_limits(ReqLimits {
+    ///         body_size: 100 * 1024 * 1024, // 100 MB for each of the 100 workers
+    ///         ..ReqLimits::default()
+    ///     })
+    ///     .try_build();
+    ///
+    /// assert!(matches!(result, Err(BuildError::MemoryCeiling { .. })));
+    /// # }
+    /// ```
+    pub fn try_build(mut self) -> Result<Server, BuildError> {
+        let on_ready = self.on_ready.take();
+        let mirror = self.request_mirror.take();
+        let watermarks = self.watermarks.take();
+        let version_counters = self.version_counters.take();
+        let preflight = self.preflight.take();
+        let wire_tap = self.wire_tap.take();
+        let policy = self.filter_failure_policy;
+        let (listener, handler, filter, limits) = self.get_all_parts()?;
+
+        let estimated = estimated_memory(limits.0.max_connections, &limits.3, &limits.4);
+        match limits.0.memory_ceiling {
+            Some(ceiling) if estimated > ceiling => {
+                return Err(BuildError::MemoryCeiling { estimated, ceiling })
+            }
+            _ => {}
+        }
+
+        let shards = match limits.0.dispatch {
+            Dispatch::RoundRobin => 1,
+            Dispatch::PeerIpHash => limits.0.max_connections.max(1),
+        };
+        let stream_queues: Box<[TcpQueue]> =
+            (0..shards).map(|_| Arc::new(SegQueue::new())).collect();
+        let error_queue = Arc::new(SegQueue::new());
+        let rejects_queue = (limits.0.filter_rejects_use_alarmists
+            && limits.0.count_503_handlers != 0)
+            .then_some(&error_queue);
+
+        for worker in 0..limits.0.max_connections {
+            let mut conn = HttpConnection::new(handler.clone(), limits.clone());
+            conn.request.conn_info.set_worker(worker);
+            conn.mirror = mirror.clone();
+            conn.watermarks = watermarks.clone();
+            conn.version_counters = version_counters.clone();
+            conn.preflight = preflight.clone();
+            conn.wire_tap = wire_tap.clone();
+
+            let queue = &stream_queues[worker % shards];
+            Self::spawn_worker(queue, rejects_queue, &filter, policy, conn);
+        }
+        if limits.0.count_503_handlers != 0 {
+            let rate = limits
+                .0
+                .alarmist_max_rate
+                .map(|rate| Arc::new(RateLimit::new(rate)));
+            for _ in 0..limits.0.count_503_handlers {
+                Self::spawn_alarmist(&error_queue, &limits, rate.clone());
+            }
+        } else {
+            Self::spawn_quiet_alarmist(&error_queue, &limits);
+        }
+        if let Some(tap) = wire_tap {
+            Self::spawn_tap_flusher(tap);
+        }
+
+        let config = ResolvedConfig::new(&listener, limits, policy);
+        if let Some(callback) = on_ready {
+            callback(&config);
+        }
+
+        Ok(Server {
+            listener,
+            stream_queues,
+            error_queue,
+            config,
+        })
+    }
+
+    #[inline]
+    fn spawn_worker(
+        queue: &TcpQueue,
+        rejects_queue: Option<&AlarmQueue>,
+        filter: &Arc<F>,
+        policy: FilterFailurePolicy,
+        mut conn: HttpConnection<H, S>,
+    ) {
+        let queue = queue.clone();
+        let rejects_queue = rejects_queue.cloned();
+        let filter = filter.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, c_addr, queued_at) =
+                    Server::get_stream(&queue, &conn.server_limits.wait_strategy).await;
+                let queue_wait = queued_at.elapsed();
+
+                let Ok(s_addr) = stream.local_addr() else {
+                    continue;
+                };
+
+                let verdict = Self::check_filter(&filter, policy, c_addr, s_addr, &mut conn).await;
+
+                let _ = match (verdict, &rejects_queue) {
+                    (FilterVerdict::Accept, _) => {
+                        conn.run(&mut stream, c_addr, s_addr, queue_wait).await
+                    }
+                    // `close_without_response()` in the filter
+                    (FilterVerdict::Reject, _) if conn.response.buffer().is_empty() => {
+                        conn.conn_limits.drop_silently(stream);
+                        Ok(())
+                    }
+                    // `filter_rejects_use_alarmists`: an alarmist writes a copy
+                    (FilterVerdict::Reject, Some(rejects)) => {
+                        let response = conn.response.buffer().to_vec();
+                        rejects.push((stream, Canned::Prepared(Cow::Owned(response))));
+                        Ok(())
+                    }
+                    (FilterVerdict::Unavailable, Some(rejects)) => {
+                        let response = ErrorKind::ServiceUnavailable
+                            .as_http(Version::Http11, conn.server_limits.json_errors);
+                        rejects.push((stream, Canned::Prepared(Cow::Borrowed(response))));
+                        Ok(())
+                    }
+                    (FilterVerdict::Reject, None) => {
+                        conn.conn_limits
+                            .write_bytes(&mut stream, conn.response.buffer())
+                            .await
+                    }
+                    (FilterVerdict::Unavailable, None) => {
+                        conn.conn_limits
+                            .send_error(
+                                &mut stream,
+                                ErrorKind::ServiceUnavailable,
+                                Version::Http11,
+                                conn.server_limits.json_errors,
+                                None,
+                            )
+                            .await
+                    }
+                };
+
+                // The filter of the next connection writes into it
+                conn.response.reset(&conn.resp_limits);
+            }
+        });
+    }
+
+    #[inline]
+    async fn check_filter(
+        filter: &F,
+        policy: FilterFailurePolicy,
+        c_addr: SocketAddr,
+        s_addr: SocketAddr,
+        conn: &mut HttpConnection<H, S>,
+    ) -> FilterVerdict {
+        if filter.filter(c_addr, s_addr, &mut conn.response).is_err() {
+            return FilterVerdict::Reject;
+        }
+
+        match filter
+            .try_filter_async(c_addr, s_addr, &mut conn.response)
+            .await
+        {
+            Ok(()) => FilterVerdict::Accept,
+            Err(FilterError::Rejected(_)) => FilterVerdict::Reject,
+            Err(FilterError::Unavailable) => {
+                filter.filter_unavailable(c_addr, s_addr);
+
+                match policy {
+                    FilterFailurePolicy::FailOpen => FilterVerdict::Accept,
+                    FilterFailurePolicy::FailClosed => FilterVerdict::Unavailable,
+                }
+            }
+        }
     }
 
     #[inline]
-    fn spawn_alarmist(queue: &TcpQueue, limits: &AllLimits) {
+    fn spawn_alarmist(queue: &AlarmQueue, limits: &AllLimits, rate: Option<Arc<RateLimit>>) {
         let queue = queue.clone();
         let (server_limits, conn_limits, ..) = limits.clone();
+        let overload = Self::overload_response(&server_limits);
 
         tokio::spawn(async move {
             loop {
-                let (mut stream, _) =
+                let (mut stream, canned) =
                     Server::get_stream(&queue, &server_limits.wait_strategy).await;
 
-                let _ = conn_limits
-                    .send_error(
-                        &mut stream,
-                        ErrorKind::ServiceUnavailable,
-                        Version::Http11,
-                        server_limits.json_errors,
-                    )
-                    .await;
+                if rate.as_ref().is_some_and(|rate| !rate.try_acquire()) {
+                    conn_limits.drop_silently(stream);
+                    continue;
+                }
+
+                let response = match &canned {
+                    Canned::Overload => &overload,
+                    Canned::Prepared(response) => response,
+                };
+                let _ = conn_limits.write_bytes(&mut stream, response).await;
+            }
+        });
+    }
+
+    // Built once per alarmist, `503` keeps the regular error response
+    fn overload_response(limits: &ServerLimits) -> Cow<'static, [u8]> {
+        const JSON: &[u8] = br#"{"error":"Server overloaded","code":"OVERLOADED"}"#;
+
+        if limits.overload_status == StatusCode::ServiceUnavailable {
+            return Cow::Borrowed(
+                ErrorKind::ServiceUnavailable.as_http(Version::Http11, limits.json_errors),
+            );
+        }
+
+        let mut response = limits
+            .overload_status
+            .to_first_line(Version::Http11)
+            .to_vec();
+        response.extend_from_slice(b"connection: close\r\n");
+        match limits.json_errors {
+            true => {
+                response.extend_from_slice(b"content-length: ");
+                JSON.len().write_to(&mut response);
+                response.extend_from_slice(b"\r\ncontent-type: application/json\r\n\r\n");
+                response.extend_from_slice(JSON);
+            }
+            false => response.extend_from_slice(b"content-length: 0\r\n\r\n"),
+        }
+
+        Cow::Owned(response)
+    }
+
+    fn spawn_tap_flusher(tap: Arc<WireTap>) {
+        tokio::spawn(async move {
+            loop {
+                tokio_sleep(Duration::from_millis(100)).await;
+
+                // Best effort, a failed write only loses the records it held
+                let tap = tap.clone();
+                let _ = tokio::task::spawn_blocking(move || tap.flush()).await;
             }
         });
     }
 
     #[inline]
-    fn spawn_quiet_alarmist(queue: &TcpQueue, limits: &AllLimits) {
+    fn spawn_quiet_alarmist(queue: &AlarmQueue, limits: &AllLimits) {
         let queue = queue.clone();
-        let (server_limits, ..) = limits.clone();
+        let (server_limits, conn_limits, ..) = limits.clone();
 
         tokio::spawn(async move {
             loop {
-                let (stream, _) = Server::get_stream(&queue, &server_limits.wait_strategy).await;
+                let (stream, ..) = Server::get_stream(&queue, &server_limits.wait_strategy).await;
 
-                drop(stream);
+                conn_limits.drop_silently(stream);
             }
         });
     }
 
     #[inline]
-    #[track_caller]
-    fn get_all_parts(self) -> (TcpListener, Arc<H>, Arc<F>, AllLimits) {
-        (
-            self.listener
-                .expect("The `listener` method must be called to create"),
-            self.handler
-                .expect("The `handler` method must be called to create"),
+    fn get_all_parts(self) -> Result<(TcpListener, Arc<H>, Arc<F>, AllLimits), BuildError> {
+        Ok((
+            self.listener.ok_or(BuildError::MissingListener)?,
+            self.handler.ok_or(BuildError::MissingHandler)?,
             self.connection_filter,
             (
                 self.server_limits.clone().unwrap_or_default(),
@@ -704,15 +1468,1782 @@ where
                     .precalculate(),
                 self.response_limits.clone().unwrap_or_default(),
             ),
-        )
+        ))
     }
 }
 
-type TcpQueue = Arc<SegQueue<(TcpStream, SocketAddr)>>;
-pub(crate) type AllLimits = (
-    ServerLimits,
-    ConnLimits,
-    Option<Http09Limits>,
-    ReqLimits,
-    RespLimits,
-);
+/// Effective server configuration after defaults are applied.
+///
+/// Returned by [`Server::config`] and passed to [`ServerBuilder::on_ready`].
+/// Limits that were not set on the builder hold their default values here.
+///
+/// The [`Display`](fmt::Display) implementation prints a multi-line summary
+/// suitable for a startup log.
+#[derive(Debug, Clone)]
+pub struct ResolvedConfig {
+    /// Effective [`ServerLimits`]
+    pub server_limits: ServerLimits,
+    /// Effective [`ConnLimits`]
+    pub conn_limits: ConnLimits,
+    /// Effective [`Http09Limits`], `None` if `HTTP/0.9+` is disabled
+    pub http_09_limits: Option<Http09Limits>,
+    /// Effective [`ReqLimits`]
+    pub req_limits: ReqLimits,
+    /// Effective [`RespLimits`]
+    pub resp_limits: RespLimits,
+
+    /// Request buffer size per connection, in bytes
+    /// (see [`ReqLimits::estimated_buffer_size`])
+    pub buffer_size: usize,
+    /// Number of connection workers (equal to `max_connections`)
+    pub workers: usize,
+    /// Number of tasks sending `503` on queue overflow
+    /// (`0` means connections are closed silently)
+    pub handlers_503: usize,
+    /// Estimated memory for all workers, in bytes: request buffer plus
+    /// initial response capacity per worker. Runtime overhead is not included.
+    pub estimated_memory: usize,
+    /// Address the listener is bound to, `None` if the OS didn't report it
+    pub local_addr: Option<SocketAddr>,
+    /// Effective [`FilterFailurePolicy`]
+    pub filter_failure_policy: FilterFailurePolicy,
+
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub _priv: (),
+}
+
+impl ResolvedConfig {
+    #[inline]
+    fn new(listener: &TcpListener, limits: AllLimits, policy: FilterFailurePolicy) -> Self {
+        let (server_limits, conn_limits, http_09_limits, req_limits, resp_limits) = limits;
+
+        let buffer_size = req_limits.precalc.buffer;
+        let workers = server_limits.max_connections;
+
+        Self {
+            buffer_size,
+            workers,
+            handlers_503: server_limits.count_503_handlers,
+            estimated_memory: estimated_memory(workers, &req_limits, &resp_limits),
+            local_addr: listener.local_addr().ok(),
+            filter_failure_policy: policy,
+
+            server_limits,
+            conn_limits,
+            http_09_limits,
+            req_limits,
+            resp_limits,
+
+            _priv: (),
+        }
+    }
+}
+
+impl fmt::Display for ResolvedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.local_addr {
+            Some(addr) => writeln!(f, "maker_web listening on {addr}")?,
+            None => writeln!(f, "maker_web listening on <unknown>")?,
+        }
+        writeln!(
+            f,
+            "  workers: {}, 503 handlers: {}, queue: {}",
+            self.workers, self.handlers_503, self.server_limits.max_pending_connections
+        )?;
+        writeln!(
+            f,
+            "  request buffer: {} B, response capacity: {}..{} B",
+            self.buffer_size, self.resp_limits.default_capacity, self.resp_limits.max_capacity
+        )?;
+        writeln!(f, "  estimated memory: {} B", self.estimated_memory)?;
+        writeln!(
+            f,
+            "  read timeout: {:?}, write timeout: {:?}, lifetime: {:?}, max requests: {}",
+            self.conn_limits.socket_read_timeout,
+            self.conn_limits.socket_write_timeout,
+            self.conn_limits.connection_lifetime,
+            self.conn_limits.max_requests_per_connection
+        )?;
+        match &self.http_09_limits {
+            Some(limits) => write!(
+                f,
+                "  HTTP/0.9+: enabled (lifetime: {:?}, max requests: {})",
+                limits.connection_lifetime, limits.max_requests_per_connection
+            ),
+            None => write!(f, "  HTTP/0.9+: disabled"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FilterVerdict {
+    Accept,
+    Reject,
+    Unavailable,
+}
+
+type OnReady = Box<dyn FnOnce(&ResolvedConfig) + Send>;
+// The `Instant` is when the connection was queued
+type TcpQueue = Arc<SegQueue<(TcpStream, SocketAddr, Instant)>>;
+type AlarmQueue = Arc<SegQueue<(TcpStream, Canned)>>;
+
+// `Dispatch::PeerIpHash`: the queue of a client IP, FNV-1a of its octets so
+// the assignment doesn't change between runs
+#[inline]
+pub(crate) fn peer_shard(ip: IpAddr, shards: usize) -> usize {
+    if shards == 1 {
+        return 0;
+    }
+
+    let hash = |octets: &[u8]| {
+        octets
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+            })
+    };
+    let hash = match ip.to_canonical() {
+        IpAddr::V4(ip) => hash(&ip.octets()),
+        IpAddr::V6(ip) => hash(&ip.octets()),
+    };
+    (hash % shards as u64) as usize
+}
+
+// What an alarmist writes before closing the connection
+enum Canned {
+    // Built by each alarmist from `ServerLimits::overload_status`
+    Overload,
+    // Prepared by a worker: a connection filter rejection
+    Prepared(Cow<'static, [u8]>),
+}
+
+// `ServerLimits::alarmist_max_rate`, shared by all alarmists: a bucket
+// holding one second of writes, an empty one allows nothing
+struct RateLimit {
+    start: Instant,
+    gcra: Gcra,
+    full_at: AtomicU64,
+}
+
+impl RateLimit {
+    fn new(per_second: u32) -> Self {
+        Self {
+            start: Instant::now(),
+            gcra: Gcra::new(per_second, per_second),
+            full_at: AtomicU64::new(0),
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        let now = self.start.elapsed().as_nanos() as u64;
+        let mut full_at = self.full_at.load(Ordering::Relaxed);
+        loop {
+            let Ok(next) = self.gcra.acquire(full_at, now) else {
+                return false;
+            };
+            match self.full_at.compare_exchange_weak(
+                full_at,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => full_at = current,
+            }
+        }
+    }
+}
+
+// `ResolvedConfig::estimated_memory`, saturated instead of overflowing
+fn estimated_memory(workers: usize, req: &ReqLimits, resp: &RespLimits) -> usize {
+    workers.saturating_mul(req.precalc.buffer.saturating_add(resp.default_capacity))
+}
+
+/// Error returned by [`ServerBuilder::try_build`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// [`ServerBuilder::listener`] was not called
+    MissingListener,
+    /// [`ServerBuilder::handler`] was not called
+    MissingHandler,
+    /// The workers would need more than [`ServerLimits::memory_ceiling`]
+    MemoryCeiling {
+        /// [`ResolvedConfig::estimated_memory`] of the configuration, in bytes
+        estimated: usize,
+        /// The ceiling, in bytes
+        ceiling: usize,
+    },
+}
+
+impl std::error::Error for BuildError {}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::MissingListener => {
+                write!(f, "The `listener` method must be called to create")
+            }
+            BuildError::MissingHandler => {
+                write!(f, "The `handler` method must be called to create")
+            }
+            BuildError::MemoryCeiling { estimated, ceiling } => write!(
+                f,
+                "Estimated memory {estimated} B exceeds the ceiling {ceiling} B \
+                 (`ServerLimits::memory_ceiling`), reduce `max_connections` or `ReqLimits`"
+            ),
+        }
+    }
+}
+
+pub(crate) type AllLimits = (
+    ServerLimits,
+    ConnLimits,
+    Option<Http09Limits>,
+    ReqLimits,
+    RespLimits,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        limits::PipelinedOverflow, server::connection::def_handler::DefHandler, tools::str_op,
+    };
+    use std::{
+        net::Ipv4Addr,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    // Returns once the server is accepting
+    async fn launch(server: Server) {
+        let ready = Arc::new(Notify::new());
+        tokio::spawn(server.launch_notified(ready.clone()));
+        ready.notified().await;
+    }
+
+    async fn request(addr: SocketAddr, data: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        if !data.is_empty() {
+            stream.write_all(data).await.unwrap();
+        }
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        String::from_utf8(response).unwrap()
+    }
+
+    #[derive(Default)]
+    struct FlakyFilter {
+        db_down: AtomicBool,
+        failures: AtomicUsize,
+    }
+
+    impl ConnectionFilter for Arc<FlakyFilter> {
+        fn filter(&self, _: SocketAddr, _: SocketAddr, _: &mut Response) -> Result<(), Handled> {
+            Ok(())
+        }
+
+        async fn try_filter_async(
+            &self,
+            _: SocketAddr,
+            _: SocketAddr,
+            _: &mut Response,
+        ) -> Result<(), FilterError> {
+            match self.db_down.load(Ordering::SeqCst) {
+                true => Err(FilterError::Unavailable),
+                false => Ok(()),
+            }
+        }
+
+        fn filter_unavailable(&self, _: SocketAddr, _: SocketAddr) {
+            self.failures.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    async fn flaky_server(policy: FilterFailurePolicy) -> (SocketAddr, Arc<FlakyFilter>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let filter = Arc::new(FlakyFilter::default());
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .conn_filter(filter.clone())
+            .filter_failure_policy(policy)
+            .server_limits(ServerLimits {
+                max_connections: 2,
+                ..ServerLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        (addr, filter)
+    }
+
+    const CLOSE_REQ: &[u8] = b"GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n";
+
+    #[tokio::test]
+    async fn bind_with_backlog() {
+        let server = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap(), 4096)
+            .unwrap()
+            .handler(DefHandler)
+            .build();
+        let addr = server.config().local_addr.unwrap();
+        launch(server).await;
+
+        // More simultaneous connects than the default backlog of most tests
+        let clients: Vec<_> = (0..64)
+            .map(|_| tokio::spawn(request(addr, CLOSE_REQ)))
+            .collect();
+        for client in clients {
+            assert!(client.await.unwrap().ends_with("\r\n\r\ntest"));
+        }
+
+        // The address is taken
+        let taken = Server::builder::<DefHandler, ()>().bind(addr, 16);
+        assert_eq!(taken.err().unwrap().kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn listener_from_fd() {
+        use std::os::unix::io::IntoRawFd;
+
+        // Bound and listening before the server exists, like an inherited socket
+        let std_listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = std_listener.local_addr().unwrap();
+        let fd = std_listener.into_raw_fd();
+
+        let server = unsafe { Server::builder().listener_from_fd(fd) }
+            .unwrap()
+            .handler(DefHandler)
+            .build();
+        assert_eq!(server.config().local_addr, Some(addr));
+        launch(server).await;
+
+        assert!(request(addr, CLOSE_REQ).await.ends_with("\r\n\r\ntest"));
+    }
+
+    #[derive(Default)]
+    struct CountHandler(AtomicUsize);
+
+    impl Handler for CountHandler {
+        async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+            let n = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            resp.status(StatusCode::Ok).body(n)
+        }
+    }
+
+    #[tokio::test]
+    async fn fork() {
+        let template = Server::builder()
+            .handler(CountHandler::default())
+            .connection_limits(ConnLimits {
+                max_requests_per_connection: 1,
+                ..ConnLimits::default()
+            });
+
+        let mut addrs = Vec::new();
+        for builder in [template.fork(), template] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            addrs.push(listener.local_addr().unwrap());
+
+            let server = builder.listener(listener).build();
+            assert_eq!(server.config().conn_limits.max_requests_per_connection, 1);
+            launch(server).await;
+        }
+        assert_ne!(addrs[0], addrs[1]);
+
+        // One handler behind both listeners
+        for (i, addr) in [addrs[0], addrs[1], addrs[0]].into_iter().enumerate() {
+            let resp = request(addr, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").await;
+            assert!(resp.ends_with(&format!("\r\n\r\n{}", i + 1)), "{resp}");
+        }
+    }
+
+    #[tokio::test]
+    async fn resolved_config_default() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let reported = Arc::new(Mutex::new(None));
+        let reported_clone = reported.clone();
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .on_ready(move |config| *reported_clone.lock().unwrap() = Some(config.clone()))
+            .build();
+
+        let config = server.config();
+        let req_limits = ReqLimits::default();
+        let resp_limits = RespLimits::default();
+        let buffer_size = req_limits.clone().estimated_buffer_size();
+
+        assert_eq!(config.local_addr, Some(addr));
+        assert_eq!(config.buffer_size, buffer_size);
+        assert_eq!(config.workers, ServerLimits::default().max_connections);
+        assert_eq!(
+            config.handlers_503,
+            ServerLimits::default().count_503_handlers
+        );
+        assert_eq!(
+            config.estimated_memory,
+            config.workers * (buffer_size + resp_limits.default_capacity)
+        );
+        assert!(config.http_09_limits.is_none());
+        assert_eq!(config.req_limits.precalc.buffer, buffer_size);
+        assert_eq!(config.req_limits.url_size, req_limits.url_size);
+        assert_eq!(config.resp_limits.max_capacity, resp_limits.max_capacity);
+        assert_eq!(
+            config.conn_limits.max_requests_per_connection,
+            ConnLimits::default().max_requests_per_connection
+        );
+
+        let reported = reported.lock().unwrap().take().unwrap();
+        assert_eq!(format!("{reported:?}"), format!("{config:?}"));
+        assert!(config.to_string().contains(&addr.to_string()));
+    }
+
+    #[tokio::test]
+    async fn resolved_config_overrides() {
+        let server = Server::builder()
+            .listener(TcpListener::bind("127.0.0.1:0").await.unwrap())
+            .handler(DefHandler)
+            .server_limits(ServerLimits {
+                max_connections: 3,
+                count_503_handlers: 0,
+                ..ServerLimits::default()
+            })
+            .http_09_limits(Http09Limits::default())
+            .build();
+
+        let config = server.config();
+        assert_eq!(config.workers, 3);
+        assert_eq!(config.handlers_503, 0);
+        assert!(config.http_09_limits.is_some());
+        assert!(config.to_string().contains("HTTP/0.9+: enabled"));
+    }
+
+    #[tokio::test]
+    async fn worker_after_close() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .server_limits(ServerLimits {
+                max_connections: 1,
+                ..ServerLimits::default()
+            })
+            .build();
+        tokio::spawn(server.launch());
+
+        // The only worker serves both connections
+        for _ in 0..2 {
+            let resp = request(addr, CLOSE_REQ).await;
+            assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_ceiling() {
+        let req_limits = ReqLimits {
+            body_size: 64 * 1024 * 1024,
+            ..ReqLimits::default()
+        };
+        let builder = || Server::builder::<DefHandler, ()>().request_limits(req_limits.clone());
+        let estimated = 100
+            * (req_limits.clone().estimated_buffer_size() + RespLimits::default().default_capacity);
+
+        assert_eq!(
+            builder().try_build().err(),
+            Some(BuildError::MissingListener)
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let result = builder().listener(listener).handler(DefHandler).try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuildError::MemoryCeiling {
+                estimated,
+                ceiling: 1 << 30
+            })
+        );
+
+        // Under a raised ceiling, or without one
+        for memory_ceiling in [Some(estimated), None] {
+            let server = builder()
+                .listener(TcpListener::bind("127.0.0.1:0").await.unwrap())
+                .handler(DefHandler)
+                .server_limits(ServerLimits {
+                    max_connections: 1,
+                    memory_ceiling,
+                    ..ServerLimits::default()
+                })
+                .try_build()
+                .unwrap();
+            assert_eq!(server.config().workers, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn request_mirror() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mirror = Arc::new(RequestMirror::new(4, 64, |_| true));
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .request_mirror(mirror.clone())
+            .build();
+        launch(server).await;
+
+        request(addr, CLOSE_REQ).await;
+        let bad = request(addr, b"GET //x HTTP/1.1\r\n\r\n").await;
+        assert!(bad.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{bad}");
+
+        let snapshot = mirror.snapshot();
+        assert_eq!(snapshot.len(), 2);
+
+        assert_eq!(snapshot[0].status, 200);
+        assert_eq!(snapshot[0].head, CLOSE_REQ);
+        assert!(snapshot[0].handler_time.is_some());
+
+        assert_eq!(snapshot[1].status, 400);
+        assert_eq!(snapshot[1].head, b"GET //x HTTP/1.1\r\n\r\n");
+        assert_eq!(snapshot[1].handler_time, None);
+    }
+
+    #[tokio::test]
+    async fn wire_tap() {
+        use crate::debug::{Direction, WireRecord};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let path =
+            std::env::temp_dir().join(format!("maker_web-{}-server.tap", std::process::id()));
+        let tap = Arc::new(WireTap::new(&path, 1024 * 1024, 2));
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .wire_tap(tap.clone())
+            .build();
+        launch(server).await;
+
+        let mut responses = Vec::new();
+        for _ in 0..3 {
+            responses.push(request(addr, CLOSE_REQ).await);
+        }
+        tap.flush().unwrap();
+
+        let file = std::fs::read(&path).unwrap();
+        let records: Vec<_> = WireRecord::parse_all(&file).collect();
+        let bytes = |connection, direction| -> Vec<u8> {
+            (records.iter())
+                .filter(|r| r.connection == connection && r.direction == direction)
+                .flat_map(|r| r.bytes.iter().copied())
+                .collect()
+        };
+
+        // Only the first and the third connection are sampled
+        assert!(records
+            .iter()
+            .all(|r| r.connection == 0 || r.connection == 2));
+        for (connection, response) in [(0, &responses[0]), (2, &responses[2])] {
+            assert_eq!(bytes(connection, Direction::Inbound), CLOSE_REQ);
+            assert_eq!(bytes(connection, Direction::Outbound), response.as_bytes());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn watermarks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let watermarks = Arc::new(Watermarks::new());
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .watermarks(watermarks.clone())
+            .build();
+        launch(server).await;
+
+        let mut previous = watermarks.snapshot();
+        for size in [0, 10, 5, 100, 1, 500] {
+            let req = format!(
+                "POST /{} HTTP/1.1\r\nhost: a\r\nconnection: close\r\n{}content-length: {size}\r\n\r\n{}",
+                "p".repeat(size / 5),
+                "x: y\r\n".repeat(size % 7),
+                "b".repeat(size),
+            );
+            request(addr, req.as_bytes()).await;
+
+            let current = watermarks.snapshot();
+            let head = req.len() - size;
+            assert_eq!(
+                current.observed_max_head_bytes,
+                previous.observed_max_head_bytes.max(head)
+            );
+            assert_eq!(
+                current.observed_max_body_bytes,
+                previous.observed_max_body_bytes.max(size)
+            );
+            assert_eq!(
+                current.observed_max_headers,
+                previous.observed_max_headers.max(size % 7 + 1)
+            );
+            previous = current;
+        }
+        assert_eq!(previous.observed_max_body_bytes, 500);
+    }
+
+    #[tokio::test]
+    async fn ready_before_connect() {
+        for _ in 0..100 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = Server::builder()
+                .listener(listener)
+                .handler(DefHandler)
+                .server_limits(ServerLimits {
+                    max_connections: 1,
+                    count_503_handlers: 1,
+                    ..ServerLimits::default()
+                })
+                .build();
+            launch(server).await;
+
+            let resp = request(addr, CLOSE_REQ).await;
+            assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+        }
+    }
+
+    struct History(Vec<String>);
+
+    impl ConnectionData for History {
+        fn new() -> Self {
+            History(Vec::with_capacity(3))
+        }
+
+        fn reset(&mut self) {
+            self.0.clear();
+        }
+    }
+
+    struct HistoryHandler;
+
+    impl Handler<History> for HistoryHandler {
+        async fn handle(
+            &self,
+            history: &mut History,
+            req: &Request,
+            resp: &mut Response,
+        ) -> Handled {
+            if history.0.len() == 3 {
+                history.0.remove(0);
+            }
+            history.0.push(format!(
+                "{} {}",
+                req.method().as_str(),
+                req.url().target_str()
+            ));
+
+            resp.status(StatusCode::Ok)
+                .body(history.0.join(",").as_str())
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_history() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(HistoryHandler)
+            .server_limits(ServerLimits {
+                // One worker, so the second connection reuses the same `History`
+                max_connections: 1,
+                ..ServerLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        let bodies = |resp: String| -> Vec<String> {
+            resp.split("HTTP/1.1 200 OK\r\n")
+                .skip(1)
+                .map(|part| part.split("\r\n\r\n").nth(1).unwrap().to_string())
+                .collect()
+        };
+
+        let resp = request(
+            addr,
+            b"GET /a HTTP/1.1\r\nhost: a\r\n\r\n\
+              POST /b?x=1 HTTP/1.1\r\nhost: a\r\ncontent-length: 0\r\n\r\n\
+              GET /c HTTP/1.1\r\nhost: a\r\n\r\nDELETE /d HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            bodies(resp),
+            [
+                "GET /a",
+                "GET /a,POST /b?x=1",
+                "GET /a,POST /b?x=1,GET /c",
+                "POST /b?x=1,GET /c,DELETE /d",
+            ]
+        );
+
+        let resp = request(
+            addr,
+            b"GET /new HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(bodies(resp), ["GET /new"]);
+    }
+
+    struct SlowHandler(Arc<Notify>, Arc<AtomicBool>);
+
+    impl Handler for SlowHandler {
+        async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+            assert!(!req.cancel_token().is_cancelled());
+            self.0.notify_one();
+
+            tokio::select! {
+                _ = req.cancel_token().cancelled() => {
+                    self.1.store(true, Ordering::SeqCst);
+                    self.0.notify_one();
+                    resp.close_without_response()
+                }
+                _ = tokio::time::sleep(std::time::Duration::from_secs(5)) => {
+                    resp.status(StatusCode::Ok).body("too late")
+                }
+            }
+        }
+    }
+
+    async fn slow_server(conn_limits: ConnLimits) -> (SocketAddr, Arc<Notify>, Arc<AtomicBool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (events, cancelled) = (Arc::<Notify>::default(), Arc::<AtomicBool>::default());
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(SlowHandler(Arc::clone(&events), Arc::clone(&cancelled)))
+            .server_limits(ServerLimits {
+                max_connections: 1,
+                ..ServerLimits::default()
+            })
+            .connection_limits(conn_limits)
+            .build();
+        launch(server).await;
+        (addr, events, cancelled)
+    }
+
+    #[tokio::test]
+    async fn cancel_on_disconnect() {
+        // A reset always cancels, a plain close (`EOF`) only with `cancel_on_eof`
+        for (cancel_on_eof, reset) in [(false, true), (true, false)] {
+            let (addr, events, cancelled) = slow_server(ConnLimits {
+                cancel_on_eof,
+                ..ConnLimits::default()
+            })
+            .await;
+
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+                .await
+                .unwrap();
+            events.notified().await;
+            if reset {
+                // Zero linger: closing sends `RST`
+                let socket = socket2::SockRef::from(&stream);
+                socket.set_linger(Some(std::time::Duration::ZERO)).unwrap();
+            }
+            drop(stream);
+
+            let wait = tokio::time::timeout(std::time::Duration::from_secs(2), events.notified());
+            assert!(wait.await.is_ok(), "handler was not cancelled");
+            assert!(cancelled.load(Ordering::SeqCst));
+
+            // The token is reset for the worker's next connection
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+                .await
+                .unwrap();
+            events.notified().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn half_close_is_not_cancelled() {
+        struct Waits;
+
+        impl Handler for Waits {
+            async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+                // Long enough for the watcher to see the `EOF`
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                let cancelled = req.cancel_token().is_cancelled();
+                resp.status(StatusCode::Ok).body(cancelled)
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder().listener(listener).handler(Waits).build();
+        launch(server).await;
+
+        // Like `curl --no-keepalive`: the request, then `shutdown(Write)`
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .await
+            .unwrap();
+        stream.shutdown().await.unwrap();
+
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await.unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+        assert!(resp.ends_with("\r\n\r\nfalse"), "{resp}");
+    }
+
+    #[tokio::test]
+    async fn filter_rejects_use_alarmists() {
+        // Too large for a client that does not read, the write blocks until the timeout
+        const BANNED_RESPONSE: usize = 6 << 20;
+
+        // Client ports, bound before connecting
+        #[derive(Clone, Default)]
+        struct Banned(Arc<std::sync::Mutex<std::collections::HashSet<u16>>>);
+
+        impl ConnectionFilter for Banned {
+            fn filter(
+                &self,
+                client_addr: SocketAddr,
+                _: SocketAddr,
+                resp: &mut Response,
+            ) -> Result<(), Handled> {
+                match self.0.lock().unwrap().contains(&client_addr.port()) {
+                    false => Ok(()),
+                    true => Err(resp
+                        .status(StatusCode::Forbidden)
+                        .body(&*vec![b'x'; BANNED_RESPONSE])),
+                }
+            }
+        }
+
+        let ports = &Banned::default();
+        let start = |filter_rejects_use_alarmists| async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = Server::builder()
+                .listener(listener)
+                .handler(DefHandler)
+                .conn_filter(ports.clone())
+                .server_limits(ServerLimits {
+                    max_connections: 1,
+                    count_503_handlers: 2,
+                    filter_rejects_use_alarmists,
+                    ..ServerLimits::default()
+                })
+                .connection_limits(ConnLimits {
+                    socket_write_timeout: Duration::from_millis(500),
+                    ..ConnLimits::default()
+                })
+                .build();
+            launch(server).await;
+            addr
+        };
+        let banned = |addr| async move {
+            let socket = tokio::net::TcpSocket::new_v4().unwrap();
+            socket.set_recv_buffer_size(4096).unwrap();
+            socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let port = socket.local_addr().unwrap().port();
+            ports.0.lock().unwrap().insert(port);
+            socket.connect(addr).await.unwrap()
+        };
+        let timed_request = |addr| async move {
+            let started = Instant::now();
+            let response = request(addr, CLOSE_REQ).await;
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+            started.elapsed()
+        };
+
+        // Written by the worker: the next client waits for the write timeout
+        let addr = start(false).await;
+        let _client = banned(addr).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(timed_request(addr).await >= Duration::from_millis(300));
+
+        // 90% of the connections are banned, the others are served at once
+        let addr = start(true).await;
+        let mut clients = Vec::new();
+        for _ in 0..3 {
+            for _ in 0..9 {
+                clients.push(banned(addr).await);
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(timed_request(addr).await < Duration::from_millis(200));
+        }
+    }
+
+    async fn overloaded(server_limits: ServerLimits) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .server_limits(ServerLimits {
+                // Every connection goes to the alarmists
+                max_pending_connections: 0,
+                ..server_limits
+            })
+            .build();
+        launch(server).await;
+
+        request(addr, b"").await
+    }
+
+    #[tokio::test]
+    async fn abortive_close_on_reject() {
+        let server = |listener, count_503_handlers| {
+            Server::builder()
+                .listener(listener)
+                .handler(DefHandler)
+                .server_limits(ServerLimits {
+                    max_pending_connections: 0,
+                    count_503_handlers,
+                    ..ServerLimits::default()
+                })
+                .connection_limits(ConnLimits {
+                    abortive_close_on_reject: true,
+                    ..ConnLimits::default()
+                })
+                .build()
+        };
+
+        // Silent drop on overflow: `RST` instead of `FIN`
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        launch(server(listener, 0)).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let err = stream.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionReset);
+
+        // A rejected connection that gets a response is closed normally
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        launch(server(listener, 1)).await;
+
+        let resp = request(addr, b"").await;
+        assert!(
+            resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{resp}"
+        );
+    }
+
+    #[tokio::test]
+    async fn abortive_close_keeps_responses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .connection_limits(ConnLimits {
+                abortive_close_on_reject: true,
+                ..ConnLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        let resp = request(addr, CLOSE_REQ).await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+        assert!(resp.ends_with("test"), "{resp}");
+    }
+
+    #[test]
+    fn rate_limit() {
+        let rate = RateLimit::new(3);
+        assert_eq!(
+            [(); 4].map(|_| rate.try_acquire()),
+            [true, true, true, false]
+        );
+
+        assert!(!RateLimit::new(0).try_acquire());
+    }
+
+    #[tokio::test]
+    async fn alarmist_max_rate() {
+        let flood = |alarmist_max_rate| async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = Server::builder()
+                .listener(listener)
+                .handler(DefHandler)
+                .server_limits(ServerLimits {
+                    max_pending_connections: 0,
+                    count_503_handlers: 2,
+                    alarmist_max_rate,
+                    ..ServerLimits::default()
+                })
+                .build();
+            launch(server).await;
+
+            let mut answered = 0;
+            for _ in 0..40 {
+                answered += !request(addr, b"").await.is_empty() as usize;
+            }
+            answered
+        };
+
+        assert_eq!(flood(None).await, 40);
+
+        // The bucket starts with one second of writes, the rest are dropped
+        let answered = flood(Some(5)).await;
+        assert!((5..10).contains(&answered), "{answered}");
+    }
+
+    #[tokio::test]
+    async fn overload_status() {
+        let resp = overloaded(ServerLimits::default()).await;
+        assert!(
+            resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{resp}"
+        );
+        assert!(resp.ends_with(r#""code":"SERVICE_UNAVAILABLE"}"#), "{resp}");
+
+        let resp = overloaded(ServerLimits {
+            overload_status: StatusCode::TooManyRequests,
+            ..ServerLimits::default()
+        })
+        .await;
+        assert_eq!(
+            resp,
+            "HTTP/1.1 429 Too Many Requests\r\nconnection: close\r\ncontent-length: 49\r\n\
+             content-type: application/json\r\n\r\n{\"error\":\"Server overloaded\",\"code\":\"OVERLOADED\"}"
+        );
+
+        let resp = overloaded(ServerLimits {
+            overload_status: StatusCode::TooManyRequests,
+            json_errors: false,
+            ..ServerLimits::default()
+        })
+        .await;
+        assert_eq!(
+            resp,
+            "HTTP/1.1 429 Too Many Requests\r\nconnection: close\r\ncontent-length: 0\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn last_request_closes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .connection_limits(ConnLimits {
+                max_requests_per_connection: 1,
+                ..ConnLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        // A keep-alive request, the connection is closed after it anyway
+        let resp = request(addr, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").await;
+        assert_eq!(
+            resp,
+            "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 0000000004\r\n\r\ntest"
+        );
+    }
+
+    async fn pipelined_overflow(policy: PipelinedOverflow) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .connection_limits(ConnLimits {
+                max_requests_per_connection: 2,
+                pipelined_overflow_policy: policy,
+                ..ConnLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        // The limit is crossed in the middle of one packet
+        request(addr, &b"GET / HTTP/1.1\r\nHost: a\r\n\r\n".repeat(5)).await
+    }
+
+    #[tokio::test]
+    async fn pipelined_overflow_discard() {
+        let resp = pipelined_overflow(PipelinedOverflow::Discard).await;
+        assert_eq!(
+            resp,
+            "HTTP/1.1 200 OK\r\ncontent-length: 0000000004\r\n\r\ntest\
+             HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 0000000004\r\n\r\ntest"
+        );
+    }
+
+    #[tokio::test]
+    async fn pipelined_overflow_reject() {
+        let resp = pipelined_overflow(PipelinedOverflow::RejectWith503).await;
+        let ok = "HTTP/1.1 200 OK\r\ncontent-length: 0000000004\r\n\r\ntest";
+        let rejected = ErrorKind::ServiceUnavailable.as_http(Version::Http11, true);
+        assert_eq!(resp, format!("{ok}{ok}{}", str_op(rejected)));
+    }
+
+    struct GateHandler(Arc<Notify>, Arc<tokio::sync::Semaphore>);
+
+    impl Handler for GateHandler {
+        async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+            self.0.notify_one();
+            // Each handler passes the permit on to the next one
+            let _permit = self.1.acquire().await.unwrap();
+            resp.status(StatusCode::Ok).body("done")
+        }
+    }
+
+    async fn saturated(admission: Admission) -> (Arc<tokio::sync::Semaphore>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let started = Arc::new(Notify::new());
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(GateHandler(Arc::clone(&started), Arc::clone(&gate)))
+            .server_limits(ServerLimits {
+                max_connections: 1,
+                max_pending_connections: 1,
+                admission,
+                ..ServerLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        // The only worker is busy, the next connection fills the queue
+        let mut busy = TcpStream::connect(addr).await.unwrap();
+        busy.write_all(CLOSE_REQ).await.unwrap();
+        started.notified().await;
+
+        let mut queued = TcpStream::connect(addr).await.unwrap();
+        queued.write_all(CLOSE_REQ).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // The OS completes the handshake whether or not `accept()` is called
+        let extra = TcpStream::connect(addr).await.unwrap();
+
+        tokio::spawn(async move {
+            for mut stream in [busy, queued] {
+                let _ = stream.read_to_end(&mut Vec::new()).await;
+            }
+        });
+
+        (gate, extra)
+    }
+
+    #[tokio::test]
+    async fn admission_queue() {
+        let (gate, mut extra) = saturated(Admission::Queue).await;
+
+        let mut resp = String::new();
+        extra.read_to_string(&mut resp).await.unwrap();
+        assert!(
+            resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{resp}"
+        );
+        gate.add_permits(1);
+    }
+
+    #[tokio::test]
+    async fn admission_backpressure() {
+        let (gate, mut extra) = saturated(Admission::Backpressure).await;
+        extra.write_all(CLOSE_REQ).await.unwrap();
+
+        // Not accepted, so neither rejected nor served while saturated
+        let mut buf = [0; 64];
+        let read =
+            tokio::time::timeout(std::time::Duration::from_millis(200), extra.read(&mut buf));
+        assert!(read.await.is_err(), "connection was accepted");
+
+        // Accepted once the queue has room, then served normally
+        gate.add_permits(1);
+        let mut resp = String::new();
+        extra.read_to_string(&mut resp).await.unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+        assert!(resp.ends_with("done"), "{resp}");
+    }
+
+    struct QueueWaitHandler;
+
+    impl Handler for QueueWaitHandler {
+        async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            let wait = req.conn_info().queue_wait().as_micros() as usize;
+            resp.status(StatusCode::Ok).body(wait)
+        }
+    }
+
+    #[tokio::test]
+    async fn queue_wait() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(QueueWaitHandler)
+            .server_limits(ServerLimits {
+                max_connections: 1,
+                ..ServerLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        let clients: Vec<_> = (0..6)
+            .map(|_| tokio::spawn(request(addr, CLOSE_REQ)))
+            .collect();
+        let mut waits = Vec::new();
+        for client in clients {
+            let resp = client.await.unwrap();
+            let body = resp.split("\r\n\r\n").nth(1).unwrap();
+            waits.push(body.parse::<u64>().unwrap());
+        }
+
+        // One worker, so all but the first client waited for at least one handler
+        waits.sort_unstable();
+        assert!(waits[1..].iter().all(|&wait| wait >= 20_000), "{waits:?}");
+    }
+
+    #[derive(Default)]
+    struct CloseHandler(Mutex<Vec<CloseReason>>, Notify);
+
+    impl Handler for Arc<CloseHandler> {
+        async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+            resp.status(StatusCode::Ok).body("ok")
+        }
+
+        fn connection_closed(&self, _: SocketAddr, reason: CloseReason) {
+            self.0.lock().unwrap().push(reason);
+            self.1.notify_one();
+        }
+    }
+
+    #[tokio::test]
+    async fn close_reasons() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(CloseHandler::default());
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(Arc::clone(&handler))
+            .connection_limits(ConnLimits {
+                socket_read_timeout: std::time::Duration::from_millis(100),
+                ..ConnLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        let next = || async {
+            handler.1.notified().await;
+            handler.0.lock().unwrap().pop().unwrap()
+        };
+
+        // Clean `EOF` after a keep-alive request
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(stream.read(&mut [0; 256]).await.unwrap() > 0);
+        drop(stream);
+        assert_eq!(next().await, CloseReason::ClientClosed);
+
+        // Silent client
+        let _stream = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(next().await, CloseReason::ReadTimeout);
+
+        request(addr, CLOSE_REQ).await;
+        assert_eq!(next().await, CloseReason::Closed);
+        request(addr, b"GET //bad HTTP/1.1\r\n\r\n").await;
+        assert_eq!(next().await, CloseReason::Closed);
+    }
+
+    #[test]
+    fn peer_shards() {
+        let v4 = |last| IpAddr::V4(Ipv4Addr::new(10, 0, 0, last));
+        for last in 0..=255 {
+            let shard = peer_shard(v4(last), 7);
+            assert!(shard < 7);
+            assert_eq!(peer_shard(v4(last), 7), shard);
+
+            // Dual-stack sockets report IPv4 clients as IPv4-mapped IPv6
+            let IpAddr::V4(ip) = v4(last) else {
+                unreachable!()
+            };
+            assert_eq!(peer_shard(IpAddr::V6(ip.to_ipv6_mapped()), 7), shard);
+        }
+        assert_eq!(peer_shard(v4(1), 1), 0);
+
+        // Clients are spread over all the queues
+        let mut used = [false; 7];
+        (0..=255).for_each(|last| used[peer_shard(v4(last), 7)] = true);
+        assert_eq!(used, [true; 7]);
+    }
+
+    struct WorkerHandler;
+
+    impl Handler for WorkerHandler {
+        async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+            resp.status(StatusCode::Ok).body(req.conn_info().worker())
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_ip_dispatch() {
+        const WORKERS: usize = 8;
+
+        let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(WorkerHandler)
+            .server_limits(ServerLimits {
+                max_connections: WORKERS,
+                dispatch: Dispatch::PeerIpHash,
+                ..ServerLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        // Loopback aliases act as different clients
+        let mut used = [false; WORKERS];
+        for last in 1..=16 {
+            let client = Ipv4Addr::new(127, 0, 0, last);
+            let expected = peer_shard(client.into(), WORKERS);
+            used[expected] = true;
+
+            for _ in 0..3 {
+                let socket = tokio::net::TcpSocket::new_v4().unwrap();
+                socket.bind((client, 0).into()).unwrap();
+                let mut stream = socket.connect((client, port).into()).await.unwrap();
+                stream.write_all(CLOSE_REQ).await.unwrap();
+
+                let mut resp = String::new();
+                stream.read_to_string(&mut resp).await.unwrap();
+                assert!(resp.ends_with(&format!("\r\n\r\n{expected}")), "{resp}");
+            }
+        }
+        assert!(used.iter().filter(|&&used| used).count() > 1);
+    }
+
+    struct SlowPathHandler(Arc<Notify>, Arc<tokio::sync::Semaphore>);
+
+    impl Handler for SlowPathHandler {
+        async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+            if req.url().path() == b"/slow" {
+                self.0.notify_one();
+                let _permit = self.1.acquire().await.unwrap();
+            }
+            resp.status(StatusCode::Ok).body("done")
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_ip_backpressure() {
+        const SLOW: &[u8] = b"GET /slow HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n";
+
+        let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let started = Arc::new(Notify::new());
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let server = Server::builder()
+            .listener(listener)
+            .handler(SlowPathHandler(Arc::clone(&started), Arc::clone(&gate)))
+            .server_limits(ServerLimits {
+                max_connections: 2,
+                max_pending_connections: 2,
+                admission: Admission::Backpressure,
+                dispatch: Dispatch::PeerIpHash,
+                ..ServerLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        let busy_ip = Ipv4Addr::new(127, 0, 0, 1);
+        let other_ip = (2..=255)
+            .map(|last| Ipv4Addr::new(127, 0, 0, last))
+            .find(|ip| peer_shard((*ip).into(), 2) != peer_shard(busy_ip.into(), 2))
+            .unwrap();
+        let connect = |ip: Ipv4Addr| async move {
+            let socket = tokio::net::TcpSocket::new_v4().unwrap();
+            socket.bind((ip, 0).into()).unwrap();
+            socket.connect((ip, port).into()).await.unwrap()
+        };
+
+        // The worker of `busy_ip` is busy and its queue is full
+        let mut busy = connect(busy_ip).await;
+        busy.write_all(SLOW).await.unwrap();
+        started.notified().await;
+        let mut queued = connect(busy_ip).await;
+        queued.write_all(SLOW).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // Accepted for the full queue, so rejected instead of waited for
+        let mut extra = connect(busy_ip).await;
+        let mut resp = String::new();
+        extra.read_to_string(&mut resp).await.unwrap();
+        assert!(
+            resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{resp}"
+        );
+
+        // The other worker's clients are still accepted
+        let mut other = connect(other_ip).await;
+        other.write_all(CLOSE_REQ).await.unwrap();
+        let mut resp = String::new();
+        let read = other.read_to_string(&mut resp);
+        tokio::time::timeout(std::time::Duration::from_secs(1), read)
+            .await
+            .expect("accept stalled by a full queue")
+            .unwrap();
+        assert!(resp.ends_with("done"), "{resp}");
+
+        gate.add_permits(1);
+        for mut stream in [busy, queued] {
+            let mut resp = String::new();
+            stream.read_to_string(&mut resp).await.unwrap();
+            assert!(resp.ends_with("done"), "{resp}");
+        }
+    }
+
+    struct ConnInfoHandler;
+
+    impl Handler for ConnInfoHandler {
+        async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+            let info = req.conn_info();
+            let summary = format!(
+                "1x={} 09={}",
+                info.http1x_requests(),
+                info.http09_requests()
+            );
+
+            match req.version() {
+                Version::Http09 => resp.http09(summary.as_str()),
+                _ => resp.status(StatusCode::Ok).body(summary.as_str()),
+            }
+        }
+    }
+
+    async fn conn_info_server(require_prior_http1x: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(ConnInfoHandler)
+            .http_09_limits(Http09Limits {
+                require_prior_http1x,
+                ..Http09Limits::default()
+            })
+            .build();
+        launch(server).await;
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn prior_http1x_allowed() {
+        let addr = conn_info_server(true).await;
+
+        let resp = request(
+            addr,
+            b"GET / HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\nGET /x\r\n",
+        )
+        .await;
+        assert!(resp.contains("\r\n\r\n1x=0 09=0HTTP/1.1"), "{resp}");
+        assert!(resp.ends_with("\r\n\r\n1x=1 09=01x=2 09=0"), "{resp}");
+
+        // Counters start over with a new connection
+        let resp = request(addr, CLOSE_REQ).await;
+        assert!(resp.ends_with("\r\n\r\n1x=0 09=0"), "{resp}");
+    }
+
+    #[tokio::test]
+    async fn prior_http1x_rejected() {
+        let addr = conn_info_server(true).await;
+
+        let resp = request(addr, b"GET /x\r\n").await;
+        assert_eq!(resp, r#"ERROR: "403 Forbidden""#);
+
+        // Without the limit the order doesn't matter
+        let addr = conn_info_server(false).await;
+        assert_eq!(request(addr, b"GET /x\r\n").await, "1x=0 09=0");
+    }
+
+    struct LimitsHandler;
+
+    impl Handler for LimitsHandler {
+        async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+            let limits = req.conn_info().limits();
+            let summary = format!(
+                "body={} requests={} capacity={}",
+                limits.req.body_size,
+                limits.conn.max_requests_per_connection,
+                limits.resp.default_capacity
+            );
+            resp.status(StatusCode::Ok).body(summary.as_str())
+        }
+    }
+
+    #[tokio::test]
+    async fn conn_info_limits() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(LimitsHandler)
+            .request_limits(ReqLimits {
+                body_size: 100,
+                ..ReqLimits::default()
+            })
+            .connection_limits(ConnLimits {
+                max_requests_per_connection: 7,
+                ..ConnLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        let resp = request(addr, CLOSE_REQ).await;
+        let expected = format!(
+            "\r\n\r\nbody=100 requests=7 capacity={}",
+            RespLimits::default().default_capacity
+        );
+        assert!(resp.ends_with(&expected), "{resp}");
+    }
+
+    struct SecureHandler;
+
+    impl Handler for SecureHandler {
+        async fn handle(&self, _: &mut (), req: &Request, resp: &mut Response) -> Handled {
+            match req.url().path() {
+                b"/close" => resp.close_without_response(),
+                b"/big" => resp.status(StatusCode::Ok).body("x".repeat(64).as_str()),
+                _ => resp.status(StatusCode::Ok).header("a", 1).body("test"),
+            }
+        }
+
+        fn post_process(&self, req: &Request, resp: &mut Response) {
+            assert_eq!(req.version(), Version::Http11);
+            resp.insert_header("x-content-type-options", "nosniff");
+        }
+    }
+
+    #[tokio::test]
+    async fn post_process() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::builder()
+            .listener(listener)
+            .handler(SecureHandler)
+            .response_limits(RespLimits {
+                hard_max_body: 32,
+                ..RespLimits::default()
+            })
+            .build();
+        launch(server).await;
+
+        let resp = request(
+            addr,
+            b"GET / HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            resp,
+            "HTTP/1.1 200 OK\r\na: 1\r\ncontent-length: 0000000004\r\n\
+             x-content-type-options: nosniff\r\n\r\ntest\
+             HTTP/1.1 200 OK\r\na: 1\r\nconnection: close\r\ncontent-length: 0000000004\r\n\
+             x-content-type-options: nosniff\r\n\r\ntest"
+        );
+
+        let resp = request(addr, b"GET /big HTTP/1.1\r\nHost: a\r\n\r\n").await;
+        assert!(
+            resp.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+            "{resp}"
+        );
+        assert!(
+            resp.ends_with("x-content-type-options: nosniff\r\n\r\n"),
+            "{resp}"
+        );
+
+        assert_eq!(
+            request(addr, b"GET /close HTTP/1.1\r\nHost: a\r\n\r\n").await,
+            ""
+        );
+    }
+
+    #[tokio::test]
+    async fn filter_fail_open() {
+        let (addr, filter) = flaky_server(FilterFailurePolicy::FailOpen).await;
+
+        let ok = request(addr, CLOSE_REQ).await;
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"), "{ok}");
+
+        filter.db_down.store(true, Ordering::SeqCst);
+        let resp = request(addr, CLOSE_REQ).await;
+        assert_eq!(resp, ok);
+        assert_eq!(filter.failures.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn filter_fail_closed() {
+        let (addr, filter) = flaky_server(FilterFailurePolicy::FailClosed).await;
+
+        // Rejected before the first read, nothing to send
+        filter.db_down.store(true, Ordering::SeqCst);
+        let resp = request(addr, b"").await;
+        assert!(
+            resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{resp}"
+        );
+        assert!(resp.ends_with(r#""code":"SERVICE_UNAVAILABLE"}"#), "{resp}");
+        assert_eq!(filter.failures.load(Ordering::SeqCst), 1);
+
+        filter.db_down.store(false, Ordering::SeqCst);
+        let resp = request(addr, CLOSE_REQ).await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+    }
+
+    #[tokio::test]
+    async fn filter_rejected_by_shim() {
+        struct Deny;
+
+        impl ConnectionFilter for Deny {
+            fn filter(
+                &self,
+                _: SocketAddr,
+                _: SocketAddr,
+                _: &mut Response,
+            ) -> Result<(), Handled> {
+                Ok(())
+            }
+
+            async fn filter_async(
+                &self,
+                _: SocketAddr,
+                _: SocketAddr,
+                err_resp: &mut Response,
+            ) -> Result<(), Handled> {
+                Err(err_resp.status(crate::StatusCode::Forbidden).body("no"))
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .conn_filter(Deny)
+            .filter_failure_policy(FilterFailurePolicy::FailOpen)
+            .build();
+        launch(server).await;
+
+        let resp = request(addr, b"").await;
+        assert!(resp.starts_with("HTTP/1.1 403 Forbidden\r\n"), "{resp}");
+        assert!(resp.ends_with("\r\n\r\nno"), "{resp}");
+    }
+
+    #[tokio::test]
+    async fn trailing_data_after_close() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(DefHandler)
+            .connection_limits(ConnLimits {
+                close_drain_size: 64 * 1024,
+                ..ConnLimits::default()
+            })
+            .build();
+        let buffer_size = server.config().buffer_size;
+        launch(server).await;
+
+        // More junk than the request buffer holds, so part of it stays unread
+        let mut data = CLOSE_REQ.to_vec();
+        data.resize(CLOSE_REQ.len() + buffer_size * 2, b'x');
+
+        let resp = request(addr, &data).await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+        assert!(resp.ends_with("\r\n\r\ntest"), "{resp}");
+    }
+
+    struct BigHandler;
+
+    impl Handler for BigHandler {
+        async fn handle(&self, _: &mut (), _: &Request, resp: &mut Response) -> Handled {
+            resp.status(StatusCode::Ok)
+                .body("x".repeat(BIG_BODY).as_str())
+        }
+    }
+
+    const BIG_BODY: usize = 256 * 1024;
+
+    #[tokio::test]
+    async fn half_closed_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .listener(listener)
+            .handler(BigHandler)
+            .build();
+        launch(server).await;
+
+        let clients = (0..8).map(|_| {
+            tokio::spawn(async move {
+                // A small window keeps the tail of the response in the server's send buffer
+                let socket = tokio::net::TcpSocket::new_v4().unwrap();
+                socket.set_recv_buffer_size(4096).unwrap();
+                let mut stream = socket.connect(addr).await.unwrap();
+                stream.write_all(CLOSE_REQ).await.unwrap();
+
+                // The server has written everything and is closing by now
+                let mut response = vec![0; BIG_BODY - 8 * 1024];
+                stream.read_exact(&mut response).await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+                // Unread data at close used to make the server reset the connection
+                stream.write_all(b"late junk").await.unwrap();
+                stream.shutdown().await.unwrap();
+
+                stream.read_to_end(&mut response).await.map(|_| response)
+            })
+        });
+
+        for client in clients {
+            let resp = client.await.unwrap().expect("response lost to `RST`");
+            assert!(resp.starts_with(b"HTTP/1.1 200 OK\r\n"));
+            assert!(resp.ends_with(&[b'x'; 16]));
+            assert!(resp.len() > BIG_BODY);
+        }
+    }
+}